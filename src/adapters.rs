@@ -0,0 +1,154 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::{Event, ParserError};
+use std::iter::{Filter, FilterMap};
+
+type Item = Result<Event, ParserError>;
+type Text = Result<String, ParserError>;
+
+/// Adapters for iterators over `Event`s, such as `Parser`
+///
+/// Errors are always passed through.
+///
+/// ~~~
+/// use xml::{EventIteratorExt, Parser};
+///
+/// let p = Parser::new("<a>x<!--c--><b>y</b><![CDATA[z]]></a>".as_bytes());
+/// let text: Result<String, _> = p.characters_only().collect();
+/// assert_eq!(text.unwrap(), "xyz");
+/// ~~~
+pub trait EventIteratorExt: Iterator<Item = Item> + Sized {
+    /// Drops `Comment` events
+    fn skip_comments(self) -> Filter<Self, fn(&Item) -> bool> {
+        self.filter(|event| !matches!(*event, Ok(Event::Comment(_))))
+    }
+
+    /// Drops `PI` events
+    fn skip_pi(self) -> Filter<Self, fn(&Item) -> bool> {
+        self.filter(|event| !matches!(*event, Ok(Event::PI(_))))
+    }
+
+    /// Yields only the text of `Characters` and `CDATA` events
+    fn characters_only(self) -> FilterMap<Self, fn(Item) -> Option<Text>> {
+        self.filter_map(|event| match event {
+            Ok(Event::Characters(text)) | Ok(Event::CDATA(text)) => Some(Ok(text)),
+            Ok(_) => None,
+            Err(err) => Some(Err(err)),
+        })
+    }
+
+    /// Replaces the text of `Characters` and `CDATA` events by the result of `f`
+    fn map_text<F>(self, f: F) -> MapText<Self, F>
+    where
+        F: FnMut(String) -> String,
+    {
+        MapText { iter: self, f }
+    }
+
+    /// Yields events until the `ElementEnd` of an element named `name` that was started
+    /// before iteration began, typically the element whose `ElementStart` was just read.
+    /// That `ElementEnd` is consumed but not yielded.
+    ///
+    /// ~~~
+    /// use xml::{Event, EventIteratorExt, Parser};
+    ///
+    /// let mut p = Parser::new("<a><item><item/>x</item><b/></a>".as_bytes());
+    /// p.seek_to_element("item", None).unwrap();
+    /// assert_eq!(p.by_ref().until_end_of("item").count(), 3);
+    /// assert!(matches!(p.next(), Some(Ok(Event::ElementStart(ref tag))) if tag.name.name == "b"));
+    /// ~~~
+    fn until_end_of(self, name: &str) -> UntilEndOf<Self> {
+        UntilEndOf {
+            iter: self,
+            name: name.to_owned(),
+            depth: 0,
+            done: false,
+        }
+    }
+}
+
+impl<I> EventIteratorExt for I where I: Iterator<Item = Item> {}
+
+/// Iterator returned by `EventIteratorExt::map_text()`
+pub struct MapText<I, F> {
+    iter: I,
+    f: F,
+}
+
+impl<I, F> Iterator for MapText<I, F>
+where
+    I: Iterator<Item = Item>,
+    F: FnMut(String) -> String,
+{
+    type Item = Item;
+
+    fn next(&mut self) -> Option<Item> {
+        Some(match self.iter.next()? {
+            Ok(Event::Characters(text)) => Ok(Event::Characters((self.f)(text))),
+            Ok(Event::CDATA(text)) => Ok(Event::CDATA((self.f)(text))),
+            event => event,
+        })
+    }
+}
+
+/// Iterator returned by `EventIteratorExt::until_end_of()`
+pub struct UntilEndOf<I> {
+    iter: I,
+    name: String,
+    depth: usize,
+    done: bool,
+}
+
+impl<I> Iterator for UntilEndOf<I>
+where
+    I: Iterator<Item = Item>,
+{
+    type Item = Item;
+
+    fn next(&mut self) -> Option<Item> {
+        if self.done {
+            return None;
+        }
+        let event = self.iter.next()?;
+        match event {
+            Ok(Event::ElementStart(_)) => self.depth += 1,
+            Ok(Event::ElementEnd(ref tag)) if self.depth == 0 && tag.name.name == self.name => {
+                self.done = true;
+                return None;
+            }
+            Ok(Event::ElementEnd(_)) => self.depth = self.depth.saturating_sub(1),
+            _ => (),
+        }
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EventIteratorExt;
+    use crate::{Event, Parser};
+
+    #[test]
+    fn test_adapters() {
+        let doc = "<?pi?><a><!--c-->x<b>y</b><![CDATA[z]]></a>";
+        let events: Vec<_> = Parser::new(doc.as_bytes())
+            .skip_comments()
+            .skip_pi()
+            .map_text(|text| text.to_uppercase())
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(events.len(), 7);
+        assert_eq!(events[1], Event::Characters("X".to_owned()));
+        assert_eq!(events[5], Event::CDATA("Z".to_owned()));
+
+        let mut p = Parser::new("<a>&x;</a>".as_bytes());
+        assert!(p.by_ref().characters_only().any(|text| text.is_err()));
+    }
+}
@@ -0,0 +1,647 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A compact binary encoding of `Event` streams
+//!
+//! Like EXI, the encoding keeps XML's data model while avoiding its textual overhead:
+//! end tags carry no name, and strings such as names, namespaces and short values are
+//! written once and then referred to by number.
+//! The encoding is specific to this crate and not compatible with EXI or Fast Infoset.
+//!
+//! ~~~
+//! use xml::binary::{BinaryReader, BinaryWriter};
+//! use xml::Parser;
+//!
+//! let input = "<log><entry level='info'>up</entry><entry level='info'>ok</entry></log>";
+//! let mut writer = BinaryWriter::new(Vec::new()).unwrap();
+//! for event in Parser::new(input.as_bytes()) {
+//!     writer.write(&event.unwrap()).unwrap();
+//! }
+//! let encoded = writer.into_inner();
+//! assert!(encoded.len() < input.len());
+//!
+//! let events: Vec<_> = BinaryReader::new(&encoded[..]).collect::<Result<_, _>>().unwrap();
+//! let expected: Vec<_> = Parser::new(input.as_bytes()).collect::<Result<_, _>>().unwrap();
+//! assert_eq!(events, expected);
+//! ~~~
+//!
+//! `record()` stores the events of a `Parser` together with their positions and a final
+//! error, if any. A `Replayer` reads them back as the parser returned them, which is useful
+//! for regression fixtures and for benchmarking consumers without parsing.
+//!
+//! ~~~
+//! use xml::binary::{record, Replayer};
+//! use xml::Parser;
+//!
+//! let input = "<a>\n<b>x</b>&y;</a>";
+//! let recording = record(&mut Parser::new(input.as_bytes()), Vec::new()).unwrap();
+//!
+//! let mut replayer = Replayer::new(&recording[..]);
+//! let replayed: Vec<_> = replayer.by_ref().collect();
+//! let parsed: Vec<_> = Parser::new(input.as_bytes()).collect();
+//! assert_eq!(replayed, parsed);
+//! assert_eq!(replayer.position().line, 2);
+//! ~~~
+
+use crate::compact::{self, StringList, StringTable, MAX_INT_LEN};
+use crate::parser::ParserErrorKind;
+pub use crate::Position;
+use crate::{
+    AttrMap, AttributeChunk, Doctype, EndTag, Event, Parser, ParserError, QName, StartTag, XmlDecl,
+};
+use std::io::{self, Read, Write};
+
+const MAGIC: &[u8] = b"XMLB\x01";
+const RECORDING_MAGIC: &[u8] = b"XMLR\x01";
+// Only strings up to this length are added to the string table
+const MAX_INTERNED_LEN: usize = 256;
+// The string table stops growing at this size, bounding memory use on long streams
+const MAX_TABLE_SIZE: usize = 1 << 16;
+
+const PI: u8 = 0;
+const START: u8 = 1;
+const END: u8 = 2;
+const CHARACTERS: u8 = 3;
+const CDATA: u8 = 4;
+const COMMENT: u8 = 5;
+const ATTRIBUTE_CHUNK: u8 = 6;
+const ERROR: u8 = 7;
+const XML_DECL: u8 = 8;
+const DOCTYPE: u8 = 9;
+
+// Error kinds are recorded as these numbers, which must not change
+fn error_kind_code(kind: ParserErrorKind) -> u64 {
+    match kind {
+        ParserErrorKind::UnboundNsPrefixInTagName => 0,
+        ParserErrorKind::UnboundNsPrefixInAttributeName => 1,
+        ParserErrorKind::SpaceInAttributeName => 2,
+        ParserErrorKind::DuplicateAttribute => 3,
+        ParserErrorKind::UndelimitedAttribute => 4,
+        ParserErrorKind::InvalidEntity => 5,
+        ParserErrorKind::InvalidCdataStart => 6,
+        ParserErrorKind::InvalidCommentStart => 7,
+        ParserErrorKind::InvalidCommentContent => 8,
+        ParserErrorKind::InvalidDoctype => 9,
+        ParserErrorKind::ExpectedTagClose => 10,
+        ParserErrorKind::ExpectedLwsOrTagClose => 11,
+        ParserErrorKind::MalformedXml => 12,
+        ParserErrorKind::NotAtElementStart => 13,
+        ParserErrorKind::UnexpectedEof => 14,
+        ParserErrorKind::Cancelled => 15,
+        ParserErrorKind::ExternalEntity => 16,
+        ParserErrorKind::InvalidUtf8 => 17,
+        ParserErrorKind::NeedMoreData => 18,
+        ParserErrorKind::TagMismatch => 19,
+        ParserErrorKind::MultipleRoots => 20,
+        ParserErrorKind::MissingRoot => 21,
+        ParserErrorKind::TextOutsideRoot => 22,
+        ParserErrorKind::MisplacedDoctype => 23,
+        ParserErrorKind::InvalidName => 24,
+        ParserErrorKind::EntityLimitExceeded => 25,
+        ParserErrorKind::InvalidCharRef => 26,
+        ParserErrorKind::LimitExceeded => 27,
+        ParserErrorKind::InvalidUtf16 => 28,
+        ParserErrorKind::InvalidXmlDecl => 29,
+        ParserErrorKind::MisplacedXmlDecl => 30,
+        ParserErrorKind::UnexpectedElement => 31,
+    }
+}
+
+fn error_kind(code: u64) -> Option<ParserErrorKind> {
+    Some(match code {
+        0 => ParserErrorKind::UnboundNsPrefixInTagName,
+        1 => ParserErrorKind::UnboundNsPrefixInAttributeName,
+        2 => ParserErrorKind::SpaceInAttributeName,
+        3 => ParserErrorKind::DuplicateAttribute,
+        4 => ParserErrorKind::UndelimitedAttribute,
+        5 => ParserErrorKind::InvalidEntity,
+        6 => ParserErrorKind::InvalidCdataStart,
+        7 => ParserErrorKind::InvalidCommentStart,
+        8 => ParserErrorKind::InvalidCommentContent,
+        9 => ParserErrorKind::InvalidDoctype,
+        10 => ParserErrorKind::ExpectedTagClose,
+        11 => ParserErrorKind::ExpectedLwsOrTagClose,
+        12 => ParserErrorKind::MalformedXml,
+        13 => ParserErrorKind::NotAtElementStart,
+        14 => ParserErrorKind::UnexpectedEof,
+        15 => ParserErrorKind::Cancelled,
+        16 => ParserErrorKind::ExternalEntity,
+        17 => ParserErrorKind::InvalidUtf8,
+        18 => ParserErrorKind::NeedMoreData,
+        19 => ParserErrorKind::TagMismatch,
+        20 => ParserErrorKind::MultipleRoots,
+        21 => ParserErrorKind::MissingRoot,
+        22 => ParserErrorKind::TextOutsideRoot,
+        23 => ParserErrorKind::MisplacedDoctype,
+        24 => ParserErrorKind::InvalidName,
+        25 => ParserErrorKind::EntityLimitExceeded,
+        26 => ParserErrorKind::InvalidCharRef,
+        27 => ParserErrorKind::LimitExceeded,
+        28 => ParserErrorKind::InvalidUtf16,
+        29 => ParserErrorKind::InvalidXmlDecl,
+        30 => ParserErrorKind::MisplacedXmlDecl,
+        31 => ParserErrorKind::UnexpectedElement,
+        _ => return None,
+    })
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// A writer encoding `Event`s in the binary format
+pub struct BinaryWriter<W>
+where
+    W: Write,
+{
+    writer: W,
+    strings: StringTable<String>,
+}
+
+impl<W> BinaryWriter<W>
+where
+    W: Write,
+{
+    /// Returns a new `BinaryWriter`, writing the format header to `writer`
+    pub fn new(writer: W) -> io::Result<BinaryWriter<W>> {
+        BinaryWriter::with_magic(writer, MAGIC)
+    }
+
+    fn with_magic(mut writer: W, magic: &[u8]) -> io::Result<BinaryWriter<W>> {
+        writer.write_all(magic)?;
+        Ok(BinaryWriter {
+            writer,
+            strings: StringTable::new(MAX_INTERNED_LEN, MAX_TABLE_SIZE),
+        })
+    }
+
+    /// Returns the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Flushes the underlying writer
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Writes a single `Event`
+    pub fn write(&mut self, event: &Event) -> io::Result<()> {
+        match *event {
+            Event::ElementStart(ref tag) => {
+                self.writer.write_all(&[START])?;
+                self.str(&tag.name.name)?;
+                self.opt(&tag.name.ns)?;
+                self.opt(&tag.prefix)?;
+                self.int(tag.attributes.len() as u64)?;
+                for (QName { name, ns }, value) in &tag.attributes {
+                    self.str(name)?;
+                    self.opt(ns)?;
+                    self.str(value)?;
+                }
+                Ok(())
+            }
+            Event::ElementEnd(_) => self.writer.write_all(&[END]),
+            Event::PI(ref data) => self.text(PI, data),
+            Event::Characters(ref data) => self.text(CHARACTERS, data),
+            Event::CDATA(ref data) => self.text(CDATA, data),
+            Event::Comment(ref data) => self.text(COMMENT, data),
+            Event::AttributeChunk(ref chunk) => {
+                self.writer.write_all(&[ATTRIBUTE_CHUNK])?;
+                self.str(&chunk.name)?;
+                self.opt(&chunk.ns)?;
+                self.str(&chunk.value)?;
+                self.writer.write_all(&[u8::from(chunk.last)])
+            }
+            Event::XmlDecl(ref decl) => {
+                self.writer.write_all(&[XML_DECL])?;
+                self.str(&decl.version)?;
+                self.opt(&decl.encoding)?;
+                self.writer.write_all(&[match decl.standalone {
+                    None => 0,
+                    Some(false) => 1,
+                    Some(true) => 2,
+                }])
+            }
+            Event::Doctype(ref doctype) => {
+                self.writer.write_all(&[DOCTYPE])?;
+                self.str(&doctype.name)?;
+                self.opt(&doctype.public_id)?;
+                self.opt(&doctype.system_id)?;
+                self.opt(&doctype.internal_subset)
+            }
+        }
+    }
+
+    fn position(&mut self, line: u64, col: u64, offset: u64) -> io::Result<()> {
+        self.int(line)?;
+        self.int(col)?;
+        self.int(offset)
+    }
+
+    fn text(&mut self, kind: u8, data: &str) -> io::Result<()> {
+        self.writer.write_all(&[kind])?;
+        self.str(data)
+    }
+
+    fn int(&mut self, n: u64) -> io::Result<()> {
+        self.writer
+            .write_all(compact::encode_int(n, &mut [0; MAX_INT_LEN]))
+    }
+
+    fn str(&mut self, s: &str) -> io::Result<()> {
+        if let Some(index) = self.strings.get(s) {
+            return self.int(index);
+        }
+        self.int(0)?;
+        self.int(s.len() as u64)?;
+        self.writer.write_all(s.as_bytes())
+    }
+
+    fn opt(&mut self, s: &Option<String>) -> io::Result<()> {
+        match *s {
+            Some(ref s) => {
+                self.writer.write_all(&[1])?;
+                self.str(s)
+            }
+            None => self.writer.write_all(&[0]),
+        }
+    }
+}
+
+/// A reader decoding `Event`s from the binary format
+pub struct BinaryReader<R>
+where
+    R: Read,
+{
+    reader: R,
+    started: bool,
+    has_error: bool,
+    strings: StringList<String>,
+    open: Vec<EndTag>,
+}
+
+impl<R> BinaryReader<R>
+where
+    R: Read,
+{
+    /// Returns a new `BinaryReader` reading from `reader`
+    pub fn new(reader: R) -> BinaryReader<R> {
+        BinaryReader {
+            reader,
+            started: false,
+            has_error: false,
+            strings: StringList::new(MAX_INTERNED_LEN, MAX_TABLE_SIZE),
+            open: Vec::new(),
+        }
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        let mut buf = [0u8; 1];
+        self.reader.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn int(&mut self) -> io::Result<u64> {
+        compact::read_int(&mut self.reader)
+    }
+
+    fn str(&mut self) -> io::Result<String> {
+        let index = self.int()?;
+        if index > 0 {
+            return match self.strings.get(index) {
+                Some(s) => Ok(s.clone()),
+                None => Err(invalid_data("Unknown string reference")),
+            };
+        }
+        let len = self.int()?;
+        let mut buf = Vec::new();
+        self.reader.by_ref().take(len).read_to_end(&mut buf)?;
+        if buf.len() as u64 != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let s = String::from_utf8(buf).map_err(|_| invalid_data("Invalid UTF-8"))?;
+        self.strings.push(s.clone());
+        Ok(s)
+    }
+
+    fn opt(&mut self) -> io::Result<Option<String>> {
+        match self.byte()? {
+            0 => Ok(None),
+            1 => self.str().map(Some),
+            _ => Err(invalid_data("Invalid optional string")),
+        }
+    }
+
+    fn event(&mut self, kind: u8) -> io::Result<Event> {
+        Ok(match kind {
+            START => {
+                let name = QName::new(self.str()?, self.opt()?);
+                let prefix = self.opt()?;
+                let count = self.int()?;
+                let mut attributes = AttrMap::new();
+                for _ in 0..count {
+                    let name = self.str()?;
+                    let ns = self.opt()?;
+                    attributes.insert(QName::new(name, ns), self.str()?);
+                }
+                self.open.push(EndTag {
+                    name: name.clone(),
+                    prefix: prefix.clone(),
+                });
+                Event::ElementStart(StartTag {
+                    name,
+                    prefix,
+                    attributes,
+                })
+            }
+            END => match self.open.pop() {
+                Some(tag) => Event::ElementEnd(tag),
+                None => return Err(invalid_data("End tag without start tag")),
+            },
+            PI => Event::PI(self.str()?),
+            CHARACTERS => Event::Characters(self.str()?),
+            CDATA => Event::CDATA(self.str()?),
+            COMMENT => Event::Comment(self.str()?),
+            ATTRIBUTE_CHUNK => Event::AttributeChunk(AttributeChunk {
+                name: self.str()?,
+                ns: self.opt()?,
+                value: self.str()?,
+                last: match self.byte()? {
+                    0 => false,
+                    1 => true,
+                    _ => return Err(invalid_data("Invalid flag")),
+                },
+            }),
+            XML_DECL => Event::XmlDecl(XmlDecl {
+                version: self.str()?,
+                encoding: self.opt()?,
+                standalone: match self.byte()? {
+                    0 => None,
+                    1 => Some(false),
+                    2 => Some(true),
+                    _ => return Err(invalid_data("Invalid flag")),
+                },
+            }),
+            DOCTYPE => Event::Doctype(Doctype {
+                name: self.str()?,
+                public_id: self.opt()?,
+                system_id: self.opt()?,
+                internal_subset: self.opt()?,
+            }),
+            _ => return Err(invalid_data("Unknown event")),
+        })
+    }
+
+    fn start(&mut self, magic: &[u8]) -> io::Result<()> {
+        if !self.started {
+            self.started = true;
+            let mut buf = [0u8; 5];
+            if self.reader.read_exact(&mut buf).is_err() || buf != magic {
+                return Err(invalid_data("Not a binary XML stream"));
+            }
+        }
+        Ok(())
+    }
+
+    fn next_event(&mut self) -> Option<io::Result<Event>> {
+        if let Err(err) = self.start(MAGIC) {
+            return Some(Err(err));
+        }
+        let mut kind = [0u8; 1];
+        match self.reader.read(&mut kind) {
+            Ok(0) => None,
+            Ok(_) => Some(self.event(kind[0])),
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+impl<R> Iterator for BinaryReader<R>
+where
+    R: Read,
+{
+    type Item = io::Result<Event>;
+
+    fn next(&mut self) -> Option<io::Result<Event>> {
+        if self.has_error {
+            return None;
+        }
+        let result = self.next_event();
+        self.has_error = matches!(result, Some(Err(_)));
+        result
+    }
+}
+
+/// Reads all events from `parser` and records them with their positions to `writer`, in a
+/// format read by `Replayer`. An error returned by the parser ends the recording and is
+/// recorded as well.
+pub fn record<R, W>(parser: &mut Parser<R>, writer: W) -> io::Result<W>
+where
+    R: Read,
+    W: Write,
+{
+    let mut writer = BinaryWriter::with_magic(writer, RECORDING_MAGIC)?;
+    while let Some(result) = parser.next() {
+        match result {
+            Ok(event) => {
+                let Position { line, col, offset } = Parser::position(parser);
+                writer.position(line, col, offset)?;
+                writer.write(&event)?;
+            }
+            Err(err) => {
+                writer.position(err.line, err.col, err.offset)?;
+                writer.writer.write_all(&[ERROR])?;
+                writer.int(error_kind_code(err.kind))?;
+                break;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(writer.into_inner())
+}
+
+/// An iterator returning the events and error recorded by `record()`
+///
+/// Recordings which cannot be read end with a `MalformedXml` error.
+pub struct Replayer<R>
+where
+    R: Read,
+{
+    reader: BinaryReader<R>,
+    position: Position,
+}
+
+impl<R> Replayer<R>
+where
+    R: Read,
+{
+    /// Returns a new `Replayer` reading from `reader`
+    pub fn new(reader: R) -> Replayer<R> {
+        Replayer {
+            reader: BinaryReader::new(reader),
+            position: Position::default(),
+        }
+    }
+
+    /// Returns the position at which the last returned event ended in the recorded input
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    fn next_result(&mut self) -> io::Result<Option<Result<Event, ParserError>>> {
+        self.reader.start(RECORDING_MAGIC)?;
+        let mut line = [0u8; 1];
+        if self.reader.reader.read(&mut line)? == 0 {
+            return Ok(None);
+        }
+        // Positions are written as integers, the first byte of which has been read
+        let position = Position {
+            line: compact::finish_int(&mut self.reader.reader, line[0])?,
+            col: self.reader.int()?,
+            offset: self.reader.int()?,
+        };
+        match self.reader.byte()? {
+            ERROR => {
+                let kind = error_kind(self.reader.int()?)
+                    .ok_or_else(|| invalid_data("Unknown error kind"))?;
+                Ok(Some(Err(ParserError {
+                    line: position.line,
+                    col: position.col,
+                    offset: position.offset,
+                    kind,
+                })))
+            }
+            kind => {
+                let event = self.reader.event(kind)?;
+                self.position = position;
+                Ok(Some(Ok(event)))
+            }
+        }
+    }
+}
+
+impl<R> Iterator for Replayer<R>
+where
+    R: Read,
+{
+    type Item = Result<Event, ParserError>;
+
+    fn next(&mut self) -> Option<Result<Event, ParserError>> {
+        if self.reader.has_error {
+            return None;
+        }
+        let result = match self.next_result() {
+            Ok(result) => result,
+            Err(_) => Some(Err(ParserError {
+                line: self.position.line,
+                col: self.position.col,
+                offset: self.position.offset,
+                kind: ParserErrorKind::MalformedXml,
+            })),
+        };
+        self.reader.has_error = matches!(result, Some(Err(_)));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        error_kind, error_kind_code, record, BinaryReader, BinaryWriter, Replayer, RECORDING_MAGIC,
+    };
+    use crate::{Event, Parser};
+    use std::io;
+
+    #[test]
+    fn test_roundtrip() {
+        let input = "<?xml version='1.0' standalone='no'?><!DOCTYPE a SYSTEM 'a.dtd' []><?pi x?>\
+                     <a xmlns='urn:a' xmlns:p='urn:p' p:x='1'>t<!--c-->\
+                     <p:b><![CDATA[<d>]]></p:b><b/></a>";
+        let mut writer = BinaryWriter::new(Vec::new()).unwrap();
+        for event in Parser::new(input.as_bytes()) {
+            writer.write(&event.unwrap()).unwrap();
+        }
+        let encoded = writer.into_inner();
+        let events: Vec<_> = BinaryReader::new(&encoded[..])
+            .map(Result::unwrap)
+            .collect();
+        let expected: Vec<_> = Parser::new(input.as_bytes()).map(Result::unwrap).collect();
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut reader = BinaryReader::new(&b"<a/>"[..]);
+        assert_eq!(
+            reader.next().unwrap().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert!(reader.next().is_none());
+
+        let truncated = b"XMLB\x01\x01\x00\x05ab";
+        let err = BinaryReader::new(&truncated[..])
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let unbalanced = b"XMLB\x01\x02";
+        let err = BinaryReader::new(&unbalanced[..])
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_record_replay() {
+        let input = "<?pi x?>\r\n<a xmlns:p='urn:p' p:x='1'>t<!--c-->\n<p:b/>\n<c>&x;</c></a>";
+        let recording = record(&mut Parser::new(input.as_bytes()), Vec::new()).unwrap();
+        let mut parser = Parser::new(input.as_bytes());
+        let mut replayer = Replayer::new(&recording[..]);
+        loop {
+            let expected = parser.next();
+            assert_eq!(replayer.next(), expected);
+            if expected.is_none() {
+                break;
+            }
+            if let Some(Ok(_)) = expected {
+                assert_eq!(replayer.position(), parser.position());
+            }
+        }
+        assert!(replayer.next().is_none());
+
+        // Long inputs need positions of several bytes
+        let input = format!("<a>{}</a>", "\n".repeat(300));
+        let recording = record(&mut Parser::new(input.as_bytes()), Vec::new()).unwrap();
+        let mut replayer = Replayer::new(&recording[..]);
+        assert_eq!(replayer.by_ref().count(), 3);
+        assert_eq!(replayer.position().line, 301);
+
+        // Positions are not truncated to `usize`
+        let mut writer = BinaryWriter::with_magic(Vec::new(), RECORDING_MAGIC).unwrap();
+        writer.position(u64::MAX, 1, 1 << 40).unwrap();
+        writer.write(&Event::Characters("x".to_owned())).unwrap();
+        let recording = writer.into_inner();
+        let mut replayer = Replayer::new(&recording[..]);
+        assert!(replayer.next().unwrap().is_ok());
+        assert_eq!(replayer.position().line, u64::MAX);
+        assert_eq!(replayer.position().offset, 1 << 40);
+
+        let mut replayer = Replayer::new(&recording[..recording.len() - 1]);
+        assert!(replayer.by_ref().last().unwrap().is_err());
+        assert!(Replayer::new(&b"XMLB\x01"[..]).next().unwrap().is_err());
+
+        // Every error kind is recorded with a distinct code
+        let codes = (0..).map_while(|code| error_kind(code).map(error_kind_code));
+        assert!((0..).zip(codes).all(|(i, code)| i == code));
+        assert!(error_kind(32).is_none());
+    }
+}
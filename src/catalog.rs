@@ -0,0 +1,565 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Mapping public and system identifiers to local files with OASIS XML Catalogs
+//!
+//! A `Catalog` is read from catalog documents as described by the OASIS XML Catalogs
+//! specification. It supports the `public`, `system`, `rewriteSystem` and `systemSuffix`
+//! entries for external identifiers, the `uri`, `rewriteURI` and `uriSuffix` entries for
+//! other references such as schema locations, as well as `group`, `xml:base` and
+//! `nextCatalog`. Delegation entries are ignored.
+//!
+//! As an `EntityResolver`, a catalog reads external entities and DTDs from the local
+//! files they are mapped to. Unmapped identifiers are rejected rather than fetched.
+//!
+//! ~~~
+//! use xml::catalog::Catalog;
+//!
+//! let catalog = Catalog::parse(
+//!     "<catalog xmlns='urn:oasis:names:tc:entity:xmlns:xml:catalog'>\
+//!      <public publicId='-//W3C//DTD XHTML 1.0 Strict//EN' uri='xhtml1-strict.dtd'/>\
+//!      <rewriteURI uriStartString='http://example.com/schemas/' rewritePrefix='xsd/'/>\
+//!      </catalog>",
+//!     "/usr/share/xml/catalog.xml",
+//! )
+//! .unwrap();
+//! assert_eq!(
+//!     catalog
+//!         .resolve_external(Some("-//W3C//DTD XHTML 1.0 Strict//EN"), "xhtml1-strict.dtd")
+//!         .as_deref(),
+//!     Some("/usr/share/xml/xhtml1-strict.dtd")
+//! );
+//! assert_eq!(
+//!     catalog.resolve_uri("http://example.com/schemas/order.xsd").as_deref(),
+//!     Some("/usr/share/xml/xsd/order.xsd")
+//! );
+//! ~~~
+
+use crate::{ns, BuilderError, Element, EntityResolver};
+use std::cell::OnceCell;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug)]
+/// Errors encountered while loading a catalog
+pub enum CatalogError {
+    /// A catalog file could not be read
+    Io(io::Error),
+    /// A catalog document is not well-formed XML
+    Builder(BuilderError),
+    /// The root element of a catalog document is not `<catalog>`
+    NotACatalog,
+    /// A catalog entry lacks a required attribute
+    InvalidEntry(String),
+}
+
+impl Error for CatalogError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            CatalogError::Io(ref err) => Some(err),
+            CatalogError::Builder(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CatalogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CatalogError::Io(ref err) => err.fmt(f),
+            CatalogError::Builder(ref err) => err.fmt(f),
+            CatalogError::NotACatalog => write!(f, "Root element is not an OASIS catalog"),
+            CatalogError::InvalidEntry(ref name) => {
+                write!(f, "Missing attribute on catalog entry <{}>", name)
+            }
+        }
+    }
+}
+
+impl From<io::Error> for CatalogError {
+    fn from(err: io::Error) -> CatalogError {
+        CatalogError::Io(err)
+    }
+}
+
+impl From<BuilderError> for CatalogError {
+    fn from(err: BuilderError) -> CatalogError {
+        CatalogError::Builder(err)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum RuleKind {
+    Public,
+    System,
+    RewriteSystem,
+    SystemSuffix,
+    Uri,
+    RewriteUri,
+    UriSuffix,
+}
+
+#[derive(Clone, Debug)]
+// A single catalog entry, mapping identifiers matching `from` to `to`
+struct Rule {
+    kind: RuleKind,
+    from: String,
+    // An absolute URI, or the prefix replacing `from` for rewrite entries
+    to: String,
+    // Whether a public entry applies to identifiers that also have a system identifier
+    prefer_public: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+/// An OASIS XML Catalog
+pub struct Catalog {
+    // The location of the catalog document, if read from one
+    location: Option<String>,
+    rules: Vec<Rule>,
+    // Catalogs consulted when no entry of this one matches
+    next: Vec<NextCatalog>,
+}
+
+#[derive(Clone, Debug)]
+// A catalog referred to by a `nextCatalog` entry, read when first consulted.
+// One that cannot be read is treated as empty.
+struct NextCatalog {
+    location: Option<String>,
+    catalog: OnceCell<Option<Catalog>>,
+}
+
+impl NextCatalog {
+    fn get(&self) -> Option<&Catalog> {
+        self.catalog
+            .get_or_init(|| {
+                let location = self.location.as_deref()?;
+                let mut catalog = Catalog::load(uri_to_path(location).ok()?).ok()?;
+                catalog.location = Some(location.to_owned());
+                Some(catalog)
+            })
+            .as_ref()
+    }
+}
+
+impl Catalog {
+    /// Returns a new, empty `Catalog`
+    pub fn new() -> Catalog {
+        Catalog::default()
+    }
+
+    /// Reads the catalog document at `path`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Catalog, CatalogError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        Catalog::parse(&text, &path.to_string_lossy())
+    }
+
+    /// Reads a catalog from `text`. Relative references in the catalog are resolved
+    /// against `base`, the location of the catalog document.
+    ///
+    /// Catalogs referred to by `nextCatalog` entries are read from the file system when
+    /// an identifier is first looked up in them, that is when no entry of the catalogs
+    /// preceding them matches. A catalog that cannot be read is treated as empty, and
+    /// one already consulted for the same identifier is skipped.
+    pub fn parse(text: &str, base: &str) -> Result<Catalog, CatalogError> {
+        let root: Element = text.parse()?;
+        if root.name.name != "catalog" || root.name.ns.as_deref() != Some(ns::CATALOG) {
+            return Err(CatalogError::NotACatalog);
+        }
+        let mut catalog = Catalog::new();
+        catalog.location = Some(base.to_owned());
+        catalog.add_entries(&root, base, true)?;
+        Ok(catalog)
+    }
+
+    /// Appends `next` to the catalogs consulted when no entry of this catalog matches
+    pub fn add_next(&mut self, next: Catalog) -> &mut Catalog {
+        self.next.push(NextCatalog {
+            location: None,
+            catalog: OnceCell::from(Some(next)),
+        });
+        self
+    }
+
+    fn add_entries(
+        &mut self,
+        elem: &Element,
+        base: &str,
+        prefer_public: bool,
+    ) -> Result<(), CatalogError> {
+        let base = match elem.get_attribute("base", Some(ns::XML)) {
+            Some(xml_base) => resolve_reference(base, xml_base),
+            None => base.to_owned(),
+        };
+        let prefer_public = match elem.get_attribute("prefer", None) {
+            Some(prefer) => prefer == "public",
+            None => prefer_public,
+        };
+        for child in elem.child_elements() {
+            if child.name.ns.as_deref() != Some(ns::CATALOG) {
+                continue;
+            }
+            let (kind, from, to) = match &child.name.name[..] {
+                "group" => {
+                    self.add_entries(child, &base, prefer_public)?;
+                    continue;
+                }
+                "nextCatalog" => {
+                    let location = required(child, "catalog")?;
+                    self.next.push(NextCatalog {
+                        location: Some(resolve_reference(&base, location)),
+                        catalog: OnceCell::new(),
+                    });
+                    continue;
+                }
+                "public" => (RuleKind::Public, "publicId", "uri"),
+                "system" => (RuleKind::System, "systemId", "uri"),
+                "rewriteSystem" => (
+                    RuleKind::RewriteSystem,
+                    "systemIdStartString",
+                    "rewritePrefix",
+                ),
+                "systemSuffix" => (RuleKind::SystemSuffix, "systemIdSuffix", "uri"),
+                "uri" => (RuleKind::Uri, "name", "uri"),
+                "rewriteURI" => (RuleKind::RewriteUri, "uriStartString", "rewritePrefix"),
+                "uriSuffix" => (RuleKind::UriSuffix, "uriSuffix", "uri"),
+                _ => continue,
+            };
+            let from = required(child, from)?;
+            let child_base = match child.get_attribute("base", Some(ns::XML)) {
+                Some(xml_base) => resolve_reference(&base, xml_base),
+                None => base.clone(),
+            };
+            self.rules.push(Rule {
+                kind,
+                from: match kind {
+                    RuleKind::Public => normalize_public_id(from),
+                    _ => from.to_owned(),
+                },
+                to: resolve_reference(&child_base, required(child, to)?),
+                prefer_public,
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns the URI an external identifier is mapped to, if any
+    pub fn resolve_external(&self, public_id: Option<&str>, system_id: &str) -> Option<String> {
+        let public_id = public_id.map(normalize_public_id);
+        self.resolve_with(|catalog| {
+            catalog
+                .lookup(
+                    system_id,
+                    RuleKind::System,
+                    RuleKind::RewriteSystem,
+                    RuleKind::SystemSuffix,
+                )
+                .or_else(|| {
+                    let public_id = public_id.as_deref()?;
+                    catalog
+                        .rules
+                        .iter()
+                        .find(|rule| {
+                            rule.kind == RuleKind::Public
+                                && rule.from == public_id
+                                && (rule.prefer_public || system_id.is_empty())
+                        })
+                        .map(|rule| rule.to.clone())
+                })
+        })
+    }
+
+    /// Returns the URI a reference such as a namespace name or schema location is mapped
+    /// to, if any
+    pub fn resolve_uri(&self, uri: &str) -> Option<String> {
+        self.resolve_with(|catalog| {
+            catalog.lookup(
+                uri,
+                RuleKind::Uri,
+                RuleKind::RewriteUri,
+                RuleKind::UriSuffix,
+            )
+        })
+    }
+
+    fn resolve_with<F>(&self, lookup: F) -> Option<String>
+    where
+        F: Fn(&Catalog) -> Option<String> + Copy,
+    {
+        let mut visited = self.location.iter().cloned().collect();
+        self.resolve_next(lookup, &mut visited)
+    }
+
+    // Consults this catalog and its next catalogs, except those at the `visited` locations
+    fn resolve_next<F>(&self, lookup: F, visited: &mut Vec<String>) -> Option<String>
+    where
+        F: Fn(&Catalog) -> Option<String> + Copy,
+    {
+        if let Some(found) = lookup(self) {
+            return Some(found);
+        }
+        for next in &self.next {
+            if let Some(ref location) = next.location {
+                if visited.contains(location) {
+                    continue;
+                }
+                visited.push(location.clone());
+            }
+            if let Some(found) = next
+                .get()
+                .and_then(|next| next.resolve_next(lookup, visited))
+            {
+                return Some(found);
+            }
+        }
+        None
+    }
+
+    // Matches `id` exactly, then by the longest prefix and by the longest suffix
+    fn lookup(
+        &self,
+        id: &str,
+        exact: RuleKind,
+        rewrite: RuleKind,
+        suffix: RuleKind,
+    ) -> Option<String> {
+        let rules = || self.rules.iter();
+        if let Some(rule) = rules().find(|rule| rule.kind == exact && rule.from == id) {
+            return Some(rule.to.clone());
+        }
+        let rewritten = rules()
+            .filter(|rule| rule.kind == rewrite && id.starts_with(&rule.from[..]))
+            .max_by_key(|rule| rule.from.len());
+        if let Some(rule) = rewritten {
+            return Some(format!("{}{}", rule.to, &id[rule.from.len()..]));
+        }
+        rules()
+            .filter(|rule| rule.kind == suffix && id.ends_with(&rule.from[..]))
+            .max_by_key(|rule| rule.from.len())
+            .map(|rule| rule.to.clone())
+    }
+}
+
+impl EntityResolver for Catalog {
+    fn resolve(&mut self, public_id: Option<&str>, system_id: &str) -> io::Result<String> {
+        let uri = self
+            .resolve_external(public_id, system_id)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No catalog entry"))?;
+        fs::read_to_string(uri_to_path(&uri)?)
+    }
+}
+
+fn required<'a>(elem: &'a Element, name: &str) -> Result<&'a str, CatalogError> {
+    elem.get_attribute(name, None)
+        .ok_or_else(|| CatalogError::InvalidEntry(elem.name.name.to_string()))
+}
+
+// Collapses whitespace as public identifiers are compared after normalization
+fn normalize_public_id(id: &str) -> String {
+    id.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn has_scheme(uri: &str) -> bool {
+    match uri.find(':') {
+        // A single letter is taken to be a drive letter
+        Some(i) if i > 1 => uri[..i]
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.')),
+        _ => false,
+    }
+}
+
+// Resolves a relative reference against the location of the document containing it
+fn resolve_reference(base: &str, reference: &str) -> String {
+    if has_scheme(reference) || reference.starts_with('/') {
+        return reference.to_owned();
+    }
+    let dir = base.rfind(['/', '\\']).map_or(0, |i| i + 1);
+    format!("{}{}", &base[..dir], reference)
+}
+
+// Returns the local path of a `file:` URI or a path, rejecting other URIs
+fn uri_to_path(uri: &str) -> io::Result<&Path> {
+    if let Some(path) = uri.strip_prefix("file://") {
+        return Ok(Path::new(path));
+    }
+    if let Some(path) = uri.strip_prefix("file:") {
+        return Ok(Path::new(path));
+    }
+    if has_scheme(uri) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Only local files are read",
+        ));
+    }
+    Ok(Path::new(uri))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve_reference, Catalog, CatalogError};
+    use crate::{EntityResolver, Event, Parser};
+    use std::{fs, io};
+
+    const CATALOG: &str = "<catalog xmlns='urn:oasis:names:tc:entity:xmlns:xml:catalog'\n\
+                           prefer='system'>\n\
+                           <public publicId='-//X//DTD  Ignored//EN' uri='ignored.dtd'/>\n\
+                           <group prefer='public' xml:base='dtd/'>\n\
+                           <public publicId='-//X//DTD Doc//EN' uri='doc.dtd'/>\n\
+                           <system systemId='http://x.org/a.dtd' uri='a.dtd'/>\n\
+                           </group>\n\
+                           <rewriteSystem systemIdStartString='http://x.org/'\n\
+                           rewritePrefix='file:///opt/x/'/>\n\
+                           <rewriteSystem systemIdStartString='http://x.org/ent/'\n\
+                           rewritePrefix='ent/'/>\n\
+                           <systemSuffix systemIdSuffix='/b.dtd' uri='b.dtd'/>\n\
+                           <uriSuffix uriSuffix='.xsd' uri='any.xsd'/>\n\
+                           <uri name='urn:x:schema' uri='http://x.org/schema.xsd'/>\n\
+                           <other:entry xmlns:other='urn:other'/>\n\
+                           </catalog>";
+
+    #[test]
+    fn test_resolve() {
+        let catalog = Catalog::parse(CATALOG, "/etc/xml/catalog").unwrap();
+        assert_eq!(
+            catalog.resolve_external(Some("-//X//DTD   Doc//EN"), "doc.dtd"),
+            Some("/etc/xml/dtd/doc.dtd".to_owned())
+        );
+        assert_eq!(
+            catalog.resolve_external(None, "http://x.org/a.dtd"),
+            Some("/etc/xml/dtd/a.dtd".to_owned())
+        );
+        assert_eq!(
+            catalog.resolve_external(None, "http://x.org/ent/c.ent"),
+            Some("/etc/xml/ent/c.ent".to_owned())
+        );
+        assert_eq!(
+            catalog.resolve_external(None, "http://x.org/c.dtd"),
+            Some("file:///opt/x/c.dtd".to_owned())
+        );
+        assert_eq!(
+            catalog.resolve_external(None, "http://y.org/b.dtd"),
+            Some("/etc/xml/b.dtd".to_owned())
+        );
+        // The entry is ignored, as prefer='system' applies to it
+        assert_eq!(
+            catalog.resolve_external(Some("-//X//DTD Ignored//EN"), "ignored.dtd"),
+            None
+        );
+        assert_eq!(
+            catalog.resolve_uri("urn:x:schema"),
+            Some("http://x.org/schema.xsd".to_owned())
+        );
+        assert_eq!(
+            catalog.resolve_uri("http://y.org/order.xsd"),
+            Some("/etc/xml/any.xsd".to_owned())
+        );
+        assert_eq!(catalog.resolve_uri("http://y.org/order.dtd"), None);
+
+        assert!(matches!(
+            Catalog::parse("<catalog/>", ""),
+            Err(CatalogError::NotACatalog)
+        ));
+        assert!(matches!(
+            Catalog::parse(
+                "<catalog xmlns='urn:oasis:names:tc:entity:xmlns:xml:catalog'><uri/></catalog>",
+                ""
+            ),
+            Err(CatalogError::InvalidEntry(ref name)) if name == "uri"
+        ));
+
+        assert_eq!(resolve_reference("a/b.xml", "c/d.dtd"), "a/c/d.dtd");
+        assert_eq!(resolve_reference("a/b.xml", "/d.dtd"), "/d.dtd");
+        assert_eq!(resolve_reference("b.xml", "urn:x"), "urn:x");
+    }
+
+    #[test]
+    fn test_entity_resolver() {
+        let dir = std::env::temp_dir().join(format!("xml-catalog-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("next.xml"),
+            "<catalog xmlns='urn:oasis:names:tc:entity:xmlns:xml:catalog'>\
+             <system systemId='http://x.org/legal.ent' uri='legal.ent'/></catalog>",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("catalog.xml"),
+            "<catalog xmlns='urn:oasis:names:tc:entity:xmlns:xml:catalog'>\
+             <system systemId='http://x.org/missing.ent' uri='missing.ent'/>\
+             <nextCatalog catalog='next.xml'/></catalog>",
+        )
+        .unwrap();
+        fs::write(dir.join("legal.ent"), "All rights reserved").unwrap();
+        let mut catalog = Catalog::load(dir.join("catalog.xml")).unwrap();
+        let err = catalog
+            .resolve(None, "http://x.org/unknown.ent")
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        let doc = "<!DOCTYPE a [<!ENTITY legal SYSTEM 'http://x.org/legal.ent'>\
+                   <!ENTITY missing SYSTEM 'http://x.org/missing.ent'>]>\
+                   <a>&legal;<b>&missing;</b></a>";
+        let mut p = Parser::new(doc.as_bytes());
+        p.set_entity_resolver(catalog);
+        let events: Vec<_> = p.collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            events[2],
+            Ok(Event::Characters("All rights reserved".to_owned()))
+        );
+        assert!(events[4].is_err());
+    }
+
+    #[test]
+    fn test_next_catalog() {
+        let dir = std::env::temp_dir().join(format!("xml-next-catalog-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("catalog.xml");
+        fs::write(
+            &path,
+            "<catalog xmlns='urn:oasis:names:tc:entity:xmlns:xml:catalog'>\
+             <nextCatalog catalog='catalog.xml'/><nextCatalog catalog='missing.xml'/>\
+             <nextCatalog catalog='other.xml'/></catalog>",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("other.xml"),
+            "<catalog xmlns='urn:oasis:names:tc:entity:xmlns:xml:catalog'>\
+             <nextCatalog catalog='catalog.xml'/>\
+             <uri name='urn:x' uri='x.xsd'/></catalog>",
+        )
+        .unwrap();
+        let catalog = Catalog::load(&path).unwrap();
+        let found = catalog.resolve_uri("urn:x");
+        let missing = catalog.resolve_uri("urn:y");
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            found,
+            Some(dir.join("x.xsd").to_string_lossy().into_owned())
+        );
+        assert_eq!(missing, None);
+
+        // Next catalogs are only read once consulted
+        let catalog = Catalog::parse(
+            "<catalog xmlns='urn:oasis:names:tc:entity:xmlns:xml:catalog'>\
+             <uri name='urn:x' uri='x.xsd'/><nextCatalog catalog='missing.xml'/></catalog>",
+            "/nonexistent/catalog.xml",
+        )
+        .unwrap();
+        assert_eq!(
+            catalog.resolve_uri("urn:x").as_deref(),
+            Some("/nonexistent/x.xsd")
+        );
+        assert_eq!(catalog.resolve_uri("urn:y"), None);
+    }
+}
@@ -0,0 +1,250 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Decoding binary content embedded as Base64 or hexadecimal text
+//!
+//! Whitespace within the text is ignored, as long payloads such as signatures and keys
+//! are usually wrapped. `Element::content_bytes()` decodes the content of an element,
+//! `ContentDecoder` decodes text as it is read.
+//!
+//! ~~~
+//! use xml::codec::{ContentDecoder, ContentEncoding};
+//! use xml::{EventIteratorExt, Parser};
+//!
+//! let mut p = Parser::new("<doc><key>\n  SGVs\n  bG8=\n</key></doc>".as_bytes());
+//! p.seek_to_element("key", None).unwrap();
+//! let mut decoder = ContentDecoder::new(ContentEncoding::Base64);
+//! let mut out = Vec::new();
+//! decoder.decode_events(p.until_end_of("key"), &mut out).unwrap();
+//! assert_eq!(out, b"Hello");
+//! ~~~
+
+use crate::{Event, ParserError};
+use std::error::Error;
+use std::fmt;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+/// Text encodings of binary content
+pub enum ContentEncoding {
+    /// Base64 with the standard alphabet, as used by XML Schema's `base64Binary`
+    Base64,
+    /// Hexadecimal digits in either case, as used by XML Schema's `hexBinary`
+    Hex,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+/// Errors returned when decoding binary content
+pub enum DecodeError {
+    /// Errors encountered by the `Parser`
+    Parser(ParserError),
+    /// A character outside of the encoding's alphabet was found
+    InvalidCharacter(char),
+    /// The content ended within an encoded byte
+    Truncated,
+}
+
+impl Error for DecodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            DecodeError::Parser(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::Parser(ref err) => err.fmt(f),
+            DecodeError::InvalidCharacter(c) => write!(f, "Invalid character {:?}", c),
+            DecodeError::Truncated => write!(f, "Content ended within an encoded byte"),
+        }
+    }
+}
+
+impl From<ParserError> for DecodeError {
+    fn from(err: ParserError) -> DecodeError {
+        DecodeError::Parser(err)
+    }
+}
+
+/// A decoder for binary content split across several pieces of text
+pub struct ContentDecoder {
+    encoding: ContentEncoding,
+    // Bits decoded but not yet written, and their number
+    bits: u32,
+    count: u32,
+    // Whether Base64 padding was found, after which only padding may follow
+    padded: bool,
+}
+
+impl ContentDecoder {
+    /// Returns a new `ContentDecoder` for `encoding`
+    pub fn new(encoding: ContentEncoding) -> ContentDecoder {
+        ContentDecoder {
+            encoding,
+            bits: 0,
+            count: 0,
+            padded: false,
+        }
+    }
+
+    /// Decodes the next piece of text, appending complete bytes to `out`
+    pub fn decode(&mut self, text: &str, out: &mut Vec<u8>) -> Result<(), DecodeError> {
+        for c in text.chars().filter(|c| !c.is_whitespace()) {
+            let (value, width) = match (self.encoding, c) {
+                (ContentEncoding::Base64, '=') => {
+                    self.padded = true;
+                    continue;
+                }
+                (ContentEncoding::Base64, _) if !self.padded => {
+                    match BASE64.iter().position(|&b| char::from(b) == c) {
+                        Some(value) => (value as u32, 6),
+                        None => return Err(DecodeError::InvalidCharacter(c)),
+                    }
+                }
+                (ContentEncoding::Hex, _) => match c.to_digit(16) {
+                    Some(value) => (value, 4),
+                    None => return Err(DecodeError::InvalidCharacter(c)),
+                },
+                _ => return Err(DecodeError::InvalidCharacter(c)),
+            };
+            self.bits = self.bits << width | value;
+            self.count += width;
+            if self.count >= 8 {
+                self.count -= 8;
+                out.push((self.bits >> self.count) as u8);
+                self.bits &= (1 << self.count) - 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes the text of all `Characters` and `CDATA` events, ignoring other events
+    pub fn decode_events<I>(&mut self, events: I, out: &mut Vec<u8>) -> Result<(), DecodeError>
+    where
+        I: Iterator<Item = Result<Event, ParserError>>,
+    {
+        for event in events {
+            match event? {
+                Event::Characters(ref text) | Event::CDATA(ref text) => self.decode(text, out)?,
+                _ => (),
+            }
+        }
+        self.finish()
+    }
+
+    /// Checks that the text decoded so far did not end within an encoded byte.
+    /// Bits left over from Base64 encoding the last byte are ignored.
+    pub fn finish(&self) -> Result<(), DecodeError> {
+        match self.encoding {
+            ContentEncoding::Base64 if self.count < 6 => Ok(()),
+            ContentEncoding::Hex if self.count == 0 => Ok(()),
+            _ => Err(DecodeError::Truncated),
+        }
+    }
+}
+
+/// Decodes `text` in `encoding`
+pub fn decode(text: &str, encoding: ContentEncoding) -> Result<Vec<u8>, DecodeError> {
+    let mut decoder = ContentDecoder::new(encoding);
+    let mut out = Vec::with_capacity(text.len() / 2);
+    decoder.decode(text, &mut out)?;
+    decoder.finish()?;
+    Ok(out)
+}
+
+#[cfg(feature = "plist")]
+pub(crate) fn encode_base64(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (i, &b)| bits | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, ContentDecoder, ContentEncoding, DecodeError};
+    use crate::Element;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode("", ContentEncoding::Base64).unwrap(), b"");
+        assert_eq!(decode("YQ==", ContentEncoding::Base64).unwrap(), b"a");
+        assert_eq!(decode("YWI=", ContentEncoding::Base64).unwrap(), b"ab");
+        assert_eq!(decode(" YW\nJj ", ContentEncoding::Base64).unwrap(), b"abc");
+        assert_eq!(
+            decode("0aFF\n10", ContentEncoding::Hex).unwrap(),
+            [0x0a, 0xff, 0x10]
+        );
+
+        assert_eq!(
+            decode("a*b", ContentEncoding::Base64),
+            Err(DecodeError::InvalidCharacter('*'))
+        );
+        assert_eq!(
+            decode("YQ=Q", ContentEncoding::Base64),
+            Err(DecodeError::InvalidCharacter('Q'))
+        );
+        assert_eq!(
+            decode("0g", ContentEncoding::Hex),
+            Err(DecodeError::InvalidCharacter('g'))
+        );
+        assert_eq!(
+            decode("YWJjZ", ContentEncoding::Base64),
+            Err(DecodeError::Truncated)
+        );
+        assert_eq!(
+            decode("abc", ContentEncoding::Hex),
+            Err(DecodeError::Truncated)
+        );
+    }
+
+    #[test]
+    fn test_chunks() {
+        let mut decoder = ContentDecoder::new(ContentEncoding::Hex);
+        let mut out = Vec::new();
+        for text in ["4", "86", "", "5 6"] {
+            decoder.decode(text, &mut out).unwrap();
+        }
+        assert!(decoder.finish().is_err());
+        decoder.decode("c6c6f", &mut out).unwrap();
+        decoder.finish().unwrap();
+        assert_eq!(out, b"Hello");
+
+        let elem: Element = "<sig>SGVs<![CDATA[bG8=]]></sig>".parse().unwrap();
+        assert_eq!(
+            elem.content_bytes(ContentEncoding::Base64).unwrap(),
+            b"Hello"
+        );
+    }
+
+    #[cfg(feature = "plist")]
+    #[test]
+    fn test_base64_roundtrip() {
+        for data in [&b""[..], b"a", b"ab", b"abc", b"abcd"] {
+            let text = super::encode_base64(data);
+            assert_eq!(decode(&text, ContentEncoding::Base64).unwrap(), data);
+        }
+        assert_eq!(super::encode_base64(b"ab"), "YWI=");
+    }
+}
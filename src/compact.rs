@@ -0,0 +1,187 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Building blocks shared by the binary event format and `Element` snapshots
+//
+// Integers are LEB128 encoded. Strings are either written inline as `0`, length and UTF-8
+// bytes, or as `n > 0` referring to the `n`th string recorded in the string table so far.
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::io::{self, Read};
+
+// The longest encoding of an integer
+pub(crate) const MAX_INT_LEN: usize = 10;
+
+// Returns the encoding of `n`, stored in `buf`
+pub(crate) fn encode_int(mut n: u64, buf: &mut [u8; MAX_INT_LEN]) -> &[u8] {
+    let mut len = 0;
+    while n >= 0x80 {
+        buf[len] = n as u8 | 0x80;
+        n >>= 7;
+        len += 1;
+    }
+    buf[len] = n as u8;
+    &buf[..=len]
+}
+
+// Reads an integer from `reader`
+pub(crate) fn read_int<R: Read + ?Sized>(reader: &mut R) -> io::Result<u64> {
+    let mut byte = [0u8; 1];
+    reader.read_exact(&mut byte)?;
+    finish_int(reader, byte[0])
+}
+
+// Reads the rest of an integer whose first byte, `first`, has already been read
+pub(crate) fn finish_int<R: Read + ?Sized>(reader: &mut R, first: u8) -> io::Result<u64> {
+    let mut n = u64::from(first & 0x7f);
+    let mut byte = [first];
+    let mut shift = 7;
+    while byte[0] & 0x80 != 0 {
+        reader.read_exact(&mut byte)?;
+        let bits = u64::from(byte[0] & 0x7f);
+        if shift >= u64::BITS || (bits << shift) >> shift != bits {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Integer overflow",
+            ));
+        }
+        n |= bits << shift;
+        shift += 7;
+    }
+    Ok(n)
+}
+
+// The strings recorded while writing, by their references
+pub(crate) struct StringTable<K> {
+    indices: HashMap<K, u64>,
+    max_len: usize,
+    max_size: usize,
+}
+
+impl<K> StringTable<K> {
+    // Returns a table recording strings up to `max_len` bytes, until it holds `max_size`
+    pub(crate) fn new(max_len: usize, max_size: usize) -> StringTable<K> {
+        StringTable {
+            indices: HashMap::new(),
+            max_len,
+            max_size,
+        }
+    }
+}
+
+impl<K> StringTable<K>
+where
+    K: Borrow<str> + Hash + Eq,
+{
+    // Returns the reference to an earlier occurrence of `s`. Otherwise `s` is to be written
+    // inline, and is recorded if the table has room for it.
+    pub(crate) fn get<'s>(&mut self, s: &'s str) -> Option<u64>
+    where
+        K: From<&'s str>,
+    {
+        if let Some(&index) = self.indices.get(s) {
+            return Some(index);
+        }
+        if s.len() <= self.max_len && self.indices.len() < self.max_size {
+            let index = self.indices.len() as u64 + 1;
+            self.indices.insert(K::from(s), index);
+        }
+        None
+    }
+}
+
+impl<K> Default for StringTable<K> {
+    fn default() -> StringTable<K> {
+        StringTable::new(usize::MAX, usize::MAX)
+    }
+}
+
+// The strings recorded while reading, recording the same strings as a `StringTable`
+pub(crate) struct StringList<T> {
+    strings: Vec<T>,
+    max_len: usize,
+    max_size: usize,
+}
+
+impl<T> StringList<T> {
+    // Returns a list matching `StringTable::new(max_len, max_size)`
+    pub(crate) fn new(max_len: usize, max_size: usize) -> StringList<T> {
+        StringList {
+            strings: Vec::new(),
+            max_len,
+            max_size,
+        }
+    }
+
+    // Returns the string referred to by `index`
+    pub(crate) fn get(&self, index: u64) -> Option<&T> {
+        let index = usize::try_from(index).ok()?;
+        self.strings.get(index.checked_sub(1)?)
+    }
+}
+
+impl<T> StringList<T>
+where
+    T: AsRef<str>,
+{
+    // Records `s`, read inline, if the list has room for it
+    pub(crate) fn push(&mut self, s: T) {
+        if s.as_ref().len() <= self.max_len && self.strings.len() < self.max_size {
+            self.strings.push(s);
+        }
+    }
+}
+
+impl<T> Default for StringList<T> {
+    fn default() -> StringList<T> {
+        StringList::new(usize::MAX, usize::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_int, read_int, StringList, StringTable, MAX_INT_LEN};
+    use std::io;
+
+    #[test]
+    fn test_ints() {
+        for n in [0, 1, 0x7f, 0x80, 300, u64::from(u32::MAX) + 1, u64::MAX] {
+            let mut buf = [0; MAX_INT_LEN];
+            let mut encoded = encode_int(n, &mut buf);
+            assert_eq!(read_int(&mut encoded).unwrap(), n);
+            assert!(encoded.is_empty());
+        }
+        let err = read_int(&mut &b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\x02"[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = read_int(&mut &b"\x80"[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_strings() {
+        let mut table = StringTable::<String>::new(3, 2);
+        let mut list = StringList::new(3, 2);
+        let refs: Vec<_> = ["a", "long", "a", "b", "c", "b", "c", "long"]
+            .iter()
+            .map(|&s| match table.get(s) {
+                Some(index) => list.get(index).cloned(),
+                None => {
+                    list.push(s);
+                    None
+                }
+            })
+            .collect();
+        assert_eq!(
+            refs,
+            [None, None, Some("a"), None, None, Some("b"), None, None]
+        );
+    }
+}
@@ -0,0 +1,212 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::{ns, Element, QName, Xml};
+
+#[derive(Clone, Debug)]
+/// A read-only cursor navigating an `Element` tree
+///
+/// The cursor starts at the root element and moves between nodes without cloning them.
+/// Moves return `false`, leaving the cursor in place, if there is no node to move to.
+///
+/// ~~~
+/// use xml::Element;
+///
+/// let elem: Element = "<a>x<b/><c><d/></c></a>".parse().unwrap();
+/// let mut cursor = elem.cursor();
+/// assert!(cursor.first_child_element());
+/// assert_eq!(cursor.element().unwrap().name.name, "b");
+/// assert!(cursor.next_sibling_element());
+/// assert!(cursor.first_child());
+/// assert_eq!(cursor.element().unwrap().name.name, "d");
+/// assert_eq!(cursor.depth(), 2);
+/// assert!(!cursor.next_sibling());
+/// assert!(cursor.parent() && cursor.parent());
+/// assert_eq!(cursor.element().unwrap().name.name, "a");
+/// ~~~
+pub struct Cursor<'a> {
+    root: &'a Element,
+    // The parents of the current node with the current node's index within each
+    stack: Vec<(&'a Element, usize)>,
+}
+
+impl<'a> Cursor<'a> {
+    /// Returns a new `Cursor` positioned at `root`
+    pub fn new(root: &'a Element) -> Cursor<'a> {
+        Cursor {
+            root,
+            stack: Vec::new(),
+        }
+    }
+
+    /// Returns the current node, or `None` at the root element
+    pub fn node(&self) -> Option<&'a Xml> {
+        self.stack
+            .last()
+            .map(|&(parent, index)| &parent.children[index])
+    }
+
+    /// Returns the current node if it is an element
+    pub fn element(&self) -> Option<&'a Element> {
+        match self.node() {
+            None => Some(self.root),
+            Some(Xml::ElementNode(elem)) => Some(elem),
+            Some(_) => None,
+        }
+    }
+
+    /// Returns the number of ancestors of the current node, `0` at the root element
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Moves to the first child of the current node
+    pub fn first_child(&mut self) -> bool {
+        match self.element() {
+            Some(elem) if !elem.children.is_empty() => {
+                self.stack.push((elem, 0));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Moves to the first child of the current node that is an element
+    pub fn first_child_element(&mut self) -> bool {
+        let elem = match self.element() {
+            Some(elem) => elem,
+            None => return false,
+        };
+        match elem.children.iter().position(is_element) {
+            Some(index) => {
+                self.stack.push((elem, index));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves to the next sibling of the current node
+    pub fn next_sibling(&mut self) -> bool {
+        match self.stack.last_mut() {
+            Some(&mut (parent, ref mut index)) if *index + 1 < parent.children.len() => {
+                *index += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Moves to the next sibling of the current node that is an element
+    pub fn next_sibling_element(&mut self) -> bool {
+        let (parent, index) = match self.stack.last_mut() {
+            Some(&mut (parent, ref mut index)) => (parent, index),
+            None => return false,
+        };
+        match parent.children[*index + 1..].iter().position(is_element) {
+            Some(offset) => {
+                *index += offset + 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves to the parent of the current node
+    pub fn parent(&mut self) -> bool {
+        self.stack.pop().is_some()
+    }
+
+    /// Returns the namespace bound to `prefix` at the current node, or the default
+    /// namespace for the empty prefix. Declarations on the current element and all its
+    /// ancestors are taken into account, for instance to resolve QNames in content.
+    pub fn namespace_for_prefix(&self, prefix: &str) -> Option<&'a str> {
+        let key = match prefix {
+            "" => QName::new("xmlns".to_owned(), None),
+            _ => QName::new(prefix.to_owned(), Some(ns::XMLNS.to_owned())),
+        };
+        let parents = self.stack.iter().rev().map(|&(parent, _)| parent);
+        for elem in self.element().into_iter().chain(parents) {
+            if let Some(value) = elem.attributes.get(&key) {
+                return Some(&value[..]).filter(|value| !value.is_empty());
+            }
+        }
+        self.root
+            .prefixes
+            .iter()
+            .find(|&(_, bound)| !prefix.is_empty() && bound == prefix)
+            .map(|(ns, _)| &ns[..])
+    }
+}
+
+fn is_element(node: &Xml) -> bool {
+    matches!(*node, Xml::ElementNode(_))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Element, Xml};
+
+    #[test]
+    fn test_cursor() {
+        let elem: Element = "<a><!--c-->x<b><c/>y</b>z</a>".parse().unwrap();
+        let mut cursor = elem.cursor();
+        assert!(cursor.node().is_none());
+        assert!(!cursor.next_sibling() && !cursor.next_sibling_element());
+
+        assert!(cursor.first_child());
+        assert_eq!(cursor.node(), Some(&Xml::CommentNode("c".to_owned())));
+        assert!(cursor.element().is_none());
+        assert!(!cursor.first_child() && !cursor.first_child_element());
+        assert!(cursor.next_sibling_element());
+        assert_eq!(cursor.element().unwrap().name.name, "b");
+        assert!(!cursor.next_sibling_element());
+        assert!(cursor.next_sibling());
+        assert_eq!(cursor.node(), Some(&Xml::CharacterNode("z".to_owned())));
+        assert!(!cursor.next_sibling());
+
+        let mut texts = Vec::new();
+        let mut cursor = elem.cursor();
+        loop {
+            if let Some(Xml::CharacterNode(text)) = cursor.node() {
+                texts.push(&text[..]);
+            }
+            if cursor.first_child() {
+                continue;
+            }
+            while !cursor.next_sibling() {
+                if !cursor.parent() {
+                    assert_eq!(texts, ["x", "y", "z"]);
+                    return;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_namespace_for_prefix() {
+        let elem: Element = "<a xmlns='urn:a' xmlns:p='urn:p'><b xmlns:p='urn:q'>x</b>\
+                             <c xmlns='' xmlns:p=''/></a>"
+            .parse()
+            .unwrap();
+        let mut cursor = elem.cursor();
+        assert_eq!(cursor.namespace_for_prefix("p"), Some("urn:p"));
+        assert_eq!(
+            cursor.namespace_for_prefix("xml"),
+            Some("http://www.w3.org/XML/1998/namespace")
+        );
+        assert!(cursor.first_child() && cursor.first_child());
+        assert_eq!(cursor.namespace_for_prefix("p"), Some("urn:q"));
+        assert_eq!(cursor.namespace_for_prefix(""), Some("urn:a"));
+        assert!(cursor.parent() && cursor.next_sibling());
+        assert_eq!(cursor.namespace_for_prefix("p"), None);
+        assert_eq!(cursor.namespace_for_prefix(""), None);
+        assert_eq!(cursor.namespace_for_prefix("q"), None);
+    }
+}
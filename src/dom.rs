@@ -0,0 +1,334 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! W3C DOM-style names for building and querying `Element` trees
+//!
+//! The functions of this module correspond to the factory methods of a DOM `Document`,
+//! the `DomElement` trait provides methods of DOM `Element` and `Node` for `Element`.
+//! They ease porting code written against a DOM, and are thin wrappers around the
+//! inherent methods of `Element`. As `Element` does not keep the prefixes of names, names
+//! are matched by their local name.
+//!
+//! ~~~
+//! use xml::dom::{self, DomElement};
+//!
+//! let mut root = dom::create_element_ns(Some("urn:doc"), "d:doc");
+//! let mut item = dom::create_element("item");
+//! item.set_attribute_ns(Some("urn:meta"), "m:id", "1");
+//! item.append_child(dom::create_text_node("first"));
+//! root.append_child(item);
+//!
+//! let items = root.get_elements_by_tag_name("item");
+//! assert_eq!(items[0].get_attribute_ns(Some("urn:meta"), "id"), Some("1"));
+//! assert_eq!(root.text_content(), "first");
+//! assert_eq!(root.namespace_uri(), Some("urn:doc"));
+//! ~~~
+
+use crate::{ns, Element, QName, Xml};
+
+/// Returns a new element without namespace, like `Document.createElement()`
+pub fn create_element(name: &str) -> Element {
+    Element::new(name.to_owned(), None, vec![])
+}
+
+/// Returns a new element in the namespace `ns`, like `Document.createElementNS()`.
+/// If `qualified_name` has a prefix, it is declared on the element, otherwise `ns` is
+/// declared as the default namespace.
+pub fn create_element_ns(ns: Option<&str>, qualified_name: &str) -> Element {
+    let constructor = match (ns, qualified_name.split_once(':')) {
+        (Some(ns), Some((prefix, name))) => Element::build(name).prefixed(prefix, ns),
+        (Some(ns), None) => Element::build(qualified_name).ns(ns),
+        (None, _) => Element::build(qualified_name),
+    };
+    constructor.finish()
+}
+
+/// Returns a new text node, like `Document.createTextNode()`
+pub fn create_text_node(data: &str) -> Xml {
+    Xml::CharacterNode(data.to_owned())
+}
+
+/// Returns a new CDATA section, like `Document.createCDATASection()`
+pub fn create_cdata_section(data: &str) -> Xml {
+    Xml::CDATANode(data.to_owned())
+}
+
+/// Returns a new comment, like `Document.createComment()`
+pub fn create_comment(data: &str) -> Xml {
+    Xml::CommentNode(data.to_owned())
+}
+
+/// Returns a new processing instruction, like `Document.createProcessingInstruction()`
+pub fn create_processing_instruction(target: &str, data: &str) -> Xml {
+    match data {
+        "" => Xml::PINode(target.to_owned()),
+        _ => Xml::PINode(format!("{} {}", target, data)),
+    }
+}
+
+/// Methods of DOM `Element` and `Node` for `Element`
+///
+/// Attributes without namespace are accessed with the inherent `get_attribute()`,
+/// `set_attribute()` and `remove_attribute()` methods, passing `None` as namespace.
+pub trait DomElement {
+    /// Returns the local name of the element, like `Element.localName`
+    fn local_name(&self) -> &str;
+
+    /// Returns the namespace of the element, like `Element.namespaceURI`
+    fn namespace_uri(&self) -> Option<&str>;
+
+    /// Returns whether the element has an attribute without namespace named `name`, like
+    /// `Element.hasAttribute()`
+    fn has_attribute(&self, name: &str) -> bool;
+
+    /// Returns whether the element has an attribute named `local_name` in the namespace
+    /// `ns`, like `Element.hasAttributeNS()`
+    fn has_attribute_ns(&self, ns: Option<&str>, local_name: &str) -> bool;
+
+    /// Returns the value of the attribute named `local_name` in the namespace `ns`, like
+    /// `Element.getAttributeNS()`
+    fn get_attribute_ns(&self, ns: Option<&str>, local_name: &str) -> Option<&str>;
+
+    /// Sets the attribute named `qualified_name` in the namespace `ns`, like
+    /// `Element.setAttributeNS()`. A prefix for `ns` is declared on the element unless it is
+    /// bound there already, using the prefix of `qualified_name` if it has one.
+    fn set_attribute_ns(&mut self, ns: Option<&str>, qualified_name: &str, value: &str);
+
+    /// Removes the attribute named `local_name` in the namespace `ns`, like
+    /// `Element.removeAttributeNS()`
+    fn remove_attribute_ns(&mut self, ns: Option<&str>, local_name: &str);
+
+    /// Returns the child nodes, like `Node.childNodes`
+    fn child_nodes(&self) -> &[Xml];
+
+    /// Returns whether the element has child nodes, like `Node.hasChildNodes()`
+    fn has_child_nodes(&self) -> bool;
+
+    /// Returns the first child node, like `Node.firstChild`
+    fn first_child(&self) -> Option<&Xml>;
+
+    /// Returns the last child node, like `Node.lastChild`
+    fn last_child(&self) -> Option<&Xml>;
+
+    /// Appends a child node, like `Node.appendChild()`. Returns a reference to the node.
+    fn append_child<N: Into<Xml>>(&mut self, child: N) -> &mut Xml;
+
+    /// Inserts a child node at `index`, like `Node.insertBefore()`.
+    /// Panics if `index` is greater than the number of child nodes.
+    fn insert_child(&mut self, index: usize, child: Xml) -> &mut Xml;
+
+    /// Removes the child node at `index`, like `Node.removeChild()`.
+    /// Returns `None` if there is no such child.
+    fn remove_child(&mut self, index: usize) -> Option<Xml>;
+
+    /// Returns the text of the element and its descendants, like `Node.textContent`
+    fn text_content(&self) -> String;
+
+    /// Replaces all child nodes by a single text node, like setting `Node.textContent`
+    fn set_text_content(&mut self, text: &str);
+
+    /// Returns the descendant elements named `local_name`, or all descendant elements for
+    /// `"*"`, in document order, like `Element.getElementsByTagName()`
+    fn get_elements_by_tag_name(&self, local_name: &str) -> Vec<&Element>;
+
+    /// Returns the descendant elements named `local_name` in the namespace `ns` in document
+    /// order, like `Element.getElementsByTagNameNS()`. `"*"` matches any name or namespace.
+    fn get_elements_by_tag_name_ns(&self, ns: Option<&str>, local_name: &str) -> Vec<&Element>;
+}
+
+impl DomElement for Element {
+    fn local_name(&self) -> &str {
+        &self.name.name
+    }
+
+    fn namespace_uri(&self) -> Option<&str> {
+        self.name.ns.as_deref()
+    }
+
+    fn has_attribute(&self, name: &str) -> bool {
+        self.get_attribute(name, None).is_some()
+    }
+
+    fn has_attribute_ns(&self, ns: Option<&str>, local_name: &str) -> bool {
+        self.get_attribute(local_name, ns).is_some()
+    }
+
+    fn get_attribute_ns(&self, ns: Option<&str>, local_name: &str) -> Option<&str> {
+        self.get_attribute(local_name, ns)
+    }
+
+    fn set_attribute_ns(&mut self, ns: Option<&str>, qualified_name: &str, value: &str) {
+        let (prefix, name) = match qualified_name.split_once(':') {
+            Some((prefix, name)) => (Some(prefix), name),
+            None => (None, qualified_name),
+        };
+        if let Some(ns) = ns {
+            let bound = self.prefixes.get(ns).map(|x| &x[..]);
+            if bound.is_none() || prefix.is_some_and(|prefix| bound != Some(prefix)) {
+                let prefix = prefix
+                    .or_else(|| ns::conventional_prefix(ns))
+                    .map(str::to_owned)
+                    .unwrap_or_else(|| {
+                        (0..)
+                            .map(|i| format!("ns{}", i))
+                            .find(|prefix| !self.prefixes.values().any(|x| x == prefix))
+                            .expect("No unused prefix")
+                    });
+                self.attributes.insert(
+                    QName::new(prefix.clone(), Some(ns::XMLNS.to_owned())),
+                    ns.to_owned(),
+                );
+                self.prefixes.insert(ns.to_owned(), prefix);
+            }
+        }
+        self.set_attribute(name.to_owned(), ns.map(str::to_owned), value.to_owned());
+    }
+
+    fn remove_attribute_ns(&mut self, ns: Option<&str>, local_name: &str) {
+        self.remove_attribute(local_name, ns);
+    }
+
+    fn child_nodes(&self) -> &[Xml] {
+        &self.children
+    }
+
+    fn has_child_nodes(&self) -> bool {
+        !self.children.is_empty()
+    }
+
+    fn first_child(&self) -> Option<&Xml> {
+        self.children.first()
+    }
+
+    fn last_child(&self) -> Option<&Xml> {
+        self.children.last()
+    }
+
+    fn append_child<N: Into<Xml>>(&mut self, child: N) -> &mut Xml {
+        self.children.push(child.into());
+        self.children.last_mut().unwrap()
+    }
+
+    fn insert_child(&mut self, index: usize, child: Xml) -> &mut Xml {
+        self.children.insert(index, child);
+        &mut self.children[index]
+    }
+
+    fn remove_child(&mut self, index: usize) -> Option<Xml> {
+        if index < self.children.len() {
+            Some(self.children.remove(index))
+        } else {
+            None
+        }
+    }
+
+    fn text_content(&self) -> String {
+        self.content_str()
+    }
+
+    fn set_text_content(&mut self, text: &str) {
+        self.children.clear();
+        if !text.is_empty() {
+            self.children.push(Xml::CharacterNode(text.to_owned()));
+        }
+    }
+
+    fn get_elements_by_tag_name(&self, local_name: &str) -> Vec<&Element> {
+        let mut found = Vec::new();
+        collect_descendants(self, &mut found, &|elem| {
+            local_name == "*" || elem.name.name == local_name
+        });
+        found
+    }
+
+    fn get_elements_by_tag_name_ns(&self, ns: Option<&str>, local_name: &str) -> Vec<&Element> {
+        let mut found = Vec::new();
+        collect_descendants(self, &mut found, &|elem| {
+            (ns == Some("*") || elem.name.ns.as_deref() == ns)
+                && (local_name == "*" || elem.name.name == local_name)
+        });
+        found
+    }
+}
+
+fn collect_descendants<'a>(
+    elem: &'a Element,
+    found: &mut Vec<&'a Element>,
+    matches: &dyn Fn(&Element) -> bool,
+) {
+    for child in elem.child_elements() {
+        if matches(child) {
+            found.push(child);
+        }
+        collect_descendants(child, found, matches);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{create_element, create_element_ns, create_processing_instruction, DomElement};
+    use crate::{Element, Xml};
+
+    #[test]
+    fn test_dom() {
+        let mut root: Element = "<r xmlns='urn:r'><a><b/><a/></a><x:a xmlns:x='urn:x'/></r>"
+            .parse()
+            .unwrap();
+        let names = |elems: Vec<&Element>| -> Vec<String> {
+            elems
+                .iter()
+                .map(|e| format!("{}@{}", e.name.name, e.name.ns.as_deref().unwrap_or("")))
+                .collect()
+        };
+        assert_eq!(
+            names(root.get_elements_by_tag_name("a")),
+            ["a@urn:r", "a@urn:r", "a@urn:x"]
+        );
+        assert_eq!(root.get_elements_by_tag_name("*").len(), 4);
+        assert_eq!(
+            names(root.get_elements_by_tag_name_ns(Some("urn:x"), "*")),
+            ["a@urn:x"]
+        );
+        assert!(root.get_elements_by_tag_name_ns(None, "a").is_empty());
+
+        root.set_text_content("t");
+        assert_eq!(
+            root.first_child(),
+            Some(&Xml::CharacterNode("t".to_owned()))
+        );
+        root.append_child(create_element_ns(None, "c"));
+        root.insert_child(0, create_processing_instruction("pi", ""));
+        assert_eq!(root.child_nodes().len(), 3);
+        assert!(root.remove_child(0).is_some() && root.remove_child(5).is_none());
+        assert_eq!(root.to_string(), "<r xmlns='urn:r'>t<c xmlns=''/></r>");
+
+        let mut elem = create_element("e");
+        elem.set_attribute_ns(Some("urn:x"), "x:a", "1");
+        elem.set_attribute_ns(Some("urn:x"), "b", "2");
+        elem.set_attribute_ns(Some("urn:y"), "c", "3");
+        elem.set_attribute_ns(Some("http://www.w3.org/1999/xlink"), "href", "#");
+        assert!(elem.has_attribute_ns(Some("urn:x"), "b"));
+        assert_eq!(
+            elem.get_attribute("x", Some(crate::ns::XMLNS)),
+            Some("urn:x")
+        );
+        assert_eq!(
+            elem.get_attribute("ns0", Some(crate::ns::XMLNS)),
+            Some("urn:y")
+        );
+        assert_eq!(
+            elem.get_attribute("xlink", Some(crate::ns::XMLNS)),
+            Some("http://www.w3.org/1999/xlink")
+        );
+        elem.remove_attribute_ns(Some("urn:x"), "a");
+        assert!(!elem.has_attribute_ns(Some("urn:x"), "a"));
+        let parsed: Element = elem.to_string().parse().unwrap();
+        assert_eq!(parsed.get_attribute("c", Some("urn:y")), Some("3"));
+    }
+}
@@ -7,25 +7,39 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+#[cfg(feature = "codec")]
+use crate::codec::{self, ContentEncoding, DecodeError};
+use crate::cursor::Cursor;
 use crate::element_builder::{BuilderError, ElementBuilder};
-use crate::parser::Parser;
-use crate::{escape, AttrMap, Xml};
+use crate::element_constructor::ElementConstructor;
+use crate::hash::FnvHasher;
+use crate::names::{self, ContentError, NameError};
+use crate::ns;
+use crate::parser::{Parser, ParserError};
+use crate::patch::{self, PatchError, Patcher};
+use crate::snapshot::{self, SnapshotError};
+use crate::writer::{canonical_attribute_order, WriterConfig};
+#[cfg(feature = "xsi")]
+use crate::xsi::{self, TypeError, TypedValue};
+use crate::{AttrMap, EndTag, Event, QName, QNameKey, StartTag, Xml};
 
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt;
+use std::hash::Hasher;
 use std::iter::IntoIterator;
+use std::mem;
+use std::ops::Index;
 use std::slice;
 use std::str::FromStr;
 
 #[derive(Clone, PartialEq, Debug)]
 /// A struct representing an XML element
 pub struct Element {
-    /// The element's name
-    pub name: String,
-    /// The element's namespace
-    pub ns: Option<String>,
+    /// The element's name, qualified by its namespace
+    pub name: QName,
     /// The element's attributes
-    pub attributes: AttrMap<(String, Option<String>), String>,
+    pub attributes: AttrMap<QName, String>,
     /// The element's child `Xml` nodes
     pub children: Vec<Xml>,
     /// The prefixes set for known namespaces
@@ -34,78 +48,267 @@ pub struct Element {
     pub(crate) default_ns: Option<String>,
 }
 
+// The default namespace in scope for `elem`. Elements without a namespace cannot be
+// prefixed, so for them the default namespace must be undeclared.
+fn default_ns(elem: &Element) -> Option<&str> {
+    match elem.name.ns {
+        Some(_) => elem.default_ns.as_deref(),
+        None => None,
+    }
+}
+
+// Whether the `xmlns` attribute of `elem`, if any, can be written as is. Otherwise it is
+// replaced by the declaration required by `default_ns()`.
+fn keeps_xmlns_attribute(elem: &Element) -> bool {
+    match elem.attributes.get(&QName::new("xmlns".to_owned(), None)) {
+        Some(value) if value.is_empty() => default_ns(elem).is_none(),
+        Some(value) => default_ns(elem) == Some(&value[..]),
+        None => false,
+    }
+}
+
+// The default namespace declaration `elem` needs, unless it keeps its `xmlns` attribute
+fn xmlns_declaration<'a>(elem: &'a Element, parent: Option<&Element>) -> Option<&'a str> {
+    match parent {
+        None => default_ns(elem),
+        Some(parent) if default_ns(parent) != default_ns(elem) => {
+            Some(default_ns(elem).unwrap_or(""))
+        }
+        _ => None,
+    }
+}
+
+// Collects the namespaces used by prefixed names within `elem` that are not bound in `scope`
+// or within `elem`, and all prefixes declared within `elem`
+fn find_unbound_ns(
+    elem: &Element,
+    scope: &HashMap<String, String>,
+    unbound: &mut BTreeSet<String>,
+    declared: &mut HashSet<String>,
+) {
+    let mut scope = scope.clone();
+    scope.extend(elem.prefixes.clone());
+    declared.extend(elem.prefixes.values().cloned());
+    let mut uses = Vec::new();
+    if elem.name.ns.as_deref() != default_ns(elem) {
+        uses.extend(elem.name.ns.as_ref());
+    }
+    for QName { name, ns } in elem.attributes.keys() {
+        match *ns {
+            Some(ref ns) if ns == ns::XMLNS => {
+                declared.insert(name.to_string());
+            }
+            Some(ref ns) => uses.push(ns),
+            None => (),
+        }
+    }
+    for ns in uses {
+        if !scope.contains_key(ns) {
+            unbound.insert(ns.clone());
+        }
+    }
+    for child in elem.child_elements() {
+        find_unbound_ns(child, &scope, unbound, declared);
+    }
+}
+
+// The namespace bindings in scope while writing an element
+#[derive(Clone, Default)]
+struct Scope {
+    // The prefixes of all namespaces known so far
+    prefixes: HashMap<String, String>,
+    // The namespaces bound by the declarations written so far, by prefix,
+    // using the empty prefix for the default namespace
+    declared: HashMap<String, String>,
+}
+
+impl Scope {
+    // Records the declaration `name='value'` if it is one, returning whether it is
+    // redundant, as the same binding is already in scope
+    fn declare(&mut self, name: &str, ns: Option<&str>, value: &str) -> bool {
+        let prefix = match ns {
+            None if name == "xmlns" => "",
+            Some(ns::XMLNS) => name,
+            _ => return false,
+        };
+        let bound = self.declared.get(prefix).map_or("", |x| &x[..]);
+        let redundant = bound == value;
+        self.declared.insert(prefix.to_owned(), value.to_owned());
+        redundant
+    }
+}
+
 fn fmt_elem(
     elem: &Element,
     parent: Option<&Element>,
-    all_prefixes: &HashMap<String, String>,
-    f: &mut fmt::Formatter,
+    scope: &Scope,
+    config: &WriterConfig,
+    depth: usize,
+    preserve: bool,
+    f: &mut dyn fmt::Write,
 ) -> fmt::Result {
-    let mut all_prefixes = all_prefixes.clone();
-    all_prefixes.extend(elem.prefixes.clone().into_iter());
+    let mut scope = scope.clone();
+    // Prefixes declared by `elem` are no longer bound to namespaces they were bound to in
+    // scope, and an empty namespace undeclares a prefix, as in XML 1.1
+    for QName { name, ns } in elem.attributes.keys() {
+        if ns.as_deref() == Some(ns::XMLNS) {
+            scope.prefixes.retain(|_, prefix| prefix != name);
+        }
+    }
+    scope.prefixes.extend(elem.prefixes.clone());
+
+    let mut root_prefixes = Vec::new();
+    if parent.is_none() {
+        for (prefix, ns) in &config.root_prefixes {
+            if !scope.prefixes.contains_key(ns) {
+                scope.prefixes.insert(ns.clone(), prefix.clone());
+                root_prefixes.push((prefix, ns));
+            }
+        }
+    }
 
     // Do we need a prefix?
-    if elem.ns != elem.default_ns {
-        let prefix = all_prefixes
-            .get(elem.ns.as_ref().map_or("", |x| &x[..]))
+    let qname = if elem.name.ns.as_deref() != default_ns(elem) {
+        let prefix = scope
+            .prefixes
+            .get(elem.name.ns.as_ref().map_or("", |x| &x[..]))
             .expect("No namespace prefix bound");
-        write!(f, "<{}:{}", *prefix, elem.name)?;
+        format!("{}:{}", *prefix, elem.name.name)
     } else {
-        write!(f, "<{}", elem.name)?;
+        elem.name.name.to_string()
+    };
+    let mut parts = Vec::new();
+    for (prefix, ns) in root_prefixes {
+        scope.declare(prefix, Some(ns::XMLNS), ns);
+        parts.push(format!("xmlns:{}='{}'", prefix, config.escape(ns)));
     }
 
     // Do we need to set the default namespace ?
-    if !elem
-        .attributes
-        .iter()
-        .any(|(&(ref name, _), _)| name == "xmlns")
-    {
-        match (parent, &elem.default_ns) {
-            // No parent, namespace is not empty
-            (None, &Some(ref ns)) => write!(f, " xmlns='{}'", *ns)?,
-            // Parent and child namespace differ
-            (Some(parent), ns) if parent.default_ns != *ns => {
-                write!(f, " xmlns='{}'", ns.as_ref().map_or("", |x| &x[..]))?
-            }
-            _ => (),
+    let keep_xmlns = keeps_xmlns_attribute(elem);
+    if !keep_xmlns {
+        if let Some(ns) = xmlns_declaration(elem, parent) {
+            scope.declare("xmlns", None, ns);
+            parts.push(format!("xmlns='{}'", config.escape(ns)));
         }
     }
 
-    for (&(ref name, ref ns), value) in &elem.attributes {
-        match *ns {
+    let mut attributes: Vec<_> = elem
+        .attributes
+        .iter()
+        .filter(|(QName { name, ns }, _)| keep_xmlns || ns.is_some() || name != "xmlns")
+        .collect();
+    if config.deterministic {
+        attributes.sort_by(|(a, _), (b, _)| canonical_attribute_order(a, b));
+    }
+    for (QName { name, ns }, value) in attributes {
+        if scope.declare(name, ns.as_deref(), value) && config.minimize_namespaces {
+            continue;
+        }
+        parts.push(match *ns {
             Some(ref ns) => {
-                let prefix = all_prefixes.get(ns).expect("No namespace prefix bound");
-                write!(f, " {}:{}='{}'", *prefix, name, escape(value))?
+                let prefix = scope.prefixes.get(ns).expect("No namespace prefix bound");
+                format!("{}:{}='{}'", *prefix, name, config.escape(value))
             }
-            None => write!(f, " {}='{}'", name, escape(value))?,
+            None if config.minimizes(name, None, value) => name.to_string(),
+            None => format!("{}='{}'", name, config.escape(value)),
+        });
+    }
+
+    write!(f, "<{}", qname)?;
+    let attr_indent = match (config.indent.as_ref(), config.wrap_attributes) {
+        (Some(indent), Some(width)) => {
+            let len = indent.chars().count() * depth
+                + qname.chars().count()
+                + parts
+                    .iter()
+                    .map(|part| part.chars().count() + 1)
+                    .sum::<usize>()
+                + 1;
+            Some(indent.repeat(depth + 1)).filter(|_| len > width)
+        }
+        _ => None,
+    };
+    for part in parts {
+        match attr_indent {
+            Some(ref indent) => write!(f, "{}{}{}", config.newline(), indent, part)?,
+            None => write!(f, " {}", part)?,
         }
     }
 
-    if elem.children.is_empty() {
-        write!(f, "/>")?;
+    let preserve = preserve || config.preserves_whitespace(&elem.name.name, &elem.attributes);
+    let raw_text = config.writes_raw_text(&elem.name.name);
+    // Indent only element content, as whitespace in mixed content is significant
+    let indent = match config.indent {
+        Some(ref indent) if !preserve && !elem.children.iter().any(is_text) => Some(indent),
+        _ => None,
+    };
+    let children: Vec<&Xml> = elem
+        .children
+        .iter()
+        .filter(|child| {
+            preserve || !(config.deterministic || indent.is_some()) || !is_whitespace_text(child)
+        })
+        .collect();
+    if children.is_empty() {
+        let end = config.empty_element_end(&qname, elem.name.ns.as_deref(), &elem.name.name);
+        write!(f, "{}", end)?;
     } else {
         write!(f, ">")?;
-        for child in &elem.children {
+        for child in children {
+            if let Some(indent) = indent {
+                write!(f, "{}{}", config.newline(), indent.repeat(depth + 1))?;
+            }
             match *child {
-                Xml::ElementNode(ref child) => fmt_elem(child, Some(elem), &all_prefixes, f)?,
-                ref o => fmt::Display::fmt(o, f)?,
+                Xml::ElementNode(ref child) => {
+                    fmt_elem(child, Some(elem), &scope, config, depth + 1, preserve, f)?
+                }
+                Xml::CharacterNode(ref text) | Xml::CDATANode(ref text) if raw_text => {
+                    write!(f, "{}", config.line_breaks(text))?
+                }
+                Xml::CharacterNode(ref text) => {
+                    write!(f, "{}", config.escape(&config.line_breaks(text)))?
+                }
+                Xml::CDATANode(ref text) => config.write_cdata(text, f)?,
+                Xml::CommentNode(ref text) => write!(f, "<!--{}-->", config.line_breaks(text))?,
+                Xml::PINode(ref text) => write!(f, "<?{}?>", config.line_breaks(text))?,
             }
         }
-        if elem.ns != elem.default_ns {
-            let prefix = all_prefixes
-                .get(elem.ns.as_ref().unwrap())
-                .expect("No namespace prefix bound");
-            write!(f, "</{}:{}>", *prefix, elem.name)?;
-        } else {
-            write!(f, "</{}>", elem.name)?;
+        if let Some(indent) = indent {
+            write!(f, "{}{}", config.newline(), indent.repeat(depth))?;
         }
+        write!(f, "</{}>", qname)?;
     }
 
     Ok(())
 }
 
+// Checks for character data that is not just whitespace
+fn is_text(node: &Xml) -> bool {
+    match *node {
+        Xml::CharacterNode(ref data) => !data.chars().all(names::is_space),
+        Xml::CDATANode(_) => true,
+        _ => false,
+    }
+}
+
+fn is_whitespace_text(node: &Xml) -> bool {
+    match *node {
+        Xml::CharacterNode(ref data) => data.chars().all(names::is_space),
+        _ => false,
+    }
+}
+
 impl fmt::Display for Element {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fmt_elem(self, None, &HashMap::new(), f)
+        fmt_elem(
+            self,
+            None,
+            &Scope::default(),
+            &WriterConfig::default(),
+            0,
+            false,
+            f,
+        )
     }
 }
 
@@ -123,7 +326,7 @@ impl<'a, 'b> Iterator for ChildElements<'a, 'b> {
         let (name, ns) = (self.name, self.ns);
         self.elems.by_ref().find_map(|child| {
             if let Xml::ElementNode(ref elem) = *child {
-                if name == elem.name && ns == elem.ns.as_ref().map(|x| &x[..]) {
+                if name == elem.name.name && ns == elem.name.ns.as_deref() {
                     return Some(elem);
                 }
             }
@@ -132,6 +335,63 @@ impl<'a, 'b> Iterator for ChildElements<'a, 'b> {
     }
 }
 
+/// An iterator returning all child `Element`s of another `Element`
+pub struct Children<'a> {
+    elems: slice::Iter<'a, Xml>,
+}
+
+impl<'a> Iterator for Children<'a> {
+    type Item = &'a Element;
+
+    fn next(&mut self) -> Option<&'a Element> {
+        self.elems.by_ref().find_map(|child| match *child {
+            Xml::ElementNode(ref elem) => Some(elem),
+            _ => None,
+        })
+    }
+}
+
+fn push_events(elem: &Element, parent: Option<&Element>, events: &mut Vec<Event>) {
+    let prefix = match elem.name.ns {
+        Some(ref ns) if Some(&ns[..]) != default_ns(elem) => elem.prefixes.get(ns).cloned(),
+        _ => None,
+    };
+    let mut attributes = elem.attributes.clone();
+    if !keeps_xmlns_attribute(elem) {
+        attributes.retain(|QName { name, ns }, _| ns.is_some() || name != "xmlns");
+        if let Some(ns) = xmlns_declaration(elem, parent) {
+            attributes.insert(QName::new("xmlns".to_owned(), None), ns.to_owned());
+        }
+    }
+    if parent.is_none() {
+        for (ns, prefix) in &elem.prefixes {
+            if prefix != "xml" && prefix != "xmlns" {
+                attributes
+                    .entry(QName::new(prefix.clone(), Some(ns::XMLNS.to_owned())))
+                    .or_insert_with(|| ns.clone());
+            }
+        }
+    }
+    events.push(Event::ElementStart(StartTag {
+        name: elem.name.clone(),
+        prefix: prefix.clone(),
+        attributes,
+    }));
+    for child in &elem.children {
+        match *child {
+            Xml::ElementNode(ref child) => push_events(child, Some(elem), events),
+            Xml::CharacterNode(ref text) => events.push(Event::Characters(text.clone())),
+            Xml::CDATANode(ref text) => events.push(Event::CDATA(text.clone())),
+            Xml::CommentNode(ref text) => events.push(Event::Comment(text.clone())),
+            Xml::PINode(ref text) => events.push(Event::PI(text.clone())),
+        }
+    }
+    events.push(Event::ElementEnd(EndTag {
+        name: elem.name.clone(),
+        prefix,
+    }));
+}
+
 impl Element {
     /// Create a new `Element`, with specified name and namespace.
     /// Attributes are specified as a `Vec` of `(name, namespace, value)` tuples.
@@ -140,23 +400,16 @@ impl Element {
         A: IntoIterator<Item = (String, Option<String>, String)>,
     {
         let mut prefixes = HashMap::with_capacity(2);
-        prefixes.insert(
-            "http://www.w3.org/XML/1998/namespace".to_owned(),
-            "xml".to_owned(),
-        );
-        prefixes.insert(
-            "http://www.w3.org/2000/xmlns/".to_owned(),
-            "xmlns".to_owned(),
-        );
+        prefixes.insert(ns::XML.to_owned(), "xml".to_owned());
+        prefixes.insert(ns::XMLNS.to_owned(), "xmlns".to_owned());
 
         let attributes: AttrMap<_, _> = attrs
             .into_iter()
-            .map(|(name, ns, value)| ((name, ns), value))
+            .map(|(name, ns, value)| (QName::new(name, ns), value))
             .collect();
 
         Element {
-            name,
-            ns: ns.clone(),
+            name: QName::new(name, ns.clone()),
             default_ns: ns,
             prefixes,
             attributes,
@@ -164,6 +417,112 @@ impl Element {
         }
     }
 
+    /// Create a new `Element` like `new()`, after checking that the names are valid local
+    /// names and that namespaces are non-empty without whitespace. Names starting with
+    /// "xml" are reserved and only allowed for namespace declarations and in the XML
+    /// namespace.
+    ///
+    /// ~~~
+    /// use xml::{Element, NameError};
+    ///
+    /// assert!(Element::try_new("a".to_owned(), Some("urn:a".to_owned()), vec![]).is_ok());
+    /// assert_eq!(
+    ///     Element::try_new("a b".to_owned(), None, vec![]),
+    ///     Err(NameError::InvalidName("a b".to_owned())),
+    /// );
+    /// ~~~
+    pub fn try_new<A>(name: String, ns: Option<String>, attrs: A) -> Result<Element, NameError>
+    where
+        A: IntoIterator<Item = (String, Option<String>, String)>,
+    {
+        names::check_element(&name, ns.as_deref())?;
+        let elem = Element::new(name, ns, attrs);
+        for QName { name, ns } in elem.attributes.keys() {
+            names::check_attribute(name, ns.as_deref())?;
+        }
+        Ok(elem)
+    }
+
+    /// Starts constructing an element with the specified name using a fluent
+    /// `ElementConstructor`.
+    pub fn build<S: Into<String>>(name: S) -> ElementConstructor {
+        ElementConstructor::new(name.into())
+    }
+
+    /// Serializes the element using the output options set in `config`.
+    pub fn to_string_with(&self, config: &WriterConfig) -> String {
+        let mut out = String::new();
+        config
+            .write_prolog(&mut out)
+            .and_then(|_| fmt_elem(self, None, &Scope::default(), config, 0, false, &mut out))
+            .expect("Writing to a String cannot fail");
+        out
+    }
+
+    /// Applies all patch operations yielded by `events`, as described for `Patcher`.
+    /// Returns the number of operations applied.
+    pub fn apply_patch<I>(&mut self, events: I) -> Result<usize, PatchError>
+    where
+        I: IntoIterator<Item = Result<Event, ParserError>>,
+    {
+        let mut patcher = Patcher::new();
+        let mut count = 0;
+        for event in events {
+            if let Some(result) = patcher.handle_event(self, event) {
+                result?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Returns the patch operations transforming this element into `other`.
+    ///
+    /// The operations are wrapped in a `<diff>` element, which sets `xml:space='preserve'` to
+    /// keep whitespace in their content. They can be applied using `Patcher` or
+    /// `apply_patch()`, after being sent over the wire using an `EventWriter` if needed.
+    ///
+    /// ~~~
+    /// use xml::Element;
+    ///
+    /// let mut old: Element = "<list><item>a</item><item>b</item></list>".parse().unwrap();
+    /// let new: Element = "<list><item>a</item><item>c</item><item>d</item></list>"
+    ///     .parse()
+    ///     .unwrap();
+    /// old.apply_patch(old.diff(&new).into_iter().map(Ok)).unwrap();
+    /// assert_eq!(old, new);
+    /// ~~~
+    pub fn diff(&self, other: &Element) -> Vec<Event> {
+        patch::diff(self, other)
+    }
+
+    /// Returns a compact binary snapshot of this element, which is much faster to load using
+    /// `from_snapshot()` than parsing XML text. The format may change between versions.
+    pub fn to_snapshot(&self) -> Vec<u8> {
+        snapshot::encode(self)
+    }
+
+    /// Loads an element from a snapshot created by `to_snapshot()`.
+    ///
+    /// ~~~
+    /// use xml::Element;
+    ///
+    /// let elem: Element = "<config><port>80</port></config>".parse().unwrap();
+    /// let snapshot = elem.to_snapshot();
+    /// assert_eq!(Element::from_snapshot(&snapshot).unwrap(), elem);
+    /// ~~~
+    pub fn from_snapshot(data: &[u8]) -> Result<Element, SnapshotError> {
+        snapshot::decode(data)
+    }
+
+    /// Returns the events describing this element and its content.
+    /// All namespace prefixes in scope are declared on this element.
+    pub fn to_events(&self) -> Vec<Event> {
+        let mut events = Vec::new();
+        push_events(self, None, &mut events);
+        events
+    }
+
     /// Returns the character and CDATA contained in the element.
     pub fn content_str(&self) -> String {
         let mut res = String::new();
@@ -177,11 +536,46 @@ impl Element {
         res
     }
 
+    /// Decodes the character and CDATA contained in the element as binary content,
+    /// ignoring whitespace
+    #[cfg(feature = "codec")]
+    pub fn content_bytes(&self, encoding: ContentEncoding) -> Result<Vec<u8>, DecodeError> {
+        codec::decode(&self.content_str(), encoding)
+    }
+
+    /// Returns the content of the element converted according to its `xsi:type`, see
+    /// `xsi::typed_content()`. Only namespaces declared on the element are in scope.
+    #[cfg(feature = "xsi")]
+    pub fn typed_content(&self) -> Result<TypedValue, TypeError> {
+        xsi::typed_content(&self.cursor())
+    }
+
+    /// Returns the character and CDATA contained in the element, with leading and trailing
+    /// whitespace removed and all other runs of whitespace replaced by a single space.
+    pub fn content_str_normalized(&self) -> String {
+        self.content_str()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Returns the character and CDATA directly contained in the element,
+    /// excluding that of descendant elements.
+    pub fn own_text(&self) -> String {
+        let mut res = String::new();
+        for child in &self.children {
+            if let Xml::CharacterNode(ref data) | Xml::CDATANode(ref data) = *child {
+                res.push_str(data);
+            }
+        }
+        res
+    }
+
     /// Gets an attribute with the specified name and namespace. When an attribute with the
     /// specified name does not exist `None` is returned.
     pub fn get_attribute<'a>(&'a self, name: &str, ns: Option<&str>) -> Option<&'a str> {
         self.attributes
-            .get(&(name.to_owned(), ns.map(|x| x.to_owned())))
+            .get(&(name, ns) as &dyn QNameKey)
             .map(|x| &x[..])
     }
 
@@ -193,14 +587,17 @@ impl Element {
         ns: Option<String>,
         value: String,
     ) -> Option<String> {
-        self.attributes.insert((name, ns), value)
+        self.attributes.insert(QName::new(name, ns), value)
     }
 
     /// Remove the attribute with the specified name and namespace.
     /// Returns the original value.
     pub fn remove_attribute(&mut self, name: &str, ns: Option<&str>) -> Option<String> {
-        self.attributes
-            .remove(&(name.to_owned(), ns.map(|x| x.to_owned())))
+        let key = &(name, ns) as &dyn QNameKey;
+        #[cfg(feature = "ordered_attrs")]
+        return self.attributes.shift_remove(key);
+        #[cfg(not(feature = "ordered_attrs"))]
+        return self.attributes.remove(key);
     }
 
     /// Gets the first child `Element` with the specified name and namespace. When no child
@@ -209,6 +606,25 @@ impl Element {
         self.get_children(name, ns).next()
     }
 
+    /// Returns all child `Element`s, ignoring other child nodes such as text.
+    /// This is also available by iterating over `&Element`.
+    pub fn child_elements(&self) -> Children<'_> {
+        Children {
+            elems: self.children.iter(),
+        }
+    }
+
+    /// Returns a `Cursor` positioned at this element, for navigating its descendants
+    pub fn cursor(&self) -> Cursor<'_> {
+        Cursor::new(self)
+    }
+
+    /// Gets the first child `Element` with the specified name, in any namespace.
+    /// When no child with the specified name exists `None` is returned.
+    pub fn get(&self, name: &str) -> Option<&Element> {
+        self.child_elements().find(|elem| elem.name.name == name)
+    }
+
     /// Get all children `Element` with the specified name and namespace. When no child
     /// with the specified name exists an empty vetor is returned.
     pub fn get_children<'a, 'b>(
@@ -232,6 +648,44 @@ impl Element {
         }
     }
 
+    /// Appends a child element taken from another tree, such as an element of another
+    /// document. Returns a reference to the added element.
+    ///
+    /// Namespaces used within `child` whose prefixes were declared outside of it are declared
+    /// on `child`. The prefix bound in this element is reused unless it is declared within
+    /// `child` already, otherwise a new prefix is chosen. This ensures the combined tree
+    /// serializes with the correct namespaces.
+    ///
+    /// ~~~
+    /// use xml::Element;
+    ///
+    /// let doc: Element = "<r xmlns:p='urn:p'><p:item/></r>".parse().unwrap();
+    /// let mut root: Element = "<root/>".parse().unwrap();
+    /// root.graft(doc["item"].clone());
+    /// assert_eq!(root.to_string(), "<root><ns0:item xmlns:ns0='urn:p'/></root>");
+    /// ~~~
+    pub fn graft(&mut self, mut child: Element) -> &mut Element {
+        let mut unbound = BTreeSet::new();
+        let mut declared = HashSet::new();
+        find_unbound_ns(&child, &HashMap::new(), &mut unbound, &mut declared);
+        for ns in unbound {
+            let prefix = match self.prefixes.get(&ns) {
+                Some(prefix) if !declared.contains(prefix) => prefix.clone(),
+                _ => (0..)
+                    .map(|i| format!("ns{}", i))
+                    .find(|prefix| !declared.contains(prefix))
+                    .expect("No unused prefix"),
+            };
+            declared.insert(prefix.clone());
+            child.attributes.insert(
+                QName::new(prefix.clone(), Some(ns::XMLNS.to_owned())),
+                ns.clone(),
+            );
+            child.prefixes.insert(ns, prefix);
+        }
+        self.tag(child)
+    }
+
     /// Appends a child element. Returns a mutable reference to self.
     pub fn tag_stay(&mut self, child: Element) -> &mut Element {
         self.children.push(Xml::ElementNode(child));
@@ -261,24 +715,243 @@ impl Element {
         self.children.push(Xml::PINode(text));
         self
     }
+
+    /// Appends a comment like `comment()`, after checking that it does not contain "--" or
+    /// end with '-', which would make the output malformed.
+    ///
+    /// ~~~
+    /// use xml::{ContentError, Element};
+    ///
+    /// let mut elem = Element::new("a".to_owned(), None, vec![]);
+    /// assert!(elem.try_comment(" ok ".to_owned()).is_ok());
+    /// assert_eq!(
+    ///     elem.try_comment("a -- b".to_owned()).err(),
+    ///     Some(ContentError::InvalidComment("a -- b".to_owned())),
+    /// );
+    /// assert_eq!(elem.to_string(), "<a><!-- ok --></a>");
+    /// ~~~
+    pub fn try_comment(&mut self, text: String) -> Result<&mut Element, ContentError> {
+        names::check_comment(&text)?;
+        Ok(self.comment(text))
+    }
+
+    /// Appends processing information like `pi()`, after checking that it does not contain
+    /// "?>" and starts with a target name without colons, other than "xml".
+    pub fn try_pi(&mut self, text: String) -> Result<&mut Element, ContentError> {
+        names::check_pi(&text)?;
+        Ok(self.pi(text))
+    }
+
+    /// Sorts the child elements using the comparator function `compare`.
+    /// The sort is stable, other child nodes such as text keep their positions.
+    pub fn sort_children_by<F>(&mut self, mut compare: F)
+    where
+        F: FnMut(&Element, &Element) -> Ordering,
+    {
+        let slots: Vec<usize> = self
+            .children
+            .iter()
+            .enumerate()
+            .filter(|(_, child)| matches!(child, Xml::ElementNode(_)))
+            .map(|(i, _)| i)
+            .collect();
+        let mut elems: Vec<Xml> = slots
+            .iter()
+            .map(|&i| mem::replace(&mut self.children[i], Xml::CharacterNode(String::new())))
+            .collect();
+        elems.sort_by(|a, b| match (a, b) {
+            (Xml::ElementNode(a), Xml::ElementNode(b)) => compare(a, b),
+            _ => unreachable!("Only elements are sorted"),
+        });
+        for (i, elem) in slots.into_iter().zip(elems) {
+            self.children[i] = elem;
+        }
+    }
+
+    /// Sorts the child elements by name, then by namespace.
+    pub fn sort_children_by_name(&mut self) {
+        self.sort_children_by(|a, b| a.name.cmp(&b.name))
+    }
+
+    /// Sorts the child elements by the value of the attribute with the specified name and
+    /// namespace. Elements lacking the attribute are placed first.
+    pub fn sort_children_by_attribute(&mut self, name: &str, ns: Option<&str>) {
+        self.sort_children_by(|a, b| a.get_attribute(name, ns).cmp(&b.get_attribute(name, ns)))
+    }
+
+    /// Returns a hash of the element's canonical form.
+    /// The hash is stable across program runs, platforms and versions of this crate.
+    ///
+    /// Namespace prefixes and declarations, attribute order, comments and the distinction
+    /// between characters and CDATA do not affect the hash.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = FnvHasher::default();
+        self.hash_content(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Feeds the element's canonical form, as described for `content_hash()`, into `hasher`.
+    pub fn hash_content<H: Hasher>(&self, hasher: &mut H) {
+        fn write_str<H: Hasher>(hasher: &mut H, s: &str) {
+            hasher.write(&(s.len() as u64).to_le_bytes());
+            hasher.write(s.as_bytes());
+        }
+        fn write_ns<H: Hasher>(hasher: &mut H, ns: Option<&str>) {
+            match ns {
+                Some(ns) => {
+                    hasher.write(&[1]);
+                    write_str(hasher, ns);
+                }
+                None => hasher.write(&[0]),
+            }
+        }
+        fn write_text<H: Hasher>(hasher: &mut H, text: &mut String) {
+            if !text.is_empty() {
+                hasher.write(b"T");
+                write_str(hasher, text);
+                text.clear();
+            }
+        }
+
+        hasher.write(b"E");
+        write_ns(hasher, self.name.ns.as_deref());
+        write_str(hasher, &self.name.name);
+        let mut attributes: Vec<_> = self
+            .attributes
+            .iter()
+            .filter(|(QName { name, ns }, _)| match *ns {
+                None => name != "xmlns",
+                Some(ref ns) => ns != ns::XMLNS,
+            })
+            .collect();
+        attributes.sort_by(|(a, _), (b, _)| canonical_attribute_order(a, b));
+        for (QName { name, ns }, value) in attributes {
+            hasher.write(b"A");
+            write_ns(hasher, ns.as_deref());
+            write_str(hasher, name);
+            write_str(hasher, value);
+        }
+        let mut text = String::new();
+        for child in &self.children {
+            match *child {
+                Xml::CharacterNode(ref data) | Xml::CDATANode(ref data) => text.push_str(data),
+                Xml::CommentNode(_) => (),
+                Xml::ElementNode(ref elem) => {
+                    write_text(hasher, &mut text);
+                    elem.hash_content(hasher);
+                }
+                Xml::PINode(ref data) => {
+                    write_text(hasher, &mut text);
+                    hasher.write(b"P");
+                    write_str(hasher, data);
+                }
+            }
+        }
+        write_text(hasher, &mut text);
+        hasher.write(b"/");
+    }
+}
+
+/// Returns the first child `Element` with the specified name, in any namespace.
+///
+/// # Panics
+///
+/// Panics if there is no such child, use `get()` to handle that case.
+///
+/// ~~~
+/// use xml::Element;
+///
+/// let doc: Element = "<rss><channel><title>News</title></channel></rss>".parse().unwrap();
+/// assert_eq!(doc["channel"]["title"].content_str(), "News");
+/// ~~~
+impl<'a> Index<&'a str> for Element {
+    type Output = Element;
+
+    fn index(&self, name: &'a str) -> &Element {
+        match self.get(name) {
+            Some(elem) => elem,
+            None => panic!("<{}> has no child <{}>", self.name.name, name),
+        }
+    }
+}
+
+/// Iterates over all child `Element`s, as returned by `child_elements()`
+///
+/// ~~~
+/// use xml::Element;
+///
+/// let list: Element = "<list><a/>text<b/></list>".parse().unwrap();
+/// let names: Vec<&str> = (&list).into_iter().map(|child| &child.name.name[..]).collect();
+/// assert_eq!(names, ["a", "b"]);
+/// ~~~
+impl<'a> IntoIterator for &'a Element {
+    type Item = &'a Element;
+    type IntoIter = Children<'a>;
+
+    fn into_iter(self) -> Children<'a> {
+        self.child_elements()
+    }
+}
+
+/// Appends child nodes
+impl Extend<Xml> for Element {
+    fn extend<I: IntoIterator<Item = Xml>>(&mut self, iter: I) {
+        self.children.extend(iter);
+    }
+}
+
+/// Appends child elements
+///
+/// ~~~
+/// use xml::Element;
+///
+/// let mut list = Element::new("list".to_owned(), None, vec![]);
+/// list.extend((1..=3).map(|n| Element::build("item").text(n.to_string()).finish()));
+/// assert_eq!(list.to_string(), "<list><item>1</item><item>2</item><item>3</item></list>");
+/// ~~~
+impl Extend<Element> for Element {
+    fn extend<I: IntoIterator<Item = Element>>(&mut self, iter: I) {
+        self.children.extend(iter.into_iter().map(Xml::ElementNode));
+    }
 }
 
 impl FromStr for Element {
     type Err = BuilderError;
-    #[inline]
+
+    /// Parses a document consisting of a single root element. Outside of the root element
+    /// only whitespace, comments and processing instructions are allowed.
     fn from_str(data: &str) -> Result<Element, BuilderError> {
         let s = data.as_bytes();
         let mut p = Parser::new(s);
         let mut e = ElementBuilder::new();
+        let mut root = None;
 
-        p.find_map(|x| e.handle_event(x))
-            .unwrap_or(Err(BuilderError::NoElement))
+        while let Some(event) = p.next() {
+            let event = event?;
+            if p.depth() == 0 && !matches!(event, Event::ElementEnd(_)) {
+                match event {
+                    Event::Characters(ref text) if text.chars().all(names::is_space) => (),
+                    Event::Comment(_) | Event::PI(_) | Event::XmlDecl(_) | Event::Doctype(_) => (),
+                    _ => return Err(BuilderError::UnexpectedContent),
+                }
+                continue;
+            }
+            if root.is_some() {
+                return Err(BuilderError::UnexpectedContent);
+            }
+            if let Some(elem) = e.handle_event(Ok(event)) {
+                root = Some(elem?);
+            }
+        }
+        root.ok_or(BuilderError::NoElement)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::Element;
+    use crate::{ns, WriterConfig, Xml};
+    use crate::{BuilderError, ContentError, NameError};
 
     #[test]
     fn test_get_children() {
@@ -301,6 +974,332 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_deterministic_output() {
+        let config = WriterConfig::new().deterministic(true);
+        let a: Element = "<r xmlns:p='urn:p' b='2' p:a='3' a='1'>\n  <c/>\n</r>"
+            .parse()
+            .unwrap();
+        let b: Element = "<r a='1' p:a='3' b='2' xmlns:p='urn:p'><c/></r>"
+            .parse()
+            .unwrap();
+        assert_eq!(a.to_string_with(&config), b.to_string_with(&config));
+        assert_eq!(
+            a.to_string_with(&config),
+            "<r xmlns:p='urn:p' a='1' b='2' p:a='3'><c/></r>",
+        );
+
+        // Only XML whitespace is dropped
+        let elem: Element = "<a>\u{a0}<b>\n</b></a>".parse().unwrap();
+        assert_eq!(elem.to_string_with(&config), "<a>\u{a0}<b/></a>");
+        let config = WriterConfig::new().indent("  ");
+        assert_eq!(elem.to_string_with(&config), "<a>\u{a0}<b/></a>");
+    }
+
+    #[test]
+    fn test_indent() {
+        let elem: Element = "<a><b><c/>\n</b><p>Some <em>mixed</em> text</p>\
+                             <pre xml:space='preserve'><x/></pre></a>"
+            .parse()
+            .unwrap();
+        let config = WriterConfig::new().indent("  ");
+        assert_eq!(
+            elem.to_string_with(&config),
+            "<a>\n  <b>\n    <c/>\n  </b>\n  <p>Some <em>mixed</em> text</p>\n  \
+             <pre xml:space='preserve'><x/></pre>\n</a>",
+        );
+    }
+
+    #[test]
+    fn test_wrap_attributes() {
+        let elem: Element = "<a><b id='first' class='wide'><c id='x'/></b></a>"
+            .parse()
+            .unwrap();
+        let config = WriterConfig::new()
+            .indent("  ")
+            .wrap_attributes(16)
+            .deterministic(true);
+        assert_eq!(
+            elem.to_string_with(&config),
+            "<a>\n  <b\n    class='wide'\n    id='first'>\n    <c id='x'/>\n  </b>\n</a>",
+        );
+    }
+
+    #[test]
+    fn test_svg_profile() {
+        let mut svg = Element::new("svg".to_owned(), Some(ns::SVG.to_owned()), vec![]);
+        svg.tag(Element::new(
+            "text".to_owned(),
+            Some(ns::SVG.to_owned()),
+            vec![],
+        ))
+        .text("  spaced  ".to_owned());
+        let out = svg.to_string_with(&WriterConfig::svg().indent(" "));
+        assert!(out.starts_with("<?xml version='1.0' encoding='UTF-8'?>\n<!DOCTYPE svg"));
+        assert!(out.ends_with(
+            "<svg xmlns:xlink='http://www.w3.org/1999/xlink' xmlns='http://www.w3.org/2000/svg'>\n \
+             <text>  spaced  </text>\n</svg>"
+        ));
+    }
+
+    #[test]
+    fn test_xhtml_profile() {
+        let elem: Element = "<html xmlns='http://www.w3.org/1999/xhtml'><head><script src='a.js'/>\
+                             <meta charset='utf-8'/></head><body><br/><p/></body></html>"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            elem.to_string_with(&WriterConfig::xhtml()),
+            "<!DOCTYPE html>\n<html xmlns='http://www.w3.org/1999/xhtml'><head>\
+             <script src='a.js'></script><meta charset='utf-8'/></head>\
+             <body><br/><p></p></body></html>",
+        );
+    }
+
+    #[test]
+    fn test_undeclare_default_ns() {
+        let mut elem: Element = "<a xmlns='urn:a'><b/><c xmlns='urn:a'/></a>"
+            .parse()
+            .unwrap();
+        for child in elem.children.iter_mut() {
+            if let Xml::ElementNode(ref mut child) = *child {
+                child.name.ns = None;
+            }
+        }
+        elem.tag(Element::new("d".to_owned(), None, vec![]));
+        let expected = "<a xmlns='urn:a'><b xmlns=''/><c xmlns=''/><d xmlns=''/></a>";
+        assert_eq!(elem.to_string(), expected);
+
+        let reparsed: Element = expected.parse().unwrap();
+        assert!(reparsed
+            .child_elements()
+            .all(|child| child.name.ns.is_none()));
+        let mut builder = crate::ElementBuilder::new();
+        let rebuilt = elem
+            .to_events()
+            .into_iter()
+            .find_map(|event| builder.handle_event(Ok(event)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(rebuilt.to_string(), expected);
+    }
+
+    #[test]
+    fn test_graft() {
+        let doc: Element = "<r xmlns:p='urn:p' xmlns:q='urn:q'>\
+                            <p:item q:id='1' xmlns:ns0='urn:x'><p:sub ns0:y='2'/></p:item></r>"
+            .parse()
+            .unwrap();
+        let mut root: Element = "<root xmlns:p='urn:other' xmlns:q='urn:q'/>"
+            .parse()
+            .unwrap();
+        root.graft(doc["item"].clone());
+
+        let out: Element = root.to_string().parse().unwrap();
+        let item = &out["item"];
+        assert_eq!(item.name.ns.as_deref(), Some("urn:p"));
+        assert_eq!(item.get_attribute("id", Some("urn:q")), Some("1"));
+        assert_eq!(item["sub"].name.ns.as_deref(), Some("urn:p"));
+        assert_eq!(item["sub"].get_attribute("y", Some("urn:x")), Some("2"));
+        assert_eq!(item.get_attribute("ns1", Some(ns::XMLNS)), Some("urn:p"));
+        assert_eq!(item.get_attribute("q", Some(ns::XMLNS)), Some("urn:q"));
+    }
+
+    #[test]
+    fn test_minimize_namespaces() {
+        let mut root: Element = "<r xmlns='urn:d' xmlns:p='urn:p'><p:x/></r>"
+            .parse()
+            .unwrap();
+        root.tag(
+            "<c xmlns='urn:d' xmlns:p='urn:p'><p:y xmlns:p='urn:p'/></c>"
+                .parse()
+                .unwrap(),
+        );
+        root.tag(
+            "<c xmlns='urn:d' xmlns:p='urn:q'><p:y/></c>"
+                .parse()
+                .unwrap(),
+        );
+        let config = WriterConfig::new()
+            .deterministic(true)
+            .minimize_namespaces(true);
+        assert_eq!(
+            root.to_string_with(&config),
+            "<r xmlns='urn:d' xmlns:p='urn:p'><p:x/><c><p:y/></c>\
+             <c xmlns:p='urn:q'><p:y/></c></r>",
+        );
+        let plain: Element = "<a xmlns=''/>".parse().unwrap();
+        assert_eq!(plain.to_string_with(&config), "<a/>");
+    }
+
+    #[test]
+    fn test_undeclared_prefix() {
+        let input = "<p:a xmlns:p='urn:p'><b xmlns:p=''><c/></b><p:d/></p:a>";
+        let elem: Element = input.parse().unwrap();
+        assert!(!elem["b"].prefixes.contains_key(""));
+        assert_eq!(elem.to_string(), input);
+
+        // Grafting must not rely on the undeclared prefix
+        let mut elem = elem;
+        let inner = Element::new("e".to_owned(), Some("urn:p".to_owned()), vec![]);
+        if let Xml::ElementNode(ref mut b) = elem.children[0] {
+            b.graft(inner);
+        }
+        let out: Element = elem.to_string().parse().unwrap();
+        assert_eq!(out["b"]["e"].name.ns.as_deref(), Some("urn:p"));
+        assert!("<p:a xmlns:p='urn:p'><b xmlns:p=''><p:c/></b></p:a>"
+            .parse::<Element>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_try_new() {
+        let attrs = |name: &str, ns: Option<&str>| {
+            vec![(name.to_owned(), ns.map(str::to_owned), String::new())]
+        };
+        assert!(Element::try_new("a-1.b".to_owned(), None, attrs("xmlns", None)).is_ok());
+        assert!(Element::try_new("a".to_owned(), None, attrs("lang", Some(ns::XML))).is_ok());
+        assert!(Element::try_new("a".to_owned(), None, attrs("xmlp", Some(ns::XMLNS))).is_ok());
+        assert!(Element::try_new("\u{e9}t\u{e9}".to_owned(), None, vec![]).is_ok());
+        for name in ["", "1a", "p:a", "a b", "-a", "XmlA"] {
+            assert_eq!(
+                Element::try_new(name.to_owned(), None, vec![]),
+                Err(NameError::InvalidName(name.to_owned())),
+            );
+        }
+        assert!(Element::try_new("a".to_owned(), None, attrs("xmlfoo", None)).is_err());
+        assert!(Element::try_new("a".to_owned(), None, attrs("b c", None)).is_err());
+        for ns in ["", "urn: a", ns::XMLNS] {
+            assert_eq!(
+                Element::try_new("a".to_owned(), Some(ns.to_owned()), vec![]),
+                Err(NameError::InvalidNamespace(ns.to_owned())),
+            );
+        }
+        assert!(Element::try_new("a".to_owned(), None, attrs("b", Some(""))).is_err());
+    }
+
+    #[test]
+    fn test_try_comment_pi() {
+        let mut elem = Element::new("a".to_owned(), None, vec![]);
+        for text in ["", " - ", "a-b"] {
+            assert!(elem.try_comment(text.to_owned()).is_ok());
+        }
+        for text in ["--", "a-", "-->"] {
+            assert_eq!(
+                elem.try_comment(text.to_owned()).err(),
+                Some(ContentError::InvalidComment(text.to_owned())),
+            );
+        }
+        for text in [
+            "t",
+            "t data",
+            "t\tdata",
+            "t-1 a?b",
+            "xml-stylesheet href='s.xsl'",
+        ] {
+            assert!(elem.try_pi(text.to_owned()).is_ok());
+        }
+        for text in [
+            "",
+            " t",
+            "1t",
+            "p:t",
+            "t\u{a0}data",
+            "t ?>",
+            "XML version='1.0'",
+        ] {
+            assert_eq!(
+                elem.try_pi(text.to_owned()).err(),
+                Some(ContentError::InvalidPI(text.to_owned())),
+            );
+        }
+        assert_eq!(elem.children.len(), 8);
+        let text = elem.to_string();
+        assert_eq!(text.parse::<Element>().unwrap(), elem);
+    }
+
+    #[test]
+    fn test_from_str_strict() {
+        let elem: Element = "<?xml version='1.0'?>\n<!--c--><a/>\n<?pi?>\n"
+            .parse()
+            .unwrap();
+        assert_eq!(elem.name.name, "a");
+        for doc in [
+            "<a/>junk",
+            "junk<a/>",
+            "<a/><b/>",
+            "<a/>&x;",
+            "<a/>\u{a0}",
+            "\u{a0}<a/>",
+        ] {
+            assert!(doc.parse::<Element>().is_err(), "{}", doc);
+        }
+        assert_eq!(
+            "<a/><b/>".parse::<Element>(),
+            Err(BuilderError::UnexpectedContent)
+        );
+        assert_eq!("<!--c-->".parse::<Element>(), Err(BuilderError::NoElement));
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let a: Element = "<a xmlns:p='urn:p' p:x='1' y='2'>text<!-- c --><p:b/></a>"
+            .parse()
+            .unwrap();
+        let b: Element = "<a y='2' xmlns:q='urn:p' q:x='1'><![CDATA[te]]>xt<q:b></q:b></a>"
+            .parse()
+            .unwrap();
+        let c: Element = "<a xmlns:p='urn:p' p:x='1' y='2'>text<b/></a>"
+            .parse()
+            .unwrap();
+        assert_eq!(a.content_hash(), b.content_hash());
+        assert_ne!(a.content_hash(), c.content_hash());
+        assert_eq!(
+            "<a/>".parse::<Element>().unwrap().content_hash(),
+            0xf4de_f0d2_8ef9_c6fd,
+        );
+    }
+
+    #[test]
+    fn test_index() {
+        let elem: Element = "<a xmlns='urn:a'><b><c>x</c></b></a>".parse().unwrap();
+        assert_eq!(elem["b"]["c"].content_str(), "x");
+        assert!(elem.get("c").is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "<a> has no child <c>")]
+    fn test_index_missing() {
+        let elem: Element = "<a><b/></a>".parse().unwrap();
+        let _ = &elem["c"];
+    }
+
+    #[test]
+    fn test_text_accessors() {
+        let elem: Element = "<p>\n  Some <b>bold\t </b>\n text<![CDATA[ ! ]]></p>"
+            .parse()
+            .unwrap();
+        assert_eq!(elem.content_str_normalized(), "Some bold text !");
+        assert_eq!(elem.own_text(), "\n  Some \n text ! ");
+    }
+
+    #[test]
+    fn test_extend() {
+        let mut elem = Element::new("a".to_owned(), None, vec![]);
+        elem.extend(vec![Xml::CharacterNode("x".to_owned())]);
+        elem.extend(Some(Element::new("b".to_owned(), None, vec![])));
+        assert_eq!(elem.to_string(), "<a>x<b/></a>");
+    }
+
+    #[test]
+    fn test_sort_children_by_name() {
+        let mut elem: Element = "<a><c/> <b x='2'/>t<b x='1'/></a>".parse().unwrap();
+        elem.sort_children_by_name();
+        assert_eq!(elem.to_string(), "<a><b x='2'/> <b x='1'/>t<c/></a>");
+        elem.sort_children_by_attribute("x", None);
+        assert_eq!(elem.to_string(), "<a><c/> <b x='1'/>t<b x='2'/></a>");
+    }
+
     #[test]
     #[cfg(feature = "ordered_attrs")]
     fn test_attribute_order_new() {
@@ -314,8 +1313,8 @@ mod tests {
         for _ in 0..5 {
             let elem = Element::new("a".to_owned(), None, input_attributes.clone());
             for (expected, actual) in input_attributes.iter().zip(elem.attributes) {
-                assert_eq!(expected.0, (actual.0).0);
-                assert_eq!(expected.1, (actual.0).1);
+                assert_eq!(expected.0, actual.0.name);
+                assert_eq!(expected.1, actual.0.ns);
                 assert_eq!(expected.2, actual.1);
             }
         }
@@ -337,8 +1336,8 @@ mod tests {
                 elem.set_attribute(attr.0.clone(), attr.1.clone(), attr.2.clone());
             }
             for (expected, actual) in input_attributes.iter().zip(elem.attributes) {
-                assert_eq!(expected.0, (actual.0).0);
-                assert_eq!(expected.1, (actual.0).1);
+                assert_eq!(expected.0, actual.0.name);
+                assert_eq!(expected.1, actual.0.ns);
                 assert_eq!(expected.2, actual.1);
             }
         }
@@ -7,13 +7,15 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use crate::ns;
 use crate::parser::ParserError;
-use crate::{Element, EndTag, Event, StartTag, Xml};
+use crate::{AttributeChunk, Element, EndTag, Event, QName, StartTag, Xml};
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 /// The structure returned for errors encountered while building an `Element`
 pub enum BuilderError {
     /// Errors encountered by the `Parser`
@@ -22,6 +24,9 @@ pub enum BuilderError {
     ImproperNesting,
     /// No element was found
     NoElement,
+    /// Content other than whitespace, comments and processing instructions was found
+    /// outside of the root element
+    UnexpectedContent,
 }
 
 impl Error for BuilderError {
@@ -39,6 +44,7 @@ impl fmt::Display for BuilderError {
             BuilderError::Parser(ref err) => err.fmt(f),
             BuilderError::ImproperNesting => write!(f, "Elements not properly nested"),
             BuilderError::NoElement => write!(f, "No elements found"),
+            BuilderError::UnexpectedContent => write!(f, "Content outside of the root element"),
         }
     }
 }
@@ -49,6 +55,15 @@ impl From<ParserError> for BuilderError {
     }
 }
 
+// Records the declaration of `prefix` in `prefixes`, replacing its previous binding.
+// An empty `ns` undeclares the prefix, as in XML 1.1.
+fn declare_prefix(prefixes: &mut HashMap<String, String>, prefix: &str, ns: &str) {
+    prefixes.retain(|_, bound| bound != prefix);
+    if !ns.is_empty() {
+        prefixes.insert(ns.to_owned(), prefix.to_owned());
+    }
+}
+
 /// An Element Builder, building `Element`s from `Event`s as produced by `Parser`
 ///
 /// ~~~
@@ -68,18 +83,18 @@ pub struct ElementBuilder {
     prefixes: HashMap<String, String>,
 }
 
+impl Default for ElementBuilder {
+    fn default() -> ElementBuilder {
+        ElementBuilder::new()
+    }
+}
+
 impl ElementBuilder {
     /// Returns a new `ElementBuilder`
     pub fn new() -> ElementBuilder {
         let mut prefixes = HashMap::with_capacity(2);
-        prefixes.insert(
-            "http://www.w3.org/XML/1998/namespace".to_owned(),
-            "xml".to_owned(),
-        );
-        prefixes.insert(
-            "http://www.w3.org/2000/xmlns/".to_owned(),
-            "xmlns".to_owned(),
-        );
+        prefixes.insert(ns::XML.to_owned(), "xml".to_owned());
+        prefixes.insert(ns::XMLNS.to_owned(), "xmlns".to_owned());
         ElementBuilder {
             stack: Vec::new(),
             default_ns: Vec::new(),
@@ -116,15 +131,15 @@ impl ElementBuilder {
                     elem.children.push(Xml::PINode(cont));
                 }
             }
+            // Only found before the root element
+            Event::XmlDecl(_) | Event::Doctype(_) => (),
             Event::ElementStart(StartTag {
                 name,
-                ns,
                 prefix: _,
                 attributes,
             }) => {
                 let mut elem = Element {
                     name,
-                    ns,
                     default_ns: None,
                     prefixes: self.prefixes.clone(),
                     attributes,
@@ -135,7 +150,7 @@ impl ElementBuilder {
                     self.default_ns.push(default)
                 }
 
-                for (&(ref name, ref ns), value) in &elem.attributes {
+                for (QName { name, ns }, value) in &elem.attributes {
                     if ns.is_none() && name == "xmlns" {
                         self.default_ns.pop();
                         if value.is_empty() {
@@ -146,28 +161,21 @@ impl ElementBuilder {
                         continue;
                     }
 
-                    if ns
-                        .as_ref()
-                        .map_or(false, |x| x == "http://www.w3.org/2000/xmlns/")
-                    {
-                        elem.prefixes.insert(value.clone(), name.clone());
+                    if ns.as_ref().is_some_and(|x| x == ns::XMLNS) {
+                        declare_prefix(&mut elem.prefixes, name, value);
                     }
                 }
                 elem.default_ns = self.default_ns.last().unwrap_or(&None).clone();
 
                 self.stack.push(elem);
             }
-            Event::ElementEnd(EndTag {
-                name,
-                ns,
-                prefix: _,
-            }) => {
+            Event::ElementEnd(EndTag { name, prefix: _ }) => {
                 let elem = match self.stack.pop() {
                     Some(elem) => elem,
                     None => return Some(Err(BuilderError::ImproperNesting)),
                 };
                 self.default_ns.pop();
-                if elem.name != name || elem.ns != ns {
+                if elem.name != name {
                     return Some(Err(BuilderError::ImproperNesting));
                 } else {
                     match self.stack.last_mut() {
@@ -191,6 +199,32 @@ impl ElementBuilder {
                     elem.children.push(Xml::CommentNode(cont));
                 }
             }
+            Event::AttributeChunk(AttributeChunk {
+                name,
+                ns,
+                value: chunk,
+                last,
+            }) => {
+                if let Some(elem) = self.stack.last_mut() {
+                    let value = elem
+                        .attributes
+                        .entry(QName::new(name.clone(), ns.clone()))
+                        .or_default();
+                    value.push_str(&chunk);
+                    if last && ns.is_none() && name == "xmlns" {
+                        let default = if value.is_empty() {
+                            None
+                        } else {
+                            Some(value.clone())
+                        };
+                        self.default_ns.pop();
+                        self.default_ns.push(default.clone());
+                        elem.default_ns = default;
+                    } else if last && ns.as_ref().is_some_and(|x| x == ns::XMLNS) {
+                        declare_prefix(&mut elem.prefixes, &name, value);
+                    }
+                }
+            }
         }
         None
     }
@@ -0,0 +1,192 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::ns;
+use crate::{Element, QName, Xml};
+
+/// A fluent builder constructing an `Element` by value, created by `Element::build()`
+///
+/// Unlike `ElementBuilder`, which assembles `Element`s from parser `Event`s, this type
+/// is meant for building trees in code.
+/// Elements are in no namespace unless one is set using `ns()` or `prefixed()`.
+///
+/// ~~~
+/// use xml::Element;
+///
+/// let feed = Element::build("feed")
+///     .ns("http://www.w3.org/2005/Atom")
+///     .child(Element::build("title").ns("http://www.w3.org/2005/Atom").text("News"))
+///     .child(
+///         Element::build("thumbnail")
+///             .prefixed("media", "http://search.yahoo.com/mrss/")
+///             .attr("url", "/thumb.png"),
+///     )
+///     .finish();
+/// let title = feed.get_child("title", Some("http://www.w3.org/2005/Atom")).unwrap();
+/// assert_eq!(title.content_str(), "News");
+/// assert!(feed.get_child("thumbnail", Some("http://search.yahoo.com/mrss/")).is_some());
+/// ~~~
+#[derive(Clone, PartialEq, Debug)]
+#[must_use = "call `finish()` to obtain the constructed `Element`"]
+pub struct ElementConstructor {
+    elem: Element,
+}
+
+impl ElementConstructor {
+    pub(crate) fn new(name: String) -> ElementConstructor {
+        ElementConstructor {
+            elem: Element::new(name, None, vec![]),
+        }
+    }
+
+    /// Puts the element into the namespace `ns`, declared as the default namespace.
+    pub fn ns<S: Into<String>>(mut self, ns: S) -> ElementConstructor {
+        let ns = ns.into();
+        self.elem.name.ns = Some(ns.clone());
+        self.elem.default_ns = Some(ns);
+        self
+    }
+
+    /// Puts the element into the namespace `ns`, written using `prefix`.
+    /// The prefix is declared on the element.
+    pub fn prefixed<P, S>(self, prefix: P, ns: S) -> ElementConstructor
+    where
+        P: Into<String>,
+        S: Into<String>,
+    {
+        let ns = ns.into();
+        let mut constructor = self.prefix(prefix, ns.clone());
+        constructor.elem.name.ns = Some(ns);
+        constructor
+    }
+
+    /// Declares `prefix` for the namespace `ns` on the element.
+    /// Descendants in that namespace are written using the prefix.
+    pub fn prefix<P, S>(mut self, prefix: P, ns: S) -> ElementConstructor
+    where
+        P: Into<String>,
+        S: Into<String>,
+    {
+        let (prefix, ns) = (prefix.into(), ns.into());
+        self.elem.attributes.insert(
+            QName::new(prefix.clone(), Some(ns::XMLNS.to_owned())),
+            ns.clone(),
+        );
+        self.elem.prefixes.insert(ns, prefix);
+        self
+    }
+
+    /// Sets an attribute without namespace.
+    pub fn attr<N, V>(mut self, name: N, value: V) -> ElementConstructor
+    where
+        N: Into<String>,
+        V: Into<String>,
+    {
+        self.elem.set_attribute(name.into(), None, value.into());
+        self
+    }
+
+    /// Sets an attribute in the namespace `ns`.
+    /// A prefix for `ns` has to be declared on the element or one of its ancestors.
+    pub fn attr_ns<N, S, V>(mut self, name: N, ns: S, value: V) -> ElementConstructor
+    where
+        N: Into<String>,
+        S: Into<String>,
+        V: Into<String>,
+    {
+        self.elem
+            .set_attribute(name.into(), Some(ns.into()), value.into());
+        self
+    }
+
+    /// Appends a child element.
+    pub fn child<E: Into<Element>>(mut self, child: E) -> ElementConstructor {
+        self.elem.children.push(Xml::ElementNode(child.into()));
+        self
+    }
+
+    /// Appends all elements yielded by `children`.
+    pub fn children<I>(mut self, children: I) -> ElementConstructor
+    where
+        I: IntoIterator,
+        I::Item: Into<Element>,
+    {
+        self.elem.extend(children.into_iter().map(Into::into));
+        self
+    }
+
+    /// Appends characters.
+    pub fn text<S: Into<String>>(mut self, text: S) -> ElementConstructor {
+        self.elem.text(text.into());
+        self
+    }
+
+    /// Appends CDATA.
+    pub fn cdata<S: Into<String>>(mut self, text: S) -> ElementConstructor {
+        self.elem.cdata(text.into());
+        self
+    }
+
+    /// Appends a comment.
+    pub fn comment<S: Into<String>>(mut self, text: S) -> ElementConstructor {
+        self.elem.comment(text.into());
+        self
+    }
+
+    /// Appends processing information.
+    pub fn pi<S: Into<String>>(mut self, text: S) -> ElementConstructor {
+        self.elem.pi(text.into());
+        self
+    }
+
+    /// Returns the constructed `Element`.
+    pub fn finish(self) -> Element {
+        self.elem
+    }
+}
+
+impl From<ElementConstructor> for Element {
+    fn from(constructor: ElementConstructor) -> Element {
+        constructor.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Element;
+
+    #[test]
+    fn test_construct_namespaces() {
+        let elem = Element::build("iq")
+            .ns("jabber:client")
+            .attr("type", "get")
+            .child(
+                Element::build("query")
+                    .ns("jabber:iq:roster")
+                    .children(vec![Element::build("item"), Element::build("item")]),
+            )
+            .finish();
+        assert_eq!(
+            elem.to_string(),
+            "<iq xmlns='jabber:client' type='get'><query xmlns='jabber:iq:roster'>\
+             <item xmlns=''/><item xmlns=''/></query></iq>",
+        );
+    }
+
+    #[test]
+    fn test_construct_prefixed_attribute() {
+        let elem = Element::build("a")
+            .prefix("x", "urn:x")
+            .child(Element::build("b").attr_ns("id", "urn:x", "1"))
+            .finish();
+        let b = elem.get_child("b", None).unwrap();
+        assert_eq!(b.get_attribute("id", Some("urn:x")), Some("1"));
+        assert_eq!(elem.to_string(), "<a xmlns:x='urn:x'><b x:id='1'/></a>");
+    }
+}
@@ -0,0 +1,362 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::Doctype;
+use std::collections::HashMap;
+use std::io;
+
+/// A source of external entities and DTDs referenced by documents
+///
+/// The parser only loads external content through the resolver set by
+/// `Parser::set_entity_resolver()`. Without one, references to external entities are
+/// rejected and the external DTD subset is not read, so a document cannot make the parser
+/// access files or the network.
+///
+/// Closures taking the public and system identifier implement this trait.
+///
+/// ~~~
+/// use std::io;
+/// use xml::{Event, Parser};
+///
+/// let doc = "<!DOCTYPE a [<!ENTITY legal SYSTEM 'legal.txt'>]><a>&legal;</a>";
+/// let mut p = Parser::new(doc.as_bytes());
+/// p.set_entity_resolver(|_: Option<&str>, system_id: &str| match system_id {
+///     "legal.txt" => Ok("All rights reserved".to_owned()),
+///     _ => Err(io::ErrorKind::NotFound.into()),
+/// });
+/// let events: Vec<_> = p.map(Result::unwrap).collect();
+/// assert_eq!(events[2], Event::Characters("All rights reserved".to_owned()));
+/// ~~~
+pub trait EntityResolver {
+    /// Returns the content of the external entity or DTD with the given public and
+    /// system identifiers. The system identifier is passed as found in the document.
+    fn resolve(&mut self, public_id: Option<&str>, system_id: &str) -> io::Result<String>;
+}
+
+impl<F> EntityResolver for F
+where
+    F: FnMut(Option<&str>, &str) -> io::Result<String>,
+{
+    fn resolve(&mut self, public_id: Option<&str>, system_id: &str) -> io::Result<String> {
+        self(public_id, system_id)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+// A general entity declared in a DTD
+pub(crate) enum Entity {
+    Internal(String),
+    External {
+        public_id: Option<String>,
+        system_id: String,
+    },
+}
+
+// A cursor over the text of a document type declaration
+struct Scanner<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos == self.text.len()
+    }
+
+    // Skips whitespace, returning whether there was any
+    fn whitespace(&mut self) -> bool {
+        let rest = self.rest();
+        let trimmed = rest.trim_start_matches([' ', '\t', '\r', '\n']);
+        self.pos += rest.len() - trimmed.len();
+        trimmed.len() != rest.len()
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        let found = self.rest().starts_with(s);
+        if found {
+            self.pos += s.len();
+        }
+        found
+    }
+
+    // Moves past the next occurrence of `s`
+    fn skip_past(&mut self, s: &str) -> Result<(), ()> {
+        let i = self.rest().find(s).ok_or(())?;
+        self.pos += i + s.len();
+        Ok(())
+    }
+
+    fn name(&mut self) -> Result<&'a str, ()> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '>' | '[' | '"' | '\'' | '%' | ';'))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(());
+        }
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    fn quoted(&mut self) -> Result<&'a str, ()> {
+        let rest = self.rest();
+        let quote = rest
+            .chars()
+            .next()
+            .filter(|&c| c == '"' || c == '\'')
+            .ok_or(())?;
+        let len = rest[1..].find(quote).ok_or(())?;
+        self.pos += len + 2;
+        Ok(&rest[1..=len])
+    }
+
+    // Parses `SYSTEM 'system'` or `PUBLIC 'public' 'system'`, if present
+    fn external_id(&mut self) -> Result<Option<(Option<String>, String)>, ()> {
+        if self.eat("SYSTEM") {
+            self.whitespace();
+            return Ok(Some((None, self.quoted()?.to_owned())));
+        }
+        if self.eat("PUBLIC") {
+            self.whitespace();
+            let public_id = self.quoted()?.to_owned();
+            self.whitespace();
+            return Ok(Some((Some(public_id), self.quoted()?.to_owned())));
+        }
+        Ok(None)
+    }
+
+    // Skips the rest of a markup declaration, up to and including its '>'
+    fn skip_declaration(&mut self) -> Result<(), ()> {
+        loop {
+            match self.rest().find(['>', '"', '\'']).ok_or(())? {
+                i if self.rest()[i..].starts_with('>') => {
+                    self.pos += i + 1;
+                    return Ok(());
+                }
+                i => {
+                    self.pos += i;
+                    self.quoted()?;
+                }
+            }
+        }
+    }
+}
+
+// Finds the end of a document type declaration as its text following `<!DOCTYPE` is fed
+// one character at a time: a '>' that is not within a literal, comment or internal subset
+#[derive(Clone, Copy, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct DoctypeScanner {
+    in_subset: bool,
+    state: DoctypeState,
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum DoctypeState {
+    // Number of characters of a "<!--" or "<?" read in the internal subset
+    Markup(u8),
+    Literal(char),
+    // Number of trailing '-', up to two
+    Comment(u8),
+    // Whether the last character was a '?'
+    Pi(bool),
+}
+
+impl Default for DoctypeState {
+    fn default() -> DoctypeState {
+        DoctypeState::Markup(0)
+    }
+}
+
+impl DoctypeScanner {
+    // Reads the next character, returning whether it ends the declaration
+    pub(crate) fn feed(&mut self, c: char) -> bool {
+        self.state = match (self.state, c) {
+            (DoctypeState::Markup(_), '"' | '\'') => DoctypeState::Literal(c),
+            (DoctypeState::Markup(_), '<') if self.in_subset => DoctypeState::Markup(1),
+            (DoctypeState::Markup(1), '?') => DoctypeState::Pi(false),
+            (DoctypeState::Markup(1), '!') => DoctypeState::Markup(2),
+            (DoctypeState::Markup(2), '-') => DoctypeState::Markup(3),
+            (DoctypeState::Markup(3), '-') => DoctypeState::Comment(0),
+            (DoctypeState::Markup(_), _) => {
+                match c {
+                    '[' => self.in_subset = true,
+                    ']' => self.in_subset = false,
+                    '>' if !self.in_subset => return true,
+                    _ => (),
+                }
+                DoctypeState::Markup(0)
+            }
+            (DoctypeState::Literal(quote), _) if c == quote => DoctypeState::Markup(0),
+            (DoctypeState::Literal(quote), _) => DoctypeState::Literal(quote),
+            (DoctypeState::Comment(2), '>') => DoctypeState::Markup(0),
+            (DoctypeState::Comment(dashes), '-') => DoctypeState::Comment((dashes + 1).min(2)),
+            (DoctypeState::Comment(_), _) => DoctypeState::Comment(0),
+            (DoctypeState::Pi(true), '>') => DoctypeState::Markup(0),
+            (DoctypeState::Pi(_), _) => DoctypeState::Pi(c == '?'),
+        };
+        false
+    }
+}
+
+// Parses the text following `<!DOCTYPE`, up to the end found by `DoctypeScanner`, returning
+// the declaration and the entities declared in its internal subset
+pub(crate) fn parse_doctype(text: &str) -> Result<(Doctype, HashMap<String, Entity>), ()> {
+    let mut scanner = Scanner { text, pos: 0 };
+    scanner.whitespace();
+    let name = scanner.name()?.to_owned();
+    scanner.whitespace();
+    let (public_id, system_id) = match scanner.external_id()? {
+        Some((public_id, system_id)) => (public_id, Some(system_id)),
+        None => (None, None),
+    };
+    let mut doctype = Doctype {
+        name,
+        public_id,
+        system_id,
+        internal_subset: None,
+    };
+    let mut entities = HashMap::new();
+    scanner.whitespace();
+    if scanner.eat("[") {
+        let len = parse_declarations(scanner.rest(), &mut entities)?;
+        doctype.internal_subset = Some(scanner.rest()[..len].to_owned());
+        scanner.pos += len;
+        if !scanner.eat("]") {
+            return Err(());
+        }
+        scanner.whitespace();
+    }
+    if !scanner.at_end() {
+        return Err(());
+    }
+    Ok((doctype, entities))
+}
+
+// Collects the general entities declared in `text`, a DTD or internal subset.
+// Only the first declaration of an entity is binding. Returns the length of text parsed,
+// which ends early at a ']' closing an internal subset.
+pub(crate) fn parse_declarations(
+    text: &str,
+    entities: &mut HashMap<String, Entity>,
+) -> Result<usize, ()> {
+    let mut scanner = Scanner { text, pos: 0 };
+    loop {
+        scanner.whitespace();
+        if scanner.at_end() || scanner.rest().starts_with(']') {
+            return Ok(scanner.pos);
+        }
+        if scanner.eat("<!--") {
+            scanner.skip_past("-->")?;
+        } else if scanner.eat("<?") {
+            scanner.skip_past("?>")?;
+        } else if scanner.eat("<!ENTITY") {
+            if !scanner.whitespace() {
+                return Err(());
+            }
+            let parameter = scanner.eat("%");
+            scanner.whitespace();
+            let name = scanner.name()?;
+            scanner.whitespace();
+            let entity = match scanner.external_id()? {
+                Some((public_id, system_id)) => Entity::External {
+                    public_id,
+                    system_id,
+                },
+                None => Entity::Internal(scanner.quoted()?.to_owned()),
+            };
+            scanner.whitespace();
+            // Unparsed entities cannot be referenced from content
+            let unparsed = scanner.eat("NDATA");
+            scanner.skip_declaration()?;
+            if !parameter && !unparsed {
+                entities.entry(name.to_owned()).or_insert(entity);
+            }
+        } else if scanner.eat("<!") {
+            scanner.skip_declaration()?;
+        } else if scanner.eat("%") {
+            // Parameter entity references are not expanded
+            scanner.name()?;
+            if !scanner.eat(";") {
+                return Err(());
+            }
+        } else {
+            return Err(());
+        }
+    }
+}
+
+// Removes the text declaration an external parsed entity may start with
+pub(crate) fn strip_text_declaration(text: &str) -> &str {
+    match text.strip_prefix("<?xml") {
+        Some(rest) if rest.starts_with(char::is_whitespace) => match rest.find("?>") {
+            Some(i) => &rest[i + 2..],
+            None => text,
+        },
+        _ => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_doctype, strip_text_declaration, DoctypeScanner, Entity};
+
+    #[test]
+    fn test_parse_doctype() {
+        let text = " doc PUBLIC '-//X//DTD Doc//EN' \"doc.dtd\" [\n\
+                    <!-- it's > here -->\n\
+                    <!ELEMENT doc (#PCDATA)>\n\
+                    <!ATTLIST doc a CDATA 'x>y'>\n\
+                    <!ENTITY % pe 'ignored'> %pe;\n\
+                    <!ENTITY copy \"(c) 'me'\">\n\
+                    <!ENTITY copy 'second'>\n\
+                    <!ENTITY ext SYSTEM 'ext.xml'>\n\
+                    <!ENTITY img SYSTEM 'img.png' NDATA png>\n\
+                    <?pi ]>?>\n] ";
+        let mut scanner = DoctypeScanner::default();
+        assert!(!text.chars().any(|c| scanner.feed(c)));
+        assert!(scanner.feed('>'));
+
+        let (doctype, entities) = parse_doctype(text).unwrap();
+        assert_eq!(doctype.name, "doc");
+        assert_eq!(doctype.public_id.as_deref(), Some("-//X//DTD Doc//EN"));
+        assert_eq!(doctype.system_id.as_deref(), Some("doc.dtd"));
+        let subset = doctype.internal_subset.as_deref().unwrap();
+        assert!(subset.starts_with("\n<!-- it's > here -->") && subset.ends_with("?>\n"));
+        assert_eq!(entities.len(), 2);
+        assert_eq!(entities["copy"], Entity::Internal("(c) 'me'".to_owned()));
+        assert_eq!(
+            entities["ext"],
+            Entity::External {
+                public_id: None,
+                system_id: "ext.xml".to_owned()
+            }
+        );
+
+        let (doctype, _) = parse_doctype(" html").unwrap();
+        assert_eq!((doctype.system_id, doctype.internal_subset), (None, None));
+        assert!(parse_doctype(" a [<!ENTITY x>]").is_err());
+        assert!(parse_doctype(" a SYSTEM").is_err());
+    }
+
+    #[test]
+    fn test_strip_text_declaration() {
+        assert_eq!(strip_text_declaration("<?xml encoding='UTF-8'?>x"), "x");
+        assert_eq!(
+            strip_text_declaration("<?xml-stylesheet?>x"),
+            "<?xml-stylesheet?>x"
+        );
+    }
+}
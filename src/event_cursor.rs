@@ -0,0 +1,179 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::{AttrMap, Event, Parser, ParserError, QName};
+use std::io::Read;
+
+/// A pull cursor over a `Parser`, keeping the current event accessible by reference
+///
+/// Like StAX's `XMLStreamReader`, the cursor is moved forward by `advance()`, after which
+/// the accessors describe the event it is positioned at. Before the first advance and
+/// after the end of input, the cursor is not positioned at any event.
+///
+/// ~~~
+/// use xml::{EventCursor, Parser};
+///
+/// let mut cursor = EventCursor::new(Parser::new("<a x='1'>t<b/></a>".as_bytes()));
+/// let mut names = Vec::new();
+/// while cursor.advance().unwrap() {
+///     if cursor.is_start() {
+///         names.push(cursor.name().unwrap().to_owned());
+///     }
+/// }
+/// assert_eq!(names, ["a", "b"]);
+/// ~~~
+pub struct EventCursor<R>
+where
+    R: Read,
+{
+    parser: Parser<R>,
+    event: Option<Event>,
+}
+
+impl<R> EventCursor<R>
+where
+    R: Read,
+{
+    /// Returns a new `EventCursor` reading events from `parser`
+    pub fn new(parser: Parser<R>) -> EventCursor<R> {
+        EventCursor {
+            parser,
+            event: None,
+        }
+    }
+
+    /// Moves to the next event. Returns `false` once the input is exhausted.
+    pub fn advance(&mut self) -> Result<bool, ParserError> {
+        self.event = self.parser.next().transpose()?;
+        Ok(self.event.is_some())
+    }
+
+    /// Returns the current event
+    pub fn event(&self) -> Option<&Event> {
+        self.event.as_ref()
+    }
+
+    /// Returns `true` if the current event is an `ElementStart`
+    pub fn is_start(&self) -> bool {
+        matches!(self.event, Some(Event::ElementStart(_)))
+    }
+
+    /// Returns `true` if the current event is an `ElementEnd`
+    pub fn is_end(&self) -> bool {
+        matches!(self.event, Some(Event::ElementEnd(_)))
+    }
+
+    /// Returns `true` if the current event is `Characters` or `CDATA`
+    pub fn is_text(&self) -> bool {
+        matches!(
+            self.event,
+            Some(Event::Characters(_)) | Some(Event::CDATA(_))
+        )
+    }
+
+    /// Returns the element name of the current `ElementStart` or `ElementEnd` event
+    pub fn name(&self) -> Option<&str> {
+        match self.event {
+            Some(Event::ElementStart(ref tag)) => Some(&tag.name.name),
+            Some(Event::ElementEnd(ref tag)) => Some(&tag.name.name),
+            _ => None,
+        }
+    }
+
+    /// Returns the element namespace of the current `ElementStart` or `ElementEnd` event
+    pub fn ns(&self) -> Option<&str> {
+        match self.event {
+            Some(Event::ElementStart(ref tag)) => tag.name.ns.as_deref(),
+            Some(Event::ElementEnd(ref tag)) => tag.name.ns.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Returns the attributes of the current `ElementStart` event
+    pub fn attributes(&self) -> Option<&AttrMap<QName, String>> {
+        match self.event {
+            Some(Event::ElementStart(ref tag)) => Some(&tag.attributes),
+            _ => None,
+        }
+    }
+
+    /// Gets an attribute of the current `ElementStart` event with the specified name and
+    /// namespace
+    pub fn get_attribute(&self, name: &str, ns: Option<&str>) -> Option<&str> {
+        self.attributes()?
+            .get(&QName::new(name.to_owned(), ns.map(|x| x.to_owned())))
+            .map(|x| &x[..])
+    }
+
+    /// Returns the text of the current `Characters` or `CDATA` event
+    pub fn text(&self) -> Option<&str> {
+        match self.event {
+            Some(Event::Characters(ref text)) | Some(Event::CDATA(ref text)) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Returns the underlying parser, for instance to query `current_path()`
+    pub fn parser(&self) -> &Parser<R> {
+        &self.parser
+    }
+
+    /// Returns the underlying parser, dropping the current event
+    pub fn into_inner(self) -> Parser<R> {
+        self.parser
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EventCursor;
+    use crate::Parser;
+
+    struct Item {
+        id: String,
+        tags: Vec<String>,
+    }
+
+    // A recursive-descent reader, as the cursor is intended for
+    fn read_item(cursor: &mut EventCursor<&[u8]>) -> Item {
+        let id = cursor.get_attribute("id", None).unwrap().to_owned();
+        let mut tags = Vec::new();
+        while cursor.advance().unwrap() && !cursor.is_end() {
+            if cursor.is_start() && cursor.name() == Some("tag") {
+                assert!(cursor.advance().unwrap() && cursor.is_text());
+                tags.push(cursor.text().unwrap().to_owned());
+                cursor.advance().unwrap();
+            }
+        }
+        Item { id, tags }
+    }
+
+    #[test]
+    fn test_event_cursor() {
+        let doc = "<items xmlns='urn:x'><item id='1'><tag>a</tag> <tag>b</tag></item>\
+                   <item id='2'/></items>";
+        let mut cursor = EventCursor::new(Parser::new(doc.as_bytes()));
+        assert!(cursor.event().is_none());
+        let mut items = Vec::new();
+        while cursor.advance().unwrap() {
+            if cursor.is_start() && cursor.name() == Some("item") {
+                assert_eq!(cursor.ns(), Some("urn:x"));
+                assert_eq!(cursor.parser().depth(), 2);
+                items.push(read_item(&mut cursor));
+            }
+        }
+        assert!(!cursor.is_start() && cursor.name().is_none());
+        assert_eq!(items[0].tags, ["a", "b"]);
+        assert_eq!(items[1].id, "2");
+
+        let mut cursor = EventCursor::new(Parser::new("<a>&x;</a>".as_bytes()));
+        assert!(cursor.advance().unwrap());
+        assert!(cursor.advance().is_err());
+    }
+}
@@ -0,0 +1,104 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::path::ElementPath;
+use crate::{Event, Name, Parser, ParserError, QName};
+use std::io::Read;
+
+/// Streams the text of all elements and attributes selected by `paths` to `sink`,
+/// without building a tree.
+///
+/// `sink` is called with the index of the matching path and the text once the element
+/// has ended, or once the start tag has been read for attributes. The text of an element
+/// includes the text of all its descendants. Returns the number of calls to `sink`.
+///
+/// ~~~
+/// let doc = "<doc><title>Hello</title><body><p>First</p><p>Second <b>bold</b></p></body></doc>";
+/// let mut texts = Vec::new();
+/// let count = xml::extract_text(doc.as_bytes(), ["/doc/title", "/doc/body/p"], |i, text| {
+///     texts.push((i, text.to_owned()))
+/// })
+/// .unwrap();
+/// assert_eq!(count, 3);
+/// assert_eq!(texts[2], (1, "Second bold".to_owned()));
+/// ~~~
+pub fn extract_text<R, I, F>(reader: R, paths: I, mut sink: F) -> Result<usize, ParserError>
+where
+    R: Read,
+    I: IntoIterator,
+    I::Item: Into<ElementPath>,
+    F: FnMut(usize, &str),
+{
+    let paths: Vec<ElementPath> = paths.into_iter().map(Into::into).collect();
+    let mut names: Vec<Name> = Vec::new();
+    // Path index, depth and text collected so far for every selected open element
+    let mut open: Vec<(usize, usize, String)> = Vec::new();
+    let mut count = 0;
+
+    for event in Parser::new(reader) {
+        match event? {
+            Event::ElementStart(tag) => {
+                names.push(tag.name.name);
+                for (i, path) in paths.iter().enumerate() {
+                    if !path.matches(&names) {
+                        continue;
+                    }
+                    match path.attribute() {
+                        Some(attr) => {
+                            let value = tag
+                                .attributes
+                                .iter()
+                                .find(|(QName { name, .. }, _)| name == attr);
+                            if let Some((_, value)) = value {
+                                sink(i, value);
+                                count += 1;
+                            }
+                        }
+                        None => open.push((i, names.len(), String::new())),
+                    }
+                }
+            }
+            Event::ElementEnd(_) => {
+                while open
+                    .last()
+                    .is_some_and(|&(_, depth, _)| depth == names.len())
+                {
+                    let (i, _, text) = open.pop().expect("No open element");
+                    sink(i, &text);
+                    count += 1;
+                }
+                names.pop();
+            }
+            Event::Characters(text) | Event::CDATA(text) => {
+                for (_, _, buf) in &mut open {
+                    buf.push_str(&text);
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::extract_text;
+
+    #[test]
+    fn test_nested_and_attributes() {
+        let doc = "<a id='1'><b>x<b>y</b></b><c id='2'>z</c></a>";
+        let mut out = Vec::new();
+        let count = extract_text(doc.as_bytes(), ["b", "*/@id"], |i, text| {
+            out.push(format!("{}:{}", i, text))
+        })
+        .unwrap();
+        assert_eq!(count, 4);
+        assert_eq!(out, ["1:1", "0:y", "0:xy", "1:2"]);
+    }
+}
@@ -0,0 +1,264 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Streaming extraction of items from RSS and Atom feeds
+//!
+//! Items are read from RSS 0.9x/1.0/2.0 `<item>` and Atom `<entry>` elements.
+//! Elements are matched by local name regardless of their namespace, as many feeds in the
+//! wild are sloppy about namespaces. Dates are returned as found in the feed.
+//! Feeds are parsed with the `lenient` parser option, and references to entities that are not
+//! declared, such as HTML's `&nbsp;`, are kept as written.
+//!
+//! ~~~
+//! let rss = "<rss version='2.0'><channel><title>News</title>\
+//!            <item><title>First</title><link>http://example.com/1</link>\
+//!            <pubDate>Mon, 01 Jan 2024 00:00:00 GMT</pubDate></item>\
+//!            </channel></rss>";
+//! let items: Vec<_> = xml::feed::items(rss.as_bytes()).collect::<Result<_, _>>().unwrap();
+//! assert_eq!(items.len(), 1);
+//! assert_eq!(items[0].title.as_deref(), Some("First"));
+//! assert_eq!(items[0].link.as_deref(), Some("http://example.com/1"));
+//! ~~~
+
+use crate::{Event, Parser, ParserConfig, ParserError, QName, StartTag, UnknownEntities};
+use std::io::Read;
+use std::mem;
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+/// A single item of an RSS feed, or entry of an Atom feed
+pub struct FeedItem {
+    /// The item's title
+    pub title: Option<String>,
+    /// The item's link, for Atom the `alternate` link
+    pub link: Option<String>,
+    /// The item's identifier, from `<guid>` or `<id>`
+    pub id: Option<String>,
+    /// The publication date, from `<pubDate>`, `<published>`, `<issued>` or `<dc:date>`
+    pub published: Option<String>,
+    /// The date of the last update, from `<updated>` or `<modified>`
+    pub updated: Option<String>,
+    /// A summary, from `<description>` or `<summary>`
+    pub summary: Option<String>,
+    /// The full content, from `<content>` or `<content:encoded>`
+    pub content: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Field {
+    Title,
+    Link,
+    Id,
+    Published,
+    Updated,
+    Summary,
+    Content,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Field> {
+        Some(match name {
+            "title" => Field::Title,
+            "link" => Field::Link,
+            "guid" | "id" => Field::Id,
+            "pubDate" | "published" | "issued" | "date" => Field::Published,
+            "updated" | "modified" => Field::Updated,
+            "description" | "summary" => Field::Summary,
+            "content" | "encoded" => Field::Content,
+            _ => return None,
+        })
+    }
+}
+
+impl FeedItem {
+    fn slot(&mut self, field: Field) -> &mut Option<String> {
+        match field {
+            Field::Title => &mut self.title,
+            Field::Link => &mut self.link,
+            Field::Id => &mut self.id,
+            Field::Published => &mut self.published,
+            Field::Updated => &mut self.updated,
+            Field::Summary => &mut self.summary,
+            Field::Content => &mut self.content,
+        }
+    }
+}
+
+/// An iterator over the items of a feed, created by `items()`
+pub struct FeedItems<R>
+where
+    R: Read,
+{
+    parser: Parser<R>,
+    item: Option<FeedItem>,
+    depth: usize,
+    item_depth: usize,
+    field: Option<Field>,
+    text: String,
+}
+
+/// Returns an iterator over the items of the RSS or Atom feed read from `reader`
+pub fn items<R: Read>(reader: R) -> FeedItems<R> {
+    let config = ParserConfig::new()
+        .lenient(true)
+        .unknown_entities(UnknownEntities::Keep);
+    FeedItems {
+        parser: Parser::new_with_config(reader, config),
+        item: None,
+        depth: 0,
+        item_depth: 0,
+        field: None,
+        text: String::new(),
+    }
+}
+
+impl<R> FeedItems<R>
+where
+    R: Read,
+{
+    fn start(&mut self, tag: &StartTag) {
+        self.depth += 1;
+        let item = match self.item {
+            Some(ref mut item) => item,
+            None => {
+                if tag.name.name == "item" || tag.name.name == "entry" {
+                    self.item = Some(FeedItem::default());
+                    self.item_depth = self.depth;
+                }
+                return;
+            }
+        };
+        if self.depth != self.item_depth + 1 {
+            return;
+        }
+        self.field = Field::from_name(&tag.name.name);
+        self.text.clear();
+        if self.field == Some(Field::Link) {
+            let href = tag
+                .attributes
+                .iter()
+                .find(|(QName { name, .. }, _)| name == "href");
+            let rel = tag
+                .attributes
+                .iter()
+                .find(|(QName { name, .. }, _)| name == "rel");
+            if let Some((_, href)) = href {
+                self.field = None;
+                let alternate = rel.is_none_or(|(_, rel)| rel == "alternate");
+                if alternate && item.link.is_none() {
+                    item.link = Some(href.trim().to_owned());
+                }
+            }
+        }
+    }
+
+    fn end(&mut self) -> Option<FeedItem> {
+        self.depth -= 1;
+        self.item.as_ref()?;
+        if self.depth < self.item_depth {
+            self.field = None;
+            return self.item.take();
+        }
+        if self.depth == self.item_depth {
+            if let Some(field) = self.field.take() {
+                let text = mem::take(&mut self.text);
+                let item = self.item.as_mut().expect("No item being read");
+                let slot = item.slot(field);
+                if slot.is_none() && !text.trim().is_empty() {
+                    *slot = Some(text.trim().to_owned());
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<R> Iterator for FeedItems<R>
+where
+    R: Read,
+{
+    type Item = Result<FeedItem, ParserError>;
+
+    fn next(&mut self) -> Option<Result<FeedItem, ParserError>> {
+        loop {
+            let event = match self.parser.next()? {
+                Ok(event) => event,
+                Err(err) => return Some(Err(err)),
+            };
+            match event {
+                Event::ElementStart(ref tag) => self.start(tag),
+                Event::ElementEnd(_) => {
+                    if let Some(item) = self.end() {
+                        return Some(Ok(item));
+                    }
+                }
+                Event::Characters(ref text) | Event::CDATA(ref text) if self.field.is_some() => {
+                    self.text.push_str(text)
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{items, FeedItem};
+
+    #[test]
+    fn test_atom() {
+        let atom = "<feed xmlns='http://www.w3.org/2005/Atom'><title>Feed</title>\
+                    <entry><title type='text'> Entry </title>\
+                    <link rel='edit' href='/edit'/><link href='/entry'/>\
+                    <id>urn:1</id><updated>2024-01-01T00:00:00Z</updated>\
+                    <content type='xhtml'><div>Hello <b>world</b></div></content></entry>\
+                    </feed>";
+        let items: Vec<FeedItem> = items(atom.as_bytes()).map(Result::unwrap).collect();
+        assert_eq!(
+            items,
+            vec![FeedItem {
+                title: Some("Entry".to_owned()),
+                link: Some("/entry".to_owned()),
+                id: Some("urn:1".to_owned()),
+                updated: Some("2024-01-01T00:00:00Z".to_owned()),
+                content: Some("Hello world".to_owned()),
+                ..FeedItem::default()
+            }],
+        );
+    }
+
+    #[test]
+    fn test_rss_content_encoded() {
+        let rss = "<rss xmlns:content='http://purl.org/rss/1.0/modules/content/'><channel>\
+                   <item><guid isPermaLink='false'>42</guid><description>Short</description>\
+                   <content:encoded><![CDATA[<p>Long</p>]]></content:encoded></item>\
+                   <item><title>Second</title></item></channel></rss>";
+        let items: Vec<FeedItem> = items(rss.as_bytes()).map(Result::unwrap).collect();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].id.as_deref(), Some("42"));
+        assert_eq!(items[0].summary.as_deref(), Some("Short"));
+        assert_eq!(items[0].content.as_deref(), Some("<p>Long</p>"));
+        assert_eq!(items[1].title.as_deref(), Some("Second"));
+    }
+
+    #[test]
+    fn test_sloppy_rss() {
+        let rss = "<rss><channel><item><title>A&nbsp;B &amp; C</title>\
+                   <link>http://example.com/?a=1&amp;b=2</link><description>One<br>Two\
+                   </description></item><item><title>Next</TITLE></item></channel></rss>";
+        let items: Vec<FeedItem> = items(rss.as_bytes()).map(Result::unwrap).collect();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].title.as_deref(), Some("A&nbsp;B & C"));
+        assert_eq!(
+            items[0].link.as_deref(),
+            Some("http://example.com/?a=1&b=2")
+        );
+        assert_eq!(items[0].summary.as_deref(), Some("OneTwo"));
+        assert_eq!(items[1].title.as_deref(), Some("Next"));
+    }
+}
@@ -0,0 +1,163 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::hash::Hasher;
+
+/// The 64-bit FNV-1a hash function
+///
+/// FNV-1a is much faster than the standard library's default for short keys such as
+/// attribute names, but it is not resistant to collisions crafted by an attacker.
+/// Its output is fixed by its specification.
+pub struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> FnvHasher {
+        FnvHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(feature = "fast_hash")]
+pub use self::attr_map::AttrMap;
+
+#[cfg(feature = "fast_hash")]
+mod attr_map {
+    use super::FnvHasher;
+    use std::fmt;
+    use std::hash::{BuildHasherDefault, Hash};
+    use std::iter::FromIterator;
+    use std::ops::{Deref, DerefMut};
+
+    type FnvBuildHasher = BuildHasherDefault<FnvHasher>;
+    #[cfg(feature = "ordered_attrs")]
+    type Map<K, V> = indexmap::IndexMap<K, V, FnvBuildHasher>;
+    #[cfg(not(feature = "ordered_attrs"))]
+    type Map<K, V> = std::collections::HashMap<K, V, FnvBuildHasher>;
+
+    /// The map type storing attributes, hashing with `FnvHasher`
+    ///
+    /// It dereferences to the map it wraps, adding the constructors of the map types used
+    /// without the `fast_hash` feature.
+    pub struct AttrMap<K, V>(Map<K, V>);
+
+    impl<K, V> AttrMap<K, V> {
+        /// Creates an empty map
+        pub fn new() -> AttrMap<K, V> {
+            AttrMap(Map::default())
+        }
+
+        /// Creates an empty map with space for at least `capacity` attributes
+        pub fn with_capacity(capacity: usize) -> AttrMap<K, V> {
+            AttrMap(Map::with_capacity_and_hasher(
+                capacity,
+                FnvBuildHasher::default(),
+            ))
+        }
+
+        /// Consumes the map, returning its keys
+        pub fn into_keys(self) -> impl Iterator<Item = K> {
+            self.0.into_keys()
+        }
+
+        /// Consumes the map, returning its values
+        pub fn into_values(self) -> impl Iterator<Item = V> {
+            self.0.into_values()
+        }
+    }
+
+    impl<K, V> Default for AttrMap<K, V> {
+        fn default() -> AttrMap<K, V> {
+            AttrMap::new()
+        }
+    }
+
+    impl<K, V> Deref for AttrMap<K, V> {
+        type Target = Map<K, V>;
+
+        fn deref(&self) -> &Map<K, V> {
+            &self.0
+        }
+    }
+
+    impl<K, V> DerefMut for AttrMap<K, V> {
+        fn deref_mut(&mut self) -> &mut Map<K, V> {
+            &mut self.0
+        }
+    }
+
+    impl<K: Clone, V: Clone> Clone for AttrMap<K, V> {
+        fn clone(&self) -> AttrMap<K, V> {
+            AttrMap(self.0.clone())
+        }
+    }
+
+    impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for AttrMap<K, V> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            self.0.fmt(f)
+        }
+    }
+
+    impl<K: Eq + Hash, V: PartialEq> PartialEq for AttrMap<K, V> {
+        fn eq(&self, other: &AttrMap<K, V>) -> bool {
+            self.0 == other.0
+        }
+    }
+
+    impl<K: Eq + Hash, V: Eq> Eq for AttrMap<K, V> {}
+
+    impl<K: Eq + Hash, V> FromIterator<(K, V)> for AttrMap<K, V> {
+        fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> AttrMap<K, V> {
+            AttrMap(Map::from_iter(iter))
+        }
+    }
+
+    impl<K: Eq + Hash, V> Extend<(K, V)> for AttrMap<K, V> {
+        fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+            self.0.extend(iter)
+        }
+    }
+
+    impl<K, V> IntoIterator for AttrMap<K, V> {
+        type Item = (K, V);
+        type IntoIter = <Map<K, V> as IntoIterator>::IntoIter;
+
+        fn into_iter(self) -> Self::IntoIter {
+            self.0.into_iter()
+        }
+    }
+
+    impl<'a, K, V> IntoIterator for &'a AttrMap<K, V> {
+        type Item = (&'a K, &'a V);
+        type IntoIter = <&'a Map<K, V> as IntoIterator>::IntoIter;
+
+        fn into_iter(self) -> Self::IntoIter {
+            self.0.iter()
+        }
+    }
+
+    impl<'a, K, V> IntoIterator for &'a mut AttrMap<K, V> {
+        type Item = (&'a K, &'a mut V);
+        type IntoIter = <&'a mut Map<K, V> as IntoIterator>::IntoIter;
+
+        fn into_iter(self) -> Self::IntoIter {
+            self.0.iter_mut()
+        }
+    }
+}
@@ -0,0 +1,299 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Conversions between `Element` and the trees of other XML crates, enabled by the feature
+// named after the crate. Trees are converted node by node, without serializing them.
+
+#[cfg(feature = "minidom")]
+use crate::Xml;
+use crate::{ns, AttrMap, BuilderError, Element, ElementBuilder, EndTag, Event, QName, StartTag};
+#[cfg(feature = "minidom")]
+use std::collections::HashMap;
+
+// Builds an element from `events`, which describe a single well-formed element
+fn build(events: Vec<Event>) -> Result<Element, BuilderError> {
+    let mut builder = ElementBuilder::new();
+    events
+        .into_iter()
+        .find_map(|event| builder.handle_event(Ok(event)))
+        .unwrap_or(Err(BuilderError::NoElement))
+}
+
+#[cfg(feature = "roxmltree")]
+fn push_roxmltree_events(node: roxmltree::Node, events: &mut Vec<Event>) {
+    match node.node_type() {
+        roxmltree::NodeType::Element => (),
+        roxmltree::NodeType::Text => {
+            events.push(Event::Characters(node.text().unwrap_or("").to_owned()));
+            return;
+        }
+        roxmltree::NodeType::Comment => {
+            events.push(Event::Comment(node.text().unwrap_or("").to_owned()));
+            return;
+        }
+        roxmltree::NodeType::PI => {
+            if let Some(pi) = node.pi() {
+                events.push(Event::PI(match pi.value {
+                    Some(value) => format!("{} {}", pi.target, value),
+                    None => pi.target.to_owned(),
+                }));
+            }
+            return;
+        }
+        roxmltree::NodeType::Root => {
+            for child in node.children() {
+                push_roxmltree_events(child, events);
+            }
+            return;
+        }
+    }
+
+    let mut attributes = AttrMap::new();
+    // roxmltree lists all namespaces in scope, those not in scope at the parent are declared
+    let parent = node.parent_element();
+    for namespace in node.namespaces() {
+        let inherited = parent.is_some_and(|parent| {
+            parent
+                .namespaces()
+                .any(|other| other.name() == namespace.name() && other.uri() == namespace.uri())
+        });
+        match namespace.name() {
+            _ if inherited => (),
+            Some("xml") => (),
+            Some(prefix) => {
+                attributes.insert(
+                    QName::new(prefix.to_owned(), Some(ns::XMLNS.to_owned())),
+                    namespace.uri().to_owned(),
+                );
+            }
+            None => {
+                attributes.insert(
+                    QName::new("xmlns".to_owned(), None),
+                    namespace.uri().to_owned(),
+                );
+            }
+        }
+    }
+    // A default namespace in scope at the parent may be undeclared
+    let default_ns = |node: roxmltree::Node| node.namespaces().any(|ns| ns.name().is_none());
+    if parent.is_some_and(default_ns) && !default_ns(node) {
+        attributes.insert(QName::new("xmlns".to_owned(), None), String::new());
+    }
+    for attr in node.attributes() {
+        attributes.insert(
+            QName::new(attr.name().to_owned(), attr.namespace().map(str::to_owned)),
+            attr.value().to_owned(),
+        );
+    }
+
+    let name = node.tag_name();
+    let ns = name
+        .namespace()
+        .filter(|ns| !ns.is_empty())
+        .map(str::to_owned);
+    let prefix = ns
+        .as_deref()
+        .and_then(|ns| node.lookup_prefix(ns))
+        .map(str::to_owned);
+    let name = QName::new(name.name().to_owned(), ns);
+    events.push(Event::ElementStart(StartTag {
+        name: name.clone(),
+        prefix: prefix.clone(),
+        attributes,
+    }));
+    for child in node.children() {
+        push_roxmltree_events(child, events);
+    }
+    events.push(Event::ElementEnd(EndTag { name, prefix }));
+}
+
+#[cfg(feature = "roxmltree")]
+/// Converts an element of a `roxmltree::Document`, or the document's root element when
+/// given the root node. Fails with `BuilderError::NoElement` for other nodes.
+impl<'a, 'input> TryFrom<roxmltree::Node<'a, 'input>> for Element {
+    type Error = BuilderError;
+
+    fn try_from(node: roxmltree::Node<'a, 'input>) -> Result<Element, BuilderError> {
+        let node = match node.node_type() {
+            roxmltree::NodeType::Root => node.first_element_child(),
+            roxmltree::NodeType::Element => Some(node),
+            _ => None,
+        };
+        let mut events = Vec::new();
+        push_roxmltree_events(node.ok_or(BuilderError::NoElement)?, &mut events);
+        build(events)
+    }
+}
+
+#[cfg(feature = "roxmltree")]
+/// Converts the root element of a `roxmltree::Document`
+impl<'input> From<&roxmltree::Document<'input>> for Element {
+    fn from(doc: &roxmltree::Document<'input>) -> Element {
+        Element::try_from(doc.root_element()).expect("Document has a root element")
+    }
+}
+
+#[cfg(feature = "minidom")]
+fn push_minidom_events(
+    elem: &minidom::Element,
+    scope: &HashMap<Option<String>, String>,
+    events: &mut Vec<Event>,
+) {
+    let ns = elem.ns().filter(|ns| !ns.is_empty());
+    let prefix = elem.prefix().map(str::to_owned);
+    let mut attributes = AttrMap::new();
+    let mut scope = scope.clone();
+    let bound = scope.get(&prefix).map_or("", |x| &x[..]);
+    if bound != ns.as_deref().unwrap_or("") {
+        let value = ns.clone().unwrap_or_default();
+        match prefix {
+            Some(ref prefix) => attributes.insert(
+                QName::new(prefix.clone(), Some(ns::XMLNS.to_owned())),
+                value.clone(),
+            ),
+            None => attributes.insert(QName::new("xmlns".to_owned(), None), value.clone()),
+        };
+        scope.insert(prefix.clone(), value);
+    }
+    // minidom does not resolve the namespaces of attributes, they keep their qualified names
+    for (name, value) in elem.attrs() {
+        attributes.insert(QName::new(name.to_owned(), None), value.to_owned());
+    }
+
+    let name = QName::new(elem.name().to_owned(), ns);
+    events.push(Event::ElementStart(StartTag {
+        name: name.clone(),
+        prefix: prefix.clone(),
+        attributes,
+    }));
+    for node in elem.nodes() {
+        match *node {
+            minidom::Node::Element(ref child) => push_minidom_events(child, &scope, events),
+            minidom::Node::Text(ref text) => events.push(Event::Characters(text.clone())),
+            minidom::Node::Comment(ref text) => events.push(Event::Comment(text.clone())),
+        }
+    }
+    events.push(Event::ElementEnd(EndTag { name, prefix }));
+}
+
+#[cfg(feature = "minidom")]
+/// Converts a `minidom::Element`. Attribute names are taken as they are, as minidom does not
+/// resolve the namespaces of attributes.
+impl From<&minidom::Element> for Element {
+    fn from(elem: &minidom::Element) -> Element {
+        let mut events = Vec::new();
+        push_minidom_events(elem, &HashMap::new(), &mut events);
+        build(events).expect("Events describe a single element")
+    }
+}
+
+#[cfg(feature = "minidom")]
+fn to_minidom(elem: &Element, parent_ns: Option<&str>) -> minidom::Element {
+    let mut builder = minidom::Element::builder(&elem.name.name[..]);
+    if elem.name.ns.as_deref() != parent_ns {
+        builder = builder.ns(elem.name.ns.as_deref().unwrap_or(""));
+    }
+    let mut prefixes = Vec::new();
+    for (QName { name, ns }, value) in &elem.attributes {
+        let prefix = match ns.as_deref() {
+            None if name == "xmlns" => continue,
+            None => {
+                builder = builder.attr(name.to_string(), value.clone());
+                continue;
+            }
+            Some(ns::XMLNS) => continue,
+            Some(ns::XML) => "xml".to_owned(),
+            Some(ns) => match prefixes.iter().position(|&x| x == ns) {
+                Some(i) => format!("ns{}", i),
+                None => {
+                    prefixes.push(ns);
+                    format!("ns{}", prefixes.len() - 1)
+                }
+            },
+        };
+        builder = builder.attr(format!("{}:{}", prefix, name), value.clone());
+    }
+    for (i, ns) in prefixes.into_iter().enumerate() {
+        builder = builder.attr(format!("xmlns:ns{}", i), ns);
+    }
+    for child in &elem.children {
+        builder = match *child {
+            Xml::ElementNode(ref child) => {
+                builder.append(to_minidom(child, elem.name.ns.as_deref()))
+            }
+            Xml::CharacterNode(ref text) | Xml::CDATANode(ref text) => builder.append(&text[..]),
+            Xml::CommentNode(ref text) => builder.append(minidom::Node::Comment(text.clone())),
+            Xml::PINode(_) => builder,
+        };
+    }
+    builder.build()
+}
+
+#[cfg(feature = "minidom")]
+/// Converts an `Element` to a `minidom::Element`. Processing instructions are dropped, as
+/// minidom does not support them.
+impl From<&Element> for minidom::Element {
+    fn from(elem: &Element) -> minidom::Element {
+        to_minidom(elem, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(any(feature = "minidom", feature = "roxmltree"))]
+    use crate::Element;
+
+    #[cfg(feature = "roxmltree")]
+    #[test]
+    fn test_roxmltree() {
+        let text = "<?pi data?><a xmlns='urn:a' xmlns:p='urn:p' p:x='1' xml:lang='en'>\
+                    t&amp;<![CDATA[<c>]]><p:b xmlns:q='urn:q' q:y='2'><!--c--><?pi?></p:b>\
+                    <c xmlns=''/></a>";
+        let doc = roxmltree::Document::parse(text).unwrap();
+        let elem = Element::from(&doc);
+        let expected: Element = "<a xmlns='urn:a' xmlns:p='urn:p' p:x='1' xml:lang='en'>t&amp;&lt;c&gt;\
+                                 <p:b xmlns:q='urn:q' q:y='2'><!--c--><?pi?></p:b><c xmlns=''/></a>"
+            .parse()
+            .unwrap();
+        assert_eq!(elem, expected);
+        let b = doc.descendants().find(|n| n.has_tag_name("b")).unwrap();
+        assert_eq!(
+            Element::try_from(b).unwrap().name.ns.as_deref(),
+            Some("urn:p")
+        );
+        assert!(Element::try_from(b.first_child().unwrap()).is_err());
+    }
+
+    #[cfg(feature = "minidom")]
+    #[test]
+    fn test_minidom() {
+        let elem: Element = "<a xmlns='urn:a' x='1' xmlns:p='urn:p' p:y='2'>t<![CDATA[<c>]]>\
+                             <b xmlns='urn:b'><!--c--><?pi?></b><c xmlns=''/></a>"
+            .parse()
+            .unwrap();
+        let converted = minidom::Element::from(&elem);
+        assert_eq!(converted.ns().as_deref(), Some("urn:a"));
+        assert_eq!(converted.attr("x"), Some("1"));
+        assert_eq!(converted.attr("ns0:y"), Some("2"));
+        assert_eq!(converted.text(), "t<c>");
+        let b = converted.get_child("b", "urn:b").unwrap();
+        assert_eq!(b.nodes().count(), 1);
+        assert!(converted.get_child("c", "").is_some());
+
+        let back = Element::from(&converted);
+        assert_eq!(back.name.ns.as_deref(), Some("urn:a"));
+        assert_eq!(back.get_attribute("ns0:y", None), Some("2"));
+        assert_eq!(
+            back.get_child("b", Some("urn:b")).unwrap().children.len(),
+            1
+        );
+        assert!(back.get_child("c", None).is_some());
+        assert_eq!(back.content_str(), "t<c>");
+    }
+}
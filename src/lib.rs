@@ -9,32 +9,123 @@
 
 #![crate_name = "xml"]
 #![crate_type = "lib"]
-#![forbid(non_camel_case_types)]
+#![cfg_attr(not(feature = "serde"), forbid(non_camel_case_types))]
+// The identifier enums generated by serde's Deserialize derive carry their own
+// `allow(non_camel_case_types)`, which no item-level attribute can reconcile with `forbid`
+#![cfg_attr(feature = "serde", deny(non_camel_case_types))]
 #![warn(missing_docs)]
 
 /*!
  * An XML parsing library
  */
 
+pub use crate::adapters::EventIteratorExt;
+pub use crate::adapters::MapText;
+pub use crate::adapters::UntilEndOf;
+pub use crate::cursor::Cursor;
 pub use crate::element::ChildElements;
+pub use crate::element::Children;
 pub use crate::element::Element;
 pub use crate::element_builder::BuilderError;
 pub use crate::element_builder::ElementBuilder;
+pub use crate::element_constructor::ElementConstructor;
+pub use crate::entities::EntityResolver;
+pub use crate::event_cursor::EventCursor;
+pub use crate::extract::extract_text;
+pub use crate::hash::FnvHasher;
+pub use crate::names::ContentError;
+pub use crate::names::NameError;
+pub use crate::parser::Checkpoint;
+pub use crate::parser::DuplicateAttributes;
 pub use crate::parser::Event;
 pub use crate::parser::Parser;
+pub use crate::parser::ParserConfig;
 pub use crate::parser::ParserError;
-
-use std::char;
-use std::fmt;
-
-#[cfg(feature = "ordered_attrs")]
+pub use crate::parser::Position;
+pub use crate::parser::PushParser;
+pub use crate::parser::UnknownEntities;
+pub use crate::patch::PatchError;
+pub use crate::patch::Patcher;
+pub use crate::path::ElementPath;
+pub use crate::pi::XmlDecl;
+pub use crate::redact::Redaction;
+pub use crate::redact::Redactor;
+pub use crate::slice::SliceAttribute;
+pub use crate::slice::SliceEndTag;
+pub use crate::slice::SliceEvent;
+pub use crate::slice::SliceParser;
+pub use crate::slice::SliceStartTag;
+pub use crate::snapshot::SnapshotError;
+pub use crate::split::split_records;
+pub use crate::tokenizer::Token;
+pub use crate::tokenizer::TokenError;
+pub use crate::tokenizer::Tokenizer;
+pub use crate::transform::Template;
+pub use crate::transform::Transformer;
+pub use crate::writer::EmptyElementStyle;
+pub use crate::writer::EventWriter;
+pub use crate::writer::LineEnding;
+pub use crate::writer::StreamError;
+pub use crate::writer::WriterConfig;
+
+#[cfg(feature = "fast_hash")]
+pub use crate::hash::AttrMap;
+#[cfg(all(feature = "ordered_attrs", not(feature = "fast_hash")))]
 pub use indexmap::IndexMap as AttrMap;
-#[cfg(not(feature = "ordered_attrs"))]
+use std::borrow::Borrow;
+use std::char;
+#[cfg(all(not(feature = "ordered_attrs"), not(feature = "fast_hash")))]
 pub use std::collections::HashMap as AttrMap;
-
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::Arc;
+
+#[macro_use]
+mod macros;
+
+#[cfg(feature = "binary")]
+pub mod binary;
+#[cfg(feature = "catalog")]
+pub mod catalog;
+#[cfg(feature = "codec")]
+pub mod codec;
+pub mod dom;
+#[cfg(feature = "feed")]
+pub mod feed;
+pub mod ns;
+pub mod pi;
+#[cfg(feature = "plist")]
+pub mod plist;
+#[cfg(feature = "regex")]
+pub mod search;
+pub mod xmlrs;
+#[cfg(feature = "xsi")]
+pub mod xsi;
+
+mod adapters;
+mod compact;
+mod cursor;
 mod element;
 mod element_builder;
+mod element_constructor;
+mod entities;
+mod event_cursor;
+mod extract;
+mod hash;
+#[cfg(any(feature = "minidom", feature = "roxmltree"))]
+mod interop;
+mod names;
 mod parser;
+mod patch;
+mod path;
+mod redact;
+mod slice;
+mod snapshot;
+mod split;
+mod tokenizer;
+mod transform;
+mod writer;
 
 // General functions
 
@@ -56,9 +147,25 @@ pub fn escape(input: &str) -> String {
     result
 }
 
+// Decodes a character reference given without its '&' and ';', such as "#x20". Only characters
+// allowed in XML documents may be referenced.
+pub(crate) fn char_ref(reference: &str) -> Option<char> {
+    let (digits, radix) = match reference.strip_prefix("#x") {
+        Some(hex) => (hex, 16),
+        None => (reference.strip_prefix('#')?, 10),
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    let c = char::from_u32(u32::from_str_radix(digits, radix).ok()?)?;
+    matches!(c,
+        '\t' | '\n' | '\r' | '\u{20}'..='\u{D7FF}' | '\u{E000}'..='\u{FFFD}' | '\u{10000}'..)
+    .then_some(c)
+}
+
 #[inline]
 /// Unescapes all valid XML entities in a string.
-/// Returns the first invalid entity on failure.
+/// Returns the first invalid entity or character reference on failure.
 pub fn unescape(input: &str) -> Result<String, String> {
     let mut result = String::with_capacity(input.len());
 
@@ -79,19 +186,10 @@ pub fn unescape(input: &str) -> Result<String, String> {
                     "gt" => result.push('>'),
                     "lt" => result.push('<'),
                     "amp" => result.push('&'),
-                    ent => {
-                        let val = if ent.starts_with("#x") {
-                            u32::from_str_radix(&ent[2..], 16).ok()
-                        } else if ent.starts_with('#') {
-                            u32::from_str_radix(&ent[1..], 10).ok()
-                        } else {
-                            None
-                        };
-                        match val.and_then(char::from_u32) {
-                            Some(c) => result.push(c),
-                            None => return Err(format!("&{};", ent)),
-                        }
-                    }
+                    ent => match char_ref(ent) {
+                        Some(c) => result.push(c),
+                        None => return Err(format!("&{};", ent)),
+                    },
                 }
                 result.push_str(&sub[idx + 1..]);
             }
@@ -117,30 +215,316 @@ pub enum Xml {
     PINode(String),
 }
 
-#[derive(PartialEq, Eq, Debug)]
+impl From<Element> for Xml {
+    fn from(elem: Element) -> Xml {
+        Xml::ElementNode(elem)
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Structure describing an opening tag
 pub struct StartTag {
-    /// The tag's name
-    pub name: String,
-    /// The tag's namespace
-    pub ns: Option<String>,
+    /// The tag's name, qualified by its namespace
+    pub name: QName,
     /// The tag's prefix
     pub prefix: Option<String>,
     /// The tag's attributes
-    pub attributes: AttrMap<(String, Option<String>), String>,
+    #[cfg_attr(feature = "serde", serde(with = "attributes_serde"))]
+    pub attributes: AttrMap<QName, String>,
+}
+
+impl StartTag {
+    /// Gets an attribute with the specified name and namespace. When an attribute with the
+    /// specified name does not exist `None` is returned.
+    pub fn get_attribute<'a>(&'a self, name: &str, ns: Option<&str>) -> Option<&'a str> {
+        self.attributes
+            .get(&(name, ns) as &dyn QNameKey)
+            .map(|x| &x[..])
+    }
 }
 
-#[derive(PartialEq, Eq, Debug)]
+// Attributes are (de)serialized as a sequence of `(name, namespace, value)`,
+// as formats like JSON only support strings as map keys
+#[cfg(feature = "serde")]
+mod attributes_serde {
+    use crate::{AttrMap, QName};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    type Attributes = AttrMap<QName, String>;
+
+    pub fn serialize<S>(attributes: &Attributes, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(
+            attributes
+                .iter()
+                .map(|(QName { name, ns }, value)| (name, ns, value)),
+        )
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Attributes, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let attributes = Vec::<(String, Option<String>, String)>::deserialize(deserializer)?;
+        Ok(attributes
+            .into_iter()
+            .map(|(name, ns, value)| (QName::new(name, ns), value))
+            .collect())
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Structure describing a closing tag
 pub struct EndTag {
-    /// The tag's name
-    pub name: String,
-    /// The tag's namespace
-    pub ns: Option<String>,
+    /// The tag's name, qualified by its namespace
+    pub name: QName,
     /// The tag's prefix
     pub prefix: Option<String>,
 }
 
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Structure describing part of a long attribute value of the preceding start tag,
+/// see `ParserConfig::attribute_chunk_size`
+pub struct AttributeChunk {
+    /// The attribute's name
+    pub name: String,
+    /// The attribute's namespace
+    pub ns: Option<String>,
+    /// The unescaped part of the value
+    pub value: String,
+    /// Whether this is the final part of the value
+    pub last: bool,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Structure describing a document type declaration
+///
+/// It is displayed as a complete declaration, which can be passed to
+/// `WriterConfig::doctype()`.
+pub struct Doctype {
+    /// The name of the root element
+    pub name: String,
+    /// The public identifier of the external DTD subset
+    pub public_id: Option<String>,
+    /// The system identifier of the external DTD subset
+    pub system_id: Option<String>,
+    /// The internal subset as found in the document, without the enclosing brackets
+    pub internal_subset: Option<String>,
+}
+
+impl fmt::Display for Doctype {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Literals may contain either kind of quote, but not both
+        let quoted = |literal: &str| match literal.contains('"') {
+            true => format!("'{}'", literal),
+            false => format!("\"{}\"", literal),
+        };
+        write!(f, "<!DOCTYPE {}", self.name)?;
+        match (&self.public_id, &self.system_id) {
+            (Some(public_id), Some(system_id)) => {
+                write!(f, " PUBLIC {} {}", quoted(public_id), quoted(system_id))?
+            }
+            (None, Some(system_id)) => write!(f, " SYSTEM {}", quoted(system_id))?,
+            _ => (),
+        }
+        if let Some(ref subset) = self.internal_subset {
+            write!(f, " [{}]", subset)?;
+        }
+        f.write_str(">")
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A name qualified by its namespace
+///
+/// The prefix used in the document is not part of a `QName`, so names are equal whenever
+/// their local name and namespace are. `QName`s are displayed in Clark notation, `{ns}name`.
+///
+/// Tags, elements and attribute maps name elements and attributes by `QName`.
+pub struct QName {
+    /// The local name
+    pub name: Name,
+    /// The namespace
+    pub ns: Option<String>,
+}
+
+impl QName {
+    /// Returns a new `QName`
+    pub fn new(name: String, ns: Option<String>) -> QName {
+        QName {
+            name: name.into(),
+            ns,
+        }
+    }
+}
+
+// A local name and namespace, for looking up `QName` keys with borrowed strings
+pub(crate) trait QNameKey {
+    fn name(&self) -> &str;
+    fn ns(&self) -> Option<&str>;
+}
+
+impl QNameKey for QName {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn ns(&self) -> Option<&str> {
+        self.ns.as_deref()
+    }
+}
+
+impl QNameKey for (&str, Option<&str>) {
+    fn name(&self) -> &str {
+        self.0
+    }
+
+    fn ns(&self) -> Option<&str> {
+        self.1
+    }
+}
+
+impl<'a> Borrow<dyn QNameKey + 'a> for QName {
+    fn borrow(&self) -> &(dyn QNameKey + 'a) {
+        self
+    }
+}
+
+impl PartialEq for dyn QNameKey + '_ {
+    fn eq(&self, other: &Self) -> bool {
+        self.name() == other.name() && self.ns() == other.ns()
+    }
+}
+
+impl Eq for dyn QNameKey + '_ {}
+
+// Hashing has to agree with the borrowed form
+impl Hash for dyn QNameKey + '_ {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name().hash(state);
+        self.ns().hash(state);
+    }
+}
+
+impl Hash for QName {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self as &dyn QNameKey).hash(state)
+    }
+}
+
+impl fmt::Display for QName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.ns {
+            Some(ref ns) => write!(f, "{{{}}}{}", ns, self.name),
+            None => self.name.fmt(f),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Default)]
+/// The name of an element or attribute
+///
+/// A `Name` dereferences to `str` and compares equal to strings. Its storage is reference
+/// counted, and with the `interning` feature a parser shares it between all events naming
+/// the same element or attribute.
+pub struct Name(Arc<str>);
+
+impl Name {
+    /// Returns the name as a string slice
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for Name {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Name {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for Name {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Name {
+    fn from(name: String) -> Name {
+        Name(name.into())
+    }
+}
+
+impl From<&str> for Name {
+    fn from(name: &str) -> Name {
+        Name(name.into())
+    }
+}
+
+impl From<Name> for String {
+    fn from(name: Name) -> String {
+        name.0.to_string()
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Debug for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+macro_rules! impl_name_eq {
+    ($($other:ty),*) => {$(
+        impl PartialEq<$other> for Name {
+            fn eq(&self, other: &$other) -> bool {
+                self[..] == other[..]
+            }
+        }
+
+        impl PartialEq<Name> for $other {
+            fn eq(&self, other: &Name) -> bool {
+                self[..] == other[..]
+            }
+        }
+    )*};
+}
+
+impl_name_eq!(str, &str, String);
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Name {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Name {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Name, D::Error> {
+        String::deserialize(deserializer).map(Name::from)
+    }
+}
+
 impl fmt::Display for Xml {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -155,7 +539,7 @@ impl fmt::Display for Xml {
 
 #[cfg(test)]
 mod lib_tests {
-    use super::{escape, unescape, Element, Xml};
+    use super::{escape, unescape, Doctype, Element, Event, Name, Parser, QName, Xml};
 
     #[test]
     fn test_escape() {
@@ -178,6 +562,32 @@ mod lib_tests {
         assert_eq!(unesc.as_ref().map_err(|x| &x[..]), Err("&nbsp;"));
     }
 
+    #[test]
+    fn test_unescape_char_refs() {
+        assert_eq!(
+            unescape("&#9;&#x10FFFF;&#xE000;&#0065;").as_deref(),
+            Ok("\t\u{10FFFF}\u{E000}A")
+        );
+        for reference in [
+            "&#x0;",
+            "&#0;",
+            "&#x1F;",
+            "&#xD800;",
+            "&#57343;",
+            "&#xFFFE;",
+            "&#x110000;",
+            "&#99999999999;",
+            "&#x;",
+            "&#;",
+            "&#+65;",
+            "&#x+41;",
+            "&#X41;",
+            "&#x4G;",
+        ] {
+            assert_eq!(unescape(reference), Err(reference.to_owned()));
+        }
+    }
+
     #[test]
     fn test_show_element() {
         let elem = Element::new("a".to_owned(), None, vec![]);
@@ -230,6 +640,28 @@ mod lib_tests {
         );
     }
 
+    #[test]
+    fn test_show_qname() {
+        let name = QName::new("a".to_owned(), Some("urn:test".to_owned()));
+        assert_eq!(name.to_string(), "{urn:test}a");
+        assert_eq!(QName::new("a".to_owned(), None).to_string(), "a");
+
+        let elem: Element = "<a xmlns='urn:test' x='1'/>".parse().unwrap();
+        assert_eq!(elem.name, name);
+        assert_eq!(elem.attributes[&QName::new("x".to_owned(), None)], "1");
+    }
+
+    #[test]
+    fn test_name() {
+        let name = Name::from("a");
+        assert_eq!(name, "a");
+        assert_eq!("a", name);
+        assert_eq!(name, "a".to_owned());
+        assert_eq!(name.len(), 1);
+        assert_eq!(format!("{} {:?}", name, name), "a \"a\"");
+        assert_eq!(String::from(name), "a");
+    }
+
     #[test]
     fn test_show_characters() {
         let chars = Xml::CharacterNode("some text".to_owned());
@@ -254,6 +686,27 @@ mod lib_tests {
         assert_eq!(format!("{}", chars), "<?xml version='1.0'?>");
     }
 
+    #[test]
+    fn test_show_doctype() {
+        let mut doctype = Doctype {
+            name: "html".to_owned(),
+            public_id: None,
+            system_id: None,
+            internal_subset: None,
+        };
+        assert_eq!(doctype.to_string(), "<!DOCTYPE html>");
+        doctype.public_id = Some("-//W3C//DTD XHTML 1.0 Strict//EN".to_owned());
+        doctype.system_id = Some("say \"x\"".to_owned());
+        doctype.internal_subset = Some("<!ENTITY e 'x'>".to_owned());
+        assert_eq!(
+            doctype.to_string(),
+            "<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Strict//EN\" 'say \"x\"' \
+             [<!ENTITY e 'x'>]>"
+        );
+        let parsed: Vec<_> = Parser::new(doctype.to_string().as_bytes()).collect();
+        assert_eq!(parsed, [Ok(Event::Doctype(doctype))]);
+    }
+
     #[test]
     fn test_content_str() {
         let mut elem = Element::new("a".to_owned(), None, vec![]);
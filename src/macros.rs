@@ -0,0 +1,112 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/// Builds an `Element` tree from a declarative description
+///
+/// An element is written as its name, optionally followed by its namespace in brackets and
+/// its attributes in parentheses, followed by its content in braces.
+/// Attribute names are identifiers or string literals, attribute values any expression
+/// implementing `ToString`. The content consists of child elements, string literals and
+/// parenthesized expressions, which are added as text.
+/// Child elements without an explicit namespace inherit the namespace of their parent.
+///
+/// ~~~
+/// use xml::xml;
+///
+/// let id = 42;
+/// let title = "Hello & welcome";
+/// let elem = xml!(item ["urn:example"] (id = id, "xml:lang" = "en") {
+///     title { (title) }
+///     summary { "Short " "text" }
+///     empty {}
+/// });
+/// assert_eq!(elem.get_attribute("id", None), Some("42"));
+/// assert_eq!(
+///     elem.get_child("title", Some("urn:example")).unwrap().content_str(),
+///     "Hello & welcome",
+/// );
+/// ~~~
+#[macro_export]
+macro_rules! xml {
+    (@attr_name $key:ident) => {
+        stringify!($key)
+    };
+    (@attr_name $key:literal) => {
+        $key
+    };
+    (@attrs $($key:tt = $value:expr),* $(,)?) => {
+        vec![$((
+            ::std::string::ToString::to_string($crate::xml!(@attr_name $key)),
+            None,
+            ::std::string::ToString::to_string(&$value),
+        )),*]
+    };
+    (@element $ns:expr; $name:ident $(($($attrs:tt)*))? { $($body:tt)* }) => {{
+        let ns: Option<String> = $ns;
+        let attrs: Vec<(String, Option<String>, String)> = $crate::xml!(@attrs $($($attrs)*)?);
+        #[allow(unused_mut)]
+        let mut elem = $crate::Element::new(stringify!($name).to_owned(), ns.clone(), attrs);
+        $crate::xml!(@children elem; ns; $($body)*);
+        elem
+    }};
+    (@children $elem:ident; $ns:ident;) => {};
+    (@children $elem:ident; $ns:ident; $text:literal $($rest:tt)*) => {
+        $elem.text(::std::string::ToString::to_string(&$text));
+        $crate::xml!(@children $elem; $ns; $($rest)*);
+    };
+    (@children $elem:ident; $ns:ident; ($text:expr) $($rest:tt)*) => {
+        $elem.text(::std::string::ToString::to_string(&$text));
+        $crate::xml!(@children $elem; $ns; $($rest)*);
+    };
+    (@children $elem:ident; $ns:ident;
+     $name:ident [$child_ns:expr] $(($($attrs:tt)*))? { $($body:tt)* } $($rest:tt)*) => {
+        $elem.tag_stay($crate::xml!(
+            @element Some(::std::string::ToString::to_string(&$child_ns));
+            $name $(($($attrs)*))? { $($body)* }
+        ));
+        $crate::xml!(@children $elem; $ns; $($rest)*);
+    };
+    (@children $elem:ident; $ns:ident;
+     $name:ident $(($($attrs:tt)*))? { $($body:tt)* } $($rest:tt)*) => {
+        $elem.tag_stay($crate::xml!(
+            @element $ns.clone();
+            $name $(($($attrs)*))? { $($body)* }
+        ));
+        $crate::xml!(@children $elem; $ns; $($rest)*);
+    };
+    ($name:ident [$ns:expr] $(($($attrs:tt)*))? { $($body:tt)* }) => {
+        $crate::xml!(
+            @element Some(::std::string::ToString::to_string(&$ns));
+            $name $(($($attrs)*))? { $($body)* }
+        )
+    };
+    ($name:ident $(($($attrs:tt)*))? { $($body:tt)* }) => {
+        $crate::xml!(@element None; $name $(($($attrs)*))? { $($body)* })
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Element;
+
+    #[test]
+    fn test_xml_macro() {
+        let count = 3;
+        let elem = xml!(list (count = count) {
+            item (n = 1) { "one" }
+            item { (count * 2) }
+            other ["urn:other"] { nested {} }
+        });
+        let expected: Element = "<list count='3'><item n='1'>one</item><item>6</item>\
+                                 <other xmlns='urn:other'><nested/></other></list>"
+            .parse()
+            .unwrap();
+        assert_eq!(elem.to_string(), expected.to_string());
+    }
+}
@@ -0,0 +1,135 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::ns;
+use std::error::Error;
+use std::fmt;
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+/// Errors returned when validating names, see `Element::try_new()`
+pub enum NameError {
+    /// A name is not a valid name without colon, or is reserved
+    InvalidName(String),
+    /// A namespace is empty, contains whitespace or is reserved
+    InvalidNamespace(String),
+}
+
+impl Error for NameError {}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NameError::InvalidName(ref name) => write!(f, "Invalid name '{}'", name),
+            NameError::InvalidNamespace(ref ns) => write!(f, "Invalid namespace '{}'", ns),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+/// Errors returned when validating comments and processing instructions, see
+/// `Element::try_comment()` and `Element::try_pi()`
+pub enum ContentError {
+    /// A comment contains "--" or ends with '-'
+    InvalidComment(String),
+    /// A processing instruction contains "?>", or its target is not a name or is reserved
+    InvalidPI(String),
+}
+
+impl Error for ContentError {}
+
+impl fmt::Display for ContentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ContentError::InvalidComment(ref text) => write!(f, "Invalid comment '{}'", text),
+            ContentError::InvalidPI(ref text) => {
+                write!(f, "Invalid processing instruction '{}'", text)
+            }
+        }
+    }
+}
+
+// NameStartChar of XML 1.0, excluding ':'
+fn is_name_start_char(c: char) -> bool {
+    matches!(c,
+        'A'..='Z' | '_' | 'a'..='z' | '\u{C0}'..='\u{D6}' | '\u{D8}'..='\u{F6}'
+        | '\u{F8}'..='\u{2FF}' | '\u{370}'..='\u{37D}' | '\u{37F}'..='\u{1FFF}'
+        | '\u{200C}'..='\u{200D}' | '\u{2070}'..='\u{218F}' | '\u{2C00}'..='\u{2FEF}'
+        | '\u{3001}'..='\u{D7FF}' | '\u{F900}'..='\u{FDCF}' | '\u{FDF0}'..='\u{FFFD}'
+        | '\u{10000}'..='\u{EFFFF}')
+}
+
+// NameChar of XML 1.0, excluding ':'
+fn is_name_char(c: char) -> bool {
+    is_name_start_char(c)
+        || matches!(c,
+            '-' | '.' | '0'..='9' | '\u{B7}' | '\u{300}'..='\u{36F}' | '\u{203F}'..='\u{2040}')
+}
+
+// The whitespace of XML 1.0, which unlike `char::is_whitespace` excludes e.g. U+00A0
+pub(crate) fn is_space(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\r' | '\n')
+}
+
+// Checks for a name without colon, as used for local names and prefixes
+pub(crate) fn is_ncname(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(is_name_start_char) && chars.all(is_name_char)
+}
+
+// Names starting with "xml" in any case are reserved by the XML specification
+fn is_reserved(name: &str) -> bool {
+    name.get(..3).is_some_and(|x| x.eq_ignore_ascii_case("xml"))
+}
+
+pub(crate) fn check_comment(text: &str) -> Result<(), ContentError> {
+    if text.contains("--") || text.ends_with('-') {
+        return Err(ContentError::InvalidComment(text.to_owned()));
+    }
+    Ok(())
+}
+
+// The target of a processing instruction is a name without colons, other than "xml"
+pub(crate) fn check_pi(text: &str) -> Result<(), ContentError> {
+    let target = text.split(is_space).next().unwrap_or("");
+    let valid_target = is_ncname(target) && !target.eq_ignore_ascii_case("xml");
+    if !valid_target || text.contains("?>") {
+        return Err(ContentError::InvalidPI(text.to_owned()));
+    }
+    Ok(())
+}
+
+pub(crate) fn check_namespace(ns: &str) -> Result<(), NameError> {
+    if ns.is_empty() || ns.chars().any(char::is_whitespace) || ns == ns::XMLNS {
+        return Err(NameError::InvalidNamespace(ns.to_owned()));
+    }
+    Ok(())
+}
+
+pub(crate) fn check_element(name: &str, ns: Option<&str>) -> Result<(), NameError> {
+    if !is_ncname(name) || is_reserved(name) {
+        return Err(NameError::InvalidName(name.to_owned()));
+    }
+    ns.map_or(Ok(()), check_namespace)
+}
+
+// Reserved names are allowed for namespace declarations and in the XML namespace
+pub(crate) fn check_attribute(name: &str, ns: Option<&str>) -> Result<(), NameError> {
+    let reserved_allowed = match ns {
+        None => name == "xmlns",
+        Some(ns) => ns == ns::XMLNS || ns == ns::XML,
+    };
+    if !is_ncname(name) || (is_reserved(name) && !reserved_allowed) {
+        return Err(NameError::InvalidName(name.to_owned()));
+    }
+    match ns {
+        Some(ns) if ns == ns::XMLNS || ns == ns::XML => Ok(()),
+        Some(ns) => check_namespace(ns),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,83 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Well-known namespace URIs and helpers for working with them
+
+/// The namespace bound to the `xml` prefix
+pub const XML: &str = "http://www.w3.org/XML/1998/namespace";
+/// The namespace bound to the `xmlns` prefix, used for namespace declarations
+pub const XMLNS: &str = "http://www.w3.org/2000/xmlns/";
+/// XHTML
+pub const XHTML: &str = "http://www.w3.org/1999/xhtml";
+/// Scalable Vector Graphics
+pub const SVG: &str = "http://www.w3.org/2000/svg";
+/// XLink, as used by SVG
+pub const XLINK: &str = "http://www.w3.org/1999/xlink";
+/// SOAP 1.1 envelopes
+pub const SOAP11: &str = "http://schemas.xmlsoap.org/soap/envelope/";
+/// SOAP 1.2 envelopes
+pub const SOAP12: &str = "http://www.w3.org/2003/05/soap-envelope";
+/// XML Schema instance attributes such as `xsi:type`
+pub const XSI: &str = "http://www.w3.org/2001/XMLSchema-instance";
+/// XML Schema definitions and built-in types
+pub const XSD: &str = "http://www.w3.org/2001/XMLSchema";
+/// The Atom syndication format
+pub const ATOM: &str = "http://www.w3.org/2005/Atom";
+/// OASIS XML Catalogs
+pub const CATALOG: &str = "urn:oasis:names:tc:entity:xmlns:xml:catalog";
+
+/// Returns whether `ns` is the `xml` namespace
+pub fn is_xml(ns: Option<&str>) -> bool {
+    ns == Some(XML)
+}
+
+/// Returns whether `ns` is the namespace used for namespace declarations
+pub fn is_xmlns(ns: Option<&str>) -> bool {
+    ns == Some(XMLNS)
+}
+
+/// Returns whether `ns` is the XML Schema instance namespace
+pub fn is_xsi(ns: Option<&str>) -> bool {
+    ns == Some(XSI)
+}
+
+/// Returns the prefix conventionally used for one of the namespaces above
+pub fn conventional_prefix(ns: &str) -> Option<&'static str> {
+    match ns {
+        XML => Some("xml"),
+        XMLNS => Some("xmlns"),
+        XHTML => Some("xhtml"),
+        SVG => Some("svg"),
+        XLINK => Some("xlink"),
+        SOAP11 | SOAP12 => Some("soap"),
+        XSI => Some("xsi"),
+        XSD => Some("xs"),
+        ATOM => Some("atom"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{conventional_prefix, is_xsi, XSI};
+    use crate::{Element, QName};
+
+    #[test]
+    fn test_is_xsi() {
+        let elem: Element =
+            "<a xmlns:xsi='http://www.w3.org/2001/XMLSchema-instance' xsi:nil='true'/>"
+                .parse()
+                .unwrap();
+        assert!(elem
+            .attributes
+            .keys()
+            .any(|QName { name, ns }| name == "nil" && is_xsi(ns.as_deref())));
+        assert_eq!(conventional_prefix(XSI), Some("xsi"));
+    }
+}
@@ -11,15 +11,35 @@
 // ObjFW, Copyright (c) 2008-2013 Jonathan Schleifer.
 // Permission to license this derived work under MIT license has been granted by ObjFW's author.
 
-use crate::{unescape, AttrMap, EndTag, StartTag};
-use std::collections::HashMap;
+use crate::entities::{self, DoctypeScanner, Entity, EntityResolver};
+use crate::names;
+use crate::ns;
+use crate::{
+    char_ref, unescape, AttrMap, AttributeChunk, BuilderError, Doctype, Element, ElementBuilder,
+    EndTag, Name, QName, StartTag, XmlDecl,
+};
+#[cfg(feature = "interning")]
+use std::collections::HashSet;
+use std::collections::{HashMap, VecDeque};
 use std::error::Error;
 use std::fmt;
-use std::io::Read;
+use std::io::{self, Read};
 use std::iter::Iterator;
 use std::mem;
+use std::ops::Range;
+use std::str;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
-#[derive(PartialEq, Eq, Debug)]
+// The number of distinct names a parser keeps for sharing
+#[cfg(feature = "interning")]
+const MAX_INTERNED_NAMES: usize = 4096;
+
+// The length of the longest reference kept whole when character data is split into chunks
+const MAX_CHUNKED_REF_LEN: usize = 1024;
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Events returned by the `Parser`
 pub enum Event {
     /// Event indicating processing information was found
@@ -34,16 +54,25 @@ pub enum Event {
     CDATA(String),
     /// Event indicating a comment was found
     Comment(String),
+    /// Event indicating part of a long attribute value was found
+    AttributeChunk(AttributeChunk),
+    /// Event indicating the XML declaration was found at the start of the document
+    XmlDecl(XmlDecl),
+    /// Event indicating a document type declaration was found
+    Doctype(Doctype),
 }
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(missing_copy_implementations)]
 /// The structure returned, when erroneous XML is read
 pub struct ParserError {
     /// The line number at which the error occurred
-    pub line: u32,
+    pub line: u64,
     /// The column number at which the error occurred
-    pub col: u32,
+    pub col: u64,
+    /// The byte offset at which the error occurred, starting at 0
+    pub offset: u64,
     /// The kind of error encountered
     pub kind: ParserErrorKind,
 }
@@ -60,7 +89,20 @@ impl fmt::Display for ParserError {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A position in the input of a `Parser`
+pub struct Position {
+    /// The line number, starting at 1
+    pub line: u64,
+    /// The column number of the last character read on the line, 0 at the start of a line
+    pub col: u64,
+    /// The number of bytes read
+    pub offset: u64,
+}
+
 #[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[non_exhaustive]
 pub enum ParserErrorKind {
     UnboundNsPrefixInTagName,
@@ -76,6 +118,25 @@ pub enum ParserErrorKind {
     ExpectedTagClose,
     ExpectedLwsOrTagClose,
     MalformedXml,
+    NotAtElementStart,
+    UnexpectedEof,
+    Cancelled,
+    ExternalEntity,
+    TagMismatch,
+    InvalidUtf8,
+    NeedMoreData,
+    MultipleRoots,
+    MissingRoot,
+    TextOutsideRoot,
+    MisplacedDoctype,
+    InvalidName,
+    EntityLimitExceeded,
+    InvalidCharRef,
+    LimitExceeded,
+    InvalidUtf16,
+    InvalidXmlDecl,
+    MisplacedXmlDecl,
+    UnexpectedElement,
 }
 
 impl fmt::Display for ParserErrorKind {
@@ -98,12 +159,451 @@ impl fmt::Display for ParserErrorKind {
             ParserErrorKind::ExpectedTagClose => "Expected '>' to close tag",
             ParserErrorKind::ExpectedLwsOrTagClose => "Expected '>' to close tag, or LWS",
             ParserErrorKind::MalformedXml => "Malformed XML",
+            ParserErrorKind::NotAtElementStart => "Last event was not an element start",
+            ParserErrorKind::UnexpectedEof => "Unexpected end of input",
+            ParserErrorKind::Cancelled => "Parsing was cancelled",
+            ParserErrorKind::ExternalEntity => "External entity could not be resolved",
+            ParserErrorKind::TagMismatch => "End tag does not match the open element",
+            ParserErrorKind::InvalidUtf8 => "Invalid UTF-8 sequence",
+            ParserErrorKind::NeedMoreData => "More input is needed",
+            ParserErrorKind::MultipleRoots => "More than one root element",
+            ParserErrorKind::MissingRoot => "No root element",
+            ParserErrorKind::TextOutsideRoot => "Character data outside the root element",
+            ParserErrorKind::MisplacedDoctype => "DOCTYPE not before the root element",
+            ParserErrorKind::InvalidName => "Invalid name",
+            ParserErrorKind::EntityLimitExceeded => "Entity expansion limit exceeded",
+            ParserErrorKind::InvalidCharRef => "Invalid character reference",
+            ParserErrorKind::LimitExceeded => "Size limit exceeded",
+            ParserErrorKind::InvalidUtf16 => "Invalid UTF-16 sequence",
+            ParserErrorKind::InvalidXmlDecl => "Invalid XML declaration",
+            ParserErrorKind::MisplacedXmlDecl => "XML declaration not at the start of the document",
+            ParserErrorKind::UnexpectedElement => "Element where only text was expected",
         };
         msg.fmt(f)
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// How a `Parser` handles an attribute given more than once in a start tag
+pub enum DuplicateAttributes {
+    /// Fail with a `DuplicateAttribute` error, as required by the XML specification
+    #[default]
+    Error,
+    /// Keep the first value and report a warning
+    KeepFirst,
+    /// Keep the last value and report a warning
+    KeepLast,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// How a `Parser` handles references to entities that are neither predefined nor declared
+pub enum UnknownEntities {
+    /// Fail with an `InvalidEntity` error, as required by the XML specification
+    #[default]
+    Error,
+    /// Keep the reference as literal text and report a warning
+    Keep,
+    /// Replace the reference with U+FFFD and report a warning
+    Replace,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Options controlling the behaviour of a `Parser`
+pub struct ParserConfig {
+    /// Record the verbatim source of every event, available from `Parser::source()`.
+    /// Lossless round trips are only possible at the event level, by copying the source of
+    /// unmodified events with `EventWriter::write_source()`. `Element` does not keep the
+    /// source, so a tree is serialized in normalized form.
+    pub fidelity: bool,
+    /// Deliver attribute values longer than this many bytes as `AttributeChunk` events
+    /// instead of buffering them in the start tag
+    pub attribute_chunk_size: Option<usize>,
+    /// How attributes given more than once in a start tag are handled
+    pub duplicate_attributes: DuplicateAttributes,
+    /// How references to unknown entities are handled
+    pub unknown_entities: UnknownEntities,
+    /// The number of bytes requested from the reader at once, 8 KiB by default
+    pub read_buffer_size: usize,
+    /// Report a reader returning `WouldBlock` or `Interrupted` as a `NeedMoreData` error,
+    /// after which parsing can be resumed, instead of failing
+    pub nonblocking: bool,
+    /// Reject documents without exactly one root element, with character data outside of it,
+    /// or with a DOCTYPE that does not come first
+    pub strict: bool,
+    /// Check that element names, attribute names and processing instruction targets are
+    /// valid names, enabled by default
+    pub check_names: bool,
+    /// The total length of entity replacement text expanded in a document, 1 MiB by default
+    pub max_entity_expansion: usize,
+    /// How deeply references to declared entities may be nested, 16 by default
+    pub max_entity_depth: usize,
+    /// The number of attributes a start tag may have, including namespace declarations
+    pub max_attributes: Option<usize>,
+    /// The length in bytes of an attribute value, before references are expanded
+    pub max_attribute_length: Option<usize>,
+    /// The length in bytes of character data or a CDATA section, before references are
+    /// expanded
+    pub max_text_length: Option<usize>,
+    /// The length in bytes of a comment
+    pub max_comment_length: Option<usize>,
+    /// Split character data and CDATA sections into consecutive events of about this many
+    /// bytes, before references are expanded, instead of buffering them whole
+    pub max_text_chunk: Option<usize>,
+    /// Merge character data and CDATA sections following each other into a single
+    /// `Characters` event
+    pub coalesce_text: bool,
+    /// Remove leading and trailing whitespace from `Characters` events, dropping those left
+    /// empty
+    pub trim_text: bool,
+    /// Tolerate HTML-like markup instead of failing, see `ParserConfig::lenient`
+    pub lenient: bool,
+    /// Continue parsing after recoverable errors, see `ParserConfig::recover`
+    pub recover: bool,
+    /// End the events at the end of the root element, leaving the input following it for the
+    /// next document, see `Parser::reset()`
+    pub stop_after_root: bool,
+}
+
+impl Default for ParserConfig {
+    fn default() -> ParserConfig {
+        ParserConfig {
+            fidelity: false,
+            attribute_chunk_size: None,
+            duplicate_attributes: DuplicateAttributes::default(),
+            unknown_entities: UnknownEntities::default(),
+            read_buffer_size: 8192,
+            nonblocking: false,
+            strict: false,
+            check_names: true,
+            max_entity_expansion: 1 << 20,
+            max_entity_depth: 16,
+            max_attributes: None,
+            max_attribute_length: None,
+            max_text_length: None,
+            max_comment_length: None,
+            max_text_chunk: None,
+            coalesce_text: false,
+            trim_text: false,
+            lenient: false,
+            recover: false,
+            stop_after_root: false,
+        }
+    }
+}
+
+impl ParserConfig {
+    /// Returns the default configuration
+    pub fn new() -> ParserConfig {
+        ParserConfig::default()
+    }
+
+    /// Sets the `fidelity` option
+    pub fn fidelity(mut self, fidelity: bool) -> ParserConfig {
+        self.fidelity = fidelity;
+        self
+    }
+
+    /// Sets the `attribute_chunk_size` option
+    ///
+    /// Once a value exceeds `size` bytes, the start tag is reported with the attributes read
+    /// so far. The value then follows in `AttributeChunk` events of about `size` bytes,
+    /// as do all later attributes of the tag, each ending with a chunk marked `last`.
+    /// Namespace declarations are never split, but those following a long value do not
+    /// apply to the tag itself. Duplicate attributes are handled as `duplicate_attributes`
+    /// says, except that the chunks of a value already reported cannot be taken back:
+    /// with `DuplicateAttributes::KeepLast`, both values are reported.
+    ///
+    /// ~~~
+    /// use xml::{Event, Parser, ParserConfig};
+    ///
+    /// let input = "<blob id='1' data='0123456789' kind='raw'/>";
+    /// let config = ParserConfig::new().attribute_chunk_size(4);
+    /// let mut data = String::new();
+    /// for event in Parser::new_with_config(input.as_bytes(), config) {
+    ///     match event.unwrap() {
+    ///         Event::ElementStart(tag) => assert_eq!(tag.attributes.len(), 1),
+    ///         Event::AttributeChunk(chunk) if chunk.name == "data" => data.push_str(&chunk.value),
+    ///         _ => (),
+    ///     }
+    /// }
+    /// assert_eq!(data, "0123456789");
+    /// ~~~
+    pub fn attribute_chunk_size(mut self, size: usize) -> ParserConfig {
+        self.attribute_chunk_size = Some(size);
+        self
+    }
+
+    /// Sets the `duplicate_attributes` option
+    ///
+    /// ~~~
+    /// use xml::{DuplicateAttributes, Event, Parser, ParserConfig};
+    ///
+    /// let config = ParserConfig::new().duplicate_attributes(DuplicateAttributes::KeepLast);
+    /// let mut p = Parser::new_with_config("<a x='1' x='2'/>".as_bytes(), config);
+    /// match p.next() {
+    ///     Some(Ok(Event::ElementStart(tag))) => assert_eq!(tag.get_attribute("x", None), Some("2")),
+    ///     _ => panic!(),
+    /// }
+    /// assert_eq!(p.warnings().len(), 1);
+    /// ~~~
+    pub fn duplicate_attributes(mut self, policy: DuplicateAttributes) -> ParserConfig {
+        self.duplicate_attributes = policy;
+        self
+    }
+
+    /// Sets the `unknown_entities` option
+    ///
+    /// ~~~
+    /// use xml::{Event, Parser, ParserConfig, UnknownEntities};
+    ///
+    /// let config = ParserConfig::new().unknown_entities(UnknownEntities::Keep);
+    /// let mut p = Parser::new_with_config("<a>1&nbsp;&amp;&nbsp;2</a>".as_bytes(), config);
+    /// p.next();
+    /// match p.next() {
+    ///     Some(Ok(Event::Characters(text))) => assert_eq!(text, "1&nbsp;&&nbsp;2"),
+    ///     _ => panic!(),
+    /// }
+    /// assert_eq!(p.warnings().len(), 2);
+    /// ~~~
+    pub fn unknown_entities(mut self, policy: UnknownEntities) -> ParserConfig {
+        self.unknown_entities = policy;
+        self
+    }
+
+    /// Sets the `read_buffer_size` option. The parser may read up to `size` bytes past the
+    /// last event returned; a size of 0 is treated as 1.
+    pub fn read_buffer_size(mut self, size: usize) -> ParserConfig {
+        self.read_buffer_size = size;
+        self
+    }
+
+    /// Sets the `nonblocking` option
+    ///
+    /// A `NeedMoreData` error does not end parsing: once the reader has more data, the next
+    /// call to `next()` continues where parsing stopped.
+    pub fn nonblocking(mut self, nonblocking: bool) -> ParserConfig {
+        self.nonblocking = nonblocking;
+        self
+    }
+
+    /// Sets the `strict` option
+    ///
+    /// ~~~
+    /// use xml::{Parser, ParserConfig};
+    ///
+    /// let config = ParserConfig::new().strict(true);
+    /// let events: Result<Vec<_>, _> = Parser::new_with_config("<a/><b/>".as_bytes(), config).collect();
+    /// assert!(events.is_err());
+    /// ~~~
+    pub fn strict(mut self, strict: bool) -> ParserConfig {
+        self.strict = strict;
+        self
+    }
+
+    /// Sets the `check_names` option. Element and attribute names must consist of a local
+    /// name and an optional prefix, each of which is a name without colons, and processing
+    /// instruction targets must be names without colons.
+    pub fn check_names(mut self, check_names: bool) -> ParserConfig {
+        self.check_names = check_names;
+        self
+    }
+
+    /// Sets the `max_entity_expansion` option
+    ///
+    /// The limits on entity expansion guard against documents growing exponentially through
+    /// nested entities. Exceeding one fails with an `EntityLimitExceeded` error.
+    ///
+    /// ~~~
+    /// use xml::{Parser, ParserConfig};
+    ///
+    /// let doc = "<!DOCTYPE a [<!ENTITY big 'xxxxxxxxxx'>]><a>&big;&big;</a>";
+    /// let config = ParserConfig::new().max_entity_expansion(15);
+    /// let events: Result<Vec<_>, _> = Parser::new_with_config(doc.as_bytes(), config).collect();
+    /// assert!(events.is_err());
+    /// ~~~
+    pub fn max_entity_expansion(mut self, len: usize) -> ParserConfig {
+        self.max_entity_expansion = len;
+        self
+    }
+
+    /// Sets the `max_entity_depth` option
+    pub fn max_entity_depth(mut self, depth: usize) -> ParserConfig {
+        self.max_entity_depth = depth;
+        self
+    }
+
+    /// Sets the `max_attributes` option
+    ///
+    /// This and the other size limits are unset by default. Parsing fails with a
+    /// `LimitExceeded` error as soon as one of them is exceeded, before the whole attribute,
+    /// text or comment is buffered.
+    ///
+    /// ~~~
+    /// use xml::{Parser, ParserConfig};
+    ///
+    /// let config = ParserConfig::new().max_attributes(2).max_text_length(8);
+    /// let mut p = Parser::new_with_config("<a x='1' y='2'>short</a>".as_bytes(), config.clone());
+    /// assert!(p.all(|event| event.is_ok()));
+    /// let mut p = Parser::new_with_config("<a>much too long</a>".as_bytes(), config);
+    /// assert!(p.any(|event| event.is_err()));
+    /// ~~~
+    pub fn max_attributes(mut self, count: usize) -> ParserConfig {
+        self.max_attributes = Some(count);
+        self
+    }
+
+    /// Sets the `max_attribute_length` option
+    pub fn max_attribute_length(mut self, len: usize) -> ParserConfig {
+        self.max_attribute_length = Some(len);
+        self
+    }
+
+    /// Sets the `max_text_length` option
+    pub fn max_text_length(mut self, len: usize) -> ParserConfig {
+        self.max_text_length = Some(len);
+        self
+    }
+
+    /// Sets the `max_comment_length` option
+    pub fn max_comment_length(mut self, len: usize) -> ParserConfig {
+        self.max_comment_length = Some(len);
+        self
+    }
+
+    /// Sets the `max_text_chunk` option. A chunk of character data ends before a reference
+    /// that is only partly read, which fails as invalid once it is longer than 1024 bytes.
+    /// Under the `coalesce_text` option, text is merged up to about this size, and the
+    /// `trim_text` option trims each chunk.
+    ///
+    /// ~~~
+    /// use xml::{Event, Parser, ParserConfig};
+    ///
+    /// let input = "<a>0123456789&lt;<![CDATA[abcdefgh]]></a>";
+    /// let config = ParserConfig::new().max_text_chunk(4);
+    /// let text: Vec<_> = Parser::new_with_config(input.as_bytes(), config)
+    ///     .filter_map(|event| match event.unwrap() {
+    ///         Event::Characters(text) => Some(format!("[{}]", text)),
+    ///         Event::CDATA(text) => Some(format!("<{}>", text)),
+    ///         _ => None,
+    ///     })
+    ///     .collect();
+    /// assert_eq!(text, ["[0123]", "[4567]", "[89]", "[<]", "<abcd>", "<efgh>"]);
+    /// ~~~
+    pub fn max_text_chunk(mut self, len: usize) -> ParserConfig {
+        self.max_text_chunk = Some(len);
+        self
+    }
+
+    /// Sets the `coalesce_text` option
+    ///
+    /// ~~~
+    /// use xml::{Event, Parser, ParserConfig};
+    ///
+    /// let input = "<a>x &lt; <![CDATA[<y>]]>!<!---->z</a>";
+    /// let config = ParserConfig::new().coalesce_text(true);
+    /// let events: Vec<_> = Parser::new_with_config(input.as_bytes(), config)
+    ///     .map(Result::unwrap)
+    ///     .collect();
+    /// assert_eq!(events[1], Event::Characters("x < <y>!".to_owned()));
+    /// assert_eq!(events[3], Event::Characters("z".to_owned()));
+    /// ~~~
+    pub fn coalesce_text(mut self, coalesce: bool) -> ParserConfig {
+        self.coalesce_text = coalesce;
+        self
+    }
+
+    /// Sets the `trim_text` option. The text is trimmed in place, and CDATA sections are left
+    /// untouched unless they are merged into character data by the `coalesce_text` option.
+    /// Spans still cover the whitespace removed.
+    ///
+    /// ~~~
+    /// use xml::{Event, Parser, ParserConfig};
+    ///
+    /// let input = "<a>\n  <b> x y </b>\n  <c><![CDATA[ z ]]></c>\n</a>";
+    /// let config = ParserConfig::new().trim_text(true);
+    /// let text: Vec<_> = Parser::new_with_config(input.as_bytes(), config)
+    ///     .filter_map(|event| match event.unwrap() {
+    ///         Event::Characters(text) | Event::CDATA(text) => Some(text),
+    ///         _ => None,
+    ///     })
+    ///     .collect();
+    /// assert_eq!(text, ["x y", " z "]);
+    /// ~~~
+    pub fn trim_text(mut self, trim: bool) -> ParserConfig {
+        self.trim_text = trim;
+        self
+    }
+
+    /// Sets the `lenient` option
+    ///
+    /// Unquoted attribute values and attributes without a value, which are given an empty
+    /// value, are accepted. End tags match their start tags ignoring ASCII case, and close
+    /// the elements left open within the element they name. End tags naming no open element
+    /// are skipped, and elements still open at the end of input are closed. Each problem
+    /// tolerated is reported in `Parser::warnings()`.
+    ///
+    /// ~~~
+    /// use xml::{Element, ElementBuilder, Parser, ParserConfig};
+    ///
+    /// let input = "<ul class=menu><li selected>One<li><A href=/two>Two</a></UL>";
+    /// let config = ParserConfig::new().lenient(true);
+    /// let mut builder = ElementBuilder::new();
+    /// let mut p = Parser::new_with_config(input.as_bytes(), config);
+    /// let elem = p.by_ref().find_map(|event| builder.handle_event(event));
+    /// assert_eq!(p.warnings().len(), 7);
+    /// let expected: Element = "<ul class='menu'><li selected=''>One<li><A href='/two'>Two</A>\
+    ///                          </li></li></ul>"
+    ///     .parse()
+    ///     .unwrap();
+    /// assert_eq!(elem.unwrap().unwrap(), expected);
+    /// ~~~
+    pub fn lenient(mut self, lenient: bool) -> ParserConfig {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Sets the `recover` option
+    ///
+    /// Errors in the markup, such as invalid references, malformed tags or duplicate
+    /// attributes, are returned without ending parsing. The construct in which the error
+    /// occurred is discarded, and parsing resumes after the next '>' or at the next '<'.
+    /// Errors reading the input and exceeded limits still end parsing.
+    ///
+    /// ~~~
+    /// use xml::{Event, Parser, ParserConfig};
+    ///
+    /// let input = "<a><b x='1' x='2'/>&bad;<c/>1 < 2</a>";
+    /// let config = ParserConfig::new().recover(true);
+    /// let mut errors = 0;
+    /// let mut names = Vec::new();
+    /// for event in Parser::new_with_config(input.as_bytes(), config) {
+    ///     match event {
+    ///         Ok(Event::ElementEnd(tag)) => names.push(tag.name.name),
+    ///         Ok(_) => (),
+    ///         Err(_) => errors += 1,
+    ///     }
+    /// }
+    /// assert_eq!(errors, 3);
+    /// assert_eq!(names, ["c", "a"]);
+    /// ~~~
+    pub fn recover(mut self, recover: bool) -> ParserConfig {
+        self.recover = recover;
+        self
+    }
+
+    /// Sets the `stop_after_root` option
+    pub fn stop_after_root(mut self, stop: bool) -> ParserConfig {
+        self.stop_after_root = stop;
+        self
+    }
+}
+
 // Event based parser
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 enum State {
     OutsideTag,
     TagOpened,
@@ -123,12 +623,109 @@ enum State {
     InComment1,
     InComment2,
     InDoctype,
+    Recovering,
+}
+
+// Why the next character could not be read
+enum ReadError {
+    Io(io::Error),
+    // A byte sequence not valid in the input's encoding, `InvalidUtf8` or `InvalidUtf16`
+    Decode(ParserErrorKind),
+}
+
+impl From<io::Error> for ReadError {
+    fn from(err: io::Error) -> ReadError {
+        ReadError::Io(err)
+    }
+}
+
+// The encodings a document can be read in
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+// Adjacent text events being merged into one
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct TextRun {
+    text: String,
+    span: Range<u64>,
+    source: Vec<u8>,
+}
+
+type PeekedEvent = (Option<Result<Event, ParserError>>, Range<u64>, Vec<u8>);
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The state of a `Parser` between two events, from which parsing can be resumed, see
+/// `Parser::checkpoint()`
+pub struct Checkpoint {
+    line: u64,
+    col: u64,
+    offset: u64,
+    base: u64,
+    base_line: u64,
+    after_cr: bool,
+    has_error: bool,
+    encoding: Option<Encoding>,
+    buf: String,
+    namespaces: Vec<HashMap<String, String>>,
+    attributes: Vec<(String, Option<String>, String)>,
+    st: State,
+    name: Option<(Option<String>, String)>,
+    attr: Option<(Option<String>, String)>,
+    delim: Option<char>,
+    level: u8,
+    doctype_scanner: DoctypeScanner,
+    raw: Vec<u8>,
+    capture: Option<Vec<u8>>,
+    after_start: bool,
+    streaming: bool,
+    streamed_attributes: Vec<QName>,
+    kept_chunks: Option<bool>,
+    attribute_count: usize,
+    value_len: usize,
+    path: Vec<QName>,
+    prefixes: Vec<Option<String>>,
+    xml_space: Vec<(usize, String)>,
+    xml_lang: Vec<(usize, String)>,
+    root_seen: bool,
+    doctype_seen: bool,
+    root_closed: bool,
+    skip_space: bool,
+    entities: HashMap<String, Entity>,
+    expanded: usize,
+    refs: Vec<Position>,
+    open_ref: Option<usize>,
+    chunked: usize,
+    config: ParserConfig,
+    warnings: Vec<ParserError>,
+    pending_source: Vec<u8>,
+    source: Vec<u8>,
+    span: Range<u64>,
+    text_run: Option<TextRun>,
+    peeked: VecDeque<PeekedEvent>,
+    unclosed: Option<(Option<String>, Name)>,
+}
+
+impl Checkpoint {
+    /// Returns the offset in the input up to which it was read, where the reader passed to
+    /// `Parser::resume()` has to start
+    pub fn offset(&self) -> u64 {
+        self.base + self.offset
+    }
 }
 
 /// A streaming XML parser
 ///
-/// Data is fed to the parser using the `feed_str()` method.
-/// The `Event`s, and `ParserError`s generated while parsing the string
+/// The parser reads its input from a `Read`, see `PushParser` for feeding it piecewise.
+/// The input is UTF-8, or UTF-16 if it starts with a byte order mark or with an XML
+/// declaration encoded in UTF-16. Positions are byte offsets in the input as it is read.
+/// The `Event`s, and `ParserError`s generated while parsing the input
 /// can be requested by iterating over the parser
 ///
 /// ~~~
@@ -147,10 +744,23 @@ pub struct Parser<R>
 where
     R: Read,
 {
-    line: u32,
-    col: u32,
+    line: u64,
+    col: u64,
+    // Number of bytes read
+    offset: u64,
+    // Offset in the input at which the document starts, and the lines before it, moved by
+    // `reset()`
+    base: u64,
+    base_line: u64,
+    // Whether the last character read was a '\r'
+    after_cr: bool,
     has_error: bool,
     data: R,
+    // Bytes read from `data`, of which those before `input_pos` have been consumed
+    input: Vec<u8>,
+    input_pos: usize,
+    // The encoding of `data`, detected before reading the first character
+    encoding: Option<Encoding>,
     buf: String,
     namespaces: Vec<HashMap<String, String>>,
     attributes: Vec<(String, Option<String>, String)>,
@@ -159,6 +769,65 @@ where
     attr: Option<(Option<String>, String)>,
     delim: Option<char>,
     level: u8,
+    // Finds the end of the document type declaration being parsed
+    doctype_scanner: DoctypeScanner,
+    // Source bytes of the markup construct currently being parsed
+    raw: Vec<u8>,
+    // Source bytes recorded by `capture_raw()`
+    capture: Option<Vec<u8>>,
+    after_start: bool,
+    // Whether the start tag being parsed was reported early, due to a long attribute value
+    streaming: bool,
+    // Names of the attributes of such a streamed start tag, checked for duplicates, and
+    // whether the chunks of the attribute value being reported are kept
+    streamed_attributes: Vec<QName>,
+    kept_chunks: Option<bool>,
+    // Attributes of the start tag being parsed, and bytes of the attribute value being parsed
+    attribute_count: usize,
+    value_len: usize,
+    // Names of the currently open elements, and their prefixes
+    path: Vec<QName>,
+    prefixes: Vec<Option<String>>,
+    // Values of `xml:space` and `xml:lang` in scope, with the depth of the element setting them
+    xml_space: Vec<(usize, String)>,
+    xml_lang: Vec<(usize, String)>,
+    // Whether a root element or DOCTYPE has been seen, checked in strict mode
+    root_seen: bool,
+    doctype_seen: bool,
+    // Whether the end of the root element has been returned
+    root_closed: bool,
+    // Whether whitespace is skipped before the document, after `reset()`
+    skip_space: bool,
+    // Set from another thread to abort parsing
+    cancel: Option<Arc<AtomicBool>>,
+    // General entities declared in the DTD
+    entities: HashMap<String, Entity>,
+    resolver: Option<Box<dyn EntityResolver + Send>>,
+    // Length of entity replacement text expanded so far
+    expanded: usize,
+    // Positions of the '&' characters in `buf`, at which invalid references are reported
+    refs: Vec<Position>,
+    // Where the reference being read starts in `buf`
+    open_ref: Option<usize>,
+    // Bytes of the text or CDATA section being read returned in earlier chunks
+    chunked: usize,
+    config: ParserConfig,
+    // Recoverable problems tolerated so far
+    warnings: Vec<ParserError>,
+    // Source bytes consumed since the last event, and those of the last event
+    pending_source: Vec<u8>,
+    source: Vec<u8>,
+    // Byte offsets of the last event
+    span: Range<u64>,
+    // Text merged so far under the `coalesce_text` option
+    text_run: Option<TextRun>,
+    // Events read ahead by `peek()` or after merged text, with their spans and sources
+    peeked: VecDeque<PeekedEvent>,
+    // An end tag closing elements left open in lenient mode, read again after each of them
+    unclosed: Option<(Option<String>, Name)>,
+    // Names shared by the events, up to `MAX_INTERNED_NAMES` of them
+    #[cfg(feature = "interning")]
+    interned: HashSet<Name>,
 }
 
 impl<R> Parser<R>
@@ -167,22 +836,28 @@ where
 {
     /// Returns a new `Parser`
     pub fn new(reader: R) -> Self {
+        Parser::new_with_config(reader, ParserConfig::default())
+    }
+
+    /// Returns a new `Parser` using the options set in `config`
+    pub fn new_with_config(reader: R, config: ParserConfig) -> Self {
         let mut ns = HashMap::with_capacity(2);
         // Add standard namespaces
-        ns.insert(
-            "xml".to_owned(),
-            "http://www.w3.org/XML/1998/namespace".to_owned(),
-        );
-        ns.insert(
-            "xmlns".to_owned(),
-            "http://www.w3.org/2000/xmlns/".to_owned(),
-        );
+        ns.insert("xml".to_owned(), ns::XML.to_owned());
+        ns.insert("xmlns".to_owned(), ns::XMLNS.to_owned());
 
         Parser {
             line: 1,
             col: 0,
+            offset: 0,
+            base: 0,
+            base_line: 0,
+            after_cr: false,
             has_error: false,
             data: reader,
+            input: Vec::new(),
+            input_pos: 0,
+            encoding: None,
             buf: String::new(),
             namespaces: vec![ns],
             attributes: Vec::new(),
@@ -191,771 +866,4211 @@ where
             attr: None,
             delim: None,
             level: 0,
+            doctype_scanner: DoctypeScanner::default(),
+            raw: Vec::new(),
+            capture: None,
+            after_start: false,
+            streaming: false,
+            streamed_attributes: Vec::new(),
+            kept_chunks: None,
+            attribute_count: 0,
+            value_len: 0,
+            path: Vec::new(),
+            prefixes: Vec::new(),
+            xml_space: Vec::new(),
+            xml_lang: Vec::new(),
+            root_seen: false,
+            doctype_seen: false,
+            root_closed: false,
+            skip_space: false,
+            cancel: None,
+            entities: HashMap::new(),
+            resolver: None,
+            expanded: 0,
+            refs: Vec::new(),
+            open_ref: None,
+            chunked: 0,
+            config,
+            warnings: Vec::new(),
+            pending_source: Vec::new(),
+            source: Vec::new(),
+            span: 0..0,
+            text_run: None,
+            peeked: VecDeque::new(),
+            unclosed: None,
+            #[cfg(feature = "interning")]
+            interned: HashSet::new(),
         }
     }
-}
 
-impl<R> Iterator for Parser<R>
-where
-    R: Read,
-{
-    type Item = Result<Event, ParserError>;
+    /// Aborts parsing with a `Cancelled` error once `flag` is set, which can be done from
+    /// another thread. The flag is checked before every event and every 4096 bytes read.
+    ///
+    /// ~~~
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    /// use std::sync::Arc;
+    /// use xml::Parser;
+    ///
+    /// let cancel = Arc::new(AtomicBool::new(false));
+    /// let mut p = Parser::new("<a><b/></a>".as_bytes());
+    /// p.set_cancel_flag(cancel.clone());
+    /// assert!(p.next().unwrap().is_ok());
+    /// cancel.store(true, Ordering::Relaxed);
+    /// assert!(p.next().unwrap().is_err());
+    /// assert!(p.next().is_none());
+    /// ~~~
+    pub fn set_cancel_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.cancel = Some(flag);
+    }
 
-    fn next(&mut self) -> Option<Result<Event, ParserError>> {
-        if self.has_error {
-            return None;
+    /// Loads external entities and the external DTD subset using `resolver`.
+    ///
+    /// Without a resolver, references to external entities are errors and only the internal
+    /// DTD subset is read. See `EntityResolver`.
+    pub fn set_entity_resolver<E>(&mut self, resolver: E)
+    where
+        E: EntityResolver + Send + 'static,
+    {
+        self.resolver = Some(Box::new(resolver));
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|flag| flag.load(Ordering::Relaxed))
+    }
+
+    /// Returns the verbatim source of the event just returned, including any input
+    /// consumed since the previous event that is not part of an event, such as a DOCTYPE.
+    /// Once the end of input has been reached, returns the input following the last event.
+    ///
+    /// Concatenating the source of all events reproduces the input byte for byte, except
+    /// that UTF-16 input is given in UTF-8 and without its byte order mark.
+    /// Always empty unless the `fidelity` option is set.
+    ///
+    /// ~~~
+    /// use xml::{EventWriter, Parser, ParserConfig};
+    ///
+    /// let input = "<!DOCTYPE a>\n<a  x=\"1\">&#65;<b /></a>\n";
+    /// let mut parser = Parser::new_with_config(input.as_bytes(), ParserConfig::new().fidelity(true));
+    /// let mut writer = EventWriter::new(Vec::new());
+    /// while let Some(event) = parser.next() {
+    ///     writer.write_source(&event.unwrap(), parser.source()).unwrap();
+    /// }
+    /// writer.write_trailing(parser.source()).unwrap();
+    /// assert_eq!(writer.into_inner().unwrap(), input.as_bytes());
+    /// ~~~
+    pub fn source(&self) -> &[u8] {
+        &self.source
+    }
+
+    /// Returns the exact source of the element whose `ElementStart` event was just returned,
+    /// from its start tag through its matching end tag.
+    ///
+    /// All events of the element's content, including its `ElementEnd`, are consumed.
+    /// Fails with `NotAtElementStart` if the previous event was not an `ElementStart`.
+    ///
+    /// ~~~
+    /// use xml::Parser;
+    ///
+    /// let mut p = Parser::new("<list><item  id=\"1\">x<b/></item></list>".as_bytes());
+    /// p.next();
+    /// p.next();
+    /// let raw = p.capture_raw().unwrap();
+    /// assert_eq!(raw, b"<item  id=\"1\">x<b/></item>");
+    /// ~~~
+    pub fn capture_raw(&mut self) -> Result<Vec<u8>, ParserError> {
+        // Events read ahead by `peek()` are no longer part of the construct being parsed
+        if !self.after_start || !self.peeked.is_empty() {
+            return Err(ParserError {
+                line: self.line,
+                col: self.col,
+                offset: self.offset.saturating_sub(1),
+                kind: ParserErrorKind::NotAtElementStart,
+            });
         }
-        let mut buf = [0u8; 1];
-        loop {
-            let c = match self.data.read(&mut buf) {
-                Ok(0) => return None,
-                Err(_) => {
-                    self.has_error = true;
-                    return Some(Err(ParserError {
+        self.capture = Some(mem::take(&mut self.raw));
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next() {
+                Some(Ok(Event::ElementStart(_))) => depth += 1,
+                Some(Ok(Event::ElementEnd(_))) => depth -= 1,
+                Some(Ok(_)) => (),
+                Some(Err(err)) => {
+                    self.capture = None;
+                    return Err(err);
+                }
+                None => {
+                    self.capture = None;
+                    return Err(ParserError {
                         line: self.line,
                         col: self.col,
-                        kind: ParserErrorKind::MalformedXml,
-                    }));
-                }
-                Ok(1) => buf[0] as char,
-                _ => unreachable!(),
-            };
-            if c == '\n' {
-                self.line += 1;
-                self.col = 0;
-            } else {
-                self.col += 1;
-            }
-
-            match self.parse_character(c) {
-                Ok(None) => continue,
-                Ok(Some(event)) => {
-                    return Some(Ok(event));
-                }
-                Err(e) => {
-                    self.has_error = true;
-                    return Some(Err(e));
+                        offset: self.offset.saturating_sub(1),
+                        kind: ParserErrorKind::UnexpectedEof,
+                    });
                 }
             }
         }
+        Ok(self.capture.take().unwrap_or_default())
     }
-}
 
-#[inline]
-// Parse a QName to get Prefix and LocalPart
-fn parse_qname(mut qname: String) -> (Option<String>, String) {
-    if let Some(i) = qname.find(':') {
-        let local = qname.split_off(i + 1);
-        qname.pop();
-        (Some(qname), local)
-    } else {
-        (None, qname)
+    /// Returns the text of the element whose `ElementStart` event was just returned, which
+    /// must not contain elements. Comments and processing instructions are skipped.
+    ///
+    /// All events of the element's content, including its `ElementEnd`, are consumed.
+    /// Fails with `NotAtElementStart` if the previous event was not an `ElementStart`, and
+    /// with `UnexpectedElement` at the first child element.
+    ///
+    /// ~~~
+    /// use xml::Parser;
+    ///
+    /// let mut p = Parser::new("<price currency='EUR'>12<!-- net -->.50</price>".as_bytes());
+    /// p.next();
+    /// assert_eq!(p.read_text().unwrap(), "12.50");
+    /// assert!(p.next().is_none());
+    /// ~~~
+    pub fn read_text(&mut self) -> Result<String, ParserError> {
+        self.collect_text(false)
     }
-}
 
-fn unescape_owned(input: String) -> Result<String, String> {
-    if input.find('&').is_none() {
-        Ok(input)
-    } else {
-        unescape(&input)
+    /// Returns the text of the element whose `ElementStart` event was just returned,
+    /// including the text of its descendants, like `Element::content_str()`.
+    ///
+    /// All events of the element's content, including its `ElementEnd`, are consumed.
+    /// Fails with `NotAtElementStart` if the previous event was not an `ElementStart`.
+    ///
+    /// ~~~
+    /// use xml::Parser;
+    ///
+    /// let mut p = Parser::new("<p>Some <b>bold</b> text</p>".as_bytes());
+    /// p.next();
+    /// assert_eq!(p.read_all_text().unwrap(), "Some bold text");
+    /// ~~~
+    pub fn read_all_text(&mut self) -> Result<String, ParserError> {
+        self.collect_text(true)
     }
-}
 
-impl<R> Parser<R>
-where
-    R: Read,
-{
-    // Get the namespace currently bound to a prefix.
-    // Bindings are stored as a stack of HashMaps, we start searching in the top most HashMap
-    // and traverse down until the prefix is found.
-    fn namespace_for_prefix(&self, prefix: &str) -> Option<String> {
-        for ns in self.namespaces.iter().rev() {
-            if let Some(namespace) = ns.get(prefix) {
-                if namespace.is_empty() {
-                    return None;
+    // Consumes the content of the element just started, returning its text
+    fn collect_text(&mut self, descendants: bool) -> Result<String, ParserError> {
+        if !self.after_start {
+            return Err(self.error_at_last_char(ParserErrorKind::NotAtElementStart));
+        }
+        let mut text = String::new();
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next() {
+                Some(Ok(Event::Characters(chars))) | Some(Ok(Event::CDATA(chars))) => {
+                    text.push_str(&chars)
                 }
-                return Some(namespace.clone());
+                Some(Ok(Event::ElementStart(_))) if !descendants => {
+                    return Err(self.error_at_last_char(ParserErrorKind::UnexpectedElement));
+                }
+                Some(Ok(Event::ElementStart(_))) => depth += 1,
+                Some(Ok(Event::ElementEnd(_))) => depth -= 1,
+                Some(Ok(_)) => (),
+                Some(Err(err)) => return Err(err),
+                None => return Err(self.error_at_last_char(ParserErrorKind::UnexpectedEof)),
             }
         }
-        None
+        Ok(text)
     }
 
-    fn take_buf(&mut self) -> String {
-        self.buf.split_off(0)
+    /// Reads the next element and its content into an `Element`, skipping the events before
+    /// its start tag. Returns `None` without consuming anything once the enclosing element
+    /// ends, or at the end of the input.
+    ///
+    /// If the previous event was an `ElementStart`, the element read is its first child.
+    /// The element is the same as in a tree built from the whole document, so namespaces
+    /// declared outside of it are declared by `Element::graft()`.
+    ///
+    /// ~~~
+    /// use xml::Parser;
+    ///
+    /// let input = "<feed><title>News</title><entry id='1'>a</entry><entry id='2'>b</entry></feed>";
+    /// let mut p = Parser::new(input.as_bytes());
+    /// p.seek_to_element("title", None).unwrap();
+    /// p.read_text().unwrap();
+    /// let mut ids = Vec::new();
+    /// while let Some(entry) = p.read_element().unwrap() {
+    ///     ids.push(entry.get_attribute("id", None).unwrap().to_owned());
+    /// }
+    /// assert_eq!(ids, ["1", "2"]);
+    /// assert!(matches!(p.next(), Some(Ok(xml::Event::ElementEnd(_)))));
+    /// ~~~
+    pub fn read_element(&mut self) -> Result<Option<Element>, BuilderError> {
+        loop {
+            match self.peek() {
+                Some(Ok(Event::ElementStart(_))) => break,
+                Some(Ok(Event::ElementEnd(_))) | None => return Ok(None),
+                _ => {
+                    self.next().transpose()?;
+                }
+            }
+        }
+
+        let mut builder = ElementBuilder::new();
+        if let Some(ns) = self.resolve_prefix("") {
+            builder.set_default_ns(ns.to_owned());
+        }
+        for event in self.by_ref() {
+            if let Some(result) = builder.handle_event(event) {
+                return result.map(Some);
+            }
+        }
+        Err(self
+            .error_at_last_char(ParserErrorKind::UnexpectedEof)
+            .into())
     }
 
-    fn error(&self, kind: ParserErrorKind) -> Result<Option<Event>, ParserError> {
-        Err(ParserError {
+    /// Returns the names of the currently open elements, starting with the root.
+    /// An element is included from its `ElementStart` event until its `ElementEnd` event.
+    ///
+    /// ~~~
+    /// use xml::Parser;
+    ///
+    /// let mut p = Parser::new("<feed><entry><title>Hi</title></entry></feed>".as_bytes());
+    /// p.seek_to_element("title", None).unwrap();
+    /// let path: Vec<_> = p.current_path().iter().map(|name| &name.name[..]).collect();
+    /// assert_eq!(path, ["feed", "entry", "title"]);
+    /// ~~~
+    pub fn current_path(&self) -> &[QName] {
+        &self.path
+    }
+
+    /// Returns the position after the input read so far. Right after an event is returned,
+    /// this is where the event ended, except for `Characters`, which are only known to end
+    /// once the '<' following them is read.
+    pub fn position(&self) -> Position {
+        Position {
             line: self.line,
             col: self.col,
-            kind,
-        })
+            offset: self.offset,
+        }
     }
 
-    fn parse_character(&mut self, c: char) -> Result<Option<Event>, ParserError> {
-        // println(fmt!("Now in state: %?", self.st));
-        match self.st {
-            State::OutsideTag => self.outside_tag(c),
-            State::TagOpened => self.tag_opened(c),
-            State::InProcessingInstructions => self.in_processing_instructions(c),
-            State::InTagName => self.in_tag_name(c),
-            State::InCloseTagName => self.in_close_tag_name(c),
-            State::InTag => self.in_tag(c),
-            State::InAttrName => self.in_attr_name(c),
-            State::InAttrValue => self.in_attr_value(c),
-            State::ExpectDelimiter => self.expect_delimiter(c),
-            State::ExpectClose => self.expect_close(c),
-            State::ExpectSpaceOrClose => self.expect_space_or_close(c),
-            State::InExclamationMark => self.in_exclamation_mark(c),
-            State::InCDATAOpening => self.in_cdata_opening(c),
-            State::InCDATA => self.in_cdata(c),
-            State::InCommentOpening => self.in_comment_opening(c),
-            State::InComment1 => self.in_comment1(c),
-            State::InComment2 => self.in_comment2(c),
-            State::InDoctype => self.in_doctype(c),
+    /// Returns the number of bytes of the input parsed so far, for reporting progress. Unlike
+    /// `position()`, this includes the documents before the last `reset()`.
+    ///
+    /// ~~~
+    /// use xml::Parser;
+    ///
+    /// let input = "<a>\n<b>x</b>\n</a>";
+    /// let mut p = Parser::new(input.as_bytes());
+    /// p.seek_to_element("b", None).unwrap();
+    /// assert_eq!((p.bytes_read(), p.lines_read()), (7, 2));
+    /// p.by_ref().count();
+    /// assert_eq!((p.bytes_read(), p.lines_read()), (input.len() as u64, 3));
+    /// ~~~
+    pub fn bytes_read(&self) -> u64 {
+        self.base + self.offset
+    }
+
+    /// Returns the line of the input parsed so far, starting at 1. Unlike `position()`, lines
+    /// of the documents before the last `reset()` are counted.
+    pub fn lines_read(&self) -> u64 {
+        self.base_line + self.line
+    }
+
+    /// Returns the range of byte offsets in the input covered by the last event returned.
+    /// The range starts where the previous event ended, so it includes any markup not
+    /// reported as an event, such as a DOCTYPE declaration.
+    ///
+    /// ~~~
+    /// use xml::Parser;
+    ///
+    /// let input = "<a>text<b/></a>";
+    /// let mut p = Parser::new(input.as_bytes());
+    /// p.next();
+    /// p.next();
+    /// let span = p.span();
+    /// assert_eq!(&input[span.start as usize..span.end as usize], "text");
+    /// ~~~
+    pub fn span(&self) -> Range<u64> {
+        self.span.clone()
+    }
+
+    // Makes `n` unconsumed bytes available in the read buffer. Returns `false` if the input
+    // ends first.
+    fn fill(&mut self, n: usize) -> io::Result<bool> {
+        while self.input.len() - self.input_pos < n {
+            self.input.drain(..self.input_pos);
+            self.input_pos = 0;
+            let len = self.input.len();
+            self.input
+                .resize(len + self.config.read_buffer_size.max(1), 0);
+            let read = self.data.read(&mut self.input[len..]);
+            self.input.truncate(len + *read.as_ref().unwrap_or(&0));
+            if read? == 0 {
+                return Ok(false);
+            }
         }
+        Ok(true)
     }
 
-    // Outside any tag, or other construct
-    // '<' => TagOpened, producing Event::Characters
-    fn outside_tag(&mut self, c: char) -> Result<Option<Event>, ParserError> {
-        match c {
-            '<' if self.buf.is_empty() => self.st = State::TagOpened,
-            '<' => {
-                self.st = State::TagOpened;
-                let buf = match unescape_owned(self.take_buf()) {
-                    Ok(unescaped) => unescaped,
-                    Err(_) => return self.error(ParserErrorKind::InvalidEntity),
-                };
-                return Ok(Some(Event::Characters(buf)));
+    // Detects the encoding from a byte order mark, which is skipped, or from the start of an
+    // XML declaration, see XML 1.0 appendix F. Anything else is read as UTF-8.
+    fn detect_encoding(&mut self) -> io::Result<Encoding> {
+        self.fill(4)?;
+        let (encoding, bom) = match self.input[self.input_pos..] {
+            [0xEF, 0xBB, 0xBF, ..] => (Encoding::Utf8, 3),
+            [0xFE, 0xFF, ..] => (Encoding::Utf16Be, 2),
+            [0xFF, 0xFE, ..] => (Encoding::Utf16Le, 2),
+            [0x00, b'<', 0x00, b'?', ..] => (Encoding::Utf16Be, 0),
+            [b'<', 0x00, b'?', 0x00, ..] => (Encoding::Utf16Le, 0),
+            _ => (Encoding::Utf8, 0),
+        };
+        self.input_pos += bom;
+        self.offset += bom as u64;
+        Ok(encoding)
+    }
+
+    // Decodes the UTF-8 sequence at the start of the unconsumed input, returning the
+    // character and the sequence's length
+    fn decode_utf8(&mut self) -> Result<(char, usize), ReadError> {
+        let invalid = ReadError::Decode(ParserErrorKind::InvalidUtf8);
+        let len = match self.input[self.input_pos] {
+            0x00..=0x7F => 1,
+            0xC2..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF4 => 4,
+            _ => return Err(invalid),
+        };
+        if !self.fill(len)? {
+            return Err(invalid);
+        }
+        let bytes = &self.input[self.input_pos..self.input_pos + len];
+        match str::from_utf8(bytes).ok().and_then(|s| s.chars().next()) {
+            Some(c) => Ok((c, len)),
+            None => Err(invalid),
+        }
+    }
+
+    // Decodes the UTF-16 code unit or surrogate pair at the start of the unconsumed input,
+    // with `unit` converting two bytes to a code unit
+    fn decode_utf16(&mut self, unit: fn([u8; 2]) -> u16) -> Result<(char, usize), ReadError> {
+        let invalid = || ReadError::Decode(ParserErrorKind::InvalidUtf16);
+        if !self.fill(2)? {
+            return Err(invalid());
+        }
+        let pos = self.input_pos;
+        let mut units = [unit([self.input[pos], self.input[pos + 1]]), 0];
+        let mut count = 1;
+        // A high surrogate is followed by a low surrogate
+        if (0xD800..0xDC00).contains(&units[0]) {
+            if !self.fill(4)? {
+                return Err(invalid());
             }
-            _ => self.buf.push(c),
+            units[1] = unit([self.input[pos + 2], self.input[pos + 3]]);
+            count = 2;
+        }
+        match char::decode_utf16(units[..count].iter().copied()).next() {
+            Some(Ok(c)) => Ok((c, c.len_utf16() * 2)),
+            _ => Err(invalid()),
         }
-        Ok(None)
     }
 
-    // Character following a '<', starting a tag or other construct
-    // '?' => InProcessingInstructions
-    // '!' => InExclamationMark
-    // '/' => InCloseTagName
-    //  _  => InTagName
-    fn tag_opened(&mut self, c: char) -> Result<Option<Event>, ParserError> {
-        self.st = match c {
-            '?' => State::InProcessingInstructions,
-            '!' => State::InExclamationMark,
-            '/' => State::InCloseTagName,
-            _ => {
-                self.buf.push(c);
-                State::InTagName
+    // Reads the next character, returning `None` at the end of input. The character is
+    // recorded UTF-8 encoded in the captured and source buffers.
+    fn read_char(&mut self) -> Result<Option<char>, ReadError> {
+        let encoding = match self.encoding {
+            Some(encoding) => encoding,
+            None => {
+                let encoding = self.detect_encoding()?;
+                self.encoding = Some(encoding);
+                encoding
             }
         };
-        Ok(None)
+        if !self.fill(1)? {
+            return Ok(None);
+        }
+        // A sequence is only consumed once it is complete, so reading can be resumed
+        let (c, len) = match encoding {
+            Encoding::Utf8 => self.decode_utf8()?,
+            Encoding::Utf16Le => self.decode_utf16(u16::from_le_bytes)?,
+            Encoding::Utf16Be => self.decode_utf16(u16::from_be_bytes)?,
+        };
+        let mut utf8 = [0; 4];
+        let bytes = c.encode_utf8(&mut utf8).as_bytes();
+        if let Some(ref mut capture) = self.capture {
+            capture.extend_from_slice(bytes);
+        }
+        if self.config.fidelity {
+            self.pending_source.extend_from_slice(bytes);
+        }
+        if !matches!(self.st, State::OutsideTag) {
+            self.raw.extend_from_slice(bytes);
+        }
+        self.input_pos += len;
+        self.offset += len as u64;
+        Ok(Some(c))
     }
 
-    // Inside a processing instruction
-    // '?' '>' => OutsideTag, producing PI
-    fn in_processing_instructions(&mut self, c: char) -> Result<Option<Event>, ParserError> {
-        match c {
-            '?' => {
-                self.level = 1;
-                self.buf.push(c);
+    // Appends the characters up to the next one with a special meaning in the current state
+    // at once, instead of passing them to `parse_character()` one by one. Only input already
+    // read is scanned, anything else is left to `read_char()`.
+    fn read_run(&mut self) {
+        if self.encoding != Some(Encoding::Utf8)
+            || self.after_cr
+            || self.unclosed.is_some()
+            || self.skip_space
+        {
+            return;
+        }
+        let input = &self.input[self.input_pos..];
+        // A partial "]]>" or "--" is completed by `parse_character()`, as is a reference
+        let (end, room) = match self.st {
+            State::OutsideTag if self.open_ref.is_none() => {
+                (memchr::memchr3(b'<', b'&', b'\r', input), self.text_room())
             }
-            '>' if self.level == 1 => {
-                self.level = 0;
-                self.st = State::OutsideTag;
-                let _ = self.buf.pop();
-                let buf = self.take_buf();
-                return Ok(Some(Event::PI(buf)));
+            State::InCDATA if self.level == 0 => {
+                (memchr::memchr2(b']', b'\r', input), self.text_room())
             }
-            _ => self.buf.push(c),
+            State::InComment1 if self.level == 0 => (
+                memchr::memchr2(b'-', b'\r', input),
+                self.config
+                    .max_comment_length
+                    .map(|max| max.saturating_sub(self.buf.len())),
+            ),
+            _ => return,
+        };
+        let run = &input[..end.unwrap_or(input.len())];
+        // Invalid sequences and those continuing past the input read are left to `read_char()`
+        let run = match str::from_utf8(run) {
+            Ok(run) => run,
+            Err(err) => str::from_utf8(&run[..err.valid_up_to()]).unwrap_or_default(),
+        };
+        // The character exceeding a limit is left to `parse_character()` to report
+        let mut len = room.map_or(run.len(), |room| room.min(run.len()));
+        while !run.is_char_boundary(len) {
+            len -= 1;
         }
-        Ok(None)
+        let run = &run[..len];
+        if run.is_empty() {
+            return;
+        }
+
+        self.buf.push_str(run);
+        if let Some(ref mut capture) = self.capture {
+            capture.extend_from_slice(run.as_bytes());
+        }
+        if self.config.fidelity {
+            self.pending_source.extend_from_slice(run.as_bytes());
+        }
+        if !matches!(self.st, State::OutsideTag) {
+            self.raw.extend_from_slice(run.as_bytes());
+        }
+        match memchr::memrchr(b'\n', run.as_bytes()) {
+            Some(last) => {
+                self.line += memchr::memchr_iter(b'\n', run.as_bytes()).count() as u64;
+                self.col = run[last + 1..].chars().count() as u64;
+            }
+            None => self.col += run.chars().count() as u64,
+        }
+        self.input_pos += len;
+        self.offset += len as u64;
     }
 
-    // Inside a tag name (opening tag)
-    // '/' => ExpectClose, producing Event::ElementStart
-    // '>' => OutsideTag, producing Event::ElementStart
-    // ' ' or '\t' or '\r' or '\n' => InTag
-    fn in_tag_name(&mut self, c: char) -> Result<Option<Event>, ParserError> {
-        match c {
-            '/' | '>' => {
-                let (prefix, name) = parse_qname(self.take_buf());
-                let ns = match prefix {
-                    None => self.namespace_for_prefix(""),
-                    Some(ref pre) => match self.namespace_for_prefix(pre) {
-                        None => return self.error(ParserErrorKind::UnboundNsPrefixInTagName),
-                        ns => ns,
-                    },
-                };
+    // Returns how many bytes of text can be appended to `buf` before the `max_text_length`
+    // limit is exceeded or, leaving the last one to `parse_character()`, a chunk is complete
+    fn text_room(&self) -> Option<usize> {
+        let length = self
+            .config
+            .max_text_length
+            .map(|max| max.saturating_sub(self.chunked + self.buf.len()));
+        let chunk = self
+            .config
+            .max_text_chunk
+            .map(|max| max.saturating_sub(self.buf.len() + 1));
+        match (length, chunk) {
+            (Some(length), Some(chunk)) => Some(length.min(chunk)),
+            (length, chunk) => length.or(chunk),
+        }
+    }
 
-                self.namespaces.push(HashMap::new());
-                self.st = if c == '/' {
-                    self.name = Some((prefix.clone(), name.clone()));
-                    State::ExpectClose
-                } else {
-                    State::OutsideTag
-                };
+    /// Returns the number of currently open elements, the length of `current_path()`.
+    ///
+    /// ~~~
+    /// use xml::{Event, Parser};
+    ///
+    /// let mut p = Parser::new("<a><b/><c>x</c></a>".as_bytes());
+    /// let mut depths = Vec::new();
+    /// while let Some(event) = p.next() {
+    ///     if let Event::ElementStart(_) = event.unwrap() {
+    ///         depths.push(p.depth());
+    ///     }
+    /// }
+    /// assert_eq!(depths, [1, 2, 2]);
+    /// assert_eq!(p.depth(), 0);
+    /// ~~~
+    pub fn depth(&self) -> usize {
+        self.path.len()
+    }
 
-                return Ok(Some(Event::ElementStart(StartTag {
-                    name,
-                    ns,
-                    prefix,
-                    attributes: AttrMap::new(),
-                })));
-            }
-            ' ' | '\t' | '\r' | '\n' => {
-                self.namespaces.push(HashMap::new());
-                self.name = Some(parse_qname(self.take_buf()));
-                self.st = State::InTag;
-            }
-            _ => self.buf.push(c),
+    /// Returns the namespace bound to `prefix` in the scope of the last event, or the default
+    /// namespace for the empty prefix, for instance to resolve QNames in attribute values
+    ///
+    /// ~~~
+    /// use xml::{Event, Parser};
+    ///
+    /// let input = "<a xmlns:xs='http://www.w3.org/2001/XMLSchema' type='xs:int'/>";
+    /// let mut p = Parser::new(input.as_bytes());
+    /// if let Some(Ok(Event::ElementStart(tag))) = p.next() {
+    ///     let (prefix, name) = tag.get_attribute("type", None).unwrap().split_once(':').unwrap();
+    ///     assert_eq!(name, "int");
+    ///     assert_eq!(p.resolve_prefix(prefix), Some("http://www.w3.org/2001/XMLSchema"));
+    /// }
+    /// ~~~
+    pub fn resolve_prefix(&self, prefix: &str) -> Option<&str> {
+        bound_namespace(self.scopes(), prefix)
+    }
+
+    /// Returns the namespace bindings in the scope of the last event, mapping prefixes to
+    /// namespaces and the empty prefix to the default namespace. The `xml` and `xmlns`
+    /// prefixes are always bound.
+    pub fn namespaces(&self) -> HashMap<&str, &str> {
+        let mut bindings = HashMap::new();
+        for scope in self.scopes() {
+            bindings.extend(scope.iter().map(|(prefix, ns)| (&prefix[..], &ns[..])));
         }
-        Ok(None)
+        bindings.retain(|_, ns| !ns.is_empty());
+        bindings
     }
 
-    // Inside a tag name (closing tag)
-    // '>' => OutsideTag, producing ElementEnd
-    // ' ' or '\t' or '\r' or '\n' => ExpectSpaceOrClose, producing ElementEnd
-    fn in_close_tag_name(&mut self, c: char) -> Result<Option<Event>, ParserError> {
-        match c {
-            ' ' | '\t' | '\r' | '\n' | '>' => {
-                let (prefix, name) = parse_qname(self.take_buf());
+    /// Returns the value of `xml:space` in scope, set on the innermost open element that has
+    /// the attribute
+    ///
+    /// ~~~
+    /// use xml::Parser;
+    ///
+    /// let mut p = Parser::new("<a xml:space='preserve'><b xml:space='default'/><c/></a>".as_bytes());
+    /// p.seek_to_element("b", None).unwrap();
+    /// assert_eq!(p.xml_space(), Some("default"));
+    /// p.seek_to_element("c", None).unwrap();
+    /// assert_eq!(p.xml_space(), Some("preserve"));
+    /// ~~~
+    pub fn xml_space(&self) -> Option<&str> {
+        self.xml_space.last().map(|(_, space)| &space[..])
+    }
 
-                let ns = match prefix {
-                    None => self.namespace_for_prefix(""),
-                    Some(ref pre) => match self.namespace_for_prefix(pre) {
-                        None => return self.error(ParserErrorKind::UnboundNsPrefixInTagName),
-                        ns => ns,
-                    },
-                };
+    /// Returns the value of `xml:lang` in scope, set on the innermost open element that has
+    /// the attribute. An empty value means that the language is unknown.
+    pub fn xml_lang(&self) -> Option<&str> {
+        self.xml_lang.last().map(|(_, lang)| &lang[..])
+    }
 
-                self.namespaces.pop();
-                self.st = if c == '>' {
-                    State::OutsideTag
-                } else {
-                    State::ExpectSpaceOrClose
-                };
+    /// Prepares the parser for the next document in its input, after the end of the root
+    /// element under the `stop_after_root` option. All state is cleared, such as the position,
+    /// the namespaces, the declared entities and the warnings, except for the input already
+    /// read and its encoding. Whitespace before the next document is skipped, and positions
+    /// are counted from its start.
+    ///
+    /// ~~~
+    /// use xml::{Element, Parser, ParserConfig};
+    ///
+    /// let input = "<?xml version='1.0'?><log>a</log>\n<?xml version='1.0'?><log>b</log>\n";
+    /// let config = ParserConfig::new().stop_after_root(true).strict(true);
+    /// let mut p = Parser::new_with_config(input.as_bytes(), config);
+    /// let mut logs = Vec::new();
+    /// while p.peek().is_some() {
+    ///     while let Some(element) = p.read_element().unwrap() {
+    ///         logs.push(element.content_str());
+    ///     }
+    ///     assert!(p.next().is_none());
+    ///     p.reset();
+    /// }
+    /// assert_eq!(logs, ["a", "b"]);
+    /// ~~~
+    pub fn reset(&mut self) {
+        self.base_line += mem::replace(&mut self.line, 1) - 1;
+        self.col = 0;
+        self.base += mem::take(&mut self.offset);
+        self.after_cr = false;
+        self.has_error = false;
+        self.buf.clear();
+        // The first scope binds the `xml` and `xmlns` prefixes
+        self.namespaces.truncate(1);
+        self.attributes.clear();
+        self.st = State::OutsideTag;
+        self.name = None;
+        self.attr = None;
+        self.delim = None;
+        self.level = 0;
+        self.doctype_scanner = DoctypeScanner::default();
+        self.raw.clear();
+        self.capture = None;
+        self.after_start = false;
+        self.streaming = false;
+        self.streamed_attributes.clear();
+        self.kept_chunks = None;
+        self.attribute_count = 0;
+        self.value_len = 0;
+        self.path.clear();
+        self.prefixes.clear();
+        self.xml_space.clear();
+        self.xml_lang.clear();
+        self.root_seen = false;
+        self.doctype_seen = false;
+        self.root_closed = false;
+        self.skip_space = true;
+        self.entities.clear();
+        self.expanded = 0;
+        self.refs.clear();
+        self.open_ref = None;
+        self.chunked = 0;
+        self.warnings.clear();
+        self.pending_source.clear();
+        self.source.clear();
+        self.span = 0..0;
+        self.text_run = None;
+        self.peeked.clear();
+        self.unclosed = None;
+    }
 
-                Ok(Some(Event::ElementEnd(EndTag { name, ns, prefix })))
-            }
-            _ => {
-                self.buf.push(c);
-                Ok(None)
+    /// Returns the state of the parser, from which parsing can be resumed with `resume()`,
+    /// for example by another process if the checkpoint is serialized with the `serde`
+    /// feature. Events read ahead by `peek()` are part of the checkpoint, the cancel flag and
+    /// the entity resolver are not.
+    ///
+    /// ~~~
+    /// use std::io::{Cursor, Seek, SeekFrom};
+    /// use xml::{Event, Parser};
+    ///
+    /// let input = "<log><entry>1</entry><entry>2</entry></log>";
+    /// let mut p = Parser::new(Cursor::new(input));
+    /// p.seek_to_element("entry", None).unwrap();
+    /// let checkpoint = p.checkpoint();
+    ///
+    /// let mut reader = Cursor::new(input);
+    /// reader.seek(SeekFrom::Start(checkpoint.offset())).unwrap();
+    /// let p = Parser::resume(reader, checkpoint);
+    /// let text: Vec<_> = p
+    ///     .filter_map(|event| match event.unwrap() {
+    ///         Event::Characters(text) => Some(text),
+    ///         _ => None,
+    ///     })
+    ///     .collect();
+    /// assert_eq!(text, ["1", "2"]);
+    /// ~~~
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            line: self.line,
+            col: self.col,
+            offset: self.offset,
+            base: self.base,
+            base_line: self.base_line,
+            after_cr: self.after_cr,
+            has_error: self.has_error,
+            encoding: self.encoding,
+            buf: self.buf.clone(),
+            namespaces: self.namespaces.clone(),
+            attributes: self.attributes.clone(),
+            st: self.st,
+            name: self.name.clone(),
+            attr: self.attr.clone(),
+            delim: self.delim,
+            level: self.level,
+            doctype_scanner: self.doctype_scanner,
+            raw: self.raw.clone(),
+            capture: self.capture.clone(),
+            after_start: self.after_start,
+            streaming: self.streaming,
+            streamed_attributes: self.streamed_attributes.clone(),
+            kept_chunks: self.kept_chunks,
+            attribute_count: self.attribute_count,
+            value_len: self.value_len,
+            path: self.path.clone(),
+            prefixes: self.prefixes.clone(),
+            xml_space: self.xml_space.clone(),
+            xml_lang: self.xml_lang.clone(),
+            root_seen: self.root_seen,
+            doctype_seen: self.doctype_seen,
+            root_closed: self.root_closed,
+            skip_space: self.skip_space,
+            entities: self.entities.clone(),
+            expanded: self.expanded,
+            refs: self.refs.clone(),
+            open_ref: self.open_ref,
+            chunked: self.chunked,
+            config: self.config.clone(),
+            warnings: self.warnings.clone(),
+            pending_source: self.pending_source.clone(),
+            source: self.source.clone(),
+            span: self.span.clone(),
+            text_run: self.text_run.clone(),
+            peeked: self.peeked.clone(),
+            unclosed: self.unclosed.clone(),
+        }
+    }
+
+    /// Returns a parser continuing from `checkpoint`, which reads the rest of the input from
+    /// `reader`, starting at `checkpoint.offset()`
+    pub fn resume(reader: R, checkpoint: Checkpoint) -> Self {
+        let Checkpoint {
+            line,
+            col,
+            offset,
+            base,
+            base_line,
+            after_cr,
+            has_error,
+            encoding,
+            buf,
+            namespaces,
+            attributes,
+            st,
+            name,
+            attr,
+            delim,
+            level,
+            doctype_scanner,
+            raw,
+            capture,
+            after_start,
+            streaming,
+            streamed_attributes,
+            kept_chunks,
+            attribute_count,
+            value_len,
+            path,
+            prefixes,
+            xml_space,
+            xml_lang,
+            root_seen,
+            doctype_seen,
+            root_closed,
+            skip_space,
+            entities,
+            expanded,
+            refs,
+            open_ref,
+            chunked,
+            config,
+            warnings,
+            pending_source,
+            source,
+            span,
+            text_run,
+            peeked,
+            unclosed,
+        } = checkpoint;
+        Parser {
+            line,
+            col,
+            offset,
+            base,
+            base_line,
+            after_cr,
+            has_error,
+            encoding,
+            buf,
+            namespaces,
+            attributes,
+            st,
+            name,
+            attr,
+            delim,
+            level,
+            doctype_scanner,
+            raw,
+            capture,
+            after_start,
+            streaming,
+            streamed_attributes,
+            kept_chunks,
+            attribute_count,
+            value_len,
+            path,
+            prefixes,
+            xml_space,
+            xml_lang,
+            root_seen,
+            doctype_seen,
+            root_closed,
+            skip_space,
+            entities,
+            expanded,
+            refs,
+            open_ref,
+            chunked,
+            config,
+            warnings,
+            pending_source,
+            source,
+            span,
+            text_run,
+            peeked,
+            unclosed,
+            data: reader,
+            input: Vec::new(),
+            input_pos: 0,
+            cancel: None,
+            resolver: None,
+            #[cfg(feature = "interning")]
+            interned: HashSet::new(),
+        }
+    }
+
+    /// Returns the problems tolerated so far instead of failing, such as duplicate attributes
+    /// under `DuplicateAttributes::KeepFirst` or `DuplicateAttributes::KeepLast`
+    pub fn warnings(&self) -> &[ParserError] {
+        &self.warnings
+    }
+
+    /// Discards events up to and including the next start tag with the specified name and
+    /// namespace, and returns that tag. Returns `None` if the input ends first.
+    ///
+    /// As the element's start tag was just read, it may be followed by `capture_raw()`.
+    ///
+    /// ~~~
+    /// use xml::Parser;
+    ///
+    /// let mut p = Parser::new("<env><head><id>1</id></head><body><id>2</id></body></env>".as_bytes());
+    /// p.seek_to_element("body", None).unwrap();
+    /// let id = p.seek_to_element("id", None).unwrap().unwrap();
+    /// assert_eq!(id.name.name, "id");
+    /// assert_eq!(p.next().unwrap().unwrap(), xml::Event::Characters("2".to_owned()));
+    /// ~~~
+    pub fn seek_to_element(
+        &mut self,
+        name: &str,
+        ns: Option<&str>,
+    ) -> Result<Option<StartTag>, ParserError> {
+        for event in self.by_ref() {
+            if let Event::ElementStart(tag) = event? {
+                if tag.name.name == name && tag.name.ns.as_deref() == ns {
+                    return Ok(Some(tag));
+                }
             }
         }
+        Ok(None)
     }
 
-    // Inside a tag, parsing attributes
-    // '/' => ExpectClose, producing StartTag
-    // '>' => OutsideTag, producing StartTag
-    // ' ' or '\t' or '\r' or '\n' => InAttrName
-    fn in_tag(&mut self, c: char) -> Result<Option<Event>, ParserError> {
-        match c {
-            '/' | '>' => {
-                let attributes = mem::take(&mut self.attributes);
-                let (prefix, name) = self
-                    .name
-                    .take()
-                    .expect("Internal error: No element name set");
-                let ns = match prefix {
-                    None => self.namespace_for_prefix(""),
-                    Some(ref pre) => match self.namespace_for_prefix(pre) {
-                        None => return self.error(ParserErrorKind::UnboundNsPrefixInTagName),
-                        ns => ns,
-                    },
-                };
+    /// Returns the next event without consuming it, like `Peekable::peek()`
+    ///
+    /// The event is read from the input, but the parser's state, such as `span()`,
+    /// `current_path()` and `namespaces()`, only changes once it is returned by `next()`.
+    /// `position()` does include the event, and `capture_raw()` fails with
+    /// `NotAtElementStart` until it is consumed.
+    ///
+    /// ~~~
+    /// use xml::{Event, Parser};
+    ///
+    /// let mut p = Parser::new("<list><item>1</item><item>2</item></list>".as_bytes());
+    /// p.next();
+    /// let mut items = 0;
+    /// while let Some(Ok(Event::ElementStart(_))) = p.peek() {
+    ///     assert_eq!(p.depth(), 1);
+    ///     p.next();
+    ///     p.read_text().unwrap();
+    ///     items += 1;
+    /// }
+    /// assert_eq!(items, 2);
+    /// ~~~
+    pub fn peek(&mut self) -> Option<&Result<Event, ParserError>> {
+        self.peek_mut().map(|event| &*event)
+    }
 
-                let mut attributes_map: AttrMap<(String, Option<String>), String> = AttrMap::new();
+    /// Returns the next event without consuming it, allowing it to be changed before it is
+    /// returned by `next()`. See `peek()`.
+    pub fn peek_mut(&mut self) -> Option<&mut Result<Event, ParserError>> {
+        if self.peeked.is_empty() {
+            // The span and source of the last event are kept until the next one is returned
+            let span = self.span.clone();
+            let source = mem::take(&mut self.source);
+            let event = self.read_ahead();
+            let span = mem::replace(&mut self.span, span);
+            let source = mem::replace(&mut self.source, source);
+            self.peeked.push_front((event, span, source));
+        }
+        self.peeked
+            .front_mut()
+            .and_then(|(event, _, _)| event.as_mut())
+    }
+}
 
-                // At this point attribute namespaces are really just prefixes,
-                // map them to the actual namespace
-                for (name, ns, value) in attributes {
-                    let ns = match ns {
-                        None => None,
-                        Some(ref prefix) => match self.namespace_for_prefix(prefix) {
-                            None => {
-                                return self.error(ParserErrorKind::UnboundNsPrefixInAttributeName)
+impl<R> Parser<R>
+where
+    R: Read,
+{
+    // Reads the next event, or error. Returns `None` at the end of input.
+    fn read_event(&mut self) -> Option<Result<Event, ParserError>> {
+        if self.has_error || self.config.stop_after_root && self.root_closed {
+            return None;
+        }
+        let mut check_cancel = true;
+        loop {
+            // Elements left open within the one an end tag closes are closed before it
+            let parsed = match self.unclosed.take() {
+                Some((prefix, name)) => self.end_tag(prefix, name),
+                None => {
+                    if check_cancel && self.cancelled() {
+                        self.has_error = true;
+                        return Some(Err(ParserError {
+                            line: self.line,
+                            col: self.col,
+                            offset: self.offset,
+                            kind: ParserErrorKind::Cancelled,
+                        }));
+                    }
+                    let start = self.offset;
+                    self.read_run();
+                    let c = match self.read_char() {
+                        Ok(Some(c)) => c,
+                        Ok(None) => {
+                            self.after_start = false;
+                            self.source = mem::take(&mut self.pending_source);
+                            // Text at the end of input is not terminated by a '<'
+                            if !matches!(self.st, State::OutsideTag) || self.buf.is_empty() {
+                                if self.config.lenient && !self.path.is_empty() {
+                                    self.warn(ParserErrorKind::UnexpectedEof);
+                                    self.span = self.offset..self.offset;
+                                    return Some(Ok(self.close_open_element()));
+                                }
+                                // Whitespace after the last document does not start another
+                                if self.config.strict && !self.root_seen && !self.skip_space {
+                                    self.has_error = true;
+                                    return Some(Err(ParserError {
+                                        line: self.line,
+                                        col: self.col,
+                                        offset: self.offset,
+                                        kind: ParserErrorKind::MissingRoot,
+                                    }));
+                                }
+                                return None;
                             }
-                            ns => ns,
-                        },
+                            let text = self.take_buf();
+                            self.span = self.span.end..self.offset;
+                            let event = match self.expand(text, false) {
+                                Ok(text) => Event::Characters(text),
+                                Err(err) => {
+                                    self.has_error = true;
+                                    return Some(Err(err));
+                                }
+                            };
+                            return Some(match self.check_document(&event) {
+                                Ok(()) => Ok(event),
+                                Err(kind) => {
+                                    self.has_error = true;
+                                    Err(ParserError {
+                                        line: self.line,
+                                        col: self.col,
+                                        offset: self.offset.saturating_sub(1),
+                                        kind,
+                                    })
+                                }
+                            });
+                        }
+                        Err(ReadError::Io(ref err))
+                            if self.config.nonblocking
+                                && matches!(
+                                    err.kind(),
+                                    io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+                                ) =>
+                        {
+                            return Some(Err(ParserError {
+                                line: self.line,
+                                col: self.col,
+                                offset: self.offset,
+                                kind: ParserErrorKind::NeedMoreData,
+                            }));
+                        }
+                        Err(ReadError::Io(_)) => {
+                            self.has_error = true;
+                            return Some(Err(ParserError {
+                                line: self.line,
+                                col: self.col,
+                                offset: self.offset.saturating_sub(1),
+                                kind: ParserErrorKind::MalformedXml,
+                            }));
+                        }
+                        Err(ReadError::Decode(kind)) => {
+                            self.has_error = true;
+                            return Some(Err(ParserError {
+                                line: self.line,
+                                col: self.col + 1,
+                                offset: self.offset,
+                                kind,
+                            }));
+                        }
+                    };
+                    check_cancel = self.offset / 4096 != start / 4096;
+                    // Line ends are normalized to '\n', see XML 1.0 section 2.11
+                    let after_cr = mem::replace(&mut self.after_cr, c == '\r');
+                    let c = match c {
+                        '\n' if after_cr => continue,
+                        '\r' => '\n',
+                        c => c,
+                    };
+                    if c == '\n' {
+                        self.line += 1;
+                        self.col = 0;
+                    } else {
+                        self.col += 1;
+                    }
+                    // Positions start after the whitespace preceding a document
+                    if self.skip_space {
+                        if matches!(c, ' ' | '\t' | '\n') {
+                            self.base_line += mem::replace(&mut self.line, 1) - 1;
+                            self.col = 0;
+                            self.base += mem::take(&mut self.offset);
+                            self.pending_source.clear();
+                            continue;
+                        }
+                        self.skip_space = false;
+                    }
+                    match self.parse_character(c) {
+                        Err(err) if self.config.recover && recoverable(err.kind) => {
+                            self.recover(c);
+                            return Some(Err(err));
+                        }
+                        parsed => parsed,
+                    }
+                }
+            };
+            match parsed {
+                Ok(None) => continue,
+                Ok(Some(event)) => {
+                    if let Err(kind) = self.check_document(&event) {
+                        self.has_error = true;
+                        return Some(Err(ParserError {
+                            line: self.line,
+                            col: self.col,
+                            offset: self.offset.saturating_sub(1),
+                            kind,
+                        }));
+                    }
+                    // Characters end at the '<' starting the next construct, and the start
+                    // tag of an empty element includes the '>' following its '/'
+                    // Elements closed by the end tag of an enclosing element end where it starts
+                    let implied = self.unclosed.is_some();
+                    let end = match (&event, &self.st) {
+                        _ if implied => self.span.end,
+                        (Event::Characters(_), State::TagOpened) => self.offset - 1,
+                        (Event::ElementStart(_), State::ExpectClose) => self.offset + 1,
+                        _ => self.offset,
                     };
-                    if attributes_map.insert((name, ns), value).is_some() {
-                        return self.error(ParserErrorKind::DuplicateAttribute);
+                    self.span = self.span.end..end;
+                    if implied {
+                        self.source.clear();
+                    } else if self.config.fidelity {
+                        self.source = mem::take(&mut self.pending_source);
+                        if let (Event::Characters(_), State::TagOpened) = (&event, &self.st) {
+                            self.pending_source.extend(self.source.pop());
+                        }
                     }
+                    return Some(Ok(event));
+                }
+                Err(e) => {
+                    self.has_error = true;
+                    return Some(Err(e));
                 }
-
-                self.st = if c == '/' {
-                    self.name = Some((prefix.clone(), name.clone()));
-                    State::ExpectClose
-                } else {
-                    State::OutsideTag
-                };
-
-                return Ok(Some(Event::ElementStart(StartTag {
-                    name,
-                    ns,
-                    prefix,
-                    attributes: attributes_map,
-                })));
             }
-            ' ' | '\t' | '\r' | '\n' => (),
-            _ => {
-                self.buf.push(c);
-                self.st = State::InAttrName;
+        }
+    }
+
+    // Reads the next event to be returned, merging text under the `coalesce_text` option and
+    // trimming it under the `trim_text` option
+    fn read_ahead(&mut self) -> Option<Result<Event, ParserError>> {
+        loop {
+            let event = self.read_event();
+            let mut event = if self.config.coalesce_text {
+                self.coalesce(event)
+            } else {
+                event
+            };
+            match event {
+                Some(Ok(Event::Characters(ref mut text))) if self.config.trim_text => {
+                    let is_space = |c| matches!(c, ' ' | '\t' | '\r' | '\n');
+                    let len = text.trim_end_matches(is_space).len();
+                    text.truncate(len);
+                    let start = len - text.trim_start_matches(is_space).len();
+                    text.drain(..start);
+                    if !text.is_empty() {
+                        return event;
+                    }
+                    // The event read after merged text is next
+                    if let Some((event, span, source)) = self.peeked.pop_front() {
+                        self.span = span;
+                        self.source = source;
+                        return event;
+                    }
+                }
+                event => return event,
             }
         }
-        Ok(None)
     }
 
-    // Inside an attribute name
-    // '=' => ExpectDelimiter
-    fn in_attr_name(&mut self, c: char) -> Result<Option<Event>, ParserError> {
-        match c {
-            '=' => {
-                self.level = 0;
-                self.attr = Some(parse_qname(self.take_buf()));
-                self.st = State::ExpectDelimiter;
+    // Merges `event` with the text events following it, if it is text. The first event
+    // after the text is kept in `peeked`.
+    fn coalesce(
+        &mut self,
+        mut event: Option<Result<Event, ParserError>>,
+    ) -> Option<Result<Event, ParserError>> {
+        loop {
+            match event {
+                Some(Ok(Event::Characters(text))) | Some(Ok(Event::CDATA(text))) => {
+                    let run = self.text_run.get_or_insert_with(|| TextRun {
+                        text: String::new(),
+                        span: self.span.clone(),
+                        source: Vec::new(),
+                    });
+                    run.text.push_str(&text);
+                    run.span.end = self.span.end;
+                    run.source.extend_from_slice(&self.source);
+                    // Merged text is returned in chunks as well
+                    if let Some(max) = self.config.max_text_chunk {
+                        if run.text.len() >= max {
+                            let run = self.text_run.take()?;
+                            self.span = run.span;
+                            self.source = run.source;
+                            return Some(Ok(Event::Characters(run.text)));
+                        }
+                    }
+                }
+                // The text may continue once more data is available
+                Some(Err(ref err)) if err.kind == ParserErrorKind::NeedMoreData => return event,
+                event => {
+                    let run = match self.text_run.take() {
+                        Some(run) => run,
+                        None => return event,
+                    };
+                    let span = mem::replace(&mut self.span, run.span);
+                    let source = mem::replace(&mut self.source, run.source);
+                    self.peeked.push_back((event, span, source));
+                    return Some(Ok(Event::Characters(run.text)));
+                }
             }
-            ' ' | '\t' | '\r' | '\n' => self.level = 1,
-            _ if self.level == 0 => self.buf.push(c),
-            _ => return self.error(ParserErrorKind::SpaceInAttributeName),
+            event = self.read_event();
         }
-        Ok(None)
     }
+}
 
-    // Inside an attribute value
-    // delimiter => InTag, adds attribute
-    fn in_attr_value(&mut self, c: char) -> Result<Option<Event>, ParserError> {
-        if c == self
-            .delim
-            .expect("Internal error: In attribute value, but no delimiter set")
-        {
-            self.delim = None;
-            self.st = State::InTag;
-            let attr = self.attr.take();
-            let (prefix, name) =
-                attr.expect("Internal error: In attribute value, but no attribute name set");
-            let value = match unescape_owned(self.take_buf()) {
-                Ok(unescaped) => unescaped,
-                Err(_) => return self.error(ParserErrorKind::InvalidEntity),
-            };
+impl<R> Iterator for Parser<R>
+where
+    R: Read,
+{
+    type Item = Result<Event, ParserError>;
 
-            let last = self
-                .namespaces
-                .last_mut()
-                .expect("Internal error: Empty namespace stack");
-            match prefix {
-                None if name == "xmlns" => {
-                    last.insert(String::new(), value.clone());
+    fn next(&mut self) -> Option<Result<Event, ParserError>> {
+        let event = match self.peeked.pop_front() {
+            Some((event, span, source)) => {
+                self.span = span;
+                self.source = source;
+                event
+            }
+            None => self.read_ahead(),
+        };
+        if let Some(Ok(ref event)) = event {
+            self.after_start = false;
+            match *event {
+                Event::ElementStart(ref tag) => {
+                    self.after_start = true;
+                    self.path.push(tag.name.clone());
+                    self.prefixes.push(tag.prefix.clone());
+                    let depth = self.path.len();
+                    if let Some(space) = tag.get_attribute("space", Some(ns::XML)) {
+                        self.xml_space.push((depth, space.to_owned()));
+                    }
+                    if let Some(lang) = tag.get_attribute("lang", Some(ns::XML)) {
+                        self.xml_lang.push((depth, lang.to_owned()));
+                    }
                 }
-                Some(ref prefix) if prefix == "xmlns" => {
-                    last.insert(name.clone(), value.clone());
+                Event::ElementEnd(_) => {
+                    let depth = self.path.len();
+                    if self.xml_space.last().is_some_and(|&(d, _)| d == depth) {
+                        self.xml_space.pop();
+                    }
+                    if self.xml_lang.last().is_some_and(|&(d, _)| d == depth) {
+                        self.xml_lang.pop();
+                    }
+                    self.path.pop();
+                    self.prefixes.pop();
+                    // Left once the end is returned, as the bindings apply up to it
+                    self.namespaces.pop();
+                    self.root_closed = self.path.is_empty();
                 }
                 _ => (),
             }
+        }
+        event
+    }
+}
+
+// The input of a `PushParser`, which has no more data until it is finished
+#[derive(Debug)]
+struct Fed {
+    finished: bool,
+}
 
-            self.attributes.push((name, prefix, value));
+impl Read for Fed {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        if self.finished {
+            Ok(0)
         } else {
-            self.buf.push(c);
+            Err(io::ErrorKind::WouldBlock.into())
         }
-        Ok(None)
     }
+}
 
-    // Looking for an attribute value delimiter
-    // '"' or '\'' => InAttrValue, sets delimiter
-    fn expect_delimiter(&mut self, c: char) -> Result<Option<Event>, ParserError> {
-        match c {
-            '"' | '\'' => {
-                self.delim = Some(c);
-                self.st = State::InAttrValue;
-            }
-            ' ' | '\t' | '\r' | '\n' => (),
-            _ => return self.error(ParserErrorKind::UndelimitedAttribute),
-        }
-        Ok(None)
+/// A parser to which the input is fed piecewise
+///
+/// Iterating over the parser returns the events of the input fed so far, ending once it is
+/// exhausted. More input can then be fed and iteration resumed. Input can be split anywhere,
+/// even within a character. Text is only known to end once the following markup or the end
+/// of input is seen, so `finish()` must be called after the last input has been fed.
+///
+/// ~~~
+/// use xml::{Event, PushParser};
+///
+/// let mut p = PushParser::new();
+/// p.feed_str("<greeting>Hel");
+/// assert!(matches!(p.next(), Some(Ok(Event::ElementStart(_)))));
+/// assert!(p.next().is_none());
+///
+/// p.feed_bytes(b"lo</greeting>");
+/// assert_eq!(p.next(), Some(Ok(Event::Characters("Hello".to_owned()))));
+/// assert!(matches!(p.next(), Some(Ok(Event::ElementEnd(_)))));
+/// p.finish();
+/// assert!(p.next().is_none());
+/// ~~~
+pub struct PushParser {
+    parser: Parser<Fed>,
+}
+
+impl PushParser {
+    /// Returns a new `PushParser`
+    pub fn new() -> PushParser {
+        PushParser::new_with_config(ParserConfig::default())
     }
 
-    // Expect closing '>' of an empty-element tag (no whitespace allowed)
-    // '>' => OutsideTag
-    fn expect_close(&mut self, c: char) -> Result<Option<Event>, ParserError> {
-        match c {
-            '>' => {
-                self.st = State::OutsideTag;
-                let (prefix, name) = self
-                    .name
-                    .take()
-                    .expect("Internal error: No element name set");
-                let ns = match prefix {
-                    None => self.namespace_for_prefix(""),
-                    Some(ref pre) => match self.namespace_for_prefix(pre) {
-                        None => return self.error(ParserErrorKind::UnboundNsPrefixInTagName),
-                        ns => ns,
-                    },
-                };
-                self.namespaces.pop();
-                Ok(Some(Event::ElementEnd(EndTag { name, ns, prefix })))
-            }
-            _ => self.error(ParserErrorKind::ExpectedTagClose),
+    /// Returns a new `PushParser` using the options set in `config`
+    pub fn new_with_config(config: ParserConfig) -> PushParser {
+        let config = config.nonblocking(true);
+        PushParser {
+            parser: Parser::new_with_config(Fed { finished: false }, config),
         }
     }
 
-    // Expect closing '>' of a start tag
-    // '>' => OutsideTag
-    fn expect_space_or_close(&mut self, c: char) -> Result<Option<Event>, ParserError> {
-        match c {
-            ' ' | '\t' | '\r' | '\n' => Ok(None),
-            '>' => {
-                self.st = State::OutsideTag;
-                Ok(None)
-            }
-            _ => self.error(ParserErrorKind::ExpectedLwsOrTagClose),
-        }
+    /// Appends `data` to the input
+    pub fn feed_bytes(&mut self, data: &[u8]) {
+        let parser = &mut self.parser;
+        parser.input.drain(..parser.input_pos);
+        parser.input_pos = 0;
+        parser.input.extend_from_slice(data);
     }
 
-    // After an '!' trying to determine the type of the following construct
-    // '-' => InCommentOpening
-    // '[' => InCDATAOpening
-    // 'D' => InDoctype
-    fn in_exclamation_mark(&mut self, c: char) -> Result<Option<Event>, ParserError> {
-        self.st = match c {
-            '-' => State::InCommentOpening,
-            '[' => State::InCDATAOpening,
-            'D' => State::InDoctype,
-            _ => return self.error(ParserErrorKind::MalformedXml),
-        };
-        Ok(None)
+    /// Appends `data` to the input
+    pub fn feed_str(&mut self, data: &str) {
+        self.feed_bytes(data.as_bytes());
     }
 
-    // Opening sequence of Event::CDATA
-    // 'C' 'D' 'A' 'T' 'A' '[' => InCDATA
-    fn in_cdata_opening(&mut self, c: char) -> Result<Option<Event>, ParserError> {
-        static CDATA_PATTERN: [char; 6] = ['C', 'D', 'A', 'T', 'A', '['];
-        if c == CDATA_PATTERN[self.level as usize] {
-            self.level += 1;
-        } else {
-            return self.error(ParserErrorKind::InvalidCdataStart);
-        }
+    /// Marks the end of the input, after which the remaining events are returned
+    pub fn finish(&mut self) {
+        self.parser.data.finished = true;
+    }
 
-        if self.level == 6 {
-            self.level = 0;
-            self.st = State::InCDATA;
-        }
-        Ok(None)
+    /// Prepares the parser for the next document in its input, see `Parser::reset()`
+    pub fn reset(&mut self) {
+        self.parser.reset();
     }
 
-    // Inside CDATA
+    /// Returns the underlying `Parser`, giving access to its position, path and warnings
+    pub fn parser(&self) -> &Parser<impl Read> {
+        &self.parser
+    }
+}
+
+impl Default for PushParser {
+    fn default() -> PushParser {
+        PushParser::new()
+    }
+}
+
+impl Iterator for PushParser {
+    type Item = Result<Event, ParserError>;
+
+    fn next(&mut self) -> Option<Result<Event, ParserError>> {
+        match self.parser.next() {
+            // Waiting for more input to be fed
+            Some(Err(ref err)) if err.kind == ParserErrorKind::NeedMoreData => None,
+            result => result,
+        }
+    }
+}
+
+// Get the namespace bound to a prefix in a stack of scopes.
+// Bindings are stored as a stack of HashMaps, we start searching in the top most HashMap
+// and traverse down until the prefix is found.
+fn bound_namespace<'a>(scopes: &'a [HashMap<String, String>], prefix: &str) -> Option<&'a str> {
+    scopes
+        .iter()
+        .rev()
+        .find_map(|scope| scope.get(prefix))
+        .map(|ns| &ns[..])
+        .filter(|ns| !ns.is_empty())
+}
+
+// Whether parsing can continue after an error under the `recover` option
+fn recoverable(kind: ParserErrorKind) -> bool {
+    !matches!(
+        kind,
+        ParserErrorKind::LimitExceeded | ParserErrorKind::EntityLimitExceeded
+    )
+}
+
+#[inline]
+// Parse a QName to get Prefix and LocalPart
+fn parse_qname(mut qname: String) -> (Option<String>, String) {
+    if let Some(i) = qname.find(':') {
+        let local = qname.split_off(i + 1);
+        qname.pop();
+        (Some(qname), local)
+    } else {
+        (None, qname)
+    }
+}
+
+impl<R> Parser<R>
+where
+    R: Read,
+{
+    // Get the namespace currently bound to a prefix, including the bindings of a start tag
+    // being parsed
+    fn namespace_for_prefix(&self, prefix: &str) -> Option<String> {
+        bound_namespace(&self.namespaces, prefix).map(str::to_owned)
+    }
+
+    // The namespace scopes of the open elements, without that of a start tag read ahead
+    fn scopes(&self) -> &[HashMap<String, String>] {
+        let ahead = self
+            .peeked
+            .iter()
+            .any(|(event, _, _)| matches!(event, Some(Ok(Event::ElementStart(_)))));
+        &self.namespaces[..self.namespaces.len() - usize::from(ahead)]
+    }
+
+    // Expands entity and character references in `text`, taken from the start of `buf`
+    fn expand(&mut self, text: String, in_attribute: bool) -> Result<String, ParserError> {
+        let count = text.matches('&').count().min(self.refs.len());
+        let refs: Vec<Position> = self.refs.drain(..count).collect();
+        if count == 0 {
+            return Ok(text);
+        }
+        let mut out = String::with_capacity(text.len());
+        match self.expand_into(&text, in_attribute, &mut Vec::new(), &mut out) {
+            Ok(unknown) => {
+                for i in unknown {
+                    let at = refs[i];
+                    self.warnings.push(ParserError {
+                        line: at.line,
+                        col: at.col,
+                        offset: at.offset,
+                        kind: ParserErrorKind::InvalidEntity,
+                    });
+                }
+                Ok(out)
+            }
+            Err((kind, i)) => {
+                let at = refs[i];
+                Err(ParserError {
+                    line: at.line,
+                    col: at.col,
+                    offset: at.offset,
+                    kind,
+                })
+            }
+        }
+    }
+
+    // Expands references in `text` into `out`, `stack` holding the entities being expanded.
+    // Returns the indices of the references in `text` through which unknown entities were
+    // passed, errors come with the index of the offending reference.
+    fn expand_into(
+        &mut self,
+        text: &str,
+        in_attribute: bool,
+        stack: &mut Vec<String>,
+        out: &mut String,
+    ) -> Result<Vec<usize>, (ParserErrorKind, usize)> {
+        let mut unknown = Vec::new();
+        let mut parts = text.split('&');
+        out.push_str(parts.next().unwrap_or(""));
+        for (i, part) in parts.enumerate() {
+            let end = part.find(';').ok_or((ParserErrorKind::InvalidEntity, i))?;
+            if self
+                .expand_reference(&part[..end], in_attribute, stack, out)
+                .map_err(|kind| (kind, i))?
+            {
+                unknown.push(i);
+            }
+            out.push_str(&part[end + 1..]);
+        }
+        Ok(unknown)
+    }
+
+    // Expands the reference to `name` into `out`.
+    // Returns whether an unknown entity was passed through.
+    fn expand_reference(
+        &mut self,
+        name: &str,
+        in_attribute: bool,
+        stack: &mut Vec<String>,
+        out: &mut String,
+    ) -> Result<bool, ParserErrorKind> {
+        let entity = match self.entities.get(name) {
+            Some(_) if stack.iter().any(|x| x == name) => {
+                return Err(ParserErrorKind::InvalidEntity)
+            }
+            Some(entity) => entity.clone(),
+            None if name.starts_with('#') => {
+                out.push(char_ref(name).ok_or(ParserErrorKind::InvalidCharRef)?);
+                return Ok(false);
+            }
+            None => {
+                let reference = format!("&{};", name);
+                match (unescape(&reference), self.config.unknown_entities) {
+                    (Ok(unescaped), _) => {
+                        out.push_str(&unescaped);
+                        return Ok(false);
+                    }
+                    (Err(_), UnknownEntities::Error) => return Err(ParserErrorKind::InvalidEntity),
+                    (Err(_), UnknownEntities::Keep) => out.push_str(&reference),
+                    (Err(_), UnknownEntities::Replace) => out.push('\u{FFFD}'),
+                }
+                return Ok(true);
+            }
+        };
+        let replacement = match entity {
+            Entity::Internal(value) => value,
+            // External entities may not be referenced from attribute values
+            Entity::External { .. } if in_attribute => return Err(ParserErrorKind::ExternalEntity),
+            Entity::External {
+                public_id,
+                system_id,
+            } => {
+                let resolver = self
+                    .resolver
+                    .as_mut()
+                    .ok_or(ParserErrorKind::ExternalEntity)?;
+                let text = resolver
+                    .resolve(public_id.as_deref(), &system_id)
+                    .map_err(|_| ParserErrorKind::ExternalEntity)?;
+                entities::strip_text_declaration(&text).to_owned()
+            }
+        };
+        self.expanded += replacement.len();
+        if self.expanded > self.config.max_entity_expansion
+            || stack.len() >= self.config.max_entity_depth
+        {
+            return Err(ParserErrorKind::EntityLimitExceeded);
+        }
+        stack.push(name.to_owned());
+        let unknown = self
+            .expand_into(&replacement, in_attribute, stack, out)
+            .map_err(|(kind, _)| kind)?;
+        stack.pop();
+        Ok(!unknown.is_empty())
+    }
+
+    fn take_buf(&mut self) -> String {
+        self.buf.split_off(0)
+    }
+
+    // Returns `name`, sharing its storage with earlier occurrences if names are interned
+    #[cfg(feature = "interning")]
+    fn intern(&mut self, name: String) -> Name {
+        if let Some(interned) = self.interned.get(&name[..]) {
+            return interned.clone();
+        }
+        let name = Name::from(name);
+        // Documents with ever new names should not grow the table without bounds
+        if self.interned.len() < MAX_INTERNED_NAMES {
+            self.interned.insert(name.clone());
+        }
+        name
+    }
+
+    #[cfg(not(feature = "interning"))]
+    fn intern(&mut self, name: String) -> Name {
+        Name::from(name)
+    }
+
+    // Fails with a `LimitExceeded` error if `len` is above `limit`
+    fn check_limit(&self, limit: Option<usize>, len: usize) -> Result<(), ParserError> {
+        if limit.is_some_and(|max| len > max) {
+            self.error(ParserErrorKind::LimitExceeded)?;
+        }
+        Ok(())
+    }
+
+    // The position of the character just read
+    fn here(&self) -> Position {
+        Position {
+            line: self.line,
+            col: self.col,
+            offset: self.offset.saturating_sub(1),
+        }
+    }
+
+    fn error(&self, kind: ParserErrorKind) -> Result<Option<Event>, ParserError> {
+        Err(self.error_at_last_char(kind))
+    }
+
+    // An error at the character just read
+    fn error_at_last_char(&self, kind: ParserErrorKind) -> ParserError {
+        ParserError {
+            line: self.line,
+            col: self.col,
+            offset: self.offset.saturating_sub(1),
+            kind,
+        }
+    }
+
+    // Checks the parts of an element or attribute name, unless disabled
+    fn valid_qname(&self, prefix: Option<&str>, name: &str) -> bool {
+        !self.config.check_names || (names::is_ncname(name) && prefix.is_none_or(names::is_ncname))
+    }
+
+    // Checks the content outside of the root element in strict mode
+    fn check_document(&mut self, event: &Event) -> Result<(), ParserErrorKind> {
+        if !self.config.strict || !self.path.is_empty() {
+            return Ok(());
+        }
+        match *event {
+            Event::ElementStart(_) if mem::replace(&mut self.root_seen, true) => {
+                Err(ParserErrorKind::MultipleRoots)
+            }
+            Event::Characters(ref text)
+                if !text.chars().all(|c| matches!(c, ' ' | '\t' | '\r' | '\n')) =>
+            {
+                Err(ParserErrorKind::TextOutsideRoot)
+            }
+            Event::CDATA(_) => Err(ParserErrorKind::TextOutsideRoot),
+            _ => Ok(()),
+        }
+    }
+
+    fn warn(&mut self, kind: ParserErrorKind) {
+        self.warnings.push(ParserError {
+            line: self.line,
+            col: self.col,
+            offset: self.offset.saturating_sub(1),
+            kind,
+        });
+    }
+
+    // Discards the construct in which a recoverable error occurred at `c`
+    fn recover(&mut self, c: char) {
+        // A start tag not yet reported leaves the namespace scope entered for it
+        let in_start_tag = matches!(
+            self.st,
+            State::InTag | State::InAttrName | State::InAttrValue | State::ExpectDelimiter
+        );
+        if in_start_tag && !mem::take(&mut self.streaming) {
+            self.namespaces.pop();
+        }
+        self.buf.clear();
+        self.attributes.clear();
+        self.refs.clear();
+        self.open_ref = None;
+        self.chunked = 0;
+        self.name = None;
+        self.attr = None;
+        self.delim = None;
+        self.level = 0;
+        self.doctype_scanner = DoctypeScanner::default();
+        self.streaming = false;
+        self.streamed_attributes.clear();
+        self.kept_chunks = None;
+        // The error's span and source cover the input discarded so far
+        self.span = self.span.end..self.offset;
+        if self.config.fidelity {
+            self.source = mem::take(&mut self.pending_source);
+            if c == '<' {
+                self.pending_source.extend(self.source.pop());
+            }
+        }
+        self.st = State::Recovering;
+        self.recovering(c);
+    }
+
+    fn parse_character(&mut self, c: char) -> Result<Option<Event>, ParserError> {
+        // println(fmt!("Now in state: %?", self.st));
+        match self.st {
+            State::OutsideTag => self.outside_tag(c),
+            State::TagOpened => self.tag_opened(c),
+            State::InProcessingInstructions => self.in_processing_instructions(c),
+            State::InTagName => self.in_tag_name(c),
+            State::InCloseTagName => self.in_close_tag_name(c),
+            State::InTag => self.in_tag(c),
+            State::InAttrName => self.in_attr_name(c),
+            State::InAttrValue => self.in_attr_value(c),
+            State::ExpectDelimiter => self.expect_delimiter(c),
+            State::ExpectClose => self.expect_close(c),
+            State::ExpectSpaceOrClose => self.expect_space_or_close(c),
+            State::InExclamationMark => self.in_exclamation_mark(c),
+            State::InCDATAOpening => self.in_cdata_opening(c),
+            State::InCDATA => self.in_cdata(c),
+            State::InCommentOpening => self.in_comment_opening(c),
+            State::InComment1 => self.in_comment1(c),
+            State::InComment2 => self.in_comment2(c),
+            State::InDoctype => self.in_doctype(c),
+            State::Recovering => {
+                self.recovering(c);
+                Ok(None)
+            }
+        }
+    }
+
+    // Outside any tag, or other construct
+    // '<' => TagOpened, producing Event::Characters
+    fn outside_tag(&mut self, c: char) -> Result<Option<Event>, ParserError> {
+        if c == '<' {
+            self.raw.clear();
+            self.raw.push(b'<');
+        }
+        match c {
+            '<' if self.buf.is_empty() => {
+                self.st = State::TagOpened;
+                self.chunked = 0;
+            }
+            '<' => {
+                self.st = State::TagOpened;
+                self.chunked = 0;
+                self.open_ref = None;
+                let buf = self.take_buf();
+                let buf = self.expand(buf, false)?;
+                return Ok(Some(Event::Characters(buf)));
+            }
+            '&' => {
+                self.refs.push(self.here());
+                self.open_ref = Some(self.buf.len());
+                self.buf.push(c);
+            }
+            ';' => {
+                self.open_ref = None;
+                self.buf.push(c);
+            }
+            _ => self.buf.push(c),
+        }
+        self.check_limit(self.config.max_text_length, self.chunked + self.buf.len())?;
+        if self
+            .config
+            .max_text_chunk
+            .is_some_and(|max| self.buf.len() >= max)
+        {
+            // References are expanded whole, so the chunk ends before one being read. One
+            // longer than any entity name expected is not waited for, and fails to expand.
+            let end = match self.open_ref {
+                Some(start) if self.buf.len() - start <= MAX_CHUNKED_REF_LEN => start,
+                _ => self.buf.len(),
+            };
+            if end > 0 {
+                let rest = self.buf.split_off(end);
+                let text = mem::replace(&mut self.buf, rest);
+                self.open_ref = self.open_ref.map(|_| 0);
+                self.chunked += end;
+                return Ok(Some(Event::Characters(self.expand(text, false)?)));
+            }
+        }
+        Ok(None)
+    }
+
+    // Character following a '<', starting a tag or other construct
+    // '?' => InProcessingInstructions
+    // '!' => InExclamationMark
+    // '/' => InCloseTagName
+    //  _  => InTagName
+    fn tag_opened(&mut self, c: char) -> Result<Option<Event>, ParserError> {
+        self.st = match c {
+            ' ' | '\t' | '\r' | '\n' if self.config.check_names => {
+                return self.error(ParserErrorKind::InvalidName)
+            }
+            '?' => State::InProcessingInstructions,
+            '!' => State::InExclamationMark,
+            '/' => State::InCloseTagName,
+            _ => {
+                self.buf.push(c);
+                State::InTagName
+            }
+        };
+        Ok(None)
+    }
+
+    // Inside a processing instruction
+    // '?' '>' => OutsideTag, producing PI
+    fn in_processing_instructions(&mut self, c: char) -> Result<Option<Event>, ParserError> {
+        match c {
+            '?' => {
+                self.level = 1;
+                self.buf.push(c);
+            }
+            '>' if self.level == 1 => {
+                self.level = 0;
+                self.st = State::OutsideTag;
+                let _ = self.buf.pop();
+                let buf = self.take_buf();
+                let target = buf.split([' ', '\t', '\r', '\n']).next().unwrap_or("");
+                if self.config.check_names && !names::is_ncname(target) {
+                    return self.error(ParserErrorKind::InvalidName);
+                }
+                if target == "xml" {
+                    // No event was returned before, which would have ended after the start
+                    if self.span.end > 0 || self.doctype_seen {
+                        return self.error(ParserErrorKind::MisplacedXmlDecl);
+                    }
+                    return match XmlDecl::from_pi(&buf) {
+                        Some(decl) => Ok(Some(Event::XmlDecl(decl))),
+                        None => self.error(ParserErrorKind::InvalidXmlDecl),
+                    };
+                }
+                return Ok(Some(Event::PI(buf)));
+            }
+            _ => self.buf.push(c),
+        }
+        Ok(None)
+    }
+
+    // Inside a tag name (opening tag)
+    // '/' => ExpectClose, producing Event::ElementStart
+    // '>' => OutsideTag, producing Event::ElementStart
+    // ' ' or '\t' or '\r' or '\n' => InTag
+    fn in_tag_name(&mut self, c: char) -> Result<Option<Event>, ParserError> {
+        match c {
+            '/' | '>' => {
+                let (prefix, name) = parse_qname(self.take_buf());
+                if !self.valid_qname(prefix.as_deref(), &name) {
+                    return self.error(ParserErrorKind::InvalidName);
+                }
+                let ns = match prefix {
+                    None => self.namespace_for_prefix(""),
+                    Some(ref pre) => match self.namespace_for_prefix(pre) {
+                        None => return self.error(ParserErrorKind::UnboundNsPrefixInTagName),
+                        ns => ns,
+                    },
+                };
+
+                self.namespaces.push(HashMap::new());
+                self.st = if c == '/' {
+                    self.name = Some((prefix.clone(), name.clone()));
+                    State::ExpectClose
+                } else {
+                    State::OutsideTag
+                };
+
+                return Ok(Some(Event::ElementStart(StartTag {
+                    name: QName {
+                        name: self.intern(name),
+                        ns,
+                    },
+                    prefix,
+                    attributes: AttrMap::new(),
+                })));
+            }
+            ' ' | '\t' | '\r' | '\n' => {
+                let (prefix, name) = parse_qname(self.take_buf());
+                if !self.valid_qname(prefix.as_deref(), &name) {
+                    return self.error(ParserErrorKind::InvalidName);
+                }
+                self.namespaces.push(HashMap::new());
+                self.name = Some((prefix, name));
+                self.attribute_count = 0;
+                self.st = State::InTag;
+            }
+            _ => self.buf.push(c),
+        }
+        Ok(None)
+    }
+
+    // Inside a tag name (closing tag)
+    // '>' => OutsideTag, producing ElementEnd
+    // ' ' or '\t' or '\r' or '\n' => ExpectSpaceOrClose
+    fn in_close_tag_name(&mut self, c: char) -> Result<Option<Event>, ParserError> {
+        match c {
+            '>' => {
+                let (prefix, name) = parse_qname(self.take_buf());
+                self.st = State::OutsideTag;
+                let name = self.intern(name);
+                self.end_tag(prefix, name)
+            }
+            ' ' | '\t' | '\r' | '\n' => {
+                self.name = Some(parse_qname(self.take_buf()));
+                self.st = State::ExpectSpaceOrClose;
+                Ok(None)
+            }
+            _ => {
+                self.buf.push(c);
+                Ok(None)
+            }
+        }
+    }
+
+    // Resolves the namespace of a closing tag and leaves its namespace scope
+    fn end_tag(
+        &mut self,
+        prefix: Option<String>,
+        name: Name,
+    ) -> Result<Option<Event>, ParserError> {
+        if self.config.lenient {
+            return Ok(self.lenient_end_tag(prefix, name));
+        }
+        let ns = match prefix {
+            None => self.namespace_for_prefix(""),
+            Some(ref pre) => match self.namespace_for_prefix(pre) {
+                None => return self.error(ParserErrorKind::UnboundNsPrefixInTagName),
+                ns => ns,
+            },
+        };
+        // The end tag must repeat the qualified name of the start tag
+        let open = self.path.last().map(|open| (&open.name, &open.ns));
+        if open != Some((&name, &ns)) || self.prefixes.last() != Some(&prefix) {
+            return self.error(ParserErrorKind::TagMismatch);
+        }
+        Ok(Some(Event::ElementEnd(EndTag {
+            name: QName { name, ns },
+            prefix,
+        })))
+    }
+
+    // Closes the innermost open element whose qualified name matches a closing tag ignoring
+    // ASCII case, after the elements left open within it. Unmatched closing tags are skipped.
+    fn lenient_end_tag(&mut self, prefix: Option<String>, name: Name) -> Option<Event> {
+        let same = |a: &str, b: &str| a.eq_ignore_ascii_case(b);
+        let found = self
+            .path
+            .iter()
+            .zip(&self.prefixes)
+            .rposition(|(open, open_prefix)| {
+                same(&open.name, &name)
+                    && match (open_prefix, &prefix) {
+                        (Some(a), Some(b)) => same(a, b),
+                        (a, b) => a.is_none() && b.is_none(),
+                    }
+            });
+        let depth = match found {
+            Some(i) => i + 1,
+            None => {
+                self.warn(ParserErrorKind::TagMismatch);
+                return None;
+            }
+        };
+        let exact = self.path.last().map(|open| &open.name) == Some(&name)
+            && self.prefixes.last() == Some(&prefix);
+        if depth < self.path.len() {
+            self.unclosed = Some((prefix, name));
+        }
+        if !exact {
+            self.warn(ParserErrorKind::TagMismatch);
+        }
+        Some(self.close_open_element())
+    }
+
+    // Returns the end of the innermost open element
+    fn close_open_element(&self) -> Event {
+        let open = self.path.last().expect("Internal error: No open element");
+        let prefix = self.prefixes.last().cloned().flatten();
+        let end = EndTag {
+            name: open.clone(),
+            prefix,
+        };
+        Event::ElementEnd(end)
+    }
+
+    // Inside a tag, parsing attributes
+    // '/' => ExpectClose, producing StartTag
+    // '>' => OutsideTag, producing StartTag
+    // ' ' or '\t' or '\r' or '\n' => InAttrName
+    fn in_tag(&mut self, c: char) -> Result<Option<Event>, ParserError> {
+        match c {
+            '/' | '>' => {
+                let event = if mem::take(&mut self.streaming) {
+                    self.streamed_attributes.clear();
+                    None
+                } else {
+                    self.start_tag()?
+                };
+                self.st = if c == '/' {
+                    State::ExpectClose
+                } else {
+                    self.name = None;
+                    State::OutsideTag
+                };
+                return Ok(event);
+            }
+            ' ' | '\t' | '\r' | '\n' => (),
+            _ => {
+                self.buf.push(c);
+                self.st = State::InAttrName;
+            }
+        }
+        Ok(None)
+    }
+
+    // Produces the Event::ElementStart for the tag name and attributes read so far
+    fn start_tag(&mut self) -> Result<Option<Event>, ParserError> {
+        let attributes = mem::take(&mut self.attributes);
+        let (prefix, name) = self
+            .name
+            .clone()
+            .expect("Internal error: No element name set");
+        let ns = match prefix {
+            None => self.namespace_for_prefix(""),
+            Some(ref pre) => match self.namespace_for_prefix(pre) {
+                None => return self.error(ParserErrorKind::UnboundNsPrefixInTagName),
+                ns => ns,
+            },
+        };
+
+        let mut attributes_map: AttrMap<QName, String> = AttrMap::new();
+
+        // At this point attribute namespaces are really just prefixes,
+        // map them to the actual namespace
+        for (name, ns, value) in attributes {
+            let ns = match ns {
+                None => None,
+                Some(ref prefix) => match self.namespace_for_prefix(prefix) {
+                    None => return self.error(ParserErrorKind::UnboundNsPrefixInAttributeName),
+                    ns => ns,
+                },
+            };
+            let key = QName {
+                name: self.intern(name),
+                ns,
+            };
+            if attributes_map.contains_key(&key) {
+                match self.config.duplicate_attributes {
+                    DuplicateAttributes::Error => {
+                        return self.error(ParserErrorKind::DuplicateAttribute)
+                    }
+                    DuplicateAttributes::KeepFirst => {
+                        self.warn(ParserErrorKind::DuplicateAttribute);
+                        continue;
+                    }
+                    DuplicateAttributes::KeepLast => self.warn(ParserErrorKind::DuplicateAttribute),
+                }
+            }
+            attributes_map.insert(key, value);
+        }
+        // Attributes following in chunks are checked against those reported here
+        if self.streaming {
+            self.streamed_attributes = attributes_map.keys().cloned().collect();
+        }
+
+        Ok(Some(Event::ElementStart(StartTag {
+            name: QName {
+                name: self.intern(name),
+                ns,
+            },
+            prefix,
+            attributes: attributes_map,
+        })))
+    }
+
+    // Inside an attribute name
+    // '=' => ExpectDelimiter
+    // '/' or '>' => as in InTag, after adding an attribute without value in lenient mode
+    fn in_attr_name(&mut self, c: char) -> Result<Option<Event>, ParserError> {
+        match c {
+            '=' => {
+                self.level = 0;
+                self.attr = Some(self.attr_name()?);
+                self.st = State::ExpectDelimiter;
+            }
+            ' ' | '\t' | '\r' | '\n' => self.level = 1,
+            '/' | '>' if self.config.lenient => {
+                let event = self.valueless_attribute()?;
+                let tag = self.in_tag(c)?;
+                return Ok(event.or(tag));
+            }
+            _ if self.level == 0 => self.buf.push(c),
+            _ if self.config.lenient => {
+                let event = self.valueless_attribute()?;
+                self.buf.push(c);
+                self.st = State::InAttrName;
+                return Ok(event);
+            }
+            _ => return self.error(ParserErrorKind::SpaceInAttributeName),
+        }
+        Ok(None)
+    }
+
+    // Takes the name of the attribute being parsed from `buf`
+    fn attr_name(&mut self) -> Result<(Option<String>, String), ParserError> {
+        let (prefix, name) = parse_qname(self.take_buf());
+        if !self.valid_qname(prefix.as_deref(), &name) {
+            self.error(ParserErrorKind::InvalidName)?;
+        }
+        self.attribute_count += 1;
+        self.check_limit(self.config.max_attributes, self.attribute_count)?;
+        Ok((prefix, name))
+    }
+
+    // Adds the attribute named in `buf` with an empty value, in lenient mode
+    fn valueless_attribute(&mut self) -> Result<Option<Event>, ParserError> {
+        self.level = 0;
+        self.warn(ParserErrorKind::UndelimitedAttribute);
+        let (prefix, name) = self.attr_name()?;
+        self.st = State::InTag;
+        self.add_attribute(prefix, name, String::new())
+    }
+
+    // Inside an attribute value
+    // delimiter => InTag, adds attribute
+    // ' ' or '\t' or '\r' or '\n' or '>' => as delimiter, ending an unquoted value
+    fn in_attr_value(&mut self, c: char) -> Result<Option<Event>, ParserError> {
+        let closed = match self.delim {
+            Some(delim) => c == delim,
+            None => matches!(c, ' ' | '\t' | '\r' | '\n' | '>'),
+        };
+        if closed {
+            self.st = State::InTag;
+            let attr = self.attr.take();
+            let (prefix, name) =
+                attr.expect("Internal error: In attribute value, but no attribute name set");
+            let value = self.take_buf();
+            let value = self.expand(value, true)?;
+            let event = self.add_attribute(prefix, name, value)?;
+            // The '>' ending an unquoted value also ends the tag
+            if self.delim.take().is_none() && c == '>' {
+                let tag = self.in_tag(c)?;
+                return Ok(event.or(tag));
+            }
+            return Ok(event);
+        } else {
+            if c == '&' {
+                self.refs.push(self.here());
+            }
+            self.buf.push(c);
+            self.value_len += c.len_utf8();
+            self.check_limit(self.config.max_attribute_length, self.value_len)?;
+            // Namespace declarations are always kept whole
+            let declaration = match self.attr {
+                Some((None, ref name)) => name == "xmlns",
+                Some((Some(ref prefix), _)) => prefix == "xmlns",
+                None => false,
+            };
+            if !declaration
+                && self
+                    .config
+                    .attribute_chunk_size
+                    .is_some_and(|size| self.buf.len() > size)
+            {
+                if !self.streaming {
+                    self.streaming = true;
+                    return self.start_tag();
+                }
+                // Keep an incomplete entity for the next chunk
+                let split = match self.buf.rfind('&') {
+                    Some(i) if !self.buf[i..].contains(';') => i,
+                    _ => self.buf.len(),
+                };
+                if split > 0 {
+                    let rest = self.buf.split_off(split);
+                    let value = mem::replace(&mut self.buf, rest);
+                    let value = self.expand(value, true)?;
+                    let (prefix, name) = self
+                        .attr
+                        .clone()
+                        .expect("Internal error: In attribute value, but no attribute name set");
+                    return self.attribute_chunk(name, prefix, value, false);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    // Adds an attribute to the start tag being parsed, binding the prefix it declares
+    fn add_attribute(
+        &mut self,
+        prefix: Option<String>,
+        name: String,
+        value: String,
+    ) -> Result<Option<Event>, ParserError> {
+        let last = self
+            .namespaces
+            .last_mut()
+            .expect("Internal error: Empty namespace stack");
+        let declared = match prefix {
+            None if name == "xmlns" => Some(String::new()),
+            Some(ref prefix) if prefix == "xmlns" => Some(name.clone()),
+            _ => None,
+        };
+        if let Some(declared) = declared {
+            // Only the kept declaration of a duplicate binds the prefix
+            if self.config.duplicate_attributes == DuplicateAttributes::KeepFirst {
+                last.entry(declared).or_insert_with(|| value.clone());
+            } else {
+                last.insert(declared, value.clone());
+            }
+        }
+
+        if self.streaming {
+            return self.attribute_chunk(name, prefix, value, true);
+        }
+        self.attributes.push((name, prefix, value));
+        Ok(None)
+    }
+
+    // Produces an Event::AttributeChunk, resolving the attribute's prefix. The chunks of a
+    // duplicate attribute are dropped if the first one is kept.
+    fn attribute_chunk(
+        &mut self,
+        name: String,
+        prefix: Option<String>,
+        value: String,
+        last: bool,
+    ) -> Result<Option<Event>, ParserError> {
+        let ns = match prefix {
+            None => None,
+            Some(ref prefix) => match self.namespace_for_prefix(prefix) {
+                None => return self.error(ParserErrorKind::UnboundNsPrefixInAttributeName),
+                ns => ns,
+            },
+        };
+        let kept = match self.kept_chunks {
+            Some(kept) => kept,
+            None => self.streamed_attribute(QName::new(name.clone(), ns.clone()))?,
+        };
+        self.kept_chunks = if last { None } else { Some(kept) };
+        if !kept {
+            return Ok(None);
+        }
+        Ok(Some(Event::AttributeChunk(AttributeChunk {
+            name,
+            ns,
+            value,
+            last,
+        })))
+    }
+
+    // Records an attribute of a streamed start tag, returning whether its value is kept
+    // under the duplicate attribute policy
+    fn streamed_attribute(&mut self, key: QName) -> Result<bool, ParserError> {
+        if !self.streamed_attributes.contains(&key) {
+            self.streamed_attributes.push(key);
+            return Ok(true);
+        }
+        match self.config.duplicate_attributes {
+            DuplicateAttributes::Error => {
+                Err(self.error_at_last_char(ParserErrorKind::DuplicateAttribute))
+            }
+            DuplicateAttributes::KeepFirst => {
+                self.warn(ParserErrorKind::DuplicateAttribute);
+                Ok(false)
+            }
+            DuplicateAttributes::KeepLast => {
+                self.warn(ParserErrorKind::DuplicateAttribute);
+                Ok(true)
+            }
+        }
+    }
+
+    // Looking for an attribute value delimiter
+    // '"' or '\'' => InAttrValue, sets delimiter
+    fn expect_delimiter(&mut self, c: char) -> Result<Option<Event>, ParserError> {
+        match c {
+            '"' | '\'' => {
+                self.delim = Some(c);
+                self.value_len = 0;
+                self.st = State::InAttrValue;
+            }
+            ' ' | '\t' | '\r' | '\n' => (),
+            _ if self.config.lenient => {
+                self.warn(ParserErrorKind::UndelimitedAttribute);
+                self.value_len = 0;
+                self.st = State::InAttrValue;
+                return self.in_attr_value(c);
+            }
+            _ => return self.error(ParserErrorKind::UndelimitedAttribute),
+        }
+        Ok(None)
+    }
+
+    // Expect closing '>' of an empty-element tag (no whitespace allowed)
+    // '>' => OutsideTag, producing ElementEnd
+    fn expect_close(&mut self, c: char) -> Result<Option<Event>, ParserError> {
+        match c {
+            '>' => {
+                self.st = State::OutsideTag;
+                let (prefix, name) = self
+                    .name
+                    .take()
+                    .expect("Internal error: No element name set");
+                let name = self.intern(name);
+                self.end_tag(prefix, name)
+            }
+            _ => self.error(ParserErrorKind::ExpectedTagClose),
+        }
+    }
+
+    // Expect closing '>' of an end tag
+    // '>' => OutsideTag, producing ElementEnd
+    fn expect_space_or_close(&mut self, c: char) -> Result<Option<Event>, ParserError> {
+        match c {
+            ' ' | '\t' | '\r' | '\n' => Ok(None),
+            '>' => {
+                self.st = State::OutsideTag;
+                let (prefix, name) = self
+                    .name
+                    .take()
+                    .expect("Internal error: No element name set");
+                let name = self.intern(name);
+                self.end_tag(prefix, name)
+            }
+            _ => self.error(ParserErrorKind::ExpectedLwsOrTagClose),
+        }
+    }
+
+    // After an '!' trying to determine the type of the following construct
+    // '-' => InCommentOpening
+    // '[' => InCDATAOpening
+    // 'D' => InDoctype
+    fn in_exclamation_mark(&mut self, c: char) -> Result<Option<Event>, ParserError> {
+        self.st = match c {
+            '-' => State::InCommentOpening,
+            '[' => State::InCDATAOpening,
+            'D' if self.config.strict && (self.root_seen || self.doctype_seen) => {
+                return self.error(ParserErrorKind::MisplacedDoctype)
+            }
+            'D' => State::InDoctype,
+            _ => return self.error(ParserErrorKind::MalformedXml),
+        };
+        Ok(None)
+    }
+
+    // Opening sequence of Event::CDATA
+    // 'C' 'D' 'A' 'T' 'A' '[' => InCDATA
+    fn in_cdata_opening(&mut self, c: char) -> Result<Option<Event>, ParserError> {
+        static CDATA_PATTERN: [char; 6] = ['C', 'D', 'A', 'T', 'A', '['];
+        if c == CDATA_PATTERN[self.level as usize] {
+            self.level += 1;
+        } else {
+            return self.error(ParserErrorKind::InvalidCdataStart);
+        }
+
+        if self.level == 6 {
+            self.level = 0;
+            self.st = State::InCDATA;
+        }
+        Ok(None)
+    }
+
+    // Inside CDATA
     // ']' ']' '>' => OutsideTag, producing Event::CDATA
     fn in_cdata(&mut self, c: char) -> Result<Option<Event>, ParserError> {
         match c {
-            ']' => {
-                self.buf.push(c);
+            ']' => {
+                self.buf.push(c);
+                self.level += 1;
+            }
+            '>' if self.level >= 2 => {
+                self.st = State::OutsideTag;
+                self.level = 0;
+                let len = self.buf.len();
+                self.buf.truncate(len - 2);
+                let buf = self.take_buf();
+                // Nothing is left after a section ending with a full chunk
+                if buf.is_empty() && mem::take(&mut self.chunked) > 0 {
+                    return Ok(None);
+                }
+                self.chunked = 0;
+                return Ok(Some(Event::CDATA(buf)));
+            }
+            _ => {
+                self.buf.push(c);
+                self.level = 0;
+            }
+        }
+        // The buffer holds up to two characters of the closing "]]>"
+        let len = self.buf.len() - usize::from(self.level.min(2));
+        self.check_limit(self.config.max_text_length, self.chunked + len)?;
+        if len > 0 && self.config.max_text_chunk.is_some_and(|max| len >= max) {
+            // Those characters stay in the buffer, in case the section ends
+            let rest = self.buf.split_off(len);
+            let text = mem::replace(&mut self.buf, rest);
+            self.chunked += len;
+            return Ok(Some(Event::CDATA(text)));
+        }
+        Ok(None)
+    }
+
+    // Opening sequence of a comment
+    // '-' => InComment1
+    fn in_comment_opening(&mut self, c: char) -> Result<Option<Event>, ParserError> {
+        if c == '-' {
+            self.st = State::InComment1;
+            self.level = 0;
+            Ok(None)
+        } else {
+            self.error(ParserErrorKind::InvalidCommentStart)
+        }
+    }
+
+    // Inside a comment
+    // '-' '-' => InComment2
+    fn in_comment1(&mut self, c: char) -> Result<Option<Event>, ParserError> {
+        if c == '-' {
+            self.level += 1;
+        } else {
+            self.level = 0;
+        }
+
+        self.buf.push(c);
+        // Trailing dashes may belong to the closing "-->"
+        let len = self.buf.len() - usize::from(self.level);
+        self.check_limit(self.config.max_comment_length, len)?;
+
+        if self.level == 2 {
+            self.level = 0;
+            self.st = State::InComment2;
+        }
+
+        Ok(None)
+    }
+
+    // Closing a comment
+    // '>' => OutsideTag, producing Comment
+    fn in_comment2(&mut self, c: char) -> Result<Option<Event>, ParserError> {
+        if c != '>' {
+            self.error(ParserErrorKind::InvalidCommentContent)
+        } else {
+            self.st = State::OutsideTag;
+            let len = self.buf.len();
+            self.buf.truncate(len - 2);
+            let buf = self.take_buf();
+            Ok(Some(Event::Comment(buf)))
+        }
+    }
+
+    // Skipping input after a recoverable error
+    // '>' => OutsideTag
+    // '<' => TagOpened
+    fn recovering(&mut self, c: char) {
+        // The next event starts where parsing resumes
+        match c {
+            '>' => {
+                self.st = State::OutsideTag;
+                self.span.end = self.offset;
+                self.pending_source.clear();
+            }
+            '<' => {
+                self.st = State::TagOpened;
+                self.span.end = self.offset - 1;
+                let len = self.pending_source.len();
+                self.pending_source.drain(..len.saturating_sub(1));
+                self.raw.clear();
+                self.raw.push(b'<');
+            }
+            _ => (),
+        }
+    }
+
+    // Inside a doctype
+    // '>' after appropriate opening => OutsideTag
+    fn in_doctype(&mut self, c: char) -> Result<Option<Event>, ParserError> {
+        static DOCTYPE_PATTERN: [char; 6] = ['O', 'C', 'T', 'Y', 'P', 'E'];
+        match self.level {
+            0..=5 => {
+                if c == DOCTYPE_PATTERN[self.level as usize] {
+                    self.level += 1;
+                } else {
+                    return self.error(ParserErrorKind::InvalidDoctype);
+                }
+            }
+            6 => {
+                match c {
+                    ' ' | '\t' | '\r' | '\n' => (),
+                    _ => return self.error(ParserErrorKind::InvalidDoctype),
+                }
                 self.level += 1;
             }
-            '>' if self.level >= 2 => {
-                self.st = State::OutsideTag;
-                self.level = 0;
-                let len = self.buf.len();
-                self.buf.truncate(len - 2);
-                let buf = self.take_buf();
-                return Ok(Some(Event::CDATA(buf)));
+            _ if self.doctype_scanner.feed(c) => {
+                self.level = 0;
+                self.doctype_scanner = DoctypeScanner::default();
+                self.st = State::OutsideTag;
+                let text = self.take_buf();
+                return self.doctype(&text);
+            }
+            _ => self.buf.push(c),
+        }
+        Ok(None)
+    }
+
+    // Reads the entities declared by a document type declaration, and in its external
+    // subset if a resolver is set, producing Event::Doctype
+    fn doctype(&mut self, text: &str) -> Result<Option<Event>, ParserError> {
+        let (mut doctype, declared) = match entities::parse_doctype(text) {
+            Ok(parsed) => parsed,
+            Err(_) => return self.error(ParserErrorKind::InvalidDoctype),
+        };
+        // The internal subset is kept as found in the input, before line ends are normalized
+        let raw = str::from_utf8(&self.raw).ok();
+        let raw = raw.and_then(|raw| raw.strip_prefix("<!DOCTYPE")?.strip_suffix('>'));
+        if let Some(Ok((verbatim, _))) = raw.map(entities::parse_doctype) {
+            doctype.internal_subset = verbatim.internal_subset;
+        }
+        self.doctype_seen = true;
+        self.entities = declared;
+        if let (Some(system_id), Some(resolver)) = (&doctype.system_id, self.resolver.as_mut()) {
+            let dtd = match resolver.resolve(doctype.public_id.as_deref(), system_id) {
+                Ok(dtd) => dtd,
+                Err(_) => return self.error(ParserErrorKind::ExternalEntity),
+            };
+            let dtd = entities::strip_text_declaration(&dtd);
+            match entities::parse_declarations(dtd, &mut self.entities) {
+                Ok(len) if len == dtd.len() => (),
+                _ => return self.error(ParserErrorKind::InvalidDoctype),
+            }
+        }
+        Ok(Some(Event::Doctype(doctype)))
+    }
+}
+
+#[cfg(test)]
+mod parser_tests {
+    use super::{
+        DuplicateAttributes, Parser, ParserConfig, ParserErrorKind, Position, PushParser,
+        UnknownEntities,
+    };
+    use crate::{
+        AttrMap, BuilderError, Doctype, Element, ElementBuilder, EndTag, Event, EventWriter,
+        ParserError, QName, StartTag, XmlDecl,
+    };
+    use std::io::{self, Read};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+
+    #[test]
+    fn test_start_tag() {
+        let s = "<a>".as_bytes();
+        let p = Parser::new(s);
+        let mut i = 0u8;
+        for event in p {
+            i += 1;
+            assert_eq!(
+                event,
+                Ok(Event::ElementStart(StartTag {
+                    name: QName::new("a".to_owned(), None),
+                    prefix: None,
+                    attributes: AttrMap::new()
+                })),
+            );
+        }
+        assert_eq!(i, 1u8);
+    }
+
+    #[test]
+    fn test_end_tag() {
+        let p = Parser::new("<a></a>".as_bytes());
+        let mut i = 0u8;
+        for event in p.skip(1) {
+            i += 1;
+            assert_eq!(
+                event,
+                Ok(Event::ElementEnd(EndTag {
+                    name: QName::new("a".to_owned(), None),
+                    prefix: None
+                })),
+            );
+        }
+        assert_eq!(i, 1u8);
+    }
+
+    #[test]
+    fn test_tag_mismatch() {
+        let inputs = [
+            "</a>",
+            "<a></b>",
+            "<a><b></a>",
+            "<p:a xmlns:p='urn:x' xmlns:q='urn:x'></q:a>",
+        ];
+        for input in inputs {
+            let err = Parser::new(input.as_bytes()).find_map(Result::err).unwrap();
+            assert_eq!(err.kind, ParserErrorKind::TagMismatch, "{}", input);
+            assert_eq!(err.offset, input.len() as u64 - 1);
+        }
+    }
+
+    #[test]
+    fn test_self_closing_with_space() {
+        let s = "<register />".as_bytes();
+        let p = Parser::new(s);
+        let v: Vec<Result<Event, ParserError>> = p.collect();
+        assert_eq!(
+            v,
+            vec![
+                Ok(Event::ElementStart(StartTag {
+                    name: QName::new("register".to_owned(), None),
+                    prefix: None,
+                    attributes: AttrMap::new()
+                })),
+                Ok(Event::ElementEnd(EndTag {
+                    name: QName::new("register".to_owned(), None),
+                    prefix: None,
+                }))
+            ],
+        );
+    }
+
+    #[test]
+    fn test_self_closing_without_space() {
+        let s = "<register/>".as_bytes();
+        let p = Parser::new(s);
+        let v: Vec<Result<Event, ParserError>> = p.collect();
+        assert_eq!(
+            v,
+            vec![
+                Ok(Event::ElementStart(StartTag {
+                    name: QName::new("register".to_owned(), None),
+                    prefix: None,
+                    attributes: AttrMap::new()
+                })),
+                Ok(Event::ElementEnd(EndTag {
+                    name: QName::new("register".to_owned(), None),
+                    prefix: None,
+                }))
+            ],
+        );
+    }
+
+    #[test]
+    fn test_self_closing_namespace() {
+        let s = "<foo:a xmlns:foo='urn:foo'/>".as_bytes();
+        let p = Parser::new(s);
+
+        let v: Vec<Result<Event, ParserError>> = p.collect();
+        let mut attr: AttrMap<QName, String> = AttrMap::new();
+        attr.insert(
+            QName::new(
+                "foo".to_owned(),
+                Some("http://www.w3.org/2000/xmlns/".to_owned()),
+            ),
+            "urn:foo".to_owned(),
+        );
+        assert_eq!(
+            v,
+            vec![
+                Ok(Event::ElementStart(StartTag {
+                    name: QName::new("a".to_owned(), Some("urn:foo".to_owned())),
+                    prefix: Some("foo".to_owned()),
+                    attributes: attr,
+                })),
+                Ok(Event::ElementEnd(EndTag {
+                    name: QName::new("a".to_owned(), Some("urn:foo".to_owned())),
+                    prefix: Some("foo".to_owned()),
+                }))
+            ],
+        );
+    }
+
+    #[test]
+    fn test_pi() {
+        let s = "<?xml-stylesheet href='a.xsl' type='text/xsl'?>".as_bytes();
+        let p = Parser::new(s);
+        let mut i = 0u8;
+
+        for event in p {
+            i += 1;
+            assert_eq!(
+                event,
+                Ok(Event::PI(
+                    "xml-stylesheet href='a.xsl' type='text/xsl'".to_owned()
+                )),
+            );
+        }
+        assert_eq!(i, 1u8);
+    }
+
+    #[test]
+    fn test_comment() {
+        let s = "<!--Nothing to see-->".as_bytes();
+        let p = Parser::new(s);
+        let mut i = 0u8;
+        for event in p {
+            i += 1;
+            assert_eq!(event, Ok(Event::Comment("Nothing to see".to_owned())));
+        }
+        assert_eq!(i, 1u8);
+    }
+    #[test]
+    fn test_cdata() {
+        let s = "<![CDATA[<html><head><title>x</title></head><body/></html>]]>".as_bytes();
+        let p = Parser::new(s);
+        let mut i = 0u8;
+        for event in p {
+            i += 1;
+            assert_eq!(
+                event,
+                Ok(Event::CDATA(
+                    "<html><head><title>x</title></head><body/></html>".to_owned()
+                )),
+            );
+        }
+        assert_eq!(i, 1u8);
+    }
+
+    #[test]
+    fn test_characters() {
+        let s = "<text>Hello World, it&apos;s a nice day</text>".as_bytes();
+        let p = Parser::new(s);
+        let mut i = 0u8;
+        for event in p {
+            i += 1;
+            if i == 2 {
+                assert_eq!(
+                    event,
+                    Ok(Event::Characters("Hello World, it's a nice day".to_owned())),
+                );
+            }
+        }
+        assert_eq!(i, 3u8);
+    }
+
+    #[test]
+    fn test_doctype() {
+        let s = "<!DOCTYPE html>".as_bytes();
+        let p = Parser::new(s);
+        let mut i = 0u8;
+
+        for event in p {
+            i += 1;
+            assert_eq!(
+                event,
+                Ok(Event::Doctype(Doctype {
+                    name: "html".to_owned(),
+                    public_id: None,
+                    system_id: None,
+                    internal_subset: None,
+                })),
+            );
+        }
+        assert_eq!(i, 1u8);
+    }
+
+    #[test]
+    fn test_capture_raw() {
+        let s = "<a>text<b x = 'y' ><!-- c --><c/></b ><d/></a>".as_bytes();
+        let mut p = Parser::new(s);
+        assert!(p.capture_raw().is_err());
+        p.next();
+        p.next();
+        p.next();
+        assert_eq!(
+            p.capture_raw().unwrap(),
+            b"<b x = 'y' ><!-- c --><c/></b >".to_vec(),
+        );
+        p.next();
+        assert_eq!(p.capture_raw().unwrap(), b"<d/>".to_vec());
+    }
+
+    #[test]
+    #[cfg(feature = "ordered_attrs")]
+    fn test_attribute_order() {
+        let input = "<a href='/' title='Home' target='_blank'>".as_bytes();
+        let expected_attributes = [
+            (QName::new("href".to_owned(), None), "/".to_owned()),
+            (QName::new("title".to_owned(), None), "Home".to_owned()),
+            (QName::new("target".to_owned(), None), "_blank".to_owned()),
+        ];
+
+        // Run this 5 times to make it unlikely this test succeeds at random
+        for _ in 0..5 {
+            let mut p = Parser::new(input);
+            if let Some(Ok(Event::ElementStart(tag))) = p.next() {
+                for (expected, actual) in expected_attributes.iter().zip(tag.attributes) {
+                    assert_eq!(expected, &actual);
+                }
+            } else {
+                panic!("Missing ElementStart event");
+            }
+            assert!(p.next().is_none());
+        }
+    }
+
+    #[test]
+    fn test_error_position() {
+        let err = Parser::new("<a>\n<b>&x;</b>".as_bytes())
+            .find_map(Result::err)
+            .unwrap();
+        assert_eq!(err.kind, ParserErrorKind::InvalidEntity);
+        assert_eq!((err.line, err.col, err.offset), (2, 4, 7));
+    }
+
+    #[test]
+    fn test_spans() {
+        let input = "<?pi?><!DOCTYPE a>\n<a x='1'>é<![CDATA[c]]><b/>&amp;</a>t";
+        let mut p = Parser::new(input.as_bytes());
+        let mut spans = Vec::new();
+        while let Some(event) = p.next() {
+            event.unwrap();
+            let span = p.span();
+            spans.push(&input[span.start as usize..span.end as usize]);
+        }
+        assert_eq!(
+            spans,
+            [
+                "<?pi?>",
+                "<!DOCTYPE a>",
+                "\n",
+                "<a x='1'>",
+                "é",
+                "<![CDATA[c]]>",
+                "<b/>",
+                "",
+                "&amp;",
+                "</a>",
+                "t",
+            ]
+        );
+        assert_eq!(
+            p.position(),
+            Position {
+                line: 2,
+                col: 37,
+                offset: input.len() as u64
             }
-            _ => {
-                self.buf.push(c);
-                self.level = 0;
+        );
+    }
+
+    #[test]
+    fn test_strict() {
+        let config = ParserConfig::new().strict(true);
+        let parse = |input: &str| -> Result<usize, ParserErrorKind> {
+            Parser::new_with_config(input.as_bytes(), config.clone())
+                .map(|event| event.map_err(|err| err.kind))
+                .collect::<Result<Vec<_>, _>>()
+                .map(|events| events.len())
+        };
+        let doc = "<?xml version='1.0'?>\n<!DOCTYPE a>\n<!--c--><a><b/>t</a>\n<?pi?> ";
+        assert_eq!(parse(doc), Ok(13));
+        for (input, kind) in [
+            ("<a/><b/>", ParserErrorKind::MultipleRoots),
+            ("<a/>\n<!--c-->\n<b></b>", ParserErrorKind::MultipleRoots),
+            ("", ParserErrorKind::MissingRoot),
+            (" <!--c--> ", ParserErrorKind::MissingRoot),
+            ("t<a/>", ParserErrorKind::TextOutsideRoot),
+            ("<a/>t", ParserErrorKind::TextOutsideRoot),
+            ("<a/>&#32;<![CDATA[ ]]>", ParserErrorKind::TextOutsideRoot),
+            ("<a/><!DOCTYPE a>", ParserErrorKind::MisplacedDoctype),
+            ("<a><!DOCTYPE a></a>", ParserErrorKind::MisplacedDoctype),
+            (
+                "<!DOCTYPE a><!DOCTYPE a><a/>",
+                ParserErrorKind::MisplacedDoctype,
+            ),
+        ] {
+            assert_eq!(parse(input), Err(kind), "{}", input);
+            assert!(Parser::new(input.as_bytes()).all(|event| event.is_ok()));
+        }
+    }
+
+    #[test]
+    fn test_check_names() {
+        let valid = "<?xml version='1.0'?><?a-b.c?><p:é xmlns:p='urn:p' _x.1='' p:y-z=''/>";
+        assert!(Parser::new(valid.as_bytes()).all(|event| event.is_ok()));
+        for input in [
+            "<1foo/>",
+            "<a b\"c='1'/>",
+            "<a:b:c xmlns:a='urn:a'/>",
+            "<a :b='1'/>",
+            "<a\tb:='1'/>",
+            "<-a >",
+            "<a>< b/></a>",
+            "<?1pi?>",
+            "<?a:b?>",
+            "<? pi?>",
+            "<?a\"b?>",
+        ] {
+            let err = Parser::new(input.as_bytes()).find_map(Result::err).unwrap();
+            assert_eq!(err.kind, ParserErrorKind::InvalidName, "{}", input);
+            let config = ParserConfig::new().check_names(false);
+            let kinds: Vec<_> = Parser::new_with_config(input.as_bytes(), config)
+                .filter_map(|event| event.err().map(|err| err.kind))
+                .collect();
+            assert!(!kinds.contains(&ParserErrorKind::InvalidName), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_char_refs() {
+        let events: Vec<_> = Parser::new("<a b='&#9;&#x1F600;'>&#xD7FF;&#65;</a>".as_bytes())
+            .map(Result::unwrap)
+            .collect();
+        match events[0] {
+            Event::ElementStart(ref tag) => {
+                assert_eq!(tag.get_attribute("b", None), Some("\t\u{1F600}"))
             }
+            _ => panic!("Expected start tag"),
         }
-        Ok(None)
+        assert_eq!(events[1], Event::Characters("\u{D7FF}A".to_owned()));
+
+        for input in [
+            "<a>&#x0;</a>",
+            "<a>&#1;</a>",
+            "<a>&#xD800;</a>",
+            "<a>&#xFFFE;</a>",
+            "<a>&#x110000;</a>",
+            "<a>&#99999999999;</a>",
+            "<a>&#x;</a>",
+            "<a>&#-1;</a>",
+            "<a>&#x+1;</a>",
+            "<a>&#12a;</a>",
+        ] {
+            let err = Parser::new(input.as_bytes()).find_map(Result::err).unwrap();
+            assert_eq!(err.kind, ParserErrorKind::InvalidCharRef, "{}", input);
+            assert_eq!((err.line, err.col, err.offset), (1, 4, 3), "{}", input);
+        }
+
+        let err = Parser::new("<a\nb='x&amp;&#xDFFF;'/>".as_bytes())
+            .find_map(Result::err)
+            .unwrap();
+        assert_eq!(err.kind, ParserErrorKind::InvalidCharRef);
+        assert_eq!((err.line, err.col, err.offset), (2, 10, 12));
     }
 
-    // Opening sequence of a comment
-    // '-' => InComment1
-    fn in_comment_opening(&mut self, c: char) -> Result<Option<Event>, ParserError> {
-        if c == '-' {
-            self.st = State::InComment1;
-            self.level = 0;
-            Ok(None)
-        } else {
-            self.error(ParserErrorKind::InvalidCommentStart)
+    #[test]
+    fn test_limits() {
+        let input = "<a x='12' y='3'>text<![CDATA[]]]]><!--1-2-->\
+                     <b xmlns:p='u' p:z='é'/></a>";
+        let parse = |config: ParserConfig| {
+            Parser::new_with_config(input.as_bytes(), config)
+                .find_map(Result::err)
+                .map(|err| (err.kind, err.offset))
+        };
+        let exceeded = |offset| Some((ParserErrorKind::LimitExceeded, offset));
+        assert_eq!(parse(ParserConfig::new()), None);
+
+        assert_eq!(parse(ParserConfig::new().max_attributes(2)), None);
+        assert_eq!(parse(ParserConfig::new().max_attributes(1)), exceeded(11));
+        assert_eq!(parse(ParserConfig::new().max_attribute_length(2)), None);
+        assert_eq!(
+            parse(ParserConfig::new().max_attribute_length(1)),
+            exceeded(7)
+        );
+        assert_eq!(parse(ParserConfig::new().max_text_length(4)), None);
+        assert_eq!(parse(ParserConfig::new().max_text_length(3)), exceeded(19));
+        assert_eq!(parse(ParserConfig::new().max_comment_length(3)), None);
+        assert_eq!(
+            parse(ParserConfig::new().max_comment_length(2)),
+            exceeded(40)
+        );
+
+        // Values split into chunks count as a whole
+        let config = ParserConfig::new()
+            .attribute_chunk_size(1)
+            .max_attribute_length(2);
+        assert_eq!(parse(config.clone()), None);
+        assert_eq!(parse(config.max_attribute_length(1)), exceeded(7));
+    }
+
+    #[test]
+    fn test_line_endings() {
+        let events: Vec<_> = Parser::new("<a b='1\r\n2'>x\r\ny\rz<![CDATA[\r\n]]></a>".as_bytes())
+            .map(Result::unwrap)
+            .collect();
+        match events[0] {
+            Event::ElementStart(ref tag) => {
+                assert_eq!(tag.attributes[&QName::new("b".to_owned(), None)], "1\n2")
+            }
+            _ => panic!("Expected start tag"),
         }
+        assert_eq!(events[1], Event::Characters("x\ny\nz".to_owned()));
+        assert_eq!(events[2], Event::CDATA("\n".to_owned()));
+
+        let err = Parser::new("<a>\r<b>\r\n&x;</b>".as_bytes())
+            .find_map(Result::err)
+            .unwrap();
+        assert_eq!((err.line, err.col, err.offset), (3, 1, 9));
     }
 
-    // Inside a comment
-    // '-' '-' => InComment2
-    fn in_comment1(&mut self, c: char) -> Result<Option<Event>, ParserError> {
-        if c == '-' {
-            self.level += 1;
-        } else {
-            self.level = 0;
+    #[test]
+    fn test_utf8() {
+        let input = "<größe einheit='µm'>ŝ€𝄞<![CDATA[ü]]></größe>";
+        let events: Vec<_> = Parser::new(input.as_bytes()).map(Result::unwrap).collect();
+        match events[0] {
+            Event::ElementStart(ref tag) => {
+                assert_eq!(tag.name.name, "größe");
+                assert_eq!(tag.get_attribute("einheit", None), Some("µm"));
+            }
+            _ => panic!("Expected start tag"),
         }
+        assert_eq!(events[1], Event::Characters("ŝ€𝄞".to_owned()));
+        assert_eq!(events[2], Event::CDATA("ü".to_owned()));
 
-        if self.level == 2 {
-            self.level = 0;
-            self.st = State::InComment2;
+        let err = Parser::new("<a>éé&x;</a>".as_bytes())
+            .find_map(Result::err)
+            .unwrap();
+        assert_eq!((err.line, err.col, err.offset), (1, 6, 7));
+
+        let inputs: [&[u8]; 4] = [
+            b"<a>x\xff</a>",
+            b"<a>x\xc3(</a>",
+            b"<a>x\xed\xa0\x80</a>",
+            b"<a>x\xe2\x82",
+        ];
+        for input in inputs {
+            let err = Parser::new(input).find_map(Result::err).unwrap();
+            assert_eq!(err.kind, ParserErrorKind::InvalidUtf8);
+            assert_eq!((err.line, err.col, err.offset), (1, 5, 4));
         }
+    }
 
-        self.buf.push(c);
+    #[test]
+    fn test_encodings() {
+        let doc = "<?xml version='1.0' encoding='UTF-16'?><a x='é'>𝄞\r\n</a>";
+        let expected: Vec<_> = Parser::new(doc.as_bytes()).map(Result::unwrap).collect();
+        let le: Vec<u8> = doc.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let be: Vec<u8> = doc.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        let inputs = [
+            [&[0xEF, 0xBB, 0xBF][..], doc.as_bytes()].concat(),
+            [&[0xFF, 0xFE][..], &le].concat(),
+            [&[0xFE, 0xFF][..], &be].concat(),
+            le.clone(),
+            be,
+        ];
+        for input in inputs {
+            let events: Vec<_> = Parser::new(&input[..]).map(Result::unwrap).collect();
+            assert_eq!(events, expected);
+        }
 
-        Ok(None)
+        let mut p = Parser::new_with_config(&le[..], ParserConfig::new().fidelity(true));
+        p.nth(1).unwrap().unwrap();
+        assert_eq!(p.span(), 78..96);
+        assert_eq!(p.source(), "<a x='é'>".as_bytes());
+
+        let invalid: [&[u8]; 3] = [
+            b"\xff\xfe<\x00a\x00>\x00\x00\xdc",
+            b"\xff\xfe<\x00a\x00>\x00\x00\xd8x\x00",
+            b"\xff\xfe<\x00a\x00>\x00x",
+        ];
+        for input in invalid {
+            let err = Parser::new(input).find_map(Result::err).unwrap();
+            assert_eq!(err.kind, ParserErrorKind::InvalidUtf16);
+            assert_eq!((err.line, err.col, err.offset), (1, 4, 8));
+        }
     }
 
-    // Closing a comment
-    // '>' => OutsideTag, producing Comment
-    fn in_comment2(&mut self, c: char) -> Result<Option<Event>, ParserError> {
-        if c != '>' {
-            self.error(ParserErrorKind::InvalidCommentContent)
-        } else {
-            self.st = State::OutsideTag;
-            let len = self.buf.len();
-            self.buf.truncate(len - 2);
-            let buf = self.take_buf();
-            Ok(Some(Event::Comment(buf)))
+    #[test]
+    fn test_current_path() {
+        let mut p = Parser::new("<a xmlns='urn:a'><b/><c xmlns=''>x</c></a>".as_bytes());
+        let mut paths = Vec::new();
+        while let Some(event) = p.next() {
+            event.unwrap();
+            paths.push(
+                p.current_path()
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>(),
+            );
         }
+        assert_eq!(
+            paths,
+            [
+                vec!["{urn:a}a"],
+                vec!["{urn:a}a", "{urn:a}b"],
+                vec!["{urn:a}a"],
+                vec!["{urn:a}a", "c"],
+                vec!["{urn:a}a", "c"],
+                vec!["{urn:a}a"],
+                vec![],
+            ]
+        );
     }
 
-    // Inside a doctype
-    // '>' after appropriate opening => OutsideTag
-    fn in_doctype(&mut self, c: char) -> Result<Option<Event>, ParserError> {
-        static DOCTYPE_PATTERN: [char; 6] = ['O', 'C', 'T', 'Y', 'P', 'E'];
-        match self.level {
-            0..=5 => {
-                if c == DOCTYPE_PATTERN[self.level as usize] {
-                    self.level += 1;
-                } else {
-                    return self.error(ParserErrorKind::InvalidDoctype);
+    #[test]
+    fn test_attribute_chunks() {
+        let input = "<a xmlns:p='urn:p'><b x='1' p:data='ab&amp;cdefg&#x41;ij' y='2'>t</b></a>";
+        let config = ParserConfig::new().attribute_chunk_size(3);
+        let events: Vec<_> = Parser::new_with_config(input.as_bytes(), config)
+            .map(Result::unwrap)
+            .collect();
+        let chunks: Vec<_> = events
+            .iter()
+            .filter_map(|event| match *event {
+                Event::AttributeChunk(ref chunk) => Some(chunk),
+                _ => None,
+            })
+            .collect();
+        let data: String = chunks[..chunks.len() - 1]
+            .iter()
+            .map(|chunk| &chunk.value[..])
+            .collect();
+        assert_eq!(data, "ab&cdefgAij");
+        assert!(chunks.iter().all(|chunk| chunk.value.len() <= 4));
+        assert_eq!(chunks[0].ns.as_deref(), Some("urn:p"));
+        assert_eq!(chunks.iter().filter(|chunk| chunk.last).count(), 2);
+
+        let mut builder = ElementBuilder::new();
+        let elem = events
+            .iter()
+            .find_map(|event| builder.handle_event(Ok(event.clone())))
+            .unwrap()
+            .unwrap();
+        assert_eq!(elem, input.parse().unwrap());
+
+        let mut writer = EventWriter::new(Vec::new());
+        for event in &events {
+            writer.write(event).unwrap();
+        }
+        let out = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(out, input.replace("&#x41;", "A"));
+    }
+
+    #[test]
+    fn test_duplicate_attributes() {
+        let input = "<a xmlns:p='urn:p' xmlns:q='urn:p' x='1' p:y='2' x='3' q:y='4'/>";
+        let values = |policy| {
+            let config = ParserConfig::new().duplicate_attributes(policy);
+            let mut p = Parser::new_with_config(input.as_bytes(), config);
+            let values = match p.next().unwrap() {
+                Ok(Event::ElementStart(tag)) => Ok((
+                    tag.attributes[&QName::new("x".to_owned(), None)].clone(),
+                    tag.attributes[&QName::new("y".to_owned(), Some("urn:p".to_owned()))].clone(),
+                )),
+                Ok(_) => panic!(),
+                Err(err) => Err(err.kind),
+            };
+            let warnings: Vec<_> = p.warnings().iter().map(|w| (w.kind, w.offset)).collect();
+            (values, warnings)
+        };
+        assert_eq!(
+            values(DuplicateAttributes::Error),
+            (Err(ParserErrorKind::DuplicateAttribute), vec![])
+        );
+        let end = input.find("/>").unwrap() as u64;
+        let warnings = vec![(ParserErrorKind::DuplicateAttribute, end); 2];
+        assert_eq!(
+            values(DuplicateAttributes::KeepFirst),
+            (Ok(("1".to_owned(), "2".to_owned())), warnings.clone())
+        );
+        assert_eq!(
+            values(DuplicateAttributes::KeepLast),
+            (Ok(("3".to_owned(), "4".to_owned())), warnings)
+        );
+
+        let config = ParserConfig::new().duplicate_attributes(DuplicateAttributes::KeepFirst);
+        let mut p =
+            Parser::new_with_config("<p:a xmlns:p='urn:1' xmlns:p='urn:2'/>".as_bytes(), config);
+        match p.next() {
+            Some(Ok(Event::ElementStart(tag))) => assert_eq!(tag.name.ns.as_deref(), Some("urn:1")),
+            _ => panic!(),
+        }
+
+        // Attributes streamed in chunks are checked against the start tag and each other
+        let input = "<a x='1' y='long2' x='long3' y='4' z='5'/>";
+        let chunks = |policy| {
+            let config = ParserConfig::new()
+                .attribute_chunk_size(2)
+                .duplicate_attributes(policy);
+            let mut p = Parser::new_with_config(input.as_bytes(), config);
+            let mut chunks = Vec::new();
+            for event in p.by_ref() {
+                match event {
+                    Ok(Event::AttributeChunk(chunk)) => chunks.push((chunk.name, chunk.value)),
+                    Ok(_) => (),
+                    Err(err) => return Err(err.kind),
                 }
             }
-            6 => {
-                match c {
-                    ' ' | '\t' | '\r' | '\n' => (),
-                    _ => return self.error(ParserErrorKind::InvalidDoctype),
+            let values: Vec<_> = chunks.iter().map(|(n, v)| format!("{}={}", n, v)).collect();
+            Ok((values.join(" "), p.warnings().len()))
+        };
+        assert_eq!(
+            chunks(DuplicateAttributes::Error),
+            Err(ParserErrorKind::DuplicateAttribute)
+        );
+        assert_eq!(
+            chunks(DuplicateAttributes::KeepFirst),
+            Ok(("y=long y=2 z=5".to_owned(), 2))
+        );
+        assert_eq!(
+            chunks(DuplicateAttributes::KeepLast),
+            Ok(("y=long y=2 x=lon x=g3 y=4 z=5".to_owned(), 2))
+        );
+    }
+
+    #[test]
+    fn test_unknown_entities() {
+        let input = "<!DOCTYPE a [<!ENTITY e 'x&u;'>]>\n<a v='&u;&lt;'>&nbsp;&e;&#x41;&amp;</a>";
+        let parse = |policy| {
+            let config = ParserConfig::new().unknown_entities(policy);
+            let mut p = Parser::new_with_config(input.as_bytes(), config);
+            let events: Result<Vec<_>, _> = p.by_ref().map(|e| e.map_err(|e| e.kind)).collect();
+            let warnings: Vec<_> = p.warnings().iter().map(|w| (w.line, w.col)).collect();
+            (events, warnings)
+        };
+        let (events, warnings) = parse(UnknownEntities::Error);
+        assert_eq!(events, Err(ParserErrorKind::InvalidEntity));
+        assert!(warnings.is_empty());
+
+        let expected = |u: &str| {
+            let mut a = StartTag {
+                name: QName::new("a".to_owned(), None),
+                prefix: None,
+                attributes: Default::default(),
+            };
+            a.attributes
+                .insert(QName::new("v".to_owned(), None), format!("{}<", u));
+            vec![
+                Event::ElementStart(a),
+                Event::Characters(format!("{}x{}A&", u.replace('u', "nbsp"), u)),
+                Event::ElementEnd(EndTag {
+                    name: QName::new("a".to_owned(), None),
+                    prefix: None,
+                }),
+            ]
+        };
+        // Warnings are reported at the references in the document
+        let warnings = vec![(2, 7), (2, 16), (2, 22)];
+        let (events, found) = parse(UnknownEntities::Keep);
+        assert_eq!(events.unwrap()[2..], expected("&u;")[..]);
+        assert_eq!(found, warnings);
+        let (events, found) = parse(UnknownEntities::Replace);
+        assert_eq!(events.unwrap()[2..], expected("\u{FFFD}")[..]);
+        assert_eq!(found, warnings);
+    }
+
+    #[test]
+    fn test_lenient() {
+        let input = "<a x=1 y z=2><B checked/><c d=e>t</b></C><p:d xmlns:p=u>\n<e></a>";
+        let strict: Result<Vec<_>, _> = Parser::new(input.as_bytes()).collect();
+        assert_eq!(
+            strict.unwrap_err().kind,
+            ParserErrorKind::UndelimitedAttribute
+        );
+
+        let config = ParserConfig::new().lenient(true);
+        let mut p = Parser::new_with_config(input.as_bytes(), config);
+        let mut events = Vec::new();
+        while let Some(event) = p.next() {
+            let event = match event.unwrap() {
+                Event::ElementStart(tag) => {
+                    let mut attributes: Vec<_> = tag
+                        .attributes
+                        .iter()
+                        .map(|(name, value)| format!("{}={}", name.name, value))
+                        .collect();
+                    attributes.sort();
+                    format!("<{} {}>", tag.name, attributes.join(" "))
                 }
-                self.level += 1;
+                Event::ElementEnd(tag) => format!("</{}>", tag.name),
+                Event::Characters(text) => text,
+                _ => panic!(),
+            };
+            events.push((event, p.span()));
+        }
+        let expected = [
+            ("<a x=1 y= z=2>", 0..13),
+            ("<B checked=>", 13..25),
+            ("</B>", 25..25),
+            ("<c d=e>", 25..32),
+            ("t", 32..33),
+            // The stray end tag </b> is skipped
+            ("</c>", 33..41),
+            ("<{u}d p=u>", 41..56),
+            ("\n", 56..57),
+            ("<e >", 57..60),
+            ("</e>", 60..60),
+            ("</{u}d>", 60..60),
+            ("</a>", 60..64),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(e, s)| (e.to_string(), s.clone()))
+            .collect();
+        assert_eq!(events, expected);
+        let warnings: Vec<_> = p.warnings().iter().map(|w| (w.kind, w.offset)).collect();
+        assert_eq!(
+            warnings,
+            [
+                (ParserErrorKind::UndelimitedAttribute, 5),
+                (ParserErrorKind::UndelimitedAttribute, 9),
+                (ParserErrorKind::UndelimitedAttribute, 11),
+                (ParserErrorKind::UndelimitedAttribute, 23),
+                (ParserErrorKind::UndelimitedAttribute, 30),
+                (ParserErrorKind::TagMismatch, 36),
+                (ParserErrorKind::TagMismatch, 40),
+                (ParserErrorKind::UndelimitedAttribute, 54),
+                (ParserErrorKind::TagMismatch, 63),
+                (ParserErrorKind::TagMismatch, 63),
+            ]
+        );
+
+        // Elements still open at the end of input are closed
+        let config = ParserConfig::new().lenient(true);
+        let mut p = Parser::new_with_config("<a><b>t".as_bytes(), config);
+        let events: Vec<_> = p.by_ref().map(Result::unwrap).collect();
+        assert_eq!(events.len(), 5);
+        assert!(matches!(events[3], Event::ElementEnd(ref tag) if tag.name.name == "b"));
+        assert!(matches!(events[4], Event::ElementEnd(ref tag) if tag.name.name == "a"));
+        assert_eq!(p.span(), 7..7);
+        assert_eq!(p.warnings().len(), 2);
+    }
+
+    #[test]
+    fn test_namespace_bindings() {
+        let input =
+            "<a xmlns='urn:a' xmlns:p='urn:p'>x<b xmlns='' xmlns:p='urn:q' xmlns:r='urn:r'>y\
+                     </b>z<![CDATA[!]]><c/></a>";
+        for coalesce in [false, true] {
+            let config = ParserConfig::new().coalesce_text(coalesce);
+            let mut p = Parser::new_with_config(input.as_bytes(), config);
+            assert_eq!(p.resolve_prefix("xml"), Some(crate::ns::XML));
+            assert_eq!(p.namespaces().len(), 2);
+            let mut scopes = Vec::new();
+            while let Some(event) = p.next() {
+                let event = match event.unwrap() {
+                    Event::ElementStart(tag) => format!("<{}", tag.name.name),
+                    Event::ElementEnd(tag) => format!("{}>", tag.name.name),
+                    Event::Characters(text) | Event::CDATA(text) => text,
+                    _ => panic!(),
+                };
+                let (default, prefix) = (p.resolve_prefix(""), p.resolve_prefix("p"));
+                let r = p.resolve_prefix("r");
+                let count = p.namespaces().len();
+                scopes.push(format!(
+                    "{} {:?} {:?} {:?} {}",
+                    event, default, prefix, r, count
+                ));
             }
-            _ if c == '>' => {
-                self.level = 0;
-                self.st = State::OutsideTag;
+            let mut expected = vec![
+                "<a Some(\"urn:a\") Some(\"urn:p\") None 4",
+                "x Some(\"urn:a\") Some(\"urn:p\") None 4",
+                "<b None Some(\"urn:q\") Some(\"urn:r\") 4",
+                "y None Some(\"urn:q\") Some(\"urn:r\") 4",
+                "b> Some(\"urn:a\") Some(\"urn:p\") None 4",
+                "z! Some(\"urn:a\") Some(\"urn:p\") None 4",
+                "<c Some(\"urn:a\") Some(\"urn:p\") None 4",
+                "c> Some(\"urn:a\") Some(\"urn:p\") None 4",
+                "a> None None None 2",
+            ];
+            if !coalesce {
+                expected[5] = "z Some(\"urn:a\") Some(\"urn:p\") None 4";
+                expected.insert(6, "! Some(\"urn:a\") Some(\"urn:p\") None 4");
             }
-            _ => (),
+            assert_eq!(scopes, expected);
         }
-        Ok(None)
     }
-}
 
-#[cfg(test)]
-mod parser_tests {
-    use super::Parser;
-    use crate::{AttrMap, EndTag, Event, ParserError, StartTag};
+    #[test]
+    fn test_xml_scope() {
+        let input = "<a xml:lang='en'><b xml:space='preserve' xml:lang='de'><c xml:lang=''/>t</b>\
+                     <d xmlns:x='http://www.w3.org/XML/1998/namespace' x:space='default'/></a>";
+        let mut p = Parser::new(input.as_bytes());
+        let mut scopes = Vec::new();
+        while let Some(event) = p.next() {
+            let name = match event.unwrap() {
+                Event::ElementStart(tag) => format!("<{}", tag.name.name),
+                Event::ElementEnd(tag) => format!("{}>", tag.name.name),
+                Event::Characters(text) => text,
+                _ => panic!(),
+            };
+            let (space, lang) = (p.xml_space(), p.xml_lang());
+            scopes.push(format!(
+                "{}:{}:{}",
+                name,
+                space.unwrap_or("-"),
+                lang.unwrap_or("-")
+            ));
+        }
+        assert_eq!(
+            scopes,
+            [
+                "<a:-:en",
+                "<b:preserve:de",
+                "<c:preserve:",
+                "c>:preserve:de",
+                "t:preserve:de",
+                "b>:-:en",
+                "<d:default:en",
+                "d>:-:en",
+                "a>:-:-",
+            ]
+        );
+    }
 
     #[test]
-    fn test_start_tag() {
-        let s = "<a>".as_bytes();
-        let p = Parser::new(s);
-        let mut i = 0u8;
-        for event in p {
-            i += 1;
-            assert_eq!(
-                event,
-                Ok(Event::ElementStart(StartTag {
-                    name: "a".to_owned(),
-                    ns: None,
-                    prefix: None,
-                    attributes: AttrMap::new()
-                })),
-            );
+    fn test_recover() {
+        let input = "<a><b xmlns:p='u' x='&bad;'>t</b><p:c/><d x y/><e/>&#0;<f/>< <g/></x></a>";
+        let items: Vec<_> = Parser::new(input.as_bytes()).collect();
+        assert_eq!(items.len(), 2);
+        let parse = |config: ParserConfig| {
+            let mut p = Parser::new_with_config(input.as_bytes(), config.fidelity(true));
+            let mut items = Vec::new();
+            while let Some(item) = p.next() {
+                let span = p.span().start as usize..p.span().end as usize;
+                items.push(match item {
+                    Ok(Event::ElementStart(tag)) => format!("<{}", tag.name.name),
+                    Ok(Event::ElementEnd(tag)) => format!("{}>", tag.name.name),
+                    Ok(Event::Characters(text)) => text,
+                    Ok(_) => panic!(),
+                    // The span and source of an error are the input discarded up to it
+                    Err(err) => {
+                        assert_eq!(p.source(), input[span.clone()].as_bytes());
+                        format!("{:?}@{} {}", err.kind, err.offset, &input[span])
+                    }
+                });
+            }
+            items
+        };
+        assert_eq!(
+            parse(ParserConfig::new().recover(true)),
+            [
+                "<a",
+                "InvalidEntity@21 <b xmlns:p='u' x='&bad;'",
+                "t",
+                // The end tag of the discarded start tag, whose declarations are out of scope
+                "TagMismatch@32 </b>",
+                "UnboundNsPrefixInTagName@37 <p:c/",
+                "SpaceInAttributeName@44 <d x y",
+                "<e",
+                "e>",
+                "InvalidCharRef@51 &#0;",
+                "<f",
+                "f>",
+                "InvalidName@60 < ",
+                "<g",
+                "g>",
+                "TagMismatch@68 </x>",
+                "a>",
+            ]
+        );
+
+        // Exceeded limits still end parsing
+        let config = ParserConfig::new().recover(true).max_text_length(1);
+        let items: Vec<_> = Parser::new_with_config("<a>xy</a>".as_bytes(), config).collect();
+        assert_eq!(items.len(), 2);
+        assert_eq!(
+            items[1].clone().unwrap_err().kind,
+            ParserErrorKind::LimitExceeded
+        );
+    }
+
+    #[test]
+    fn test_read_text() {
+        let input = "<a><b>x &amp; <![CDATA[<y>]]><?pi?></b><c>1<d>2</d><!---->3</c><e/></a>";
+        let mut p = Parser::new(input.as_bytes());
+        p.next();
+        p.next();
+        assert_eq!(p.read_text().unwrap(), "x & <y>");
+        assert_eq!(p.depth(), 1);
+        p.next();
+        let err = p.read_text().unwrap_err();
+        assert_eq!(
+            (err.kind, err.offset),
+            (ParserErrorKind::UnexpectedElement, 45)
+        );
+        assert_eq!(p.read_text().unwrap(), "2");
+        assert_eq!(
+            p.read_all_text().unwrap_err().kind,
+            ParserErrorKind::NotAtElementStart
+        );
+
+        let mut p = Parser::new(input.as_bytes());
+        p.next();
+        assert_eq!(p.read_all_text().unwrap(), "x & <y>123");
+        assert!(p.next().is_none());
+
+        let mut p = Parser::new("<a><b>x</b>".as_bytes());
+        p.next();
+        assert_eq!(
+            p.read_text().unwrap_err().kind,
+            ParserErrorKind::UnexpectedElement
+        );
+        let mut p = Parser::new("<a>x".as_bytes());
+        p.next();
+        assert_eq!(
+            p.read_text().unwrap_err().kind,
+            ParserErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn test_peek() {
+        let input = "<a xmlns:p='u'>x<![CDATA[y]]><p:b/></a>";
+        for coalesce in [false, true] {
+            let config = ParserConfig::new().coalesce_text(coalesce).fidelity(true);
+            let mut p = Parser::new_with_config(input.as_bytes(), config);
+            let mut events = Vec::new();
+            loop {
+                let (span, depth) = (p.span(), p.depth());
+                let (source, bound) = (p.source().to_vec(), p.resolve_prefix("p").is_some());
+                let peeked = p.peek().cloned();
+                assert_eq!(p.peek().cloned(), peeked);
+                // Peeking does not change the state describing the last event
+                assert_eq!(
+                    (p.span(), p.depth(), p.source()),
+                    (span, depth, &source[..])
+                );
+                assert_eq!(p.resolve_prefix("p").is_some(), bound);
+                let event = p.next();
+                assert_eq!(event, peeked);
+                let span = p.span().start as usize..p.span().end as usize;
+                match event {
+                    Some(event) => events.push((event.unwrap(), &input[span])),
+                    None => break,
+                }
+            }
+            assert_eq!(events.len(), if coalesce { 5 } else { 6 });
+            assert_eq!(events[1].1, if coalesce { "x<![CDATA[y]]>" } else { "x" });
+            assert_eq!(events.last().unwrap().1, "</a>");
+        }
+
+        let mut p = Parser::new("<a><b/></a>".as_bytes());
+        p.next();
+        if let Some(Ok(Event::ElementStart(tag))) = p.peek_mut() {
+            tag.name.name = "c".into();
+        }
+        assert_eq!(
+            p.capture_raw().unwrap_err().kind,
+            ParserErrorKind::NotAtElementStart
+        );
+        match p.next() {
+            Some(Ok(Event::ElementStart(tag))) => assert_eq!(tag.name.name, "c"),
+            _ => panic!(),
         }
-        assert_eq!(i, 1u8);
+        assert_eq!(p.current_path()[1].name, "c");
     }
 
     #[test]
-    fn test_end_tag() {
-        let p = Parser::new("</a>".as_bytes());
-        let mut i = 0u8;
-        for event in p {
-            i += 1;
-            assert_eq!(
-                event,
-                Ok(Event::ElementEnd(EndTag {
-                    name: "a".to_owned(),
-                    ns: None,
-                    prefix: None
-                })),
-            );
+    fn test_read_element() {
+        let input = "<a xmlns='urn:a' xmlns:p='urn:p'>x<!--c--><p:b q='1'><c/>t</p:b> <d/></a>";
+        let mut p = Parser::new(input.as_bytes());
+        p.next();
+        let b = p.read_element().unwrap().unwrap();
+        let doc: Element = input.parse().unwrap();
+        assert_eq!(&b, doc.child_elements().next().unwrap());
+        let mut root = Element::new("root".to_owned(), None, vec![]);
+        root.graft(b);
+        let root: Element = root.to_string().parse().unwrap();
+        let b = root.get_child("b", Some("urn:p")).unwrap();
+        assert_eq!(b.get_child("c", Some("urn:a")).unwrap().name.name, "c");
+        assert_eq!(p.depth(), 1);
+        let d = p.read_element().unwrap().unwrap();
+        assert_eq!(d.name.ns.as_deref(), Some("urn:a"));
+        assert_eq!(p.read_element(), Ok(None));
+        assert!(matches!(p.next(), Some(Ok(Event::ElementEnd(_)))));
+        assert_eq!(p.read_element(), Ok(None));
+
+        let mut p = Parser::new("<a><b></c></a>".as_bytes());
+        p.next();
+        assert!(matches!(p.read_element(), Err(BuilderError::Parser(_))));
+    }
+
+    #[test]
+    fn test_cancel() {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let mut p = Parser::new(b"<a>".chain(io::repeat(b'x')));
+        p.set_cancel_flag(cancel.clone());
+        assert!(p.next().unwrap().is_ok());
+        let parse = thread::spawn(move || p.next());
+        cancel.store(true, Ordering::Relaxed);
+        let err = parse.join().unwrap().unwrap().unwrap_err();
+        assert_eq!(err.kind, ParserErrorKind::Cancelled);
+    }
+
+    #[test]
+    fn test_read_buffer() {
+        struct CountingReader<'a> {
+            data: &'a [u8],
+            reads: usize,
+        }
+
+        impl Read for CountingReader<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.reads += 1;
+                self.data.read(buf)
+            }
+        }
+
+        let input = format!("<a>{}</a>", "<b x='ü'>€</b>".repeat(500));
+        let mut reader = CountingReader {
+            data: input.as_bytes(),
+            reads: 0,
+        };
+        let config = ParserConfig::new().read_buffer_size(1024);
+        let events: Vec<_> = Parser::new_with_config(&mut reader, config)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(events.len(), 1502);
+        assert_eq!(reader.reads, input.len() / 1024 + 2);
+
+        for size in [0, 3] {
+            let config = ParserConfig::new().read_buffer_size(size);
+            let other: Vec<_> = Parser::new_with_config(input.as_bytes(), config)
+                .map(Result::unwrap)
+                .collect();
+            assert_eq!(other, events);
         }
-        assert_eq!(i, 1u8);
     }
 
     #[test]
-    fn test_self_closing_with_space() {
-        let s = "<register />".as_bytes();
-        let p = Parser::new(s);
-        let v: Vec<Result<Event, ParserError>> = p.collect();
+    fn test_runs() {
+        // Runs of text, CDATA and comments split at buffer boundaries, within characters,
+        // line ends and closing sequences are read like character by character
+        let input = "<a>x\r\nyé&amp;€\r<![CDATA[ü]]\n]]]><!--- -\r\n€-->\n\ntext</a>";
+        let parse = |size: usize| {
+            let config = ParserConfig::new().read_buffer_size(size).fidelity(true);
+            let mut p = Parser::new_with_config(input.as_bytes(), config);
+            let mut events = Vec::new();
+            while let Some(event) = p.next() {
+                let source = String::from_utf8(p.source().to_vec()).unwrap();
+                events.push((event.unwrap(), p.span(), p.position(), source));
+            }
+            events
+        };
+        let events = parse(1);
+        assert_eq!(events[3].0, Event::Comment("- -\n€".to_owned()),);
         assert_eq!(
-            v,
-            vec![
-                Ok(Event::ElementStart(StartTag {
-                    name: "register".to_owned(),
-                    ns: None,
-                    prefix: None,
-                    attributes: AttrMap::new()
-                })),
-                Ok(Event::ElementEnd(EndTag {
-                    name: "register".to_owned(),
-                    ns: None,
-                    prefix: None,
-                }))
-            ],
+            events[3].2,
+            Position {
+                line: 5,
+                col: 4,
+                offset: 51
+            }
         );
+        for size in 2..input.len() {
+            assert_eq!(parse(size), events, "buffer size {}", size);
+        }
+
+        let limited = |limit| {
+            let config = ParserConfig::new()
+                .max_text_length(limit)
+                .read_buffer_size(64);
+            let err = Parser::new_with_config(input.as_bytes(), config)
+                .find_map(Result::err)
+                .unwrap();
+            (err.kind, err.offset)
+        };
+        assert_eq!(limited(3), (ParserErrorKind::LimitExceeded, 8));
+        assert_eq!(limited(5), (ParserErrorKind::LimitExceeded, 9));
     }
 
     #[test]
-    fn test_self_closing_without_space() {
-        let s = "<register/>".as_bytes();
-        let p = Parser::new(s);
-        let v: Vec<Result<Event, ParserError>> = p.collect();
+    fn test_nonblocking() {
+        // Returns the chunks one at a time, failing with the given error in between
+        struct Chunks(Vec<Result<&'static str, io::ErrorKind>>);
+
+        impl Read for Chunks {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if self.0.is_empty() {
+                    return Ok(0);
+                }
+                match self.0.remove(0) {
+                    Ok(chunk) => chunk.as_bytes().read(buf),
+                    Err(kind) => Err(kind.into()),
+                }
+            }
+        }
+
+        let chunks = || {
+            Chunks(vec![
+                Ok("<a>x"),
+                Err(io::ErrorKind::WouldBlock),
+                Ok("y</a"),
+                Err(io::ErrorKind::Interrupted),
+                Ok(">"),
+            ])
+        };
+        let config = ParserConfig::new().nonblocking(true);
+        let results: Vec<_> = Parser::new_with_config(chunks(), config)
+            .map(|result| result.map_err(|err| (err.kind, err.offset)))
+            .collect();
         assert_eq!(
-            v,
-            vec![
+            results,
+            [
                 Ok(Event::ElementStart(StartTag {
-                    name: "register".to_owned(),
-                    ns: None,
+                    name: QName::new("a".to_owned(), None),
                     prefix: None,
-                    attributes: AttrMap::new()
+                    attributes: Default::default(),
                 })),
+                Err((ParserErrorKind::NeedMoreData, 4)),
+                Ok(Event::Characters("xy".to_owned())),
+                Err((ParserErrorKind::NeedMoreData, 8)),
                 Ok(Event::ElementEnd(EndTag {
-                    name: "register".to_owned(),
-                    ns: None,
+                    name: QName::new("a".to_owned(), None),
                     prefix: None,
-                }))
-            ],
+                })),
+            ]
+        );
+
+        let mut p = Parser::new(chunks());
+        assert!(p.next().unwrap().is_ok());
+        assert_eq!(
+            p.next().unwrap().unwrap_err().kind,
+            ParserErrorKind::MalformedXml
         );
+        assert!(p.next().is_none());
     }
 
     #[test]
-    fn test_self_closing_namespace() {
-        let s = "<foo:a xmlns:foo='urn:foo'/>".as_bytes();
-        let p = Parser::new(s);
+    fn test_push_parser() {
+        let input =
+            "<?pi x?><a xmlns:p='urn:p' p:x='ü'>t€xt<![CDATA[<c>]]><!--c--><b/>&amp;</a>end";
+        let expected: Vec<_> = Parser::new(input.as_bytes()).collect();
+        let mut p = PushParser::new();
+        let mut events = Vec::new();
+        for byte in input.as_bytes() {
+            p.feed_bytes(&[*byte]);
+            events.extend(p.by_ref());
+        }
+        // The trailing text is only returned at the end of input
+        assert_eq!(events.len(), expected.len() - 1);
+        p.finish();
+        events.extend(p.by_ref());
+        assert_eq!(events, expected);
+        assert_eq!(p.parser().position().offset, input.len() as u64);
 
-        let v: Vec<Result<Event, ParserError>> = p.collect();
-        let mut attr: AttrMap<(String, Option<String>), String> = AttrMap::new();
-        attr.insert(
+        let mut p = PushParser::new();
+        p.feed_str("<a>\u{e9}");
+        assert!(p.next().unwrap().is_ok());
+        p.feed_bytes(b"\xff");
+        let err = p.next().unwrap().unwrap_err();
+        assert_eq!(err.kind, ParserErrorKind::InvalidUtf8);
+        assert_eq!(err.offset, 5);
+        p.feed_str("</a>");
+        assert!(p.next().is_none());
+    }
+
+    #[test]
+    fn test_trailing_text() {
+        let events: Vec<_> = Parser::new("<a/> x&amp;".as_bytes()).collect();
+        assert_eq!(events[2], Ok(Event::Characters(" x&".to_owned())));
+        assert!(Parser::new("<a/>&x".as_bytes()).any(|event| event.is_err()));
+    }
+
+    #[test]
+    fn test_xml_decl() {
+        let mut p = Parser::new("<?xml version='1.0' standalone='yes'?><a/>".as_bytes());
+        let decl = XmlDecl {
+            standalone: Some(true),
+            ..XmlDecl::new("1.0")
+        };
+        assert_eq!(p.next(), Some(Ok(Event::XmlDecl(decl))));
+        assert!(p.all(|event| event.is_ok()));
+
+        for (input, kind) in [
             (
-                "foo".to_owned(),
-                Some("http://www.w3.org/2000/xmlns/".to_owned()),
+                "<?xml encoding='UTF-8'?><a/>",
+                ParserErrorKind::InvalidXmlDecl,
             ),
-            "urn:foo".to_owned(),
-        );
-        assert_eq!(
-            v,
-            vec![
-                Ok(Event::ElementStart(StartTag {
-                    name: "a".to_owned(),
-                    ns: Some("urn:foo".to_owned()),
-                    prefix: Some("foo".to_owned()),
-                    attributes: attr,
-                })),
-                Ok(Event::ElementEnd(EndTag {
-                    name: "a".to_owned(),
-                    ns: Some("urn:foo".to_owned()),
-                    prefix: Some("foo".to_owned()),
-                }))
-            ],
-        );
+            (
+                " <?xml version='1.0'?><a/>",
+                ParserErrorKind::MisplacedXmlDecl,
+            ),
+            (
+                "<!--c--><?xml version='1.0'?><a/>",
+                ParserErrorKind::MisplacedXmlDecl,
+            ),
+            (
+                "<!DOCTYPE a><?xml version='1.0'?><a/>",
+                ParserErrorKind::MisplacedXmlDecl,
+            ),
+            (
+                "<a><?xml version='1.0'?></a>",
+                ParserErrorKind::MisplacedXmlDecl,
+            ),
+        ] {
+            let err = Parser::new(input.as_bytes()).find_map(Result::err).unwrap();
+            assert_eq!(err.kind, kind, "{}", input);
+        }
+        // Only the target "xml" itself is the declaration
+        let events: Vec<_> = Parser::new("<a><?xml-model href='a'?></a>".as_bytes()).collect();
+        assert_eq!(events[1], Ok(Event::PI("xml-model href='a'".to_owned())));
     }
 
     #[test]
-    fn test_pi() {
-        let s = "<?xml version='1.0' encoding='utf-8'?>".as_bytes();
-        let p = Parser::new(s);
-        let mut i = 0u8;
+    fn test_internal_subset() {
+        let subset = "\r\n  <!ENTITY x \"a]>b\r\nc\">\r\n  <!-- [x]> -->\r\n  <?pi ]]>?>\
+                      <!ATTLIST a b CDATA '[]'> %pe;\r\n";
+        let input = format!("<!DOCTYPE a [{}]>\r\n<a>&x;</a>", subset);
+        let events: Vec<_> = Parser::new(input.as_bytes()).map(Result::unwrap).collect();
+        let doctype = match events[0] {
+            Event::Doctype(ref doctype) => doctype,
+            _ => panic!("Expected a doctype"),
+        };
+        assert_eq!(doctype.internal_subset.as_deref(), Some(subset));
+        // Entity values are normalized like the rest of the document
+        assert_eq!(events[3], Event::Characters("a]>b\nc".to_owned()));
 
-        for event in p {
-            i += 1;
+        let mut writer = EventWriter::new(Vec::new());
+        writer.write(&events[0]).unwrap();
+        let written = writer.into_inner().unwrap();
+        let declaration = input.strip_suffix("\r\n<a>&x;</a>").unwrap();
+        assert_eq!(written, declaration.as_bytes());
+    }
+
+    #[test]
+    fn test_coalesce_text() {
+        let input = "<a>x<![CDATA[y]]>&amp;<b>z</b><![CDATA[]]></a> <?pi?>t<![CDATA[u]]>";
+        let config = ParserConfig::new().coalesce_text(true).fidelity(true);
+        let mut p = Parser::new_with_config(input.as_bytes(), config.clone());
+        let mut events = Vec::new();
+        let mut source = Vec::new();
+        while let Some(event) = p.next() {
+            let event = event.unwrap();
+            let span = p.span();
             assert_eq!(
-                event,
-                Ok(Event::PI("xml version='1.0' encoding='utf-8'".to_owned())),
+                &input.as_bytes()[span.start as usize..span.end as usize],
+                p.source()
             );
+            source.extend_from_slice(p.source());
+            events.push((event, p.depth()));
         }
-        assert_eq!(i, 1u8);
+        source.extend_from_slice(p.source());
+        assert_eq!(source, input.as_bytes());
+        let text = |text: &str| Event::Characters(text.to_owned());
+        assert_eq!(events[1], (text("xy&"), 1));
+        assert!(matches!(events[2], (Event::ElementStart(_), 2)));
+        assert_eq!(events[3], (text("z"), 2));
+        assert_eq!(events[5], (text(""), 1));
+        assert_eq!(events[7], (text(" "), 0));
+        assert_eq!(events[9], (text("tu"), 0));
+        assert_eq!(events.len(), 10);
+
+        let mut p = PushParser::new_with_config(config);
+        let mut pushed = Vec::new();
+        for byte in input.as_bytes() {
+            p.feed_bytes(&[*byte]);
+            pushed.extend(p.by_ref().map(Result::unwrap));
+        }
+        p.finish();
+        pushed.extend(p.by_ref().map(Result::unwrap));
+        let events: Vec<_> = events.into_iter().map(|(event, _)| event).collect();
+        assert_eq!(pushed, events);
     }
 
     #[test]
-    fn test_comment() {
-        let s = "<!--Nothing to see-->".as_bytes();
-        let p = Parser::new(s);
-        let mut i = 0u8;
-        for event in p {
-            i += 1;
-            assert_eq!(event, Ok(Event::Comment("Nothing to see".to_owned())));
+    fn test_trim_text() {
+        let input = "<a>\n <b> x\u{a0}</b>\t<![CDATA[ y ]]> <!--c--> &#32;z\r\n<c/>\n</a>\n";
+        let events = |config: ParserConfig| {
+            let mut p = Parser::new_with_config(input.as_bytes(), config.trim_text(true));
+            let mut events = Vec::new();
+            while let Some(peeked) = p.peek().cloned() {
+                let event = p.next().unwrap().unwrap();
+                assert_eq!(peeked.unwrap(), event);
+                let text = match event {
+                    Event::Characters(text) => format!("[{}]", text),
+                    Event::CDATA(text) => format!("<{}>", text),
+                    _ => continue,
+                };
+                let span = p.span();
+                events.push((text, &input[span.start as usize..span.end as usize]));
+            }
+            events
+        };
+        assert_eq!(
+            events(ParserConfig::new()),
+            [
+                ("[x\u{a0}]".to_owned(), " x\u{a0}"),
+                ("< y >".to_owned(), "<![CDATA[ y ]]>"),
+                ("[z]".to_owned(), " &#32;z\r\n"),
+            ]
+        );
+        assert_eq!(
+            events(ParserConfig::new().coalesce_text(true)),
+            [
+                ("[x\u{a0}]".to_owned(), " x\u{a0}"),
+                ("[y]".to_owned(), "\t<![CDATA[ y ]]> "),
+                ("[z]".to_owned(), " &#32;z\r\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_documents() {
+        let docs = [
+            "<?xml version='1.0'?><a xmlns:p='urn:p'><p:b>1</p:b></a>",
+            "<?xml version='1.0'?>\n<!DOCTYPE a [<!ENTITY e 'x'>]><a>&e;<b/></a>",
+            "<a>\n</a>",
+        ];
+        let input = format!("{}\r\n\n{} {}\n", docs[0], docs[1], docs[2]);
+        for size in [1, 7, 8192] {
+            let config = ParserConfig::new()
+                .stop_after_root(true)
+                .strict(true)
+                .read_buffer_size(size);
+            let mut p = Parser::new_with_config(input.as_bytes(), config);
+            let mut parsed = Vec::new();
+            while p.peek().is_some() {
+                let base = input.find(docs[parsed.len()]).unwrap();
+                let mut doc = String::new();
+                while let Some(event) = p.next() {
+                    event.unwrap();
+                    let span = p.span();
+                    doc.push_str(&input[base + span.start as usize..base + span.end as usize]);
+                }
+                assert_eq!(p.position().offset, doc.len() as u64);
+                assert_eq!(p.position().line, 1 + doc.matches('\n').count() as u64);
+                let end = base + doc.len();
+                assert_eq!(p.bytes_read(), end as u64);
+                assert_eq!(
+                    p.lines_read(),
+                    1 + input[..end].matches('\n').count() as u64
+                );
+                assert!(p.peek().is_none());
+                parsed.push(doc);
+                p.reset();
+            }
+            assert_eq!(parsed, docs);
         }
-        assert_eq!(i, 1u8);
+
+        // Entities and namespaces are declared for one document
+        let input = "<!DOCTYPE a [<!ENTITY e 'x'>]><a xmlns:p='urn:p'>&e;</a><p:a/><a>&e;</a>";
+        let config = ParserConfig::new().stop_after_root(true);
+        let mut p = Parser::new_with_config(input.as_bytes(), config);
+        assert!(p.by_ref().all(|event| event.is_ok()));
+        for kind in [
+            ParserErrorKind::UnboundNsPrefixInTagName,
+            ParserErrorKind::InvalidEntity,
+        ] {
+            p.reset();
+            assert_eq!(p.find_map(Result::err).map(|err| err.kind), Some(kind));
+            while p.next().is_some() {}
+        }
+
+        let mut p = PushParser::new_with_config(ParserConfig::new().stop_after_root(true));
+        p.feed_str("<a>1</a><b>2</b><c>");
+        let mut names = Vec::new();
+        while let Some(event) = p.next() {
+            if let Event::ElementEnd(tag) = event.unwrap() {
+                names.push(tag.name.name);
+                if p.parser().depth() == 0 {
+                    p.reset();
+                }
+            }
+        }
+        assert_eq!(names, ["a", "b"]);
     }
+
     #[test]
-    fn test_cdata() {
-        let s = "<![CDATA[<html><head><title>x</title></head><body/></html>]]>".as_bytes();
-        let p = Parser::new(s);
-        let mut i = 0u8;
-        for event in p {
-            i += 1;
-            assert_eq!(
-                event,
-                Ok(Event::CDATA(
-                    "<html><head><title>x</title></head><body/></html>".to_owned()
-                )),
-            );
+    fn test_checkpoint() {
+        let input = "\u{feff}<!DOCTYPE a [<!ENTITY e 'x&#38;y'>]>\r\n<a xmlns:p='urn:p' p:x='1'>\
+                     t&e;<![CDATA[c]]><!--m--><?pi d?><p:b/>\u{e9}</a> <a/>";
+        // The events with their spans and the positions after them
+        let parse = |p: &mut Parser<&[u8]>, peek: bool| {
+            let mut events = Vec::new();
+            while let Some(event) = p.next() {
+                events.push((event.unwrap(), p.span(), Parser::position(p)));
+                if peek {
+                    p.peek();
+                }
+            }
+            events
+        };
+        for (config, peek) in [
+            (ParserConfig::new(), false),
+            (
+                ParserConfig::new().coalesce_text(true).read_buffer_size(3),
+                true,
+            ),
+            (
+                ParserConfig::new().stop_after_root(true).fidelity(true),
+                true,
+            ),
+        ] {
+            let mut p = Parser::new_with_config(input.as_bytes(), config.clone());
+            let events = parse(&mut p, peek);
+            for i in 0..events.len() {
+                let mut p = Parser::new_with_config(input.as_bytes(), config.clone());
+                for _ in 0..i {
+                    p.next().unwrap().unwrap();
+                }
+                if peek {
+                    p.peek();
+                }
+                let checkpoint = p.checkpoint();
+                #[cfg(feature = "serde")]
+                let checkpoint: super::Checkpoint =
+                    serde_json::from_str(&serde_json::to_string(&checkpoint).unwrap()).unwrap();
+                let offset = checkpoint.offset() as usize;
+                let mut p = Parser::resume(&input.as_bytes()[offset..], checkpoint);
+                assert_eq!(parse(&mut p, peek), events[i..], "{}", i);
+            }
         }
-        assert_eq!(i, 1u8);
+
+        // Offsets are in the whole input after `reset()`
+        let config = ParserConfig::new().stop_after_root(true);
+        let mut p = Parser::new_with_config("<a/>\n <b>1</b>".as_bytes(), config);
+        while p.next().is_some() {}
+        p.reset();
+        p.next();
+        assert_eq!(p.position().offset, 3);
+        let checkpoint = p.checkpoint();
+        assert_eq!(checkpoint.offset(), 9);
+        let mut p = Parser::resume("1</b>".as_bytes(), checkpoint);
+        assert_eq!(
+            p.next().unwrap().unwrap(),
+            Event::Characters("1".to_owned())
+        );
+        assert_eq!(p.span(), 3..4);
     }
 
     #[test]
-    fn test_characters() {
-        let s = "<text>Hello World, it&apos;s a nice day</text>".as_bytes();
-        let p = Parser::new(s);
-        let mut i = 0u8;
-        for event in p {
-            i += 1;
-            if i == 2 {
+    fn test_text_chunks() {
+        let input = "<a>x&amp;y\r\nz\u{e9}&#x1F600;text<![CDATA[a]b]]c\u{e9}]]]]><b>t</b>1</a>";
+        // Adjacent text events of the same kind are merged to compare with unchunked text
+        let parse = |config: ParserConfig| {
+            let mut p = Parser::new_with_config(input.as_bytes(), config.fidelity(true));
+            let mut events: Vec<Event> = Vec::new();
+            let mut source = Vec::new();
+            while let Some(event) = p.next() {
+                let span = p.span();
                 assert_eq!(
-                    event,
-                    Ok(Event::Characters("Hello World, it's a nice day".to_owned())),
+                    &input.as_bytes()[span.start as usize..span.end as usize],
+                    p.source()
                 );
+                source.extend_from_slice(p.source());
+                match (events.last_mut(), event.unwrap()) {
+                    (Some(Event::Characters(a)), Event::Characters(b))
+                    | (Some(Event::CDATA(a)), Event::CDATA(b)) => {
+                        assert!(!b.is_empty());
+                        a.push_str(&b);
+                    }
+                    (_, event) => events.push(event),
+                }
+            }
+            assert_eq!(source, input.as_bytes());
+            (events, p.count())
+        };
+        let expected = parse(ParserConfig::new());
+        for size in [1, 8192] {
+            for max in 0..12 {
+                let config = ParserConfig::new()
+                    .read_buffer_size(size)
+                    .max_text_chunk(max);
+                assert_eq!(parse(config.clone()), expected, "{} {}", size, max);
+                assert_eq!(parse(config.coalesce_text(true)).1, 0);
             }
         }
-        assert_eq!(i, 3u8);
+
+        let chunks = |config: ParserConfig| {
+            Parser::new_with_config(input.as_bytes(), config)
+                .filter_map(|event| match event.unwrap() {
+                    Event::Characters(text) | Event::CDATA(text) => Some(text),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let config = ParserConfig::new().max_text_chunk(4);
+        assert_eq!(
+            chunks(config.clone()),
+            [
+                "x",
+                "&",
+                "y\nz\u{e9}",
+                "\u{1F600}",
+                "text",
+                "a]b]]c",
+                "\u{e9}]]",
+                "t",
+                "1"
+            ]
+        );
+        assert_eq!(
+            chunks(config.clone().coalesce_text(true)),
+            [
+                "x&y\nz\u{e9}",
+                "\u{1F600}",
+                "text",
+                "a]b]]c",
+                "\u{e9}]]",
+                "t",
+                "1"
+            ]
+        );
+
+        // The length of the whole text is limited
+        let config = ParserConfig::new().max_text_chunk(2).max_text_length(5);
+        let mut p = Parser::new_with_config("<a>123456</a>".as_bytes(), config);
+        assert!(p.by_ref().take(3).all(|event| event.is_ok()));
+        let err = p.next().unwrap().unwrap_err();
+        assert_eq!((err.kind, err.offset), (ParserErrorKind::LimitExceeded, 8));
+
+        // Neither an unterminated reference nor a run of ']' is buffered whole
+        let config = ParserConfig::new().max_text_chunk(4);
+        let input = io::Cursor::new("<a>x&").chain(io::repeat(b'y'));
+        let err = Parser::new_with_config(input, config.clone())
+            .find_map(Result::err)
+            .unwrap();
+        assert_eq!((err.kind, err.offset), (ParserErrorKind::InvalidEntity, 4));
+        let input = format!("<a><![CDATA[{}]]></a>", "]".repeat(50));
+        let chunks: Vec<_> = Parser::new_with_config(input.as_bytes(), config)
+            .filter_map(|event| match event.unwrap() {
+                Event::CDATA(text) => Some(text),
+                _ => None,
+            })
+            .collect();
+        assert!(chunks.iter().all(|text| text.len() == 4 || text == "]]"));
+        assert_eq!(chunks.concat(), "]".repeat(50));
     }
 
     #[test]
-    fn test_doctype() {
-        let s = "<!DOCTYPE html>".as_bytes();
-        let p = Parser::new(s);
-        let mut i = 0u8;
+    fn test_seek_to_element() {
+        let doc =
+            "<s:env xmlns:s='urn:s'><s:head><body/></s:head><s:body a='1'><x/></s:body></s:env>";
+        let mut p = Parser::new(doc.as_bytes());
+        let tag = p.seek_to_element("body", Some("urn:s")).unwrap().unwrap();
+        assert_eq!(tag.attributes.len(), 1);
+        assert_eq!(p.capture_raw().unwrap(), b"<s:body a='1'><x/></s:body>");
+        assert_eq!(p.seek_to_element("body", None).unwrap(), None);
+        assert!(Parser::new("<a><b>&x;</b><c/></a>".as_bytes())
+            .seek_to_element("c", None)
+            .is_err());
+    }
 
-        for _ in p {
-            i += 1;
+    fn entity_error(doc: &str) -> ParserErrorKind {
+        Parser::new(doc.as_bytes())
+            .find_map(Result::err)
+            .unwrap()
+            .kind
+    }
+
+    #[test]
+    fn test_entities() {
+        let doc = "<!DOCTYPE a SYSTEM 'a.dtd' [\n\
+                   <!ENTITY me '&#60;me&#62;'><!-- a > b -->\n\
+                   <!ENTITY greeting 'hello &me;'>\n\
+                   <!ENTITY ext SYSTEM 'ext.xml'>\n\
+                   ]><a x='&greeting;'>&greeting; &amp; &ext;</a>";
+        assert_eq!(entity_error(doc), ParserErrorKind::ExternalEntity);
+
+        let mut p = Parser::new(doc.as_bytes());
+        let (sender, receiver) = mpsc::channel();
+        p.set_entity_resolver(move |public_id: Option<&str>, system_id: &str| {
+            assert_eq!(public_id, None);
+            sender.send(system_id.to_owned()).unwrap();
+            match system_id {
+                "a.dtd" => Ok("<!ENTITY ext 'ignored'><!ENTITY more 'x'>".to_owned()),
+                _ => Ok("<?xml encoding='UTF-8'?>external".to_owned()),
+            }
+        });
+        let events: Vec<_> = p.map(Result::unwrap).collect();
+        let requested: Vec<_> = receiver.try_iter().collect();
+        assert_eq!(requested, ["a.dtd", "ext.xml"]);
+        match events[1] {
+            Event::ElementStart(ref tag) => {
+                assert_eq!(
+                    tag.attributes[&QName::new("x".to_owned(), None)],
+                    "hello <me>"
+                )
+            }
+            _ => panic!("Expected a start tag"),
+        }
+        assert_eq!(
+            events[2],
+            Event::Characters("hello <me> & external".to_owned())
+        );
+
+        assert_eq!(
+            entity_error("<!DOCTYPE a [<!ENTITY e SYSTEM 'e'>]><a x='&e;'/>"),
+            ParserErrorKind::ExternalEntity
+        );
+        assert_eq!(
+            entity_error("<!DOCTYPE a [<!ENTITY a '&b;'><!ENTITY b '&a;'>]><a>&a;</a>"),
+            ParserErrorKind::InvalidEntity
+        );
+        assert_eq!(
+            entity_error("<!DOCTYPE a [<!ENTITY a>]><a/>"),
+            ParserErrorKind::InvalidDoctype
+        );
+
+        let mut laughs = "<!DOCTYPE a [<!ENTITY l0 'lol'>".to_owned();
+        for i in 1..20 {
+            let refs = format!("&l{};", i - 1).repeat(10);
+            laughs.push_str(&format!("<!ENTITY l{} '{}'>", i, refs));
         }
-        assert_eq!(i, 0u8);
+        laughs.push_str("]><a>&l19;</a>");
+        assert_eq!(entity_error(&laughs), ParserErrorKind::EntityLimitExceeded);
+
+        let nested = "<!DOCTYPE a [<!ENTITY a 'x'><!ENTITY b '&a;&a;'><!ENTITY c '&b;&b;'>]>\
+                      <a v='&c;'>&c;</a>";
+        let limited = |config: ParserConfig| {
+            Parser::new_with_config(nested.as_bytes(), config)
+                .find_map(Result::err)
+                .map(|err| err.kind)
+        };
+        assert_eq!(limited(ParserConfig::new()), None);
+        assert_eq!(limited(ParserConfig::new().max_entity_expansion(44)), None);
+        assert_eq!(
+            limited(ParserConfig::new().max_entity_expansion(43)),
+            Some(ParserErrorKind::EntityLimitExceeded)
+        );
+        assert_eq!(limited(ParserConfig::new().max_entity_depth(3)), None);
+        assert_eq!(
+            limited(ParserConfig::new().max_entity_depth(2)),
+            Some(ParserErrorKind::EntityLimitExceeded)
+        );
     }
 
+    #[cfg(feature = "serde")]
     #[test]
-    #[cfg(feature = "ordered_attrs")]
-    fn test_attribute_order() {
-        let input = "<a href='/' title='Home' target='_blank'>".as_bytes();
-        let expected_attributes = vec![
-            (("href".to_owned(), None), "/".to_owned()),
-            (("title".to_owned(), None), "Home".to_owned()),
-            (("target".to_owned(), None), "_blank".to_owned()),
-        ];
+    fn test_serialize_error() {
+        let err = Parser::new("<a>\n<b>&x;</b>".as_bytes())
+            .find_map(Result::err)
+            .unwrap();
+        assert_eq!(
+            serde_json::to_string(&crate::BuilderError::Parser(err)).unwrap(),
+            r#"{"Parser":{"line":2,"col":4,"offset":7,"kind":"InvalidEntity"}}"#,
+        );
+    }
 
-        // Run this 5 times to make it unlikely this test succeeds at random
-        for _ in 0..5 {
-            let mut p = Parser::new(input);
-            if let Some(Ok(Event::ElementStart(tag))) = p.next() {
-                for (expected, actual) in expected_attributes.iter().zip(tag.attributes) {
-                    assert_eq!(expected, &actual);
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_events() {
+        use super::Event;
+
+        let events: Vec<Event> = Parser::new("<a xmlns='urn:a' x='1'>t<!--c--></a>".as_bytes())
+            .map(Result::unwrap)
+            .collect();
+        let json = serde_json::to_string(&events).unwrap();
+        assert!(json.contains(r#""attributes":[["#));
+        let replayed: Vec<Event> = serde_json::from_str(&json).unwrap();
+        assert_eq!(replayed, events);
+    }
+
+    #[cfg(feature = "interning")]
+    #[test]
+    fn test_interning() {
+        use super::{Event, MAX_INTERNED_NAMES};
+        use std::ptr;
+
+        let mut names = Vec::new();
+        for event in Parser::new("<a><b x='1'/><b x='2'/><c b='3'/></a>".as_bytes()) {
+            match event.unwrap() {
+                Event::ElementStart(tag) => {
+                    names.push(tag.name.name);
+                    names.extend(tag.attributes.into_keys().map(|key| key.name));
                 }
-            } else {
-                panic!("Missing ElementStart event");
+                Event::ElementEnd(tag) => names.push(tag.name.name),
+                _ => (),
+            }
+        }
+        let names: Vec<_> = names.iter().map(|name| (&name[..], name)).collect();
+        for (i, &(a, first)) in names.iter().enumerate() {
+            for &(b, other) in &names[i + 1..] {
+                assert_eq!(
+                    a == b,
+                    ptr::eq(first.as_ptr(), other.as_ptr()),
+                    "{} {}",
+                    a,
+                    b
+                );
             }
-            assert!(p.next().is_none());
         }
+
+        // Once the table is full, new names are no longer shared
+        let doc: String = (0..=MAX_INTERNED_NAMES)
+            .map(|i| format!("<e{}/>", i))
+            .chain(["<last/><last/>".to_owned()])
+            .collect();
+        let names: Vec<_> = Parser::new(format!("<r>{}</r>", doc).as_bytes())
+            .filter_map(|event| match event.unwrap() {
+                Event::ElementStart(tag) => Some(tag.name.name),
+                _ => None,
+            })
+            .collect();
+        let last = &names[names.len() - 2..];
+        assert_eq!(&last[0][..], "last");
+        assert!(!ptr::eq(last[0].as_ptr(), last[1].as_ptr()));
     }
 }
@@ -0,0 +1,720 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::names;
+use crate::ns;
+use crate::{
+    AttrMap, BuilderError, Element, ElementBuilder, EndTag, Event, ParserError, QName, StartTag,
+    Xml,
+};
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug)]
+/// Errors encountered while applying a patch
+pub enum PatchError {
+    /// Errors encountered while reading the patch
+    Builder(BuilderError),
+    /// The selector of an operation did not match anything in the tree
+    NoMatch(String),
+    /// An operation was malformed or cannot be applied at the selected node
+    InvalidOperation(String),
+}
+
+impl Error for PatchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            PatchError::Builder(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PatchError::Builder(ref err) => err.fmt(f),
+            PatchError::NoMatch(ref sel) => write!(f, "Selector '{}' matched nothing", sel),
+            PatchError::InvalidOperation(ref msg) => write!(f, "Invalid operation: {}", msg),
+        }
+    }
+}
+
+impl From<BuilderError> for PatchError {
+    fn from(err: BuilderError) -> PatchError {
+        PatchError::Builder(err)
+    }
+}
+
+impl From<ParserError> for PatchError {
+    fn from(err: ParserError) -> PatchError {
+        PatchError::Builder(BuilderError::Parser(err))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Kind {
+    Add,
+    Replace,
+    Remove,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+// The absolute location selected by a `sel` attribute
+struct Selector {
+    // Local name and 1-based position among same-named siblings, starting at the root
+    steps: Vec<(String, usize)>,
+    attribute: Option<String>,
+}
+
+impl Selector {
+    // Parses the subset of XPath used by `sel`: `/a/b[2]/c` or `/a/b/@attr`
+    fn parse(sel: &str) -> Option<Selector> {
+        let mut steps = Vec::new();
+        let mut attribute = None;
+        for step in sel.strip_prefix('/')?.split('/') {
+            if attribute.is_some() {
+                return None;
+            }
+            if let Some(attr) = step.strip_prefix('@') {
+                attribute = Some(attr.to_owned());
+                continue;
+            }
+            let (name, position) = match step.strip_suffix(']') {
+                Some(step) => {
+                    let (name, position) = step.split_once('[')?;
+                    (name, position.parse().ok().filter(|&n| n > 0)?)
+                }
+                None => (step, 1),
+            };
+            let name = name.rsplit(':').next().unwrap_or(name);
+            if name.is_empty() {
+                return None;
+            }
+            steps.push((name.to_owned(), position));
+        }
+        if steps.is_empty() {
+            return None;
+        }
+        Some(Selector { steps, attribute })
+    }
+
+    // Returns the indices into `children` leading from `root` to the selected element
+    fn locate(&self, root: &Element) -> Option<Vec<usize>> {
+        let (ref name, position) = self.steps[0];
+        if position != 1 || !matches_name(root, name) {
+            return None;
+        }
+        let mut location = Vec::with_capacity(self.steps.len() - 1);
+        let mut elem = root;
+        for &(ref name, position) in &self.steps[1..] {
+            let (index, child) = elem
+                .children
+                .iter()
+                .enumerate()
+                .filter_map(|(i, child)| match *child {
+                    Xml::ElementNode(ref child) if matches_name(child, name) => Some((i, child)),
+                    _ => None,
+                })
+                .nth(position - 1)?;
+            location.push(index);
+            elem = child;
+        }
+        Some(location)
+    }
+}
+
+fn matches_name(elem: &Element, name: &str) -> bool {
+    name == "*" || elem.name.name == name
+}
+
+fn element_mut<'a>(root: &'a mut Element, location: &[usize]) -> &'a mut Element {
+    location
+        .iter()
+        .fold(root, |elem, &i| match elem.children[i] {
+            Xml::ElementNode(ref mut child) => child,
+            _ => unreachable!("Location does not lead to an element"),
+        })
+}
+
+#[derive(Debug)]
+struct Operation {
+    kind: Kind,
+    sel: String,
+    pos: Option<String>,
+    attribute: Option<String>,
+    content: Vec<Xml>,
+    preserve_space: bool,
+}
+
+impl Operation {
+    fn text(&self) -> String {
+        self.content
+            .iter()
+            .filter_map(|node| match *node {
+                Xml::CharacterNode(ref text) | Xml::CDATANode(ref text) => Some(&text[..]),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn apply(self, tree: &mut Element) -> Result<(), PatchError> {
+        let invalid = |msg: &str| Err(PatchError::InvalidOperation(msg.to_owned()));
+        let selector = match Selector::parse(&self.sel) {
+            Some(selector) => selector,
+            None => return invalid(&format!("malformed selector '{}'", self.sel)),
+        };
+        let location = match selector.locate(tree) {
+            Some(location) => location,
+            None => return Err(PatchError::NoMatch(self.sel)),
+        };
+
+        if let Some(ref attr) = selector.attribute {
+            let elem = element_mut(tree, &location);
+            let exists = elem.get_attribute(attr, None).is_some();
+            return match self.kind {
+                Kind::Add => invalid("cannot add to an attribute"),
+                _ if !exists => Err(PatchError::NoMatch(self.sel)),
+                Kind::Replace => {
+                    elem.set_attribute(attr.clone(), None, self.text());
+                    Ok(())
+                }
+                Kind::Remove => {
+                    elem.remove_attribute(attr, None);
+                    Ok(())
+                }
+            };
+        }
+
+        if let (Kind::Add, Some(attr)) = (self.kind, self.attribute.as_ref()) {
+            let elem = element_mut(tree, &location);
+            elem.set_attribute(attr.clone(), None, self.text());
+            return Ok(());
+        }
+
+        // Whitespace around elements is taken as formatting unless `xml:space` says otherwise
+        let mut content = self.content;
+        if !self.preserve_space
+            && content
+                .iter()
+                .any(|node| matches!(node, Xml::ElementNode(_)))
+        {
+            content.retain(|node| match node {
+                Xml::CharacterNode(text) => !text.chars().all(names::is_space),
+                _ => true,
+            });
+        }
+
+        let position = match (self.kind, self.pos.as_deref()) {
+            (Kind::Add, None) => {
+                element_mut(tree, &location).children.extend(content);
+                return Ok(());
+            }
+            (Kind::Add, Some("prepend")) => {
+                let elem = element_mut(tree, &location);
+                elem.children.splice(0..0, content);
+                return Ok(());
+            }
+            (Kind::Add, Some("before")) => 0,
+            (Kind::Add, Some("after")) => 1,
+            (Kind::Add, Some(pos)) => return invalid(&format!("unknown position '{}'", pos)),
+            _ => 0,
+        };
+
+        let (index, parent) = match location.split_last() {
+            Some((&index, parent)) => (index, parent),
+            None if self.kind != Kind::Replace => return invalid("the root cannot be a sibling"),
+            None => {
+                let mut content = content.into_iter().filter_map(|node| match node {
+                    Xml::ElementNode(elem) => Some(elem),
+                    _ => None,
+                });
+                return match (content.next(), content.next()) {
+                    (Some(root), None) => {
+                        *tree = root;
+                        Ok(())
+                    }
+                    _ => invalid("the root has to be replaced by a single element"),
+                };
+            }
+        };
+        let children = &mut element_mut(tree, parent).children;
+        match self.kind {
+            Kind::Add => {
+                children.splice(index + position..index + position, content);
+            }
+            Kind::Replace => {
+                children.splice(index..=index, content);
+            }
+            Kind::Remove => {
+                children.remove(index);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Applies a stream of patch operations to an `Element` tree
+///
+/// The stream is a document whose root element contains the operations `<add>`, `<replace>`
+/// and `<remove>` as defined by RFC 5261, regardless of their namespace.
+/// Every operation is applied as soon as its end tag has been read, which allows keeping a
+/// tree up to date from a long-lived stream of updates.
+///
+/// The `sel` attribute addresses the target by an absolute path, in which each step may
+/// carry a 1-based position among same-named siblings, e.g. `/list/item[2]`.
+/// A final `@name` step selects an attribute. Other XPath expressions are not supported.
+/// Whitespace-only text next to elements in the content of an operation is dropped as
+/// formatting, unless `xml:space='preserve'` is set on the operation or the root element.
+///
+/// ~~~
+/// use xml::{Element, Parser, Patcher};
+///
+/// let mut tree: Element = "<list><item>a</item><item>b</item></list>".parse().unwrap();
+/// let updates = "<updates>\
+///                <replace sel='/list/item[2]'><item>B</item></replace>\
+///                <add sel='/list'><item>c</item></add>\
+///                <add sel='/list/item[1]' type='@id'>1</add>\
+///                </updates>";
+/// let mut patcher = Patcher::new();
+/// for event in Parser::new(updates.as_bytes()) {
+///     if let Some(result) = patcher.handle_event(&mut tree, event) {
+///         result.unwrap();
+///     }
+/// }
+/// assert_eq!(tree.to_string(), "<list><item id='1'>a</item><item>B</item><item>c</item></list>");
+/// ~~~
+#[derive(Default)]
+pub struct Patcher {
+    depth: usize,
+    preserve_space: bool,
+    operation: Option<Operation>,
+    builder: ElementBuilder,
+}
+
+impl Patcher {
+    /// Returns a new `Patcher`
+    pub fn new() -> Patcher {
+        Patcher::default()
+    }
+
+    /// Processes an `Event` of the patch stream.
+    ///
+    /// Returns `Some(Ok(()))` after an operation has been applied to `tree`, `Some(Err(_))` if
+    /// reading or applying an operation failed and `None` otherwise.
+    pub fn handle_event(
+        &mut self,
+        tree: &mut Element,
+        e: Result<Event, ParserError>,
+    ) -> Option<Result<(), PatchError>> {
+        let e = match e {
+            Ok(e) => e,
+            Err(err) => return Some(Err(From::from(err))),
+        };
+        if self.depth > 2 {
+            match e {
+                Event::ElementStart(_) => self.depth += 1,
+                Event::ElementEnd(_) => self.depth -= 1,
+                _ => (),
+            }
+            let elem = match self.builder.handle_event(Ok(e))? {
+                Ok(elem) => elem,
+                Err(err) => return Some(Err(From::from(err))),
+            };
+            self.push(Xml::ElementNode(elem));
+            return None;
+        }
+        match e {
+            Event::ElementStart(tag) => {
+                self.depth += 1;
+                match self.depth {
+                    1 => self.preserve_space = preserves_space(&tag, false),
+                    2 => match Patcher::start(&tag, self.preserve_space) {
+                        Ok(operation) => self.operation = Some(operation),
+                        Err(err) => return Some(Err(err)),
+                    },
+                    _ => {
+                        self.builder.handle_event(Ok(Event::ElementStart(tag)));
+                    }
+                }
+            }
+            Event::ElementEnd(_) => {
+                self.depth = self.depth.saturating_sub(1);
+                if self.depth == 1 {
+                    return self.operation.take().map(|operation| operation.apply(tree));
+                }
+            }
+            Event::Characters(text) => self.push(Xml::CharacterNode(text)),
+            Event::CDATA(text) => self.push(Xml::CDATANode(text)),
+            Event::Comment(text) => self.push(Xml::CommentNode(text)),
+            Event::PI(text) => self.push(Xml::PINode(text)),
+            Event::XmlDecl(_) | Event::Doctype(_) => (),
+            Event::AttributeChunk(chunk) => {
+                return Some(Err(PatchError::InvalidOperation(format!(
+                    "chunked attribute '{}'",
+                    chunk.name
+                ))))
+            }
+        }
+        None
+    }
+
+    fn start(tag: &StartTag, preserve_space: bool) -> Result<Operation, PatchError> {
+        let kind = match &tag.name.name[..] {
+            "add" => Kind::Add,
+            "replace" => Kind::Replace,
+            "remove" => Kind::Remove,
+            name => return Err(PatchError::InvalidOperation(format!("unknown <{}>", name))),
+        };
+        let attribute = |name: &str| {
+            tag.attributes
+                .iter()
+                .find(|(QName { name: n, ns }, _)| n == name && ns.is_none())
+                .map(|(_, value)| value.clone())
+        };
+        let sel = attribute("sel").ok_or_else(|| {
+            PatchError::InvalidOperation(format!("<{}> without selector", tag.name.name))
+        })?;
+        Ok(Operation {
+            kind,
+            sel,
+            pos: attribute("pos"),
+            attribute: attribute("type").and_then(|t| t.strip_prefix('@').map(str::to_owned)),
+            content: Vec::new(),
+            preserve_space: preserves_space(tag, preserve_space),
+        })
+    }
+
+    // Adds a node to the content of the current operation
+    fn push(&mut self, node: Xml) {
+        if let (2, Some(operation)) = (self.depth, self.operation.as_mut()) {
+            operation.content.push(node);
+        }
+    }
+}
+
+// Returns whether whitespace is significant within `tag`, given whether it is in its parent
+fn preserves_space(tag: &StartTag, inherited: bool) -> bool {
+    let space = tag
+        .attributes
+        .iter()
+        .find(|(QName { name, ns }, _)| name == "space" && ns.as_deref() == Some(ns::XML));
+    match space {
+        Some((_, value)) => value == "preserve",
+        None => inherited,
+    }
+}
+
+// Collects the operations of a diff as events
+struct Diff {
+    events: Vec<Event>,
+}
+
+impl Diff {
+    fn op(&mut self, kind: &str, sel: String, attrs: &[(&str, &str)], content: &[&Xml]) {
+        let mut attributes = AttrMap::new();
+        attributes.insert(QName::new("sel".to_owned(), None), sel);
+        for &(name, value) in attrs {
+            attributes.insert(QName::new(name.to_owned(), None), value.to_owned());
+        }
+        self.events.push(Event::ElementStart(StartTag {
+            name: QName::new(kind.to_owned(), None),
+            prefix: None,
+            attributes,
+        }));
+        for node in content {
+            match **node {
+                Xml::ElementNode(ref elem) => self.events.extend(elem.to_events()),
+                Xml::CharacterNode(ref text) => self.events.push(Event::Characters(text.clone())),
+                Xml::CDATANode(ref text) => self.events.push(Event::CDATA(text.clone())),
+                Xml::CommentNode(ref text) => self.events.push(Event::Comment(text.clone())),
+                Xml::PINode(ref text) => self.events.push(Event::PI(text.clone())),
+            }
+        }
+        self.events.push(Event::ElementEnd(EndTag {
+            name: QName::new(kind.to_owned(), None),
+            prefix: None,
+        }));
+    }
+
+    fn replace(&mut self, sel: String, new: &Element) {
+        let new = Xml::ElementNode(new.clone());
+        self.op("replace", sel, &[], &[&new]);
+    }
+
+    fn element(&mut self, old: &Element, new: &Element, sel: String) {
+        if old.name != new.name {
+            return self.replace(sel, new);
+        }
+
+        // Only attributes without namespace can be addressed
+        let (old_attrs, new_attrs) = (&old.attributes, &new.attributes);
+        let unaddressable = |(QName { name, ns }, _): (&QName, _)| ns.is_some() || name == "xmlns";
+        let declarations_differ = old_attrs
+            .iter()
+            .chain(new_attrs.iter())
+            .filter(|&attr| unaddressable(attr))
+            .any(|(key, value)| {
+                old_attrs.get(key) != Some(value) || new_attrs.get(key) != Some(value)
+            });
+        if declarations_differ {
+            return self.replace(sel, new);
+        }
+        for (key, value) in old_attrs.iter().filter(|&attr| !unaddressable(attr)) {
+            let attr_sel = format!("{}/@{}", sel, key.name);
+            match new_attrs.get(key) {
+                None => self.op("remove", attr_sel, &[], &[]),
+                Some(new_value) if new_value != value => {
+                    let text = Xml::CharacterNode(new_value.clone());
+                    self.op("replace", attr_sel, &[], &[&text]);
+                }
+                Some(_) => (),
+            }
+        }
+        for (key, value) in new_attrs.iter().filter(|&attr| !unaddressable(attr)) {
+            if !old_attrs.contains_key(key) {
+                let text = Xml::CharacterNode(value.clone());
+                let attr = format!("@{}", key.name);
+                self.op("add", sel.clone(), &[("type", &attr)], &[&text]);
+            }
+        }
+
+        self.children(old, new, sel);
+    }
+
+    fn children(&mut self, old: &Element, new: &Element, sel: String) {
+        let (old_children, new_children) = (&old.children, &new.children);
+        let prefix = old_children
+            .iter()
+            .zip(new_children)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let max_suffix = old_children.len().min(new_children.len()) - prefix;
+        let suffix = old_children
+            .iter()
+            .rev()
+            .zip(new_children.iter().rev())
+            .take(max_suffix)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let removed = &old_children[prefix..old_children.len() - suffix];
+        let added = &new_children[prefix..new_children.len() - suffix];
+
+        // Children with matching names are updated in place
+        let same_shape = removed.len() == added.len()
+            && removed.iter().zip(added).all(|pair| match pair {
+                (Xml::ElementNode(a), Xml::ElementNode(b)) => a.name.name == b.name.name,
+                (a, b) => a == b,
+            });
+        if same_shape {
+            let old_children: Vec<&Xml> = old_children.iter().collect();
+            for (i, pair) in removed.iter().zip(added).enumerate() {
+                if let (Xml::ElementNode(a), Xml::ElementNode(b)) = pair {
+                    if a != b {
+                        let child_sel = format!("{}/{}", sel, step(&old_children, prefix + i));
+                        self.element(a, b, child_sel);
+                    }
+                }
+            }
+            return;
+        }
+
+        // Otherwise the differing element children are replaced, anchored at a sibling
+        let is_element = |node: &&Xml| matches!(**node, Xml::ElementNode(_));
+        let remaining: Vec<&Xml> = old_children[..prefix]
+            .iter()
+            .chain(&old_children[old_children.len() - suffix..])
+            .collect();
+        let anchor = if prefix > 0 && is_element(&remaining[prefix - 1]) {
+            Some((prefix - 1, "after"))
+        } else if suffix > 0 && is_element(&remaining[prefix]) {
+            Some((prefix, "before"))
+        } else {
+            None
+        };
+        if !removed.iter().all(|node| is_element(&node))
+            || (anchor.is_none() && !remaining.is_empty())
+        {
+            return self.replace(sel, new);
+        }
+        let old_children: Vec<&Xml> = old_children.iter().collect();
+        for i in (prefix..prefix + removed.len()).rev() {
+            self.op(
+                "remove",
+                format!("{}/{}", sel, step(&old_children, i)),
+                &[],
+                &[],
+            );
+        }
+        if added.is_empty() {
+            return;
+        }
+        let added: Vec<&Xml> = added.iter().collect();
+        match anchor {
+            Some((i, pos)) => {
+                let sibling_sel = format!("{}/{}", sel, step(&remaining, i));
+                self.op("add", sibling_sel, &[("pos", pos)], &added);
+            }
+            None => self.op("add", sel, &[], &added),
+        }
+    }
+}
+
+// Returns the selector step for the element at `index` among `children`
+fn step(children: &[&Xml], index: usize) -> String {
+    let name = match *children[index] {
+        Xml::ElementNode(ref elem) => &elem.name.name,
+        _ => unreachable!("Selector step for a non-element"),
+    };
+    let position = children[..=index]
+        .iter()
+        .filter(|node| matches!(***node, Xml::ElementNode(ref elem) if elem.name.name == *name))
+        .count();
+    format!("{}[{}]", name, position)
+}
+
+// Returns the patch operations transforming `old` into `new`, wrapped in a `<diff>` element
+pub(crate) fn diff(old: &Element, new: &Element) -> Vec<Event> {
+    // Whitespace in the content of the operations is part of the new tree
+    let mut attributes = AttrMap::new();
+    attributes.insert(
+        QName::new("space".to_owned(), Some(ns::XML.to_owned())),
+        "preserve".to_owned(),
+    );
+    let mut diff = Diff {
+        events: vec![Event::ElementStart(StartTag {
+            name: QName::new("diff".to_owned(), None),
+            prefix: None,
+            attributes,
+        })],
+    };
+    diff.element(old, new, format!("/{}", old.name.name));
+    diff.events.push(Event::ElementEnd(EndTag {
+        name: QName::new("diff".to_owned(), None),
+        prefix: None,
+    }));
+    diff.events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PatchError, Selector};
+    use crate::{Element, EventWriter, Parser, WriterConfig};
+
+    fn patch(tree: &str, patch: &str) -> Result<String, PatchError> {
+        let mut tree: Element = tree.parse().unwrap();
+        tree.apply_patch(Parser::new(patch.as_bytes()))?;
+        Ok(tree.to_string())
+    }
+
+    #[test]
+    fn test_selector() {
+        let tree: Element = "<a><b/>text<c/><b><d/></b></a>".parse().unwrap();
+        let locate = |sel| Selector::parse(sel).and_then(|s| s.locate(&tree));
+        assert_eq!(locate("/a"), Some(vec![]));
+        assert_eq!(locate("/a/b[2]/d"), Some(vec![3, 0]));
+        assert_eq!(locate("/a/*[2]"), Some(vec![2]));
+        assert_eq!(locate("/a/b[3]"), None);
+        assert_eq!(locate("a/b"), None);
+        assert!(Selector::parse("/a/@x/b").is_none());
+    }
+
+    #[test]
+    fn test_operations() {
+        let tree = "<a x='1'><b/><c/></a>";
+        assert_eq!(
+            patch(tree, "<p><add sel='/a/c' pos='before'><n/> </add></p>").unwrap(),
+            "<a x='1'><b/><n/><c/></a>",
+        );
+        assert_eq!(
+            patch(tree, "<p><add sel='/a' pos='prepend'>t</add></p>").unwrap(),
+            "<a x='1'>t<b/><c/></a>",
+        );
+        assert_eq!(
+            patch(
+                tree,
+                "<p><remove sel='/a/b'/><replace sel='/a/@x'>2</replace></p>"
+            )
+            .unwrap(),
+            "<a x='2'><c/></a>",
+        );
+        assert_eq!(
+            patch(
+                tree,
+                "<p><replace sel='/a/@x'> </replace><add sel='/a/b'>\n</add></p>"
+            )
+            .unwrap(),
+            "<a x=' '><b>\n</b><c/></a>",
+        );
+        assert_eq!(
+            patch(
+                tree,
+                "<p xml:space='preserve'><add sel='/a/b'> <n/></add></p>"
+            )
+            .unwrap(),
+            "<a x='1'><b> <n/></b><c/></a>",
+        );
+        assert_eq!(
+            patch(tree, "<p><replace sel='/a'><r><s/></r></replace></p>").unwrap(),
+            "<r><s/></r>",
+        );
+        assert!(matches!(
+            patch(tree, "<p><remove sel='/a/d'/></p>"),
+            Err(PatchError::NoMatch(_)),
+        ));
+        assert!(matches!(
+            patch(tree, "<p><remove sel='/a'/></p>"),
+            Err(PatchError::InvalidOperation(_)),
+        ));
+    }
+
+    #[test]
+    fn test_diff_roundtrip() {
+        let cases = [
+            ("<a><b/></a>", "<c/>"),
+            ("<a x='1' y='2'><b>t</b></a>", "<a x='3' z='4'><b>u</b></a>"),
+            ("<a><b/><c/><d/></a>", "<a><b/><e/><f/><d/></a>"),
+            ("<a><b/><c/></a>", "<a><c/></a>"),
+            ("<a>t<b/></a>", "<a>t<b/><c/></a>"),
+            ("<a>t</a>", "<a>u</a>"),
+            ("<a/>", "<a><b/>text</a>"),
+            (
+                "<a xmlns:p='urn:p'><b><p:c/></b></a>",
+                "<a xmlns:p='urn:p'><b><p:c/><p:d/></b></a>",
+            ),
+            ("<p><b>x</b><i>y</i></p>", "<p><b>x</b> <i>y</i></p>"),
+            ("<a x='1'/>", "<a x=' '/>"),
+            ("<a/>", "<a x=' '/>"),
+            ("<a><b/></a>", "<a><b/> <c/></a>"),
+            ("<a>\n  <b/>\n</a>", "<a>\n  <c/>\n</a>"),
+        ];
+        for &(old, new) in &cases {
+            let mut old: Element = old.parse().unwrap();
+            let new: Element = new.parse().unwrap();
+            let diff = old.diff(&new);
+            old.apply_patch(diff.into_iter().map(Ok)).unwrap();
+            assert_eq!(old, new);
+        }
+    }
+
+    #[test]
+    fn test_diff_is_compact() {
+        let old: Element = "<a><b/><c><d/></c></a>".parse().unwrap();
+        let new: Element = "<a><b/><c><d x='1'/></c></a>".parse().unwrap();
+        let config = WriterConfig::new().deterministic(true);
+        let mut writer = EventWriter::new_with_config(Vec::new(), config);
+        for event in old.diff(&new) {
+            writer.write(&event).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+            "<diff xml:space='preserve'><add sel='/a/c[1]/d[1]' type='@x'>1</add></diff>",
+        );
+    }
+}
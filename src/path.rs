@@ -0,0 +1,137 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+/// A simple path expression selecting elements, and optionally one of their attributes.
+///
+/// Steps are separated by `/` and match elements by their local name, `*` matches any name.
+/// A path starting with `/` is anchored at the root element, any other path matches the
+/// innermost open elements. A final `@name` step selects an attribute of the matched element.
+///
+/// ~~~
+/// use xml::ElementPath;
+///
+/// let path = ElementPath::new("/feed/entry");
+/// assert!(path.matches(&["feed", "entry"]));
+/// assert!(!path.matches(&["feed", "entry", "title"]));
+///
+/// let path = ElementPath::new("entry/title");
+/// assert!(path.matches(&["feed", "entry", "title"]));
+/// ~~~
+pub struct ElementPath {
+    absolute: bool,
+    steps: Vec<String>,
+    attribute: Option<String>,
+}
+
+impl ElementPath {
+    /// Parses a path expression. Empty steps are ignored.
+    pub fn new(path: &str) -> ElementPath {
+        let absolute = path.starts_with('/');
+        let mut steps: Vec<String> = path
+            .split('/')
+            .filter(|step| !step.is_empty())
+            .map(|step| step.to_owned())
+            .collect();
+        let attribute = match steps.last() {
+            Some(last) if last.starts_with('@') => steps.pop().map(|attr| attr[1..].to_owned()),
+            _ => None,
+        };
+        ElementPath {
+            absolute,
+            steps,
+            attribute,
+        }
+    }
+
+    /// Returns whether the path is anchored at the root element
+    pub fn is_absolute(&self) -> bool {
+        self.absolute
+    }
+
+    /// Returns the name of the selected attribute, if any
+    pub fn attribute(&self) -> Option<&str> {
+        self.attribute.as_deref()
+    }
+
+    /// Checks whether the element at the top of `stack` is selected by this path.
+    /// `stack` lists the local names of all open elements, starting at the root.
+    pub fn matches<S: AsRef<str>>(&self, stack: &[S]) -> bool {
+        if self.steps.is_empty() || stack.len() < self.steps.len() {
+            return false;
+        }
+        if self.absolute && stack.len() != self.steps.len() {
+            return false;
+        }
+        let tail = &stack[stack.len() - self.steps.len()..];
+        self.steps
+            .iter()
+            .zip(tail)
+            .all(|(step, name)| step == "*" || step == name.as_ref())
+    }
+}
+
+impl FromStr for ElementPath {
+    type Err = ();
+    #[inline]
+    fn from_str(path: &str) -> Result<ElementPath, ()> {
+        Ok(ElementPath::new(path))
+    }
+}
+
+impl<'a> From<&'a str> for ElementPath {
+    fn from(path: &'a str) -> ElementPath {
+        ElementPath::new(path)
+    }
+}
+
+impl fmt::Display for ElementPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.absolute {
+            write!(f, "/")?;
+        }
+        write!(f, "{}", self.steps.join("/"))?;
+        if let Some(ref attr) = self.attribute {
+            write!(f, "/@{}", attr)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ElementPath;
+
+    #[test]
+    fn test_absolute() {
+        let path = ElementPath::new("/a/b");
+        assert!(path.matches(&["a", "b"]));
+        assert!(!path.matches(&["x", "a", "b"]));
+        assert!(!path.matches(&["a"]));
+    }
+
+    #[test]
+    fn test_relative_and_wildcard() {
+        let path = ElementPath::new("*/c");
+        assert!(path.matches(&["a", "b", "c"]));
+        assert!(!path.matches(&["c"]));
+        assert_eq!(path.attribute(), None);
+    }
+
+    #[test]
+    fn test_attribute() {
+        let path = ElementPath::new("/user/@password");
+        assert!(path.matches(&["user"]));
+        assert_eq!(path.attribute(), Some("password"));
+        assert_eq!(path.to_string(), "/user/@password");
+    }
+}
@@ -0,0 +1,430 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Processing instructions with pseudo-attributes
+//!
+//! Some processing instructions found in the prolog of a document carry their data as
+//! pseudo-attributes, written like attributes of a start tag. This module parses them into
+//! typed structures, which are displayed as the content of the processing instruction for
+//! writing them back.
+//!
+//! ~~~
+//! use xml::pi::XmlModel;
+//! use xml::{Event, Parser};
+//!
+//! let doc = "<?xml-model href='book.rng' schematypens='http://relaxng.org/ns/structure/1.0'?>\
+//!            <book/>";
+//! let models: Vec<_> = Parser::new(doc.as_bytes())
+//!     .filter_map(|event| match event.unwrap() {
+//!         Event::PI(data) => XmlModel::from_pi(&data),
+//!         _ => None,
+//!     })
+//!     .collect();
+//! assert_eq!(models[0].href, "book.rng");
+//! assert_eq!(
+//!     models[0].to_string(),
+//!     "xml-model href=\"book.rng\" schematypens=\"http://relaxng.org/ns/structure/1.0\"",
+//! );
+//! ~~~
+
+use crate::{escape, unescape};
+use std::fmt;
+
+// Splits the content of a processing instruction into its target and data
+fn split_target(pi: &str) -> (&str, &str) {
+    match pi.find(char::is_whitespace) {
+        Some(i) => (&pi[..i], pi[i..].trim_start()),
+        None => (pi, ""),
+    }
+}
+
+/// Parses the pseudo-attributes in the data of a processing instruction, such as
+/// `href="a.xsl" type="text/xsl"`. Returns `None` if the data is not a sequence of
+/// pseudo-attributes.
+pub fn pseudo_attributes(data: &str) -> Option<Vec<(String, String)>> {
+    let mut attributes = Vec::new();
+    let mut rest = data.trim_start();
+    while !rest.is_empty() {
+        let (name, value) = rest.split_once('=')?;
+        let name = name.trim_end();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return None;
+        }
+        let value = value.trim_start();
+        let delim = value.chars().next().filter(|&c| c == '"' || c == '\'')?;
+        let (value, tail) = value[1..].split_once(delim)?;
+        attributes.push((name.to_owned(), unescape(value).ok()?));
+        // Pseudo-attributes are separated by whitespace
+        if !tail.is_empty() && !tail.starts_with(char::is_whitespace) {
+            return None;
+        }
+        rest = tail.trim_start();
+    }
+    Some(attributes)
+}
+
+// Writes the pseudo-attributes with a value. Values are escaped, which also keeps one
+// containing "?>" from ending the processing instruction.
+fn write_pseudo_attributes(
+    f: &mut fmt::Formatter,
+    attributes: &[(&str, Option<&str>)],
+) -> fmt::Result {
+    for &(name, value) in attributes {
+        if let Some(value) = value {
+            write!(f, " {}=\"{}\"", name, escape(value))?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The XML declaration at the start of a document, reported as `Event::XmlDecl`
+pub struct XmlDecl {
+    /// The XML version, such as "1.0"
+    pub version: String,
+    /// The declared character encoding
+    pub encoding: Option<String>,
+    /// The standalone document declaration
+    pub standalone: Option<bool>,
+}
+
+impl XmlDecl {
+    /// Returns a new `XmlDecl` declaring `version`
+    pub fn new<S: Into<String>>(version: S) -> XmlDecl {
+        XmlDecl {
+            version: version.into(),
+            encoding: None,
+            standalone: None,
+        }
+    }
+
+    /// Parses the content of an XML declaration, such as
+    /// `xml version="1.0" encoding="UTF-8"`. Returns `None` for other processing
+    /// instructions, or if the pseudo-attributes are not `version`, `encoding` and
+    /// `standalone` in that order, with `standalone` being "yes" or "no".
+    pub fn from_pi(pi: &str) -> Option<XmlDecl> {
+        let (target, data) = split_target(pi);
+        if target != "xml" {
+            return None;
+        }
+        let mut attributes = pseudo_attributes(data)?.into_iter().peekable();
+        let (name, version) = attributes.next()?;
+        if name != "version" {
+            return None;
+        }
+        let mut decl = XmlDecl::new(version);
+        decl.encoding = attributes
+            .next_if(|(name, _)| name == "encoding")
+            .map(|(_, value)| value);
+        if let Some((_, value)) = attributes.next_if(|(name, _)| name == "standalone") {
+            decl.standalone = match &value[..] {
+                "yes" => Some(true),
+                "no" => Some(false),
+                _ => return None,
+            };
+        }
+        match attributes.next() {
+            Some(_) => None,
+            None => Some(decl),
+        }
+    }
+}
+
+impl fmt::Display for XmlDecl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("xml")?;
+        write_pseudo_attributes(
+            f,
+            &[
+                ("version", Some(&self.version)),
+                ("encoding", self.encoding.as_deref()),
+                (
+                    "standalone",
+                    self.standalone.map(|yes| if yes { "yes" } else { "no" }),
+                ),
+            ],
+        )
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+/// An `xml-model` processing instruction, associating a document with a schema as defined by
+/// ISO/IEC 19757-11
+pub struct XmlModel {
+    /// The location of the schema
+    pub href: String,
+    /// The media type of the schema, the `type` pseudo-attribute
+    pub schema_type: Option<String>,
+    /// The namespace identifying the schema language
+    pub schematypens: Option<String>,
+    /// The character encoding of the schema
+    pub charset: Option<String>,
+    /// The title of the association
+    pub title: Option<String>,
+    /// The name of a group of associations to be used together
+    pub group: Option<String>,
+    /// The Schematron phase to validate
+    pub phase: Option<String>,
+}
+
+impl XmlModel {
+    /// Returns a new `XmlModel` referring to the schema at `href`
+    pub fn new<S: Into<String>>(href: S) -> XmlModel {
+        XmlModel {
+            href: href.into(),
+            ..XmlModel::default()
+        }
+    }
+
+    /// Parses the content of an `xml-model` processing instruction, as found in
+    /// `Event::PI`. Returns `None` for other processing instructions, or if the `href`
+    /// pseudo-attribute is missing. Unknown pseudo-attributes are ignored.
+    pub fn from_pi(pi: &str) -> Option<XmlModel> {
+        let (target, data) = split_target(pi);
+        if target != "xml-model" {
+            return None;
+        }
+        let mut model = XmlModel::default();
+        let mut href = None;
+        for (name, value) in pseudo_attributes(data)? {
+            match &name[..] {
+                "href" => href = Some(value),
+                "type" => model.schema_type = Some(value),
+                "schematypens" => model.schematypens = Some(value),
+                "charset" => model.charset = Some(value),
+                "title" => model.title = Some(value),
+                "group" => model.group = Some(value),
+                "phase" => model.phase = Some(value),
+                _ => (),
+            }
+        }
+        model.href = href?;
+        Some(model)
+    }
+}
+
+impl fmt::Display for XmlModel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("xml-model")?;
+        write_pseudo_attributes(
+            f,
+            &[
+                ("href", Some(&self.href)),
+                ("type", self.schema_type.as_deref()),
+                ("schematypens", self.schematypens.as_deref()),
+                ("charset", self.charset.as_deref()),
+                ("title", self.title.as_deref()),
+                ("group", self.group.as_deref()),
+                ("phase", self.phase.as_deref()),
+            ],
+        )
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+/// An `xml-stylesheet` processing instruction, associating a document with a style sheet
+pub struct XmlStylesheet {
+    /// The location of the style sheet
+    pub href: String,
+    /// The media type of the style sheet, such as `text/xsl` or `text/css`
+    pub stylesheet_type: String,
+    /// The title of the style sheet
+    pub title: Option<String>,
+    /// The media the style sheet applies to
+    pub media: Option<String>,
+    /// The character encoding of the style sheet
+    pub charset: Option<String>,
+    /// Whether this is an alternative style sheet, the `alternate` pseudo-attribute
+    pub alternate: bool,
+}
+
+impl XmlStylesheet {
+    /// Returns a new `XmlStylesheet` referring to the style sheet at `href` of media type
+    /// `stylesheet_type`
+    pub fn new<H, T>(href: H, stylesheet_type: T) -> XmlStylesheet
+    where
+        H: Into<String>,
+        T: Into<String>,
+    {
+        XmlStylesheet {
+            href: href.into(),
+            stylesheet_type: stylesheet_type.into(),
+            ..XmlStylesheet::default()
+        }
+    }
+
+    /// Parses the content of an `xml-stylesheet` processing instruction, as found in
+    /// `Event::PI`. Returns `None` for other processing instructions, if the `href` or
+    /// `type` pseudo-attribute is missing, or if `alternate` is not "yes" or "no".
+    ///
+    /// ~~~
+    /// use xml::pi::XmlStylesheet;
+    ///
+    /// let pi = "xml-stylesheet href='print.css' type='text/css' media='print' alternate='yes'";
+    /// let stylesheet = XmlStylesheet::from_pi(pi).unwrap();
+    /// assert_eq!(stylesheet.media.as_deref(), Some("print"));
+    /// assert!(stylesheet.alternate);
+    /// ~~~
+    pub fn from_pi(pi: &str) -> Option<XmlStylesheet> {
+        let (target, data) = split_target(pi);
+        if target != "xml-stylesheet" {
+            return None;
+        }
+        let mut stylesheet = XmlStylesheet::default();
+        let (mut href, mut stylesheet_type) = (None, None);
+        for (name, value) in pseudo_attributes(data)? {
+            match &name[..] {
+                "href" => href = Some(value),
+                "type" => stylesheet_type = Some(value),
+                "title" => stylesheet.title = Some(value),
+                "media" => stylesheet.media = Some(value),
+                "charset" => stylesheet.charset = Some(value),
+                "alternate" => {
+                    stylesheet.alternate = match &value[..] {
+                        "yes" => true,
+                        "no" => false,
+                        _ => return None,
+                    }
+                }
+                _ => (),
+            }
+        }
+        stylesheet.href = href?;
+        stylesheet.stylesheet_type = stylesheet_type?;
+        Some(stylesheet)
+    }
+}
+
+impl fmt::Display for XmlStylesheet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("xml-stylesheet")?;
+        write_pseudo_attributes(
+            f,
+            &[
+                ("href", Some(&self.href)),
+                ("type", Some(&self.stylesheet_type)),
+                ("title", self.title.as_deref()),
+                ("media", self.media.as_deref()),
+                ("charset", self.charset.as_deref()),
+                ("alternate", Some("yes").filter(|_| self.alternate)),
+            ],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pseudo_attributes, XmlDecl, XmlModel, XmlStylesheet};
+    use crate::{Element, Event, EventWriter};
+
+    #[test]
+    fn test_pseudo_attributes() {
+        assert_eq!(
+            pseudo_attributes(" a = 'x &amp; y'\tb=\"'\" "),
+            Some(vec![
+                ("a".to_owned(), "x & y".to_owned()),
+                ("b".to_owned(), "'".to_owned()),
+            ])
+        );
+        assert_eq!(pseudo_attributes(""), Some(vec![]));
+        for data in ["a", "a=x", "a='x", "a='x'b='y'", "a b='x'", "a='&x;'"] {
+            assert_eq!(pseudo_attributes(data), None);
+        }
+    }
+
+    #[test]
+    fn test_xml_decl() {
+        let decl =
+            XmlDecl::from_pi("xml version='1.1' encoding=\"utf-8\" standalone='no'").unwrap();
+        assert_eq!(
+            decl,
+            XmlDecl {
+                encoding: Some("utf-8".to_owned()),
+                standalone: Some(false),
+                ..XmlDecl::new("1.1")
+            }
+        );
+        assert_eq!(
+            decl.to_string(),
+            "xml version=\"1.1\" encoding=\"utf-8\" standalone=\"no\""
+        );
+        assert_eq!(
+            XmlDecl::from_pi("xml version='1.0'"),
+            Some(XmlDecl::new("1.0"))
+        );
+        for pi in [
+            "xml",
+            "xml encoding='utf-8'",
+            "xml version='1.0' standalone='yes' encoding='utf-8'",
+            "xml version='1.0' standalone='true'",
+            "xml version='1.0' other=''",
+            "xml-stylesheet version='1.0'",
+        ] {
+            assert_eq!(XmlDecl::from_pi(pi), None, "{}", pi);
+        }
+    }
+
+    #[test]
+    fn test_xml_model() {
+        let pi = "xml-model href='s.sch' type=\"application/xml\" phase='#ALL' other='1'";
+        let model = XmlModel::from_pi(pi).unwrap();
+        assert_eq!(
+            model,
+            XmlModel {
+                schema_type: Some("application/xml".to_owned()),
+                phase: Some("#ALL".to_owned()),
+                ..XmlModel::new("s.sch")
+            }
+        );
+        assert_eq!(XmlModel::from_pi(&model.to_string()), Some(model));
+        let model = XmlModel {
+            title: Some("?>".to_owned()),
+            ..XmlModel::new("a.rng?>")
+        };
+        let pi = model.to_string();
+        assert_eq!(pi, "xml-model href=\"a.rng?&gt;\" title=\"?&gt;\"");
+        assert_eq!(XmlModel::from_pi(&pi), Some(model));
+        assert_eq!(XmlModel::from_pi("xml-model type='a'"), None);
+        assert_eq!(XmlModel::from_pi("xml-models href='a'"), None);
+    }
+
+    #[test]
+    fn test_xml_stylesheet() {
+        let pi = "xml-stylesheet type='text/xsl' href='a.xsl?x=1&amp;y=2' title=\"A\"";
+        let stylesheet = XmlStylesheet::from_pi(pi).unwrap();
+        assert_eq!(
+            stylesheet,
+            XmlStylesheet {
+                title: Some("A".to_owned()),
+                ..XmlStylesheet::new("a.xsl?x=1&y=2", "text/xsl")
+            }
+        );
+        assert_eq!(
+            XmlStylesheet::from_pi(&stylesheet.to_string()),
+            Some(stylesheet.clone())
+        );
+        assert_eq!(XmlStylesheet::from_pi("xml-stylesheet href='a.css'"), None);
+        assert_eq!(
+            XmlStylesheet::from_pi("xml-stylesheet href='a.css' type='text/css' alternate='1'"),
+            None
+        );
+
+        let mut writer = EventWriter::new(Vec::new());
+        writer.write(&Event::PI(stylesheet.to_string())).unwrap();
+        let mut doc = Element::new("doc".to_owned(), None, vec![]);
+        doc.pi(stylesheet.to_string());
+        let written = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            written,
+            "<?xml-stylesheet href=\"a.xsl?x=1&amp;y=2\" type=\"text/xsl\" title=\"A\"?>"
+        );
+        assert_eq!(doc.to_string(), format!("<doc>{}</doc>", written));
+    }
+}
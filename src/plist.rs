@@ -0,0 +1,299 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reading and writing Apple XML property lists
+//!
+//! Property lists are mapped to `PlistValue`s. Dates are kept as the ISO 8601 text found in
+//! the document, `<data>` is decoded from Base64. Binary property lists are not supported.
+//!
+//! ~~~
+//! use xml::plist::{self, PlistValue};
+//!
+//! let doc = "<plist version='1.0'><dict>\
+//!            <key>CFBundleName</key><string>Example</string>\
+//!            <key>LSRequiresIPhoneOS</key><true/>\
+//!            </dict></plist>";
+//! let value = plist::from_reader(doc.as_bytes()).unwrap();
+//! assert_eq!(value.get("CFBundleName").and_then(PlistValue::as_str), Some("Example"));
+//! assert_eq!(value.get("LSRequiresIPhoneOS"), Some(&PlistValue::Boolean(true)));
+//! ~~~
+
+use crate::codec::{decode, encode_base64, ContentEncoding};
+use crate::{BuilderError, Element, ElementBuilder, Parser, WriterConfig};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+/// The document type declaration written by Apple's tools
+pub const DOCTYPE: &str = "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \
+                           \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">";
+
+#[derive(Clone, PartialEq, Debug)]
+/// A value in a property list
+pub enum PlistValue {
+    /// `<array>`
+    Array(Vec<PlistValue>),
+    /// `<dict>`
+    Dictionary(BTreeMap<String, PlistValue>),
+    /// `<string>`
+    String(String),
+    /// `<integer>`
+    Integer(i64),
+    /// `<real>`
+    Real(f64),
+    /// `<true/>` or `<false/>`
+    Boolean(bool),
+    /// `<date>`, in ISO 8601 format such as `2024-01-01T00:00:00Z`
+    Date(String),
+    /// `<data>`
+    Data(Vec<u8>),
+}
+
+#[derive(Debug)]
+/// Errors encountered while reading a property list
+pub enum PlistError {
+    /// The document is not well-formed XML
+    Builder(BuilderError),
+    /// An element not allowed at its position was found
+    UnexpectedElement(String),
+    /// The content of an element could not be interpreted
+    InvalidValue(String),
+}
+
+impl Error for PlistError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            PlistError::Builder(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PlistError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PlistError::Builder(ref err) => err.fmt(f),
+            PlistError::UnexpectedElement(ref name) => write!(f, "Unexpected element <{}>", name),
+            PlistError::InvalidValue(ref name) => write!(f, "Invalid content in <{}>", name),
+        }
+    }
+}
+
+impl From<BuilderError> for PlistError {
+    fn from(err: BuilderError) -> PlistError {
+        PlistError::Builder(err)
+    }
+}
+
+/// Reads a property list from `reader`
+pub fn from_reader<R: Read>(reader: R) -> Result<PlistValue, PlistError> {
+    let mut builder = ElementBuilder::new();
+    let root = Parser::new(reader)
+        .find_map(|event| builder.handle_event(event))
+        .unwrap_or(Err(BuilderError::NoElement))?;
+    PlistValue::from_element(&root)
+}
+
+/// Writes `value` as a property list document to `writer`
+pub fn to_writer<W: Write>(value: &PlistValue, mut writer: W) -> io::Result<()> {
+    let config = WriterConfig {
+        xml_declaration: true,
+        doctype: Some(DOCTYPE.to_owned()),
+        indent: Some("\t".to_owned()),
+        // Strings and keys may consist of whitespace only, which indenting would drop
+        preserve_whitespace: vec!["string".to_owned(), "key".to_owned()],
+        ..WriterConfig::default()
+    };
+    let mut plist = Element::new(
+        "plist".to_owned(),
+        None,
+        vec![("version".to_owned(), None, "1.0".to_owned())],
+    );
+    plist.tag(value.to_element());
+    writeln!(writer, "{}", plist.to_string_with(&config))
+}
+
+impl PlistValue {
+    /// Interprets `elem` as a value. A `<plist>` element is unwrapped.
+    pub fn from_element(elem: &Element) -> Result<PlistValue, PlistError> {
+        let invalid = || PlistError::InvalidValue(elem.name.name.to_string());
+        let text = elem.content_str();
+        Ok(match &elem.name.name[..] {
+            "plist" => {
+                let mut children = elem.child_elements();
+                match (children.next(), children.next()) {
+                    (Some(value), None) => PlistValue::from_element(value)?,
+                    (_, Some(extra)) => return Err(unexpected(extra)),
+                    (None, None) => return Err(invalid()),
+                }
+            }
+            "array" => PlistValue::Array(
+                elem.child_elements()
+                    .map(PlistValue::from_element)
+                    .collect::<Result<_, _>>()?,
+            ),
+            "dict" => {
+                let mut dict = BTreeMap::new();
+                let mut children = elem.child_elements();
+                while let Some(key) = children.next() {
+                    if key.name.name != "key" {
+                        return Err(unexpected(key));
+                    }
+                    let value = children.next().ok_or_else(invalid)?;
+                    dict.insert(key.content_str(), PlistValue::from_element(value)?);
+                }
+                PlistValue::Dictionary(dict)
+            }
+            "string" => PlistValue::String(text),
+            "integer" => {
+                let text = text.trim();
+                let value = match text.strip_prefix("0x") {
+                    Some(hex) => i64::from_str_radix(hex, 16),
+                    None => text.parse(),
+                };
+                PlistValue::Integer(value.map_err(|_| invalid())?)
+            }
+            "real" => PlistValue::Real(text.trim().parse().map_err(|_| invalid())?),
+            "true" => PlistValue::Boolean(true),
+            "false" => PlistValue::Boolean(false),
+            "date" => PlistValue::Date(text.trim().to_owned()),
+            "data" => {
+                PlistValue::Data(decode(&text, ContentEncoding::Base64).map_err(|_| invalid())?)
+            }
+            _ => return Err(unexpected(elem)),
+        })
+    }
+
+    /// Returns the element representing this value.
+    pub fn to_element(&self) -> Element {
+        let leaf = |name: &str, text: String| {
+            let mut elem = Element::new(name.to_owned(), None, vec![]);
+            elem.text(text);
+            elem
+        };
+        match *self {
+            PlistValue::Array(ref values) => {
+                let mut elem = Element::new("array".to_owned(), None, vec![]);
+                for value in values {
+                    elem.tag(value.to_element());
+                }
+                elem
+            }
+            PlistValue::Dictionary(ref dict) => {
+                let mut elem = Element::new("dict".to_owned(), None, vec![]);
+                for (key, value) in dict {
+                    elem.tag(leaf("key", key.clone()));
+                    elem.tag(value.to_element());
+                }
+                elem
+            }
+            PlistValue::String(ref text) => leaf("string", text.clone()),
+            PlistValue::Integer(value) => leaf("integer", value.to_string()),
+            PlistValue::Real(value) => leaf("real", value.to_string()),
+            PlistValue::Boolean(value) => Element::new(value.to_string(), None, vec![]),
+            PlistValue::Date(ref date) => leaf("date", date.clone()),
+            PlistValue::Data(ref data) => leaf("data", encode_base64(data)),
+        }
+    }
+
+    /// Looks up `key` if this is a dictionary.
+    pub fn get(&self, key: &str) -> Option<&PlistValue> {
+        match *self {
+            PlistValue::Dictionary(ref dict) => dict.get(key),
+            _ => None,
+        }
+    }
+
+    /// Returns the contained string if this is a string.
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            PlistValue::String(ref text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for PlistValue {
+    type Err = PlistError;
+    #[inline]
+    fn from_str(data: &str) -> Result<PlistValue, PlistError> {
+        from_reader(data.as_bytes())
+    }
+}
+
+impl fmt::Display for PlistValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.to_element().fmt(f)
+    }
+}
+
+fn unexpected(elem: &Element) -> PlistError {
+    PlistError::UnexpectedElement(elem.name.name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_reader, to_writer, PlistValue};
+
+    #[test]
+    fn test_roundtrip() {
+        let doc = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                   <plist version=\"1.0\">\n<dict>\n\
+                   \t<key>Count</key>\n\t<integer>-3</integer>\n\
+                   \t<key>Items</key>\n\t<array>\n\t\t<real>1.5</real>\n\t\t<false/>\n\t</array>\n\
+                   \t<key>Modified</key>\n\t<date>2024-01-01T00:00:00Z</date>\n\
+                   \t<key>Payload</key>\n\t<data>\n\tSGVsbG8=\n\t</data>\n\
+                   </dict>\n</plist>\n";
+        let value = from_reader(doc.as_bytes()).unwrap();
+        assert_eq!(value.get("Count"), Some(&PlistValue::Integer(-3)));
+        assert_eq!(
+            value.get("Items"),
+            Some(&PlistValue::Array(vec![
+                PlistValue::Real(1.5),
+                PlistValue::Boolean(false)
+            ])),
+        );
+        assert_eq!(
+            value.get("Payload"),
+            Some(&PlistValue::Data(b"Hello".to_vec()))
+        );
+
+        let mut out = Vec::new();
+        to_writer(&value, &mut out).unwrap();
+        assert_eq!(from_reader(&out[..]).unwrap(), value);
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .contains("<dict>\n\t\t<key>Count</key>\n\t\t<integer>-3</integer>"));
+    }
+
+    #[test]
+    fn test_whitespace_strings() {
+        let value = PlistValue::Dictionary(
+            [(" ", "  "), ("k", "\n"), ("t", "\ta ")]
+                .iter()
+                .map(|&(key, value)| (key.to_owned(), PlistValue::String(value.to_owned())))
+                .collect(),
+        );
+        let mut out = Vec::new();
+        to_writer(&value, &mut out).unwrap();
+        assert_eq!(from_reader(&out[..]).unwrap(), value);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!("<plist><dict><string/></dict></plist>"
+            .parse::<PlistValue>()
+            .is_err());
+        assert!("<plist><integer>x</integer></plist>"
+            .parse::<PlistValue>()
+            .is_err());
+    }
+}
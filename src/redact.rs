@@ -0,0 +1,188 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::path::ElementPath;
+use crate::writer::{EventWriter, StreamError};
+use crate::{ns, Event, Name, Parser, QName};
+use std::io::{Read, Write};
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+/// What to do with content selected by a `Redactor` rule
+pub enum Redaction {
+    /// Remove the element's content, or the attribute
+    Drop,
+    /// Replace the element's content, or the attribute's value, with the given text
+    Replace(String),
+}
+
+/// A streaming transform masking the content of selected elements and attributes
+///
+/// Everything not selected by one of the rules is copied to the output unchanged.
+///
+/// ~~~
+/// use xml::{Redaction, Redactor};
+///
+/// let input = "<login user='bob' token='abc'><password>secret</password></login>";
+/// let mut redactor = Redactor::new();
+/// redactor
+///     .add_rule("password", Redaction::Replace("***".to_owned()))
+///     .add_rule("/login/@token", Redaction::Drop);
+///
+/// let out = redactor.redact(input.as_bytes(), Vec::new()).unwrap();
+/// assert_eq!(
+///     String::from_utf8(out).unwrap(),
+///     "<login user='bob'><password>***</password></login>",
+/// );
+/// ~~~
+#[derive(Clone, Debug, Default)]
+pub struct Redactor {
+    rules: Vec<(ElementPath, Redaction)>,
+}
+
+impl Redactor {
+    /// Returns a new `Redactor` without any rules
+    pub fn new() -> Redactor {
+        Redactor { rules: Vec::new() }
+    }
+
+    /// Adds a rule applying `redaction` to the elements or attributes selected by `path`.
+    /// Returns a mutable reference to self.
+    pub fn add_rule<P: Into<ElementPath>>(
+        &mut self,
+        path: P,
+        redaction: Redaction,
+    ) -> &mut Redactor {
+        self.rules.push((path.into(), redaction));
+        self
+    }
+
+    /// Copies the document read from `reader` to `writer`, applying all rules.
+    /// Returns the writer once the input is exhausted.
+    pub fn redact<R, W>(&self, reader: R, writer: W) -> Result<W, StreamError>
+    where
+        R: Read,
+        W: Write,
+    {
+        let mut writer = EventWriter::new(writer);
+        let mut names: Vec<Name> = Vec::new();
+        let mut redacted_depth: Option<usize> = None;
+
+        for event in Parser::new(reader) {
+            let mut event = event?;
+            match event {
+                Event::ElementStart(ref mut tag) => {
+                    names.push(tag.name.name.clone());
+                    if redacted_depth.is_some() {
+                        continue;
+                    }
+                    let mut replacement = None;
+                    for (path, redaction) in &self.rules {
+                        if !path.matches(&names) {
+                            continue;
+                        }
+                        // Namespace declarations are never selected by attribute rules
+                        let selects = |QName { name, ns }: &QName, attr: &str| match ns {
+                            Some(ns) => name == attr && ns != ns::XMLNS,
+                            None => name == attr && name != "xmlns",
+                        };
+                        match (path.attribute(), redaction) {
+                            (Some(attr), Redaction::Drop) => {
+                                tag.attributes.retain(|key, _| !selects(key, attr))
+                            }
+                            (Some(attr), Redaction::Replace(text)) => {
+                                for (key, value) in tag.attributes.iter_mut() {
+                                    if selects(key, attr) {
+                                        value.clone_from(text);
+                                    }
+                                }
+                            }
+                            (None, redaction) => replacement = Some(redaction),
+                        }
+                    }
+                    writer.write(&event)?;
+                    if let Some(redaction) = replacement {
+                        redacted_depth = Some(names.len());
+                        if let Redaction::Replace(ref text) = *redaction {
+                            writer.write(&Event::Characters(text.clone()))?;
+                        }
+                    }
+                }
+                Event::ElementEnd(_) => {
+                    match redacted_depth {
+                        Some(depth) if depth == names.len() => redacted_depth = None,
+                        Some(_) => {
+                            names.pop();
+                            continue;
+                        }
+                        None => (),
+                    }
+                    names.pop();
+                    writer.write(&event)?;
+                }
+                _ if redacted_depth.is_some() => (),
+                _ => writer.write(&event)?,
+            }
+        }
+        Ok(writer.into_inner()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Redaction, Redactor};
+    use crate::Element;
+
+    fn redact(redactor: &Redactor, input: &str) -> String {
+        let out = redactor.redact(input.as_bytes(), Vec::new()).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_drop_content() {
+        let mut redactor = Redactor::new();
+        redactor.add_rule("card", Redaction::Drop);
+        assert_eq!(
+            redact(&redactor, "<a><card>1234<n>5</n></card><b>keep</b></a>"),
+            "<a><card/><b>keep</b></a>",
+        );
+    }
+
+    #[test]
+    fn test_replace_attribute() {
+        let mut redactor = Redactor::new();
+        redactor.add_rule("/a/b/@pin", Redaction::Replace("x".to_owned()));
+        assert_eq!(
+            redact(&redactor, "<a pin='1'><b pin='2'/></a>"),
+            "<a pin='1'><b pin='x'/></a>",
+        );
+    }
+
+    #[test]
+    fn test_keep_namespace_declarations() {
+        let input = "<a xmlns:p='urn:p' xmlns='urn:d' p:p='1' p='2'/>";
+        let redacted = |redactor: &Redactor| redact(redactor, input).parse::<Element>().unwrap();
+        let mut redactor = Redactor::new();
+        redactor.add_rule("a/@p", Redaction::Drop);
+        assert_eq!(
+            redacted(&redactor),
+            "<a xmlns:p='urn:p' xmlns='urn:d'/>".parse().unwrap()
+        );
+
+        let mut redactor = Redactor::new();
+        redactor
+            .add_rule("a/@p", Redaction::Replace("x".to_owned()))
+            .add_rule("a/@xmlns", Redaction::Drop);
+        assert_eq!(
+            redacted(&redactor),
+            "<a xmlns:p='urn:p' xmlns='urn:d' p:p='x' p='x'/>"
+                .parse()
+                .unwrap()
+        );
+    }
+}
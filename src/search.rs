@@ -0,0 +1,132 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Searching text content with regular expressions while parsing
+//!
+//! `search()` scans the character data and CDATA sections of a document for matches of a
+//! `Regex`, without building a tree or collecting the text of elements first. Every text
+//! node is searched on its own, so matches do not span markup.
+//!
+//! ~~~
+//! use regex::Regex;
+//! use xml::search;
+//!
+//! let log = "<log><entry id='1'>disk full</entry><entry id='2'>ok</entry>\
+//!            <entry id='3'>disk <b>failing</b></entry></log>";
+//! let regex = Regex::new(r"disk \w+").unwrap();
+//! let matches: Vec<_> = search::search(log.as_bytes(), regex)
+//!     .collect::<Result<_, _>>()
+//!     .unwrap();
+//! assert_eq!(matches.len(), 1);
+//! assert_eq!(matches[0].text, "disk full");
+//! assert_eq!(matches[0].offset, 19);
+//! ~~~
+
+use crate::{Event, Parser, ParserError, QName};
+use regex::Regex;
+use std::collections::VecDeque;
+use std::io::Read;
+use std::ops::Range;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+/// A match of a regular expression in the text of a document
+pub struct Match {
+    /// The names of the open elements, starting with the root
+    pub path: Vec<QName>,
+    /// The byte offset in the input of the text or CDATA section containing the match
+    pub offset: u64,
+    /// The range of the match in the text, after character and entity references are
+    /// expanded
+    pub range: Range<usize>,
+    /// The matched text
+    pub text: String,
+}
+
+/// An iterator over the matches of a regular expression in a document, see `search()`
+pub struct Search<R: Read> {
+    parser: Parser<R>,
+    regex: Regex,
+    pending: VecDeque<Match>,
+}
+
+/// Returns an iterator over the matches of `regex` in the text and CDATA sections read from
+/// `reader`. Iteration stops after the first parser error.
+pub fn search<R: Read>(reader: R, regex: Regex) -> Search<R> {
+    Search {
+        parser: Parser::new(reader),
+        regex,
+        pending: VecDeque::new(),
+    }
+}
+
+impl<R: Read> Iterator for Search<R> {
+    type Item = Result<Match, ParserError>;
+
+    fn next(&mut self) -> Option<Result<Match, ParserError>> {
+        while self.pending.is_empty() {
+            let event = match self.parser.next()? {
+                Ok(event) => event,
+                Err(err) => return Some(Err(err)),
+            };
+            let text = match event {
+                Event::Characters(text) | Event::CDATA(text) => text,
+                _ => continue,
+            };
+            let offset = self.parser.span().start;
+            let path = self.parser.current_path();
+            self.pending
+                .extend(self.regex.find_iter(&text).map(|found| Match {
+                    path: path.to_vec(),
+                    offset,
+                    range: found.range(),
+                    text: found.as_str().to_owned(),
+                }));
+        }
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::search;
+    use crate::QName;
+    use regex::Regex;
+
+    #[test]
+    fn test_search() {
+        let doc = "<a xmlns='urn:a'>x1 y22<b><![CDATA[<z333>]]></b>&#x41;44<c/>\
+                   <d xmlns=''>5 <!-- 66 --></d></a>";
+        let regex = Regex::new(r"\d+").unwrap();
+        let matches: Vec<_> = search(doc.as_bytes(), regex)
+            .map(Result::unwrap)
+            .map(|m| {
+                let path: Vec<_> = m.path.iter().map(QName::to_string).collect();
+                (path.join("/"), m.offset, m.range, m.text)
+            })
+            .collect();
+        assert_eq!(
+            matches,
+            [
+                ("{urn:a}a".to_owned(), 17, 1..2, "1".to_owned()),
+                ("{urn:a}a".to_owned(), 17, 4..6, "22".to_owned()),
+                ("{urn:a}a/{urn:a}b".to_owned(), 26, 2..5, "333".to_owned()),
+                ("{urn:a}a".to_owned(), 48, 1..3, "44".to_owned()),
+                ("{urn:a}a/d".to_owned(), 72, 0..1, "5".to_owned()),
+            ]
+        );
+        assert_eq!(&doc[26..35], "<![CDATA[");
+        assert_eq!(&doc[48..54], "&#x41;");
+        assert_eq!(&doc[72..73], "5");
+
+        let mut results = search("<a>1<b>&x;</b></a>".as_bytes(), Regex::new("1").unwrap());
+        assert!(results.next().unwrap().is_ok());
+        assert!(results.next().unwrap().is_err());
+        assert!(results.next().is_none());
+    }
+}
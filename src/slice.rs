@@ -0,0 +1,644 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::parser::ParserErrorKind;
+use crate::{entities, names, ns, unescape};
+use crate::{AttrMap, Doctype, EndTag, Event, ParserError, QName, StartTag, Token, Tokenizer};
+use crate::{TokenError, XmlDecl};
+use std::borrow::Cow;
+use std::mem;
+use std::str;
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+/// An attribute of a `SliceStartTag`
+pub struct SliceAttribute<'a> {
+    /// The attribute's local name
+    pub name: &'a str,
+    /// The attribute's namespace
+    pub ns: Option<Cow<'a, str>>,
+    /// The attribute's value, with references expanded
+    pub value: Cow<'a, str>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+/// A start tag borrowing from the input of a `SliceParser`
+pub struct SliceStartTag<'a> {
+    /// The tag's local name
+    pub name: &'a str,
+    /// The tag's namespace
+    pub ns: Option<Cow<'a, str>>,
+    /// The tag's prefix
+    pub prefix: Option<&'a str>,
+    /// The tag's attributes, in document order
+    pub attributes: Vec<SliceAttribute<'a>>,
+}
+
+impl SliceStartTag<'_> {
+    /// Gets an attribute with the specified name and namespace. When an attribute with the
+    /// specified name does not exist `None` is returned.
+    pub fn get_attribute(&self, name: &str, ns: Option<&str>) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|attr| attr.name == name && attr.ns.as_deref() == ns)
+            .map(|attr| &attr.value[..])
+    }
+
+    /// Converts the tag into a `StartTag`
+    pub fn into_owned(self) -> StartTag {
+        let mut attributes = AttrMap::new();
+        for attr in self.attributes {
+            attributes.insert(
+                QName::new(attr.name.to_owned(), attr.ns.map(Cow::into_owned)),
+                attr.value.into_owned(),
+            );
+        }
+        StartTag {
+            name: QName::new(self.name.to_owned(), self.ns.map(Cow::into_owned)),
+            prefix: self.prefix.map(str::to_owned),
+            attributes,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+/// An end tag borrowing from the input of a `SliceParser`
+pub struct SliceEndTag<'a> {
+    /// The tag's local name
+    pub name: &'a str,
+    /// The tag's namespace
+    pub ns: Option<Cow<'a, str>>,
+    /// The tag's prefix
+    pub prefix: Option<&'a str>,
+}
+
+impl SliceEndTag<'_> {
+    /// Converts the tag into an `EndTag`
+    pub fn into_owned(self) -> EndTag {
+        EndTag {
+            name: QName::new(self.name.to_owned(), self.ns.map(Cow::into_owned)),
+            prefix: self.prefix.map(str::to_owned),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+/// Events returned by the `SliceParser`, borrowing from its input
+///
+/// Names always borrow from the input. Text, attribute values and namespaces only own their
+/// content when references had to be expanded or line ends normalized.
+pub enum SliceEvent<'a> {
+    /// Event indicating processing information was found
+    PI(Cow<'a, str>),
+    /// Event indicating a start tag was found
+    ElementStart(SliceStartTag<'a>),
+    /// Event indicating a end tag was found
+    ElementEnd(SliceEndTag<'a>),
+    /// Event indicating character data was found
+    Characters(Cow<'a, str>),
+    /// Event indicating CDATA was found
+    CDATA(Cow<'a, str>),
+    /// Event indicating a comment was found
+    Comment(Cow<'a, str>),
+    /// Event indicating the XML declaration was found at the start of the document
+    XmlDecl(XmlDecl),
+    /// Event indicating a document type declaration was found
+    Doctype(Doctype),
+}
+
+impl SliceEvent<'_> {
+    /// Converts the event into the `Event` returned by `Parser` for the same input
+    pub fn into_owned(self) -> Event {
+        match self {
+            SliceEvent::PI(text) => Event::PI(text.into_owned()),
+            SliceEvent::ElementStart(tag) => Event::ElementStart(tag.into_owned()),
+            SliceEvent::ElementEnd(tag) => Event::ElementEnd(tag.into_owned()),
+            SliceEvent::Characters(text) => Event::Characters(text.into_owned()),
+            SliceEvent::CDATA(text) => Event::CDATA(text.into_owned()),
+            SliceEvent::Comment(text) => Event::Comment(text.into_owned()),
+            SliceEvent::XmlDecl(decl) => Event::XmlDecl(decl),
+            SliceEvent::Doctype(doctype) => Event::Doctype(doctype),
+        }
+    }
+}
+
+// Normalizes line ends to '\n', see XML 1.0 section 2.11
+fn normalize_line_ends(text: &str) -> Cow<'_, str> {
+    match text.contains('\r') {
+        true => Cow::Owned(text.replace("\r\n", "\n").replace('\r', "\n")),
+        false => Cow::Borrowed(text),
+    }
+}
+
+// A start tag being read: its name and attributes with the offsets at which they start
+struct PendingTag<'a> {
+    qname: &'a str,
+    offset: usize,
+    attributes: Vec<(&'a str, &'a str, usize)>,
+}
+
+// An element opened by a start tag, with the namespaces it declares
+struct OpenElement<'a> {
+    qname: &'a str,
+    declared: Vec<(&'a str, Cow<'a, str>)>,
+}
+
+/// A parser for a complete document held in memory, returning `SliceEvent`s that borrow
+/// from the input instead of allocating a `String` for every name, value and text
+///
+/// It checks well-formedness like a `Parser` with the `strict` option, except that only
+/// predefined entities and character references are expanded: references to entities
+/// declared in a DTD fail with `InvalidEntity`. Events can be converted into the `Event`s
+/// returned by `Parser` with `into_owned()`. Iteration stops after the first error.
+///
+/// ~~~
+/// use std::borrow::Cow;
+/// use xml::{SliceEvent, SliceParser};
+///
+/// let mut p = SliceParser::new("<msg lang='en'>Hi &amp; bye</msg>");
+/// match p.next() {
+///     Some(Ok(SliceEvent::ElementStart(tag))) => {
+///         assert_eq!(tag.name, "msg");
+///         assert!(matches!(tag.attributes[0].value, Cow::Borrowed("en")));
+///     }
+///     _ => panic!(),
+/// }
+/// match p.next() {
+///     Some(Ok(SliceEvent::Characters(text))) => assert_eq!(text, "Hi & bye"),
+///     _ => panic!(),
+/// }
+/// ~~~
+pub struct SliceParser<'a> {
+    input: &'a str,
+    // The length of the byte order mark preceding `input`
+    bom: usize,
+    // The offset of the first byte that is not valid UTF-8
+    invalid_utf8: Option<usize>,
+    tokens: Tokenizer<'a>,
+    open: Vec<OpenElement<'a>>,
+    tag: Option<PendingTag<'a>>,
+    pending_end: Option<SliceEndTag<'a>>,
+    root_seen: bool,
+    events_seen: bool,
+    done: bool,
+}
+
+impl<'a> SliceParser<'a> {
+    /// Returns a new `SliceParser` reading from `input`
+    pub fn new(input: &'a str) -> SliceParser<'a> {
+        let (input, bom) = match input.strip_prefix('\u{FEFF}') {
+            Some(input) => (input, '\u{FEFF}'.len_utf8()),
+            None => (input, 0),
+        };
+        SliceParser {
+            input,
+            bom,
+            invalid_utf8: None,
+            tokens: Tokenizer::new(input.as_bytes()),
+            open: Vec::new(),
+            tag: None,
+            pending_end: None,
+            root_seen: false,
+            events_seen: false,
+            done: false,
+        }
+    }
+
+    /// Returns a new `SliceParser` reading from `input`, which must be UTF-8 encoded.
+    /// Parsing fails with `InvalidUtf8` once the first invalid byte is reached.
+    pub fn from_bytes(input: &'a [u8]) -> SliceParser<'a> {
+        let (valid, invalid_utf8) = match str::from_utf8(input) {
+            Ok(valid) => (valid, None),
+            Err(err) => {
+                let end = err.valid_up_to();
+                let valid = str::from_utf8(&input[..end]).expect("Checked as valid UTF-8");
+                (valid, Some(end))
+            }
+        };
+        let parser = SliceParser::new(valid);
+        SliceParser {
+            // Offsets into `input` exclude the byte order mark
+            invalid_utf8: invalid_utf8.map(|offset| offset - parser.bom),
+            ..parser
+        }
+    }
+
+    /// Returns the number of open elements
+    pub fn depth(&self) -> usize {
+        self.open.len()
+    }
+
+    fn error(&self, offset: usize, kind: ParserErrorKind) -> ParserError {
+        let before = &self.input[..offset.min(self.input.len())];
+        // Lines end with "\r\n", '\n' or a lone '\r'
+        let lone_crs = before
+            .match_indices('\r')
+            .filter(|&(i, _)| !before[i + 1..].starts_with('\n'))
+            .count();
+        let line_start = before.rfind(['\n', '\r']).map_or(0, |i| i + 1);
+        ParserError {
+            line: (before.matches('\n').count() + lone_crs) as u64 + 1,
+            col: before[line_start..].chars().count() as u64 + 1,
+            offset: (self.bom + offset) as u64,
+            kind,
+        }
+    }
+
+    fn namespace(&self, prefix: &str) -> Option<Cow<'a, str>> {
+        match prefix {
+            "xml" => return Some(Cow::Borrowed(ns::XML)),
+            "xmlns" => return Some(Cow::Borrowed(ns::XMLNS)),
+            _ => (),
+        }
+        self.open
+            .iter()
+            .rev()
+            .find_map(|elem| elem.declared.iter().rev().find(|(p, _)| *p == prefix))
+            .map(|(_, ns)| ns.clone())
+            .filter(|ns| !ns.is_empty())
+    }
+
+    // Splits a qualified name, checking its parts
+    fn split_name(
+        &self,
+        qname: &'a str,
+        offset: usize,
+    ) -> Result<(Option<&'a str>, &'a str), ParserError> {
+        let (prefix, name) = match qname.split_once(':') {
+            Some((prefix, name)) => (Some(prefix), name),
+            None => (None, qname),
+        };
+        if !names::is_ncname(name) || !prefix.is_none_or(names::is_ncname) {
+            return Err(self.error(offset, ParserErrorKind::InvalidName));
+        }
+        Ok((prefix, name))
+    }
+
+    // The offset in the input of `text`, which is a slice of it
+    fn offset_of(&self, text: &str) -> usize {
+        text.as_ptr() as usize - self.input.as_ptr() as usize
+    }
+
+    // Expands the references in `text`, found at `offset` in the input, after normalizing
+    // its line ends
+    fn expand(&self, text: &'a str, offset: usize) -> Result<Cow<'a, str>, ParserError> {
+        let normalized = normalize_line_ends(text);
+        if !normalized.contains('&') {
+            return Ok(normalized);
+        }
+        unescape(&normalized).map(Cow::Owned).map_err(|reference| {
+            // The invalid reference is located in `text`, whose line ends are unchanged, as
+            // the first one not followed by valid text up to the next '&'
+            let at = text
+                .match_indices('&')
+                .map(|(i, _)| i)
+                .find(|&i| {
+                    let end = text[i + 1..].find('&').map_or(text.len(), |j| i + 1 + j);
+                    unescape(&text[i..end]).is_err()
+                })
+                .unwrap_or(0);
+            let kind = match reference.starts_with("&#") && reference.ends_with(';') {
+                true => ParserErrorKind::InvalidCharRef,
+                false => ParserErrorKind::InvalidEntity,
+            };
+            self.error(offset + at, kind)
+        })
+    }
+
+    fn start_tag(&mut self, empty: bool) -> Result<SliceEvent<'a>, ParserError> {
+        let PendingTag {
+            qname,
+            offset,
+            attributes: attrs,
+        } = self.tag.take().expect("Start tag read");
+        if self.open.is_empty() && mem::replace(&mut self.root_seen, true) {
+            return Err(self.error(offset, ParserErrorKind::MultipleRoots));
+        }
+        let mut declared = Vec::new();
+        let mut values = Vec::with_capacity(attrs.len());
+        for &(attr, value, at) in &attrs {
+            let value = self.expand(value, self.offset_of(value))?;
+            match self.split_name(attr, at)? {
+                (None, "xmlns") => declared.push(("", value.clone())),
+                (Some("xmlns"), prefix) => declared.push((prefix, value.clone())),
+                _ => (),
+            }
+            values.push(value);
+        }
+        self.open.push(OpenElement { qname, declared });
+
+        let (prefix, name) = self.split_name(qname, offset)?;
+        let ns = self.namespace(prefix.unwrap_or(""));
+        if prefix.is_some() && ns.is_none() {
+            return Err(self.error(offset, ParserErrorKind::UnboundNsPrefixInTagName));
+        }
+        let mut attributes: Vec<SliceAttribute> = Vec::with_capacity(attrs.len());
+        for (&(attr, _, at), value) in attrs.iter().zip(values) {
+            let (prefix, name) = self.split_name(attr, at)?;
+            let ns = match prefix {
+                Some(prefix) => match self.namespace(prefix) {
+                    Some(ns) => Some(ns),
+                    None => {
+                        return Err(self.error(at, ParserErrorKind::UnboundNsPrefixInAttributeName))
+                    }
+                },
+                None => None,
+            };
+            if attributes
+                .iter()
+                .any(|other| other.name == name && other.ns == ns)
+            {
+                return Err(self.error(at, ParserErrorKind::DuplicateAttribute));
+            }
+            attributes.push(SliceAttribute { name, ns, value });
+        }
+        if empty {
+            self.pending_end = Some(SliceEndTag {
+                name,
+                ns: ns.clone(),
+                prefix,
+            });
+        }
+        Ok(SliceEvent::ElementStart(SliceStartTag {
+            name,
+            ns,
+            prefix,
+            attributes,
+        }))
+    }
+
+    fn end_tag(&mut self, qname: &'a str, offset: usize) -> Result<SliceEvent<'a>, ParserError> {
+        match self.open.last() {
+            Some(elem) if elem.qname == qname => (),
+            _ => return Err(self.error(offset, ParserErrorKind::TagMismatch)),
+        }
+        let (prefix, name) = self.split_name(qname, offset)?;
+        let ns = self.namespace(prefix.unwrap_or(""));
+        self.open.pop();
+        Ok(SliceEvent::ElementEnd(SliceEndTag { name, ns, prefix }))
+    }
+
+    // Checks the content outside of the root element
+    fn outside_root(&self, event: &SliceEvent, offset: usize) -> Result<(), ParserError> {
+        match *event {
+            SliceEvent::Characters(ref text)
+                if !text.chars().all(|c| matches!(c, ' ' | '\t' | '\n')) =>
+            {
+                Err(self.error(offset, ParserErrorKind::TextOutsideRoot))
+            }
+            SliceEvent::CDATA(_) => Err(self.error(offset, ParserErrorKind::TextOutsideRoot)),
+            SliceEvent::Doctype(_) if self.root_seen => {
+                Err(self.error(offset, ParserErrorKind::MisplacedDoctype))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Returns the event for `token`, or `None` for the parts of a start tag
+    fn event(
+        &mut self,
+        token: Token<'a>,
+        offset: usize,
+    ) -> Result<Option<SliceEvent<'a>>, ParserError> {
+        let event = match token {
+            Token::ElementStart(qname) => {
+                self.tag = Some(PendingTag {
+                    qname,
+                    offset,
+                    attributes: Vec::new(),
+                });
+                return Ok(None);
+            }
+            Token::Attribute(name, value) => {
+                // Attribute values follow their name and a quote
+                let at = offset + self.input[offset..].find(name).unwrap_or(0);
+                if let Some(ref mut tag) = self.tag {
+                    tag.attributes.push((name, value, at));
+                }
+                return Ok(None);
+            }
+            Token::StartTagEnd(empty) => return self.start_tag(empty).map(Some),
+            Token::ElementEnd(qname) => return self.end_tag(qname, offset).map(Some),
+            Token::Text(text) => SliceEvent::Characters(self.expand(text, offset)?),
+            Token::CDATA(text) => SliceEvent::CDATA(normalize_line_ends(text)),
+            Token::Comment(text) => {
+                if text.contains("--") || text.ends_with('-') {
+                    return Err(self.error(offset, ParserErrorKind::InvalidCommentContent));
+                }
+                SliceEvent::Comment(normalize_line_ends(text))
+            }
+            Token::PI(text) => {
+                let target = text.split([' ', '\t', '\r', '\n']).next().unwrap_or("");
+                if !target.split(':').all(names::is_ncname) {
+                    return Err(self.error(offset, ParserErrorKind::InvalidName));
+                }
+                if target == "xml" {
+                    if self.events_seen {
+                        return Err(self.error(offset, ParserErrorKind::MisplacedXmlDecl));
+                    }
+                    let decl = XmlDecl::from_pi(&normalize_line_ends(text))
+                        .ok_or_else(|| self.error(offset, ParserErrorKind::InvalidXmlDecl))?;
+                    SliceEvent::XmlDecl(decl)
+                } else {
+                    SliceEvent::PI(normalize_line_ends(text))
+                }
+            }
+            Token::Doctype(text) => {
+                let doctype = text
+                    .strip_prefix("DOCTYPE")
+                    .filter(|rest| rest.starts_with([' ', '\t', '\r', '\n']))
+                    .and_then(|rest| entities::parse_doctype(rest).ok())
+                    .ok_or_else(|| self.error(offset, ParserErrorKind::InvalidDoctype))?;
+                SliceEvent::Doctype(doctype.0)
+            }
+        };
+        if self.open.is_empty() {
+            self.outside_root(&event, offset)?;
+        }
+        Ok(Some(event))
+    }
+
+    // Checks the input left once the tokenizer is exhausted, which may be trailing text
+    fn end(&mut self, offset: usize) -> Option<Result<SliceEvent<'a>, ParserError>> {
+        self.done = true;
+        let rest = &self.input[offset..];
+        if let Some(invalid) = self.invalid_utf8 {
+            return Some(Err(self.error(invalid, ParserErrorKind::InvalidUtf8)));
+        }
+        if !self.open.is_empty() || self.tag.is_some() || rest.starts_with('<') {
+            return Some(Err(
+                self.error(self.input.len(), ParserErrorKind::UnexpectedEof)
+            ));
+        }
+        if !self.root_seen {
+            return Some(Err(
+                self.error(self.input.len(), ParserErrorKind::MissingRoot)
+            ));
+        }
+        if rest.is_empty() {
+            return None;
+        }
+        let event = self.expand(rest, offset).map(SliceEvent::Characters);
+        Some(event.and_then(|event| self.outside_root(&event, offset).map(|_| event)))
+    }
+}
+
+impl<'a> Iterator for SliceParser<'a> {
+    type Item = Result<SliceEvent<'a>, ParserError>;
+
+    fn next(&mut self) -> Option<Result<SliceEvent<'a>, ParserError>> {
+        if let Some(tag) = self.pending_end.take() {
+            self.open.pop();
+            return Some(Ok(SliceEvent::ElementEnd(tag)));
+        }
+        while !self.done {
+            let offset = self.tokens.position();
+            let token = match self.tokens.next() {
+                Some(Ok(token)) => token,
+                Some(Err(TokenError { offset, kind })) => {
+                    self.done = true;
+                    return Some(Err(self.error(offset, kind)));
+                }
+                None => return self.end(offset),
+            };
+            match self.event(token, offset) {
+                Ok(Some(event)) => {
+                    self.events_seen = true;
+                    return Some(Ok(event));
+                }
+                Ok(None) => (),
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SliceEvent, SliceParser};
+    use crate::parser::ParserErrorKind;
+    use crate::{Event, Parser};
+    use std::borrow::Cow;
+
+    #[test]
+    fn test_same_events() {
+        let doc = "<?xml version='1.0'?>\r\n<!DOCTYPE a [<!ENTITY x 'y'>]>\n\
+                   <a xmlns='urn:a' xmlns:p='urn:p' p:x='1 &lt; 2' y=\"&#x41;\">\
+                   t&amp;<!--c--><?pi data?><![CDATA[<d>\r\n]]><p:b/><c xmlns=''/></a>\n";
+        let events: Vec<_> = SliceParser::new(doc)
+            .map(|event| event.unwrap().into_owned())
+            .collect();
+        let expected: Vec<Event> = Parser::new(doc.as_bytes()).map(Result::unwrap).collect();
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn test_borrowed() {
+        let doc = "<a xmlns='urn:a' x='1' y='&amp;'>text<b>x\r\ny</b></a>";
+        let events: Vec<_> = SliceParser::new(doc).map(Result::unwrap).collect();
+        match events[0] {
+            SliceEvent::ElementStart(ref tag) => {
+                assert!(matches!(tag.ns, Some(Cow::Borrowed("urn:a"))));
+                assert!(matches!(tag.attributes[1].value, Cow::Borrowed("1")));
+                assert!(matches!(tag.attributes[2].value, Cow::Owned(_)));
+                assert_eq!(tag.get_attribute("y", None), Some("&"));
+            }
+            _ => panic!("Expected a start tag"),
+        }
+        assert!(matches!(
+            events[1],
+            SliceEvent::Characters(Cow::Borrowed("text"))
+        ));
+        match events[3] {
+            SliceEvent::Characters(ref text) => assert_eq!(text, "x\ny"),
+            _ => panic!("Expected text"),
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        for (doc, kind, offset) in [
+            ("<a><b></a>", ParserErrorKind::TagMismatch, 6),
+            ("<a>", ParserErrorKind::UnexpectedEof, 3),
+            ("<a>&x;</a>", ParserErrorKind::InvalidEntity, 3),
+            ("<a>&#0;</a>", ParserErrorKind::InvalidCharRef, 3),
+            (
+                "<a p:x='1'/>",
+                ParserErrorKind::UnboundNsPrefixInAttributeName,
+                3,
+            ),
+            ("<p:a/>", ParserErrorKind::UnboundNsPrefixInTagName, 0),
+            ("<a x='1' x='2'/>", ParserErrorKind::DuplicateAttribute, 9),
+            ("<a/><b/>", ParserErrorKind::MultipleRoots, 4),
+            ("<a/>t", ParserErrorKind::TextOutsideRoot, 4),
+            (" ", ParserErrorKind::MissingRoot, 1),
+            (
+                "<a><!-- - -- --></a>",
+                ParserErrorKind::InvalidCommentContent,
+                3,
+            ),
+            (
+                "<a/><?xml version='1.0'?>",
+                ParserErrorKind::MisplacedXmlDecl,
+                4,
+            ),
+        ] {
+            let err = SliceParser::new(doc).find_map(Result::err).unwrap();
+            assert_eq!((err.kind, err.offset), (kind, offset), "{}", doc);
+        }
+        // Offsets and positions refer to the input before line ends are normalized
+        for (doc, kind, offset, line, col) in [
+            (
+                "<a>\r\n&amp;é&x;</a>",
+                ParserErrorKind::InvalidEntity,
+                12,
+                2,
+                7,
+            ),
+            ("<a>\r\n&é&</a>", ParserErrorKind::InvalidEntity, 5, 2, 1),
+            (
+                "<a>\r\r\n\r&#0;</a>",
+                ParserErrorKind::InvalidCharRef,
+                7,
+                4,
+                1,
+            ),
+            (
+                "<a x='\r\n\u{e9}&lt&gt;'/>",
+                ParserErrorKind::InvalidEntity,
+                10,
+                2,
+                2,
+            ),
+            (
+                "<a x='x' y='&a;'/>",
+                ParserErrorKind::InvalidEntity,
+                12,
+                1,
+                13,
+            ),
+        ] {
+            let err = SliceParser::new(doc).find_map(Result::err).unwrap();
+            assert_eq!(
+                (err.kind, err.offset, err.line, err.col),
+                (kind, offset, line, col),
+                "{:?}",
+                doc
+            );
+        }
+
+        let err = SliceParser::from_bytes(b"<a>x\xff</a>")
+            .find_map(Result::err)
+            .unwrap();
+        assert_eq!((err.kind, err.offset), (ParserErrorKind::InvalidUtf8, 4));
+    }
+}
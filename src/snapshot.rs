@@ -0,0 +1,309 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Binary snapshots of `Element` trees
+//
+// A snapshot starts with `MAGIC` followed by the root element. Integers and strings are
+// encoded as described in `compact`, with every string written inline recorded in the string
+// table. Namespace prefix maps, which are usually shared by many elements, are referenced
+// the same way as strings.
+
+use crate::compact::{self, StringList, StringTable, MAX_INT_LEN};
+use crate::{AttrMap, Element, QName, Xml};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+const MAGIC: &[u8] = b"XMLS\x01";
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+/// Errors encountered while loading a snapshot
+pub enum SnapshotError {
+    /// The data is not a snapshot in a format supported by this version
+    UnsupportedFormat,
+    /// The data ended prematurely
+    UnexpectedEnd,
+    /// The data is corrupt
+    Malformed,
+}
+
+impl Error for SnapshotError {}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match *self {
+            SnapshotError::UnsupportedFormat => "Unsupported snapshot format",
+            SnapshotError::UnexpectedEnd => "Unexpected end of snapshot",
+            SnapshotError::Malformed => "Malformed snapshot",
+        };
+        msg.fmt(f)
+    }
+}
+
+struct Encoder<'a> {
+    out: Vec<u8>,
+    strings: StringTable<&'a str>,
+    prefix_maps: HashMap<Vec<(&'a String, &'a String)>, usize>,
+}
+
+impl<'a> Encoder<'a> {
+    fn int(&mut self, n: usize) {
+        self.out
+            .extend_from_slice(compact::encode_int(n as u64, &mut [0; MAX_INT_LEN]));
+    }
+
+    fn str(&mut self, s: &'a str) {
+        if let Some(index) = self.strings.get(s) {
+            return self.int(index as usize);
+        }
+        self.int(0);
+        self.int(s.len());
+        self.out.extend_from_slice(s.as_bytes());
+    }
+
+    fn opt(&mut self, s: &'a Option<String>) {
+        match *s {
+            Some(ref s) => {
+                self.out.push(1);
+                self.str(s);
+            }
+            None => self.out.push(0),
+        }
+    }
+
+    fn element(&mut self, elem: &'a Element) {
+        self.str(&elem.name.name);
+        self.opt(&elem.name.ns);
+        self.opt(&elem.default_ns);
+
+        let mut prefixes: Vec<_> = elem.prefixes.iter().collect();
+        prefixes.sort();
+        if let Some(&index) = self.prefix_maps.get(&prefixes) {
+            self.int(index);
+        } else {
+            self.int(0);
+            self.int(prefixes.len());
+            for &(ns, prefix) in &prefixes {
+                self.str(ns);
+                self.str(prefix);
+            }
+            self.prefix_maps
+                .insert(prefixes, self.prefix_maps.len() + 1);
+        }
+
+        #[allow(unused_mut)]
+        let mut attributes: Vec<_> = elem.attributes.iter().collect();
+        #[cfg(not(feature = "ordered_attrs"))]
+        attributes.sort();
+        self.int(attributes.len());
+        for (QName { name, ns }, value) in attributes {
+            self.str(name);
+            self.opt(ns);
+            self.str(value);
+        }
+
+        self.int(elem.children.len());
+        for child in &elem.children {
+            match *child {
+                Xml::ElementNode(ref elem) => {
+                    self.out.push(0);
+                    self.element(elem);
+                }
+                Xml::CharacterNode(ref text) => {
+                    self.out.push(1);
+                    self.str(text);
+                }
+                Xml::CDATANode(ref text) => {
+                    self.out.push(2);
+                    self.str(text);
+                }
+                Xml::CommentNode(ref text) => {
+                    self.out.push(3);
+                    self.str(text);
+                }
+                Xml::PINode(ref text) => {
+                    self.out.push(4);
+                    self.str(text);
+                }
+            }
+        }
+    }
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    strings: StringList<&'a str>,
+    prefix_maps: Vec<HashMap<String, String>>,
+}
+
+impl<'a> Decoder<'a> {
+    fn byte(&mut self) -> Result<u8, SnapshotError> {
+        let (&byte, rest) = self
+            .data
+            .split_first()
+            .ok_or(SnapshotError::UnexpectedEnd)?;
+        self.data = rest;
+        Ok(byte)
+    }
+
+    fn int(&mut self) -> Result<usize, SnapshotError> {
+        let n = compact::read_int(&mut self.data).map_err(|err| match err.kind() {
+            io::ErrorKind::UnexpectedEof => SnapshotError::UnexpectedEnd,
+            _ => SnapshotError::Malformed,
+        })?;
+        usize::try_from(n).map_err(|_| SnapshotError::Malformed)
+    }
+
+    fn str(&mut self) -> Result<String, SnapshotError> {
+        let index = self.int()?;
+        if index > 0 {
+            let s = self
+                .strings
+                .get(index as u64)
+                .ok_or(SnapshotError::Malformed)?;
+            return Ok((*s).to_owned());
+        }
+        let len = self.int()?;
+        if len > self.data.len() {
+            return Err(SnapshotError::UnexpectedEnd);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        let s = std::str::from_utf8(bytes).map_err(|_| SnapshotError::Malformed)?;
+        self.strings.push(s);
+        Ok(s.to_owned())
+    }
+
+    fn opt(&mut self) -> Result<Option<String>, SnapshotError> {
+        match self.byte()? {
+            0 => Ok(None),
+            1 => self.str().map(Some),
+            _ => Err(SnapshotError::Malformed),
+        }
+    }
+
+    fn element(&mut self) -> Result<Element, SnapshotError> {
+        let name = self.str()?;
+        let ns = self.opt()?;
+        let default_ns = self.opt()?;
+
+        let index = self.int()?;
+        let prefixes = if index > 0 {
+            self.prefix_maps
+                .get(index - 1)
+                .ok_or(SnapshotError::Malformed)?
+                .clone()
+        } else {
+            let count = self.int()?;
+            let mut prefixes = HashMap::with_capacity(count.min(self.data.len()));
+            for _ in 0..count {
+                let ns = self.str()?;
+                prefixes.insert(ns, self.str()?);
+            }
+            self.prefix_maps.push(prefixes.clone());
+            prefixes
+        };
+
+        let count = self.int()?;
+        let mut attributes = AttrMap::with_capacity(count.min(self.data.len()));
+        for _ in 0..count {
+            let name = self.str()?;
+            let ns = self.opt()?;
+            attributes.insert(QName::new(name, ns), self.str()?);
+        }
+
+        let count = self.int()?;
+        let mut children = Vec::with_capacity(count.min(self.data.len()));
+        for _ in 0..count {
+            children.push(match self.byte()? {
+                0 => Xml::ElementNode(self.element()?),
+                1 => Xml::CharacterNode(self.str()?),
+                2 => Xml::CDATANode(self.str()?),
+                3 => Xml::CommentNode(self.str()?),
+                4 => Xml::PINode(self.str()?),
+                _ => return Err(SnapshotError::Malformed),
+            });
+        }
+
+        Ok(Element {
+            name: QName::new(name, ns),
+            attributes,
+            children,
+            prefixes,
+            default_ns,
+        })
+    }
+}
+
+// Returns the snapshot of `elem`
+pub(crate) fn encode(elem: &Element) -> Vec<u8> {
+    let mut encoder = Encoder {
+        out: MAGIC.to_vec(),
+        strings: StringTable::default(),
+        prefix_maps: HashMap::new(),
+    };
+    encoder.element(elem);
+    encoder.out
+}
+
+// Loads an `Element` from a snapshot created by `encode()`
+pub(crate) fn decode(data: &[u8]) -> Result<Element, SnapshotError> {
+    let data = data
+        .strip_prefix(MAGIC)
+        .ok_or(SnapshotError::UnsupportedFormat)?;
+    let mut decoder = Decoder {
+        data,
+        strings: StringList::default(),
+        prefix_maps: Vec::new(),
+    };
+    let elem = decoder.element()?;
+    if !decoder.data.is_empty() {
+        return Err(SnapshotError::Malformed);
+    }
+    Ok(elem)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SnapshotError;
+    use crate::Element;
+
+    #[test]
+    fn test_roundtrip() {
+        let elem: Element = "<a xmlns='urn:a' xmlns:p='urn:p' p:x='1'><?pi?>\
+                             <b>text<![CDATA[<c>]]><!--c--></b><p:b/><b y='1'/></a>"
+            .parse()
+            .unwrap();
+        assert_eq!(Element::from_snapshot(&elem.to_snapshot()).unwrap(), elem);
+
+        let mut list = Element::new("list".to_owned(), None, vec![]);
+        list.extend((0..20).map(|_| elem.clone()));
+        assert!(list.to_snapshot().len() < list.to_string().len());
+    }
+
+    #[test]
+    fn test_invalid() {
+        let snapshot = "<a><b/></a>".parse::<Element>().unwrap().to_snapshot();
+        assert_eq!(
+            Element::from_snapshot(b"<a/>"),
+            Err(SnapshotError::UnsupportedFormat)
+        );
+        assert_eq!(
+            Element::from_snapshot(&snapshot[..snapshot.len() - 1]),
+            Err(SnapshotError::UnexpectedEnd)
+        );
+        let mut corrupt = snapshot.clone();
+        corrupt.push(0);
+        assert_eq!(
+            Element::from_snapshot(&corrupt),
+            Err(SnapshotError::Malformed)
+        );
+    }
+}
@@ -0,0 +1,163 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::ns;
+use crate::path::ElementPath;
+use crate::writer::{EventWriter, StreamError};
+use crate::{AttrMap, EndTag, Event, Name, Parser, QName, StartTag};
+use std::io::{self, Read, Write};
+
+// Keep only the namespace declarations of an element's attributes
+pub(crate) fn ns_declarations(tag: &StartTag) -> AttrMap<QName, String> {
+    tag.attributes
+        .iter()
+        .filter(|(QName { name, ns }, _)| match *ns {
+            Some(ref ns) => ns == ns::XMLNS,
+            None => name == "xmlns",
+        })
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+/// Splits a document into one document per record element selected by `path`.
+///
+/// Each record is written to the writer returned by `output`, which is called with the
+/// zero-based index of the record. Unless the record is the root element itself it is
+/// wrapped in a copy of the root element carrying all namespace declarations in scope
+/// for the record, so every output is a well-formed document on its own.
+/// Content outside of records is discarded. Returns the number of records written.
+///
+/// ~~~no_run
+/// use std::fs::File;
+///
+/// let input = File::open("dump.xml").unwrap();
+/// let count = xml::split_records(input, "/feed/entry", |i| {
+///     File::create(format!("entry-{}.xml", i))
+/// }).unwrap();
+/// println!("Wrote {} entries", count);
+/// ~~~
+pub fn split_records<R, W, F, P>(reader: R, path: P, mut output: F) -> Result<usize, StreamError>
+where
+    R: Read,
+    W: Write,
+    F: FnMut(usize) -> io::Result<W>,
+    P: Into<ElementPath>,
+{
+    let path = path.into();
+    let mut names: Vec<Name> = Vec::new();
+    let mut declarations: Vec<AttrMap<QName, String>> = Vec::new();
+    let mut root: Option<(QName, Option<String>)> = None;
+    let mut record: Option<EventWriter<W>> = None;
+    let mut record_depth = 0;
+    let mut count = 0;
+
+    for event in Parser::new(reader) {
+        let event = event?;
+        match event {
+            Event::ElementStart(ref tag) => {
+                names.push(tag.name.name.clone());
+                declarations.push(ns_declarations(tag));
+                if root.is_none() {
+                    root = Some((tag.name.clone(), tag.prefix.clone()));
+                }
+                if record.is_none() && path.matches(&names) {
+                    let mut writer = EventWriter::new(output(count)?);
+                    if names.len() > 1 {
+                        let (name, prefix) = root.clone().expect("Root element not set");
+                        let mut attributes = AttrMap::new();
+                        for decls in &declarations[..declarations.len() - 1] {
+                            attributes.extend(decls.clone());
+                        }
+                        writer.write(&Event::ElementStart(StartTag {
+                            name,
+                            prefix,
+                            attributes,
+                        }))?;
+                    }
+                    record = Some(writer);
+                    record_depth = names.len();
+                }
+            }
+            Event::ElementEnd(_) => {
+                names.pop();
+                declarations.pop();
+            }
+            _ => (),
+        }
+
+        if let Some(ref mut writer) = record {
+            writer.write(&event)?;
+        }
+
+        if record.is_some() && names.len() < record_depth {
+            let mut writer = record.take().expect("No record being written");
+            if record_depth > 1 {
+                let (name, prefix) = root.clone().expect("Root element not set");
+                writer.write(&Event::ElementEnd(EndTag { name, prefix }))?;
+            }
+            writer.flush()?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_records;
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+
+    struct Output(Rc<RefCell<Vec<Vec<u8>>>>, usize);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut()[self.1].extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn split(input: &str, path: &str) -> Vec<String> {
+        let outputs = Rc::new(RefCell::new(Vec::new()));
+        let count = split_records(input.as_bytes(), path, |i| {
+            outputs.borrow_mut().push(Vec::new());
+            Ok(Output(outputs.clone(), i))
+        })
+        .unwrap();
+        let outputs = outputs.take();
+        assert_eq!(count, outputs.len());
+        outputs
+            .into_iter()
+            .map(|out| String::from_utf8(out).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_split_namespaces() {
+        let input = "<root><list xmlns:m='urn:m'>\
+                     <item m:id='1'/><skip/><item m:id='2'>x</item></list></root>";
+        assert_eq!(
+            split(input, "item"),
+            vec![
+                "<root xmlns:m='urn:m'><item m:id='1'/></root>",
+                "<root xmlns:m='urn:m'><item m:id='2'>x</item></root>",
+            ],
+        );
+    }
+
+    #[test]
+    fn test_split_root() {
+        assert_eq!(split("<a><b/></a>", "/a"), vec!["<a><b/></a>"]);
+    }
+}
@@ -0,0 +1,390 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::parser::ParserErrorKind;
+use std::error::Error;
+use std::fmt;
+use std::str;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+/// Tokens returned by the `Tokenizer`, borrowing from its buffer
+///
+/// Names include their prefix, and text and attribute values are returned as found
+/// in the document, without expanding entities.
+pub enum Token<'a> {
+    /// The start of a start tag, `<name`
+    ElementStart(&'a str),
+    /// An attribute within a start tag, `name='value'`
+    Attribute(&'a str, &'a str),
+    /// The end of a start tag, `>`, or `/>` for an empty element in which case it is `true`
+    StartTagEnd(bool),
+    /// An end tag, `</name>`
+    ElementEnd(&'a str),
+    /// Character data
+    Text(&'a str),
+    /// The content of a CDATA section
+    CDATA(&'a str),
+    /// The content of a comment
+    Comment(&'a str),
+    /// The content of a processing instruction
+    PI(&'a str),
+    /// The content of a document type declaration, such as `DOCTYPE a`
+    Doctype(&'a str),
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+/// The error returned by the `Tokenizer` for malformed input
+pub struct TokenError {
+    /// The offset into the buffer at which the malformed token starts
+    pub offset: usize,
+    /// The kind of error encountered
+    pub kind: ParserErrorKind,
+}
+
+impl Error for TokenError {}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Tokenizer error; Offset: {}, Reason: {}",
+            self.offset, self.kind
+        )
+    }
+}
+
+/// A tokenizer splitting XML held in a caller-provided buffer into borrowed `Token`s,
+/// without allocating
+///
+/// The tokenizer neither resolves namespaces nor expands entities, and does not check
+/// that tags are nested properly. A token is returned once it is complete: iteration ends
+/// when the buffer is exhausted or ends within a token, including text not followed by
+/// markup. `position()` then tells how much of the buffer was consumed, so input can be
+/// processed in chunks by moving the remainder to the start of the buffer, refilling it and
+/// continuing with `Tokenizer::resume()`.
+///
+/// ~~~
+/// use xml::{Token, Tokenizer};
+///
+/// let buf = b"<cfg rate='9600'>on</cfg><cf";
+/// let mut tokens = Tokenizer::new(buf);
+/// assert_eq!(tokens.next(), Some(Ok(Token::ElementStart("cfg"))));
+/// assert_eq!(tokens.next(), Some(Ok(Token::Attribute("rate", "9600"))));
+/// assert_eq!(tokens.next(), Some(Ok(Token::StartTagEnd(false))));
+/// assert_eq!(tokens.next(), Some(Ok(Token::Text("on"))));
+/// assert_eq!(tokens.next(), Some(Ok(Token::ElementEnd("cfg"))));
+/// assert_eq!(tokens.next(), None);
+/// assert_eq!(tokens.position(), 25);
+/// ~~~
+pub struct Tokenizer<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    in_tag: bool,
+    has_error: bool,
+}
+
+// The outcome of scanning for a token: `None` if the buffer ends within it
+type Scan<'a> = Result<Option<(Token<'a>, usize)>, ParserErrorKind>;
+
+impl<'a> Tokenizer<'a> {
+    /// Returns a new `Tokenizer` reading from `buf`
+    pub fn new(buf: &'a [u8]) -> Tokenizer<'a> {
+        Tokenizer {
+            buf,
+            pos: 0,
+            in_tag: false,
+            has_error: false,
+        }
+    }
+
+    /// Returns a new `Tokenizer` reading from `buf`, continuing after a previous buffer that
+    /// ended within a start tag if `in_start_tag` is `true`, see `in_start_tag()`
+    pub fn resume(buf: &'a [u8], in_start_tag: bool) -> Tokenizer<'a> {
+        Tokenizer {
+            in_tag: in_start_tag,
+            ..Tokenizer::new(buf)
+        }
+    }
+
+    /// Returns `true` if the tokenizer is within a start tag, after its name
+    pub fn in_start_tag(&self) -> bool {
+        self.in_tag
+    }
+
+    /// Returns the offset of the first byte not part of a returned token
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    fn str(&self, start: usize, end: usize) -> Result<&'a str, ParserErrorKind> {
+        str::from_utf8(&self.buf[start..end]).map_err(|_| ParserErrorKind::MalformedXml)
+    }
+
+    // Returns the offset of `pattern` at or after `start`
+    fn find(&self, start: usize, pattern: &[u8]) -> Option<usize> {
+        self.buf[start.min(self.buf.len())..]
+            .windows(pattern.len())
+            .position(|window| window == pattern)
+            .map(|i| start + i)
+    }
+
+    fn skip_whitespace(&self, mut pos: usize) -> usize {
+        while pos < self.buf.len() && self.buf[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        pos
+    }
+
+    fn name_end(&self, mut pos: usize) -> usize {
+        while pos < self.buf.len()
+            && !self.buf[pos].is_ascii_whitespace()
+            && !matches!(self.buf[pos], b'>' | b'/' | b'=')
+        {
+            pos += 1;
+        }
+        pos
+    }
+
+    // Scans a construct ending with `end_marker`, with content starting `prefix_len`
+    // bytes after the current position
+    fn delimited(
+        &self,
+        prefix_len: usize,
+        end_marker: &[u8],
+        token: fn(&'a str) -> Token<'a>,
+    ) -> Scan<'a> {
+        let start = self.pos + prefix_len;
+        match self.find(start, end_marker) {
+            Some(end) => Ok(Some((token(self.str(start, end)?), end + end_marker.len()))),
+            None => Ok(None),
+        }
+    }
+
+    // Returns the offset of the '>' ending a document type declaration, skipping literals,
+    // and comments and processing instructions within its internal subset
+    fn doctype_end(&self, mut pos: usize) -> Option<usize> {
+        let mut in_subset = false;
+        while let Some(&c) = self.buf.get(pos) {
+            let rest = &self.buf[pos..];
+            pos = match c {
+                b'"' | b'\'' => self.find(pos + 1, &rest[..1])? + 1,
+                b'<' if in_subset && rest.starts_with(b"<!--") => self.find(pos + 4, b"-->")? + 3,
+                b'<' if in_subset && rest.starts_with(b"<?") => self.find(pos + 2, b"?>")? + 2,
+                b'>' if !in_subset => return Some(pos),
+                _ => {
+                    in_subset = match c {
+                        b'[' => true,
+                        b']' => false,
+                        _ => in_subset,
+                    };
+                    pos + 1
+                }
+            };
+        }
+        None
+    }
+
+    fn scan_markup(&self) -> Scan<'a> {
+        let rest = &self.buf[self.pos..];
+        for (marker, end_marker, token) in [
+            (
+                &b"<?"[..],
+                &b"?>"[..],
+                Token::PI as fn(&'a str) -> Token<'a>,
+            ),
+            (b"<!--", b"-->", Token::Comment),
+            (b"<![CDATA[", b"]]>", Token::CDATA),
+        ] {
+            if rest.starts_with(marker) {
+                return self.delimited(marker.len(), end_marker, token);
+            }
+            if marker.starts_with(rest) {
+                return Ok(None);
+            }
+        }
+        if rest.starts_with(b"<![") {
+            return Err(ParserErrorKind::InvalidCdataStart);
+        }
+        if rest.starts_with(b"<!-") && rest.len() > 3 {
+            return Err(ParserErrorKind::InvalidCommentStart);
+        }
+        if rest.starts_with(b"<!") {
+            return match self.doctype_end(self.pos + 2) {
+                Some(end) => Ok(Some((
+                    Token::Doctype(self.str(self.pos + 2, end)?),
+                    end + 1,
+                ))),
+                None => Ok(None),
+            };
+        }
+        if rest.starts_with(b"</") {
+            return match self.find(self.pos, b">") {
+                Some(end) => {
+                    let name = self.str(self.pos + 2, end)?.trim_end();
+                    Ok(Some((Token::ElementEnd(name), end + 1)))
+                }
+                None => Ok(None),
+            };
+        }
+        let end = self.name_end(self.pos + 1);
+        if end == self.buf.len() {
+            return Ok(None);
+        }
+        if end == self.pos + 1 {
+            return Err(ParserErrorKind::MalformedXml);
+        }
+        Ok(Some((
+            Token::ElementStart(self.str(self.pos + 1, end)?),
+            end,
+        )))
+    }
+
+    fn scan_tag(&self) -> Scan<'a> {
+        let pos = self.skip_whitespace(self.pos);
+        match self.buf.get(pos..pos + 2) {
+            _ if self.buf.get(pos) == Some(&b'>') => {
+                return Ok(Some((Token::StartTagEnd(false), pos + 1)))
+            }
+            Some(b"/>") => return Ok(Some((Token::StartTagEnd(true), pos + 2))),
+            Some([b'/', _]) => return Err(ParserErrorKind::ExpectedTagClose),
+            None => return Ok(None),
+            _ => (),
+        }
+        let name_end = self.name_end(pos);
+        let eq = self.skip_whitespace(name_end);
+        if eq == self.buf.len() {
+            return Ok(None);
+        }
+        if self.buf[eq] != b'=' || name_end == pos {
+            return Err(ParserErrorKind::MalformedXml);
+        }
+        let quote = self.skip_whitespace(eq + 1);
+        let delim = match self.buf.get(quote) {
+            Some(&c) if c == b'"' || c == b'\'' => c,
+            Some(_) => return Err(ParserErrorKind::UndelimitedAttribute),
+            None => return Ok(None),
+        };
+        match self.find(quote + 1, &[delim]) {
+            Some(end) => {
+                let name = self.str(pos, name_end)?;
+                let value = self.str(quote + 1, end)?;
+                Ok(Some((Token::Attribute(name, value), end + 1)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn scan_token(&self) -> Scan<'a> {
+        if self.in_tag {
+            return self.scan_tag();
+        }
+        if self.buf[self.pos] == b'<' {
+            return self.scan_markup();
+        }
+        match self.find(self.pos, b"<") {
+            Some(end) => Ok(Some((Token::Text(self.str(self.pos, end)?), end))),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = Result<Token<'a>, TokenError>;
+
+    fn next(&mut self) -> Option<Result<Token<'a>, TokenError>> {
+        if self.has_error || self.pos >= self.buf.len() {
+            return None;
+        }
+        match self.scan_token() {
+            Ok(Some((token, end))) => {
+                self.pos = end;
+                self.in_tag = match token {
+                    Token::ElementStart(_) => true,
+                    Token::StartTagEnd(_) => false,
+                    _ => self.in_tag,
+                };
+                Some(Ok(token))
+            }
+            Ok(None) => None,
+            Err(kind) => {
+                self.has_error = true;
+                Some(Err(TokenError {
+                    offset: self.pos,
+                    kind,
+                }))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Token, Tokenizer};
+    use crate::parser::ParserErrorKind;
+
+    #[test]
+    fn test_tokens() {
+        let doc = b"<?xml version='1.0'?><!DOCTYPE a [<!ENTITY e '>'>]><a:b x = \"1\" y='&lt;'>\
+                    t&amp;<!--c--><![CDATA[<d>]]><e/></a:b >";
+        let tokens: Vec<_> = Tokenizer::new(doc).map(Result::unwrap).collect();
+        assert_eq!(
+            tokens,
+            [
+                Token::PI("xml version='1.0'"),
+                Token::Doctype("DOCTYPE a [<!ENTITY e '>'>]"),
+                Token::ElementStart("a:b"),
+                Token::Attribute("x", "1"),
+                Token::Attribute("y", "&lt;"),
+                Token::StartTagEnd(false),
+                Token::Text("t&amp;"),
+                Token::Comment("c"),
+                Token::CDATA("<d>"),
+                Token::ElementStart("e"),
+                Token::StartTagEnd(true),
+                Token::ElementEnd("a:b"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_chunks() {
+        let doc = b"<a x='1'><!--comment--><b>text</b></a>";
+        for split in 0..doc.len() {
+            let mut buf = [0u8; 64];
+            buf[..split].copy_from_slice(&doc[..split]);
+            let mut tokens = Tokenizer::new(&buf[..split]);
+            let mut count = tokens.by_ref().map(Result::unwrap).count();
+            let consumed = tokens.position();
+            let in_start_tag = tokens.in_start_tag();
+
+            let len = split - consumed + doc.len() - split;
+            buf.copy_within(consumed..split, 0);
+            buf[split - consumed..len].copy_from_slice(&doc[split..]);
+            count += Tokenizer::resume(&buf[..len], in_start_tag)
+                .map(Result::unwrap)
+                .count();
+            assert_eq!(count, 9, "split at {}", split);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        for (doc, kind) in [
+            (&b"<a x>"[..], ParserErrorKind::MalformedXml),
+            (b"<a x=1>", ParserErrorKind::UndelimitedAttribute),
+            (b"<a / >", ParserErrorKind::ExpectedTagClose),
+            (b"<![CDAT[x]]>", ParserErrorKind::InvalidCdataStart),
+            (b"<!-x-->", ParserErrorKind::InvalidCommentStart),
+            (b"<a>\xff<", ParserErrorKind::MalformedXml),
+        ] {
+            let err = Tokenizer::new(doc).find_map(Result::err).unwrap();
+            assert_eq!(err.kind, kind);
+        }
+    }
+}
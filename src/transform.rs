@@ -0,0 +1,374 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::path::ElementPath;
+use crate::split::ns_declarations;
+use crate::writer::{EventWriter, StreamError};
+use crate::{ns, AttrMap, EndTag, Event, Name, Parser, QName, StartTag, Xml};
+use std::io::{Read, Write};
+use std::mem;
+
+#[derive(Clone, PartialEq, Debug)]
+/// What to do with an element selected by a `Transformer` rule
+pub enum Template {
+    /// Change the local name of the element, keeping its namespace
+    Rename(String),
+    /// Enclose the element in a new element with the given local name, in the same namespace.
+    /// Fragments inserted before or after the element are placed outside of the wrapper.
+    Wrap(String),
+    /// Remove the element and its content
+    Drop,
+    /// Remove the element's tags, keeping its content. The element's namespace declarations
+    /// are repeated on its child elements.
+    Unwrap,
+    /// Set an attribute without a namespace to the given value
+    SetAttribute(String, String),
+    /// Remove an attribute without a namespace
+    RemoveAttribute(String),
+    /// Insert a fragment before the element's start tag
+    InsertBefore(Xml),
+    /// Insert a fragment after the element's end tag
+    InsertAfter(Xml),
+    /// Insert a fragment at the start of the element's content
+    Prepend(Xml),
+    /// Insert a fragment at the end of the element's content
+    Append(Xml),
+    /// Replace the element and its content with a fragment
+    Replace(Xml),
+}
+
+// Returns the events describing a fragment
+fn fragment_events(fragment: &Xml) -> Vec<Event> {
+    match *fragment {
+        Xml::ElementNode(ref elem) => elem.to_events(),
+        Xml::CharacterNode(ref text) => vec![Event::Characters(text.clone())],
+        Xml::CDATANode(ref text) => vec![Event::CDATA(text.clone())],
+        Xml::CommentNode(ref text) => vec![Event::Comment(text.clone())],
+        Xml::PINode(ref text) => vec![Event::PI(text.clone())],
+    }
+}
+
+fn write_fragments<W: Write>(
+    writer: &mut EventWriter<W>,
+    fragments: &[&Xml],
+) -> Result<(), StreamError> {
+    for fragment in fragments {
+        for event in fragment_events(fragment) {
+            writer.write(&event)?;
+        }
+    }
+    Ok(())
+}
+
+fn end_tag(tag: &StartTag) -> EndTag {
+    EndTag {
+        name: tag.name.clone(),
+        prefix: tag.prefix.clone(),
+    }
+}
+
+// The output still to be written when an element ends
+#[derive(Default)]
+struct Pending<'a> {
+    end: Option<EndTag>,
+    append: Vec<&'a Xml>,
+    wrappers: Vec<EndTag>,
+    after: Vec<&'a Xml>,
+    // Namespace declarations of an unwrapped element and its unwrapped ancestors, made on
+    // its child elements instead
+    declarations: AttrMap<QName, String>,
+}
+
+/// A streaming transform rewriting selected elements according to templates
+///
+/// Rules select elements by their path; a final `@name` step restricts a rule to elements
+/// carrying that attribute. All rules matching an element are applied in the order they were
+/// added, and paths are always matched against the names in the input. Everything not
+/// selected by one of the rules is copied to the output unchanged.
+///
+/// ~~~
+/// use xml::{Template, Transformer, Xml};
+///
+/// let input = "<catalog><item id='1' internal='x'><name>Pen</name></item>\
+///              <draft><name>Ink</name></draft></catalog>";
+/// let mut transformer = Transformer::new();
+/// transformer
+///     .add_rule("item", Template::Rename("product".to_owned()))
+///     .add_rule("item", Template::RemoveAttribute("internal".to_owned()))
+///     .add_rule("item", Template::Wrap("entry".to_owned()))
+///     .add_rule("draft", Template::Replace(Xml::CommentNode(" draft ".to_owned())));
+///
+/// let out = transformer.transform(input.as_bytes(), Vec::new()).unwrap();
+/// assert_eq!(
+///     String::from_utf8(out).unwrap(),
+///     "<catalog><entry><product id='1'><name>Pen</name></product></entry>\
+///      <!-- draft --></catalog>",
+/// );
+/// ~~~
+#[derive(Clone, Debug, Default)]
+pub struct Transformer {
+    rules: Vec<(ElementPath, Template)>,
+}
+
+impl Transformer {
+    /// Returns a new `Transformer` without any rules
+    pub fn new() -> Transformer {
+        Transformer { rules: Vec::new() }
+    }
+
+    /// Adds a rule applying `template` to the elements selected by `path`.
+    /// Returns a mutable reference to self.
+    pub fn add_rule<P: Into<ElementPath>>(
+        &mut self,
+        path: P,
+        template: Template,
+    ) -> &mut Transformer {
+        self.rules.push((path.into(), template));
+        self
+    }
+
+    /// Copies the document read from `reader` to `writer`, applying all rules.
+    /// Returns the writer once the input is exhausted.
+    pub fn transform<R, W>(&self, reader: R, writer: W) -> Result<W, StreamError>
+    where
+        R: Read,
+        W: Write,
+    {
+        let mut writer = EventWriter::new(writer);
+        let mut names: Vec<Name> = Vec::new();
+        let mut pending: Vec<Pending> = Vec::new();
+        let mut dropped_depth: Option<usize> = None;
+
+        for event in Parser::new(reader) {
+            let event = event?;
+            match event {
+                Event::ElementStart(mut tag) => {
+                    names.push(tag.name.name.clone());
+                    if dropped_depth.is_some() {
+                        continue;
+                    }
+                    let mut state = Pending::default();
+                    let (mut before, mut prepend, mut wrappers) =
+                        (Vec::new(), Vec::new(), Vec::new());
+                    let (mut dropped, mut unwrap, mut replacement) = (false, false, None);
+                    let templates: Vec<&Template> = self
+                        .rules
+                        .iter()
+                        .filter(|(path, _)| {
+                            path.matches(&names)
+                                && path.attribute().is_none_or(|attr| {
+                                    tag.attributes
+                                        .keys()
+                                        .any(|QName { name, ns }| ns.is_none() && name == attr)
+                                })
+                        })
+                        .map(|(_, template)| template)
+                        .collect();
+                    for template in templates {
+                        match *template {
+                            Template::Rename(ref name) => tag.name.name = name.clone().into(),
+                            Template::Wrap(ref name) => wrappers.push(name),
+                            Template::Drop => dropped = true,
+                            Template::Unwrap => unwrap = true,
+                            Template::SetAttribute(ref name, ref value) => {
+                                tag.attributes
+                                    .insert(QName::new(name.clone(), None), value.clone());
+                            }
+                            Template::RemoveAttribute(ref attr) => tag
+                                .attributes
+                                .retain(|QName { name, ns }, _| ns.is_some() || name != attr),
+                            Template::InsertBefore(ref fragment) => before.push(fragment),
+                            Template::InsertAfter(ref fragment) => state.after.push(fragment),
+                            Template::Prepend(ref fragment) => prepend.push(fragment),
+                            Template::Append(ref fragment) => state.append.push(fragment),
+                            Template::Replace(ref fragment) => {
+                                dropped = true;
+                                replacement = Some(fragment);
+                            }
+                        }
+                    }
+
+                    write_fragments(&mut writer, &before)?;
+                    // The outermost element written carries the declarations of unwrapped
+                    // ancestors
+                    let mut inherited = pending
+                        .last()
+                        .map(|parent| parent.declarations.clone())
+                        .unwrap_or_default();
+                    // Wrappers added first end up outermost, declaring the prefix of their
+                    // name if the element does
+                    let prefix_declaration = match tag.prefix {
+                        Some(ref prefix) => QName::new(prefix.clone(), Some(ns::XMLNS.to_owned())),
+                        None => QName::new("xmlns".to_owned(), None),
+                    };
+                    for name in wrappers {
+                        let mut attributes = mem::take(&mut inherited);
+                        if state.wrappers.is_empty() {
+                            if let Some(ns) = tag.attributes.get(&prefix_declaration) {
+                                attributes.insert(prefix_declaration.clone(), ns.clone());
+                            }
+                        }
+                        let wrapper = StartTag {
+                            name: QName::new(name.clone(), tag.name.ns.clone()),
+                            prefix: tag.prefix.clone(),
+                            attributes,
+                        };
+                        writer.write(&Event::ElementStart(wrapper.clone()))?;
+                        state.wrappers.push(end_tag(&wrapper));
+                    }
+                    if dropped {
+                        dropped_depth = Some(names.len());
+                        write_fragments(&mut writer, replacement.as_slice())?;
+                        state.append.clear();
+                    } else {
+                        if unwrap {
+                            inherited.extend(ns_declarations(&tag));
+                            state.declarations = inherited;
+                        } else {
+                            for (key, value) in inherited {
+                                tag.attributes.entry(key).or_insert(value);
+                            }
+                            state.end = Some(end_tag(&tag));
+                            writer.write(&Event::ElementStart(tag))?;
+                        }
+                        write_fragments(&mut writer, &prepend)?;
+                    }
+                    pending.push(state);
+                }
+                Event::ElementEnd(_) => {
+                    match dropped_depth {
+                        Some(depth) if depth == names.len() => dropped_depth = None,
+                        Some(_) => {
+                            names.pop();
+                            continue;
+                        }
+                        None => (),
+                    }
+                    names.pop();
+                    let state = pending.pop().unwrap_or_default();
+                    write_fragments(&mut writer, &state.append)?;
+                    // Written from the start tag, so renamed elements are closed correctly
+                    if let Some(tag) = state.end {
+                        writer.write(&Event::ElementEnd(tag))?;
+                    }
+                    for wrapper in state.wrappers.into_iter().rev() {
+                        writer.write(&Event::ElementEnd(wrapper))?;
+                    }
+                    write_fragments(&mut writer, &state.after)?;
+                }
+                _ if dropped_depth.is_some() => (),
+                event => writer.write(&event)?,
+            }
+        }
+        Ok(writer.into_inner()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Template, Transformer};
+    use crate::{Element, Xml};
+
+    fn transform(transformer: &Transformer, input: &str) -> String {
+        let out = transformer.transform(input.as_bytes(), Vec::new()).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_structure() {
+        let mut transformer = Transformer::new();
+        transformer
+            .add_rule("b", Template::Unwrap)
+            .add_rule("c", Template::Drop)
+            .add_rule("/a/d", Template::Wrap("w1".to_owned()))
+            .add_rule("d", Template::Wrap("w2".to_owned()))
+            .add_rule("d", Template::Rename("e".to_owned()));
+        assert_eq!(
+            transform(
+                &transformer,
+                "<a><b>x<c>y<d/></c></b><d>z</d><b><d/></b></a>"
+            ),
+            "<a>x<w1><w2><e>z</e></w2></w1><w2><e/></w2></a>",
+        );
+    }
+
+    #[test]
+    fn test_fragments() {
+        let mut note = Element::new("note".to_owned(), None, vec![]);
+        note.text("n".to_owned());
+        let mut transformer = Transformer::new();
+        transformer
+            .add_rule(
+                "b",
+                Template::InsertBefore(Xml::CommentNode("1".to_owned())),
+            )
+            .add_rule("b", Template::Prepend(Xml::CharacterNode("2".to_owned())))
+            .add_rule("b", Template::Append(Xml::ElementNode(note)))
+            .add_rule("b", Template::InsertAfter(Xml::PINode("pi".to_owned())))
+            .add_rule("c", Template::Replace(Xml::CDATANode("<c>".to_owned())))
+            .add_rule(
+                "c",
+                Template::InsertAfter(Xml::CharacterNode("!".to_owned())),
+            )
+            .add_rule("c", Template::Wrap("w".to_owned()));
+        assert_eq!(
+            transform(&transformer, "<a><b>x</b><c>y</c></a>"),
+            "<a><!--1--><b>2x<note>n</note></b><?pi?><w><![CDATA[<c>]]></w>!</a>",
+        );
+    }
+
+    #[test]
+    fn test_attributes() {
+        let mut transformer = Transformer::new();
+        transformer
+            .add_rule("b/@old", Template::RemoveAttribute("old".to_owned()))
+            .add_rule(
+                "b/@old",
+                Template::SetAttribute("new".to_owned(), "1".to_owned()),
+            )
+            .add_rule("c", Template::Rename("d".to_owned()));
+        assert_eq!(
+            transform(
+                &transformer,
+                "<a xmlns:p='urn:p'><b old='x'/><b/><p:c>t</p:c></a>"
+            ),
+            "<a xmlns:p='urn:p'><b new='1'/><b/><p:d>t</p:d></a>",
+        );
+    }
+
+    #[test]
+    fn test_namespaces() {
+        let mut transformer = Transformer::new();
+        transformer
+            .add_rule("b", Template::Unwrap)
+            .add_rule("c", Template::Unwrap)
+            .add_rule("e", Template::Wrap("w".to_owned()));
+        assert_eq!(
+            transform(&transformer, "<r><b xmlns:p='urn:p'>t<p:d/></b></r>"),
+            "<r>t<p:d xmlns:p='urn:p'/></r>",
+        );
+        assert_eq!(
+            transform(&transformer, "<r><b xmlns='urn:d'><d/></b></r>"),
+            "<r><d xmlns='urn:d'/></r>",
+        );
+        assert_eq!(
+            transform(&transformer, "<p:e xmlns:p='urn:p'/>"),
+            "<p:w xmlns:p='urn:p'><p:e xmlns:p='urn:p'/></p:w>",
+        );
+        // Declarations pass through nested unwrapped elements, the inner ones taking
+        // precedence, and are made on wrappers
+        assert_eq!(
+            transform(
+                &transformer,
+                "<r><b xmlns:p='urn:p'><c xmlns:p='urn:q'><p:e/></c><p:d/></b></r>"
+            ),
+            "<r><p:w xmlns:p='urn:q'><p:e/></p:w><p:d xmlns:p='urn:p'/></r>",
+        );
+    }
+}
@@ -0,0 +1,925 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::names;
+use crate::ns;
+use crate::parser::ParserError;
+use crate::{escape, AttrMap, AttributeChunk, EndTag, Event, QName, StartTag};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+
+#[derive(Debug)]
+/// Errors encountered while copying parsed `Event`s to a writer
+pub enum StreamError {
+    /// Errors encountered by the `Parser`
+    Parser(ParserError),
+    /// Errors encountered while writing output
+    Io(io::Error),
+}
+
+impl Error for StreamError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            StreamError::Parser(ref err) => Some(err),
+            StreamError::Io(ref err) => Some(err),
+        }
+    }
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StreamError::Parser(ref err) => err.fmt(f),
+            StreamError::Io(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl From<ParserError> for StreamError {
+    fn from(err: ParserError) -> StreamError {
+        StreamError::Parser(err)
+    }
+}
+
+impl From<io::Error> for StreamError {
+    fn from(err: io::Error) -> StreamError {
+        StreamError::Io(err)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+/// How elements without content are written
+pub enum EmptyElementStyle {
+    /// Use the empty-element form `<a/>` for all elements
+    #[default]
+    SelfClosing,
+    /// Use `<br/>` for HTML void elements and `<p></p>` for all other elements
+    Xhtml,
+    /// Use `<br>` for HTML void elements and `<p></p>` for all other elements
+    Html,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+/// The line break written by `EventWriter` and `Element::to_string_with()`
+pub enum LineEnding {
+    /// `\n`, as used on Unix
+    #[default]
+    Lf,
+    /// `\r\n`, as used on Windows and by many network protocols
+    CrLf,
+}
+
+// Elements which never have content in HTML
+const HTML_VOID_ELEMENTS: [&str; 15] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "keygen", "link", "meta", "param",
+    "source", "track", "wbr",
+];
+
+// Attributes whose presence alone means `true` in HTML
+const HTML_BOOLEAN_ATTRIBUTES: [&str; 25] = [
+    "allowfullscreen",
+    "async",
+    "autofocus",
+    "autoplay",
+    "checked",
+    "controls",
+    "default",
+    "defer",
+    "disabled",
+    "formnovalidate",
+    "hidden",
+    "inert",
+    "ismap",
+    "itemscope",
+    "loop",
+    "multiple",
+    "muted",
+    "nomodule",
+    "novalidate",
+    "open",
+    "playsinline",
+    "readonly",
+    "required",
+    "reversed",
+    "selected",
+];
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+/// Options controlling how XML is serialized, by `EventWriter` and `Element::to_string_with()`
+pub struct WriterConfig {
+    /// Sort namespace declarations and attributes canonically and drop whitespace-only text,
+    /// so logically identical documents serialize to identical bytes
+    pub deterministic: bool,
+    /// Indent nested elements using this string, placing each on its own line.
+    /// Elements containing text are written unchanged. Not supported by `EventWriter`.
+    pub indent: Option<String>,
+    /// When indenting, place each attribute on its own line, one level deeper than its
+    /// element, if the start tag would otherwise be wider than this many characters
+    pub wrap_attributes: Option<usize>,
+    /// Names of elements whose content is always written verbatim, even when indenting or
+    /// dropping whitespace. This also applies to elements with `xml:space='preserve'`.
+    pub preserve_whitespace: Vec<String>,
+    /// Names of elements whose text and CDATA sections are written without escaping, as HTML
+    /// requires for `script` and `style`. Their text must not contain the element's end tag.
+    pub raw_text_elements: Vec<String>,
+    /// Write an XML declaration before the root element
+    pub xml_declaration: bool,
+    /// A document type declaration, e.g. `<!DOCTYPE html>`, written before the root element
+    pub doctype: Option<String>,
+    /// `(prefix, namespace)` bindings declared on the root element unless already declared
+    pub root_prefixes: Vec<(String, String)>,
+    /// Omit namespace declarations binding a prefix, or the default namespace, to the
+    /// namespace it is already bound to. Not supported by `EventWriter`.
+    pub minimize_namespaces: bool,
+    /// How elements without content are written
+    pub empty_elements: EmptyElementStyle,
+    /// Write HTML boolean attributes such as `checked='checked'` as just their name.
+    /// Only has an effect with `EmptyElementStyle::Html`, as the result is not XML.
+    pub minimize_boolean_attributes: bool,
+    /// Write characters outside of ASCII in text and attribute values as numeric character
+    /// references, and CDATA sections containing them as text. Names, comments and
+    /// processing instructions cannot contain references and are written unchanged.
+    pub ascii_only: bool,
+    /// The line break written after the prolog, between indented elements and between
+    /// wrapped attributes
+    pub line_ending: LineEnding,
+    /// Also write the line breaks in text, CDATA sections, comments and processing
+    /// instructions as `line_ending`. Otherwise they are written unchanged.
+    pub normalize_line_endings: bool,
+}
+
+impl WriterConfig {
+    /// Returns a `WriterConfig` with all options at their defaults
+    pub fn new() -> WriterConfig {
+        WriterConfig::default()
+    }
+
+    /// Returns a profile suited for SVG documents.
+    ///
+    /// Text in `<text>`, `<tspan>` and similar elements is preserved exactly,
+    /// attribute values are never reformatted,
+    /// and the document is written with the SVG 1.1 doctype and the `xlink` prefix declared.
+    /// Attributes are written in their stored order only with the `ordered_attrs` feature,
+    /// which keeps the order they were parsed or inserted in.
+    /// Elements are expected to be in the `ns::SVG` namespace.
+    pub fn svg() -> WriterConfig {
+        WriterConfig {
+            preserve_whitespace: [
+                "text", "tspan", "textPath", "title", "desc", "style", "script",
+            ]
+            .iter()
+            .map(|name| (*name).to_owned())
+            .collect(),
+            xml_declaration: true,
+            doctype: Some(
+                "<!DOCTYPE svg PUBLIC \"-//W3C//DTD SVG 1.1//EN\" \
+                 \"http://www.w3.org/Graphics/SVG/1.1/DTD/svg11.dtd\">"
+                    .to_owned(),
+            ),
+            root_prefixes: vec![("xlink".to_owned(), ns::XLINK.to_owned())],
+            ..WriterConfig::default()
+        }
+    }
+
+    /// Returns a profile producing HTML syntax, as understood by browsers.
+    ///
+    /// Void elements such as `<br>` are written without end tag, other elements are never
+    /// self-closed, boolean attributes are minimized, and the content of `<script>` and
+    /// `<style>` is written unescaped.
+    /// Note that the output is not necessarily well-formed XML.
+    pub fn html() -> WriterConfig {
+        WriterConfig {
+            doctype: Some("<!DOCTYPE html>".to_owned()),
+            empty_elements: EmptyElementStyle::Html,
+            minimize_boolean_attributes: true,
+            preserve_whitespace: vec!["pre".to_owned(), "textarea".to_owned()],
+            raw_text_elements: vec!["script".to_owned(), "style".to_owned()],
+            ..WriterConfig::default()
+        }
+    }
+
+    /// Returns a profile producing XHTML which is also safe to serve as HTML.
+    ///
+    /// Void elements such as `<br/>` are self-closed, all other elements are never self-closed.
+    pub fn xhtml() -> WriterConfig {
+        WriterConfig {
+            doctype: Some("<!DOCTYPE html>".to_owned()),
+            empty_elements: EmptyElementStyle::Xhtml,
+            preserve_whitespace: vec!["pre".to_owned(), "textarea".to_owned()],
+            ..WriterConfig::default()
+        }
+    }
+
+    /// Sets the `deterministic` option
+    pub fn deterministic(mut self, deterministic: bool) -> WriterConfig {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Sets the `indent` option
+    pub fn indent<S: Into<String>>(mut self, indent: S) -> WriterConfig {
+        self.indent = Some(indent.into());
+        self
+    }
+
+    /// Sets the `wrap_attributes` option
+    pub fn wrap_attributes(mut self, width: usize) -> WriterConfig {
+        self.wrap_attributes = Some(width);
+        self
+    }
+
+    /// Sets the `minimize_namespaces` option
+    pub fn minimize_namespaces(mut self, minimize_namespaces: bool) -> WriterConfig {
+        self.minimize_namespaces = minimize_namespaces;
+        self
+    }
+
+    /// Sets the `ascii_only` option
+    pub fn ascii_only(mut self, ascii_only: bool) -> WriterConfig {
+        self.ascii_only = ascii_only;
+        self
+    }
+
+    /// Sets the `line_ending` option
+    ///
+    /// ~~~
+    /// use xml::{Element, LineEnding, WriterConfig};
+    ///
+    /// let elem: Element = "<a><b>1\n2</b></a>".parse().unwrap();
+    /// let config = WriterConfig::new().indent(" ").line_ending(LineEnding::CrLf);
+    /// assert_eq!(elem.to_string_with(&config), "<a>\r\n <b>1\n2</b>\r\n</a>");
+    /// let config = config.normalize_line_endings(true);
+    /// assert_eq!(elem.to_string_with(&config), "<a>\r\n <b>1\r\n2</b>\r\n</a>");
+    /// ~~~
+    pub fn line_ending(mut self, line_ending: LineEnding) -> WriterConfig {
+        self.line_ending = line_ending;
+        self
+    }
+
+    /// Sets the `normalize_line_endings` option
+    pub fn normalize_line_endings(mut self, normalize: bool) -> WriterConfig {
+        self.normalize_line_endings = normalize;
+        self
+    }
+
+    /// Sets the `xml_declaration` option
+    pub fn xml_declaration(mut self, xml_declaration: bool) -> WriterConfig {
+        self.xml_declaration = xml_declaration;
+        self
+    }
+
+    /// Sets the `doctype` option
+    pub fn doctype<S: Into<String>>(mut self, doctype: S) -> WriterConfig {
+        self.doctype = Some(doctype.into());
+        self
+    }
+
+    // Writes the XML declaration and doctype, if enabled
+    pub(crate) fn write_prolog(&self, f: &mut dyn fmt::Write) -> fmt::Result {
+        if self.xml_declaration {
+            write!(
+                f,
+                "<?xml version='1.0' encoding='UTF-8'?>{}",
+                self.newline()
+            )?;
+        }
+        if let Some(ref doctype) = self.doctype {
+            write!(f, "{}{}", doctype, self.newline())?;
+        }
+        Ok(())
+    }
+
+    // Returns the line break to write
+    pub(crate) fn newline(&self) -> &'static str {
+        match self.line_ending {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    // Replaces the line breaks in content with `line_ending`, if enabled
+    pub(crate) fn line_breaks<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if !self.normalize_line_endings || !text.contains(['\r', '\n']) {
+            return Cow::Borrowed(text);
+        }
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        match self.line_ending {
+            LineEnding::Lf => Cow::Owned(text),
+            LineEnding::CrLf => Cow::Owned(text.replace('\n', "\r\n")),
+        }
+    }
+
+    // Escapes character data or an attribute value
+    pub(crate) fn escape(&self, text: &str) -> String {
+        let escaped = escape(text);
+        if !self.ascii_only || escaped.is_ascii() {
+            return escaped;
+        }
+        let mut result = String::with_capacity(escaped.len() + 8);
+        for c in escaped.chars() {
+            match c {
+                c if c.is_ascii() => result.push(c),
+                c => result.push_str(&format!("&#x{:X};", u32::from(c))),
+            }
+        }
+        result
+    }
+
+    // Checks whether text in an element with the given name is written unescaped
+    pub(crate) fn writes_raw_text(&self, name: &str) -> bool {
+        self.raw_text_elements.iter().any(|x| x == name)
+    }
+
+    // Writes a CDATA section, or escaped text if it cannot be written as is
+    pub(crate) fn write_cdata(&self, text: &str, f: &mut dyn fmt::Write) -> fmt::Result {
+        let text = self.line_breaks(text);
+        if self.ascii_only && !text.is_ascii() {
+            write!(f, "{}", self.escape(&text))
+        } else {
+            write!(f, "<![CDATA[{}]]>", text)
+        }
+    }
+
+    // Returns the text closing an element without content, given its qualified name
+    pub(crate) fn empty_element_end(&self, qname: &str, ns: Option<&str>, name: &str) -> String {
+        let html = ns.is_none_or(|ns| ns == ns::XHTML);
+        let void = html && HTML_VOID_ELEMENTS.contains(&name);
+        match self.empty_elements {
+            EmptyElementStyle::SelfClosing => "/>".to_owned(),
+            EmptyElementStyle::Xhtml if void => "/>".to_owned(),
+            EmptyElementStyle::Html if void => ">".to_owned(),
+            _ => format!("></{}>", qname),
+        }
+    }
+
+    // Checks whether an attribute is written as just its name
+    pub(crate) fn minimizes(&self, name: &str, ns: Option<&String>, value: &str) -> bool {
+        self.minimize_boolean_attributes
+            && self.empty_elements == EmptyElementStyle::Html
+            && ns.is_none()
+            && HTML_BOOLEAN_ATTRIBUTES.contains(&name)
+            && (value.is_empty() || value.eq_ignore_ascii_case(name))
+    }
+
+    // Checks whether whitespace in an element with the given name and attributes is significant
+    pub(crate) fn preserves_whitespace(
+        &self,
+        name: &str,
+        attributes: &AttrMap<QName, String>,
+    ) -> bool {
+        self.preserve_whitespace.iter().any(|x| x == name)
+            || attributes
+                .get(&QName::new("space".to_owned(), Some(ns::XML.to_owned())))
+                .is_some_and(|x| x == "preserve")
+    }
+}
+
+// Canonical attribute order: the default namespace declaration, prefix declarations sorted
+// by prefix, then all other attributes sorted by namespace and name
+pub(crate) fn canonical_attribute_order(a: &QName, b: &QName) -> Ordering {
+    fn class(key: &QName) -> u8 {
+        match key.ns {
+            None if key.name == "xmlns" => 0,
+            Some(ref ns) if ns == ns::XMLNS => 1,
+            _ => 2,
+        }
+    }
+    class(a)
+        .cmp(&class(b))
+        .then_with(|| a.ns.cmp(&b.ns))
+        .then_with(|| a.name.cmp(&b.name))
+}
+
+/// A writer serializing `Event`s, as produced by `Parser`, back into XML text
+///
+/// Namespace prefixes of attributes are resolved using the `xmlns` declarations
+/// found on previously written start tags.
+/// An element without content is written using the empty-element form.
+///
+/// ~~~
+/// use xml::{EventWriter, Parser};
+///
+/// let mut out = Vec::new();
+/// {
+///     let mut writer = EventWriter::new(&mut out);
+///     for event in Parser::new("<a x='1'><b/></a>".as_bytes()) {
+///         writer.write(&event.unwrap()).unwrap();
+///     }
+///     writer.flush().unwrap();
+/// }
+/// assert_eq!(String::from_utf8(out).unwrap(), "<a x='1'><b/></a>");
+/// ~~~
+pub struct EventWriter<W>
+where
+    W: Write,
+{
+    writer: W,
+    config: WriterConfig,
+    prefixes: Vec<HashMap<String, String>>,
+    open_tag: bool,
+    // An attribute written by `AttributeChunk` events awaits its remaining value,
+    // which is collected if the attribute declares a prefix
+    open_attribute: Option<String>,
+    // A start tag written by `write_source()` awaits the '>' of its empty-element form
+    open_source: bool,
+    started: bool,
+    preserve: Vec<bool>,
+    raw_text: Vec<bool>,
+}
+
+impl<W> EventWriter<W>
+where
+    W: Write,
+{
+    /// Returns a new `EventWriter` writing to `writer`
+    pub fn new(writer: W) -> EventWriter<W> {
+        EventWriter::new_with_config(writer, WriterConfig::default())
+    }
+
+    /// Returns a new `EventWriter` writing to `writer` using the output options in `config`
+    pub fn new_with_config(writer: W, config: WriterConfig) -> EventWriter<W> {
+        let mut prefixes = HashMap::with_capacity(2);
+        prefixes.insert(ns::XML.to_owned(), "xml".to_owned());
+        prefixes.insert(ns::XMLNS.to_owned(), "xmlns".to_owned());
+        EventWriter {
+            writer,
+            config,
+            prefixes: vec![prefixes],
+            open_tag: false,
+            open_attribute: None,
+            open_source: false,
+            started: false,
+            preserve: Vec::new(),
+            raw_text: Vec::new(),
+        }
+    }
+
+    /// Returns a reference to the underlying writer
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Finishes any pending start tag and returns the underlying writer
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.close_start_tag()?;
+        Ok(self.writer)
+    }
+
+    /// Finishes any pending start tag and flushes the underlying writer
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.close_start_tag()?;
+        self.writer.flush()
+    }
+
+    /// Writes a single `Event`
+    pub fn write(&mut self, event: &Event) -> io::Result<()> {
+        match *event {
+            Event::ElementStart(ref tag) => self.write_start(tag),
+            Event::ElementEnd(ref tag) => self.write_end(tag),
+            Event::Characters(ref data) => {
+                let preserve = self.preserve.last().cloned().unwrap_or(false);
+                if self.config.deterministic && !preserve && data.chars().all(names::is_space) {
+                    return Ok(());
+                }
+                self.close_start_tag()?;
+                let text = self.config.line_breaks(data);
+                if self.in_raw_text() {
+                    return write!(self.writer, "{}", text);
+                }
+                write!(self.writer, "{}", self.config.escape(&text))
+            }
+            Event::CDATA(ref data) => {
+                self.close_start_tag()?;
+                if self.in_raw_text() {
+                    return write!(self.writer, "{}", self.config.line_breaks(data));
+                }
+                let mut text = String::new();
+                self.config
+                    .write_cdata(data, &mut text)
+                    .expect("Writing to a String cannot fail");
+                self.writer.write_all(text.as_bytes())
+            }
+            Event::Comment(ref data) => {
+                self.close_start_tag()?;
+                write!(self.writer, "<!--{}-->", self.config.line_breaks(data))
+            }
+            Event::PI(ref data) => {
+                self.close_start_tag()?;
+                write!(self.writer, "<?{}?>", self.config.line_breaks(data))
+            }
+            Event::AttributeChunk(ref chunk) => self.write_attribute_chunk(chunk),
+            Event::XmlDecl(ref decl) => write!(self.writer, "<?{}?>", decl),
+            Event::Doctype(ref doctype) => write!(self.writer, "{}", doctype),
+        }
+    }
+
+    /// Writes `source`, the verbatim text of `event` as returned by `Parser::source()` in
+    /// fidelity mode, instead of serializing `event`.
+    ///
+    /// Unmodified events can be copied byte for byte this way, while modified events are
+    /// written using `write()`.
+    pub fn write_source(&mut self, event: &Event, source: &[u8]) -> io::Result<()> {
+        if let Event::ElementEnd(_) = *event {
+            self.open_source = false;
+        }
+        self.close_start_tag()?;
+        match *event {
+            Event::ElementStart(ref tag) => {
+                self.started = true;
+                self.enter(tag);
+                self.open_source = source.ends_with(b"/");
+            }
+            Event::ElementEnd(_) => {
+                self.prefixes.pop();
+                self.preserve.pop();
+                self.raw_text.pop();
+            }
+            _ => (),
+        }
+        self.writer.write_all(source)
+    }
+
+    /// Writes input following the last event verbatim, as returned by `Parser::source()`
+    /// once the parser reached the end of input.
+    pub fn write_trailing(&mut self, source: &[u8]) -> io::Result<()> {
+        self.close_start_tag()?;
+        self.writer.write_all(source)
+    }
+
+    // Checks whether text is written unescaped in the current element
+    fn in_raw_text(&self) -> bool {
+        self.raw_text.last().cloned().unwrap_or(false)
+    }
+
+    // Enters the scope of `tag`, returning the prefixes it declares
+    fn enter(&mut self, tag: &StartTag) -> &mut HashMap<String, String> {
+        let preserve = self.preserve.last().cloned().unwrap_or(false)
+            || self
+                .config
+                .preserves_whitespace(&tag.name.name, &tag.attributes);
+        self.preserve.push(preserve);
+        self.raw_text
+            .push(self.config.writes_raw_text(&tag.name.name));
+
+        self.prefixes.push(HashMap::new());
+        for (QName { name, ns }, value) in &tag.attributes {
+            if ns.as_ref().is_some_and(|x| x == ns::XMLNS) {
+                self.declare(name, value.clone());
+            }
+        }
+        self.prefixes.last_mut().expect("No prefix scope")
+    }
+
+    // Records the declaration of `prefix` in the innermost scope, hiding the namespaces bound
+    // to `prefix` in outer scopes. An empty `ns` undeclares the prefix, as in XML 1.1.
+    fn declare(&mut self, prefix: &str, ns: String) {
+        let hidden: Vec<String> = self
+            .prefixes
+            .iter()
+            .flat_map(|scope| scope.iter())
+            .filter(|&(_, bound)| bound == prefix)
+            .map(|(ns, _)| ns.clone())
+            .collect();
+        let scope = self.prefixes.last_mut().expect("No prefix scope");
+        for ns in hidden {
+            // The empty prefix marks a namespace without prefix in this scope
+            scope.entry(ns).or_default();
+        }
+        if !ns.is_empty() {
+            scope.insert(ns, prefix.to_owned());
+        }
+    }
+
+    fn close_start_tag(&mut self) -> io::Result<()> {
+        if self.open_source {
+            self.open_source = false;
+            write!(self.writer, ">")?;
+        }
+        if self.open_tag {
+            self.open_tag = false;
+            write!(self.writer, ">")?;
+        }
+        Ok(())
+    }
+
+    fn prefix_for(&self, ns: &str) -> io::Result<&str> {
+        self.prefixes
+            .iter()
+            .rev()
+            .find_map(|prefixes| prefixes.get(ns))
+            .filter(|x| !x.is_empty())
+            .map(|x| &x[..])
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No namespace prefix bound"))
+    }
+
+    fn write_start(&mut self, tag: &StartTag) -> io::Result<()> {
+        self.close_start_tag()?;
+        let root = !self.started;
+        if root {
+            self.started = true;
+            let mut prolog = String::new();
+            self.config
+                .write_prolog(&mut prolog)
+                .expect("Writing to a String cannot fail");
+            self.writer.write_all(prolog.as_bytes())?;
+        }
+        let root_prefixes = if root {
+            self.config.root_prefixes.clone()
+        } else {
+            Vec::new()
+        };
+        let scope = self.enter(tag);
+        let mut write_root_prefixes = Vec::new();
+        for (prefix, ns) in root_prefixes {
+            if !scope.contains_key(&ns) {
+                scope.insert(ns.clone(), prefix.clone());
+                write_root_prefixes.push((prefix, ns));
+            }
+        }
+
+        match tag.prefix {
+            Some(ref prefix) => write!(self.writer, "<{}:{}", prefix, tag.name.name)?,
+            None => write!(self.writer, "<{}", tag.name.name)?,
+        }
+        for (prefix, ns) in write_root_prefixes {
+            write!(
+                self.writer,
+                " xmlns:{}='{}'",
+                prefix,
+                self.config.escape(&ns)
+            )?;
+        }
+        let mut attributes: Vec<_> = tag.attributes.iter().collect();
+        if self.config.deterministic {
+            attributes.sort_by(|(a, _), (b, _)| canonical_attribute_order(a, b));
+        }
+        for (QName { name, ns }, value) in attributes {
+            match *ns {
+                Some(ref ns) => {
+                    let prefix = self.prefix_for(ns)?.to_owned();
+                    write!(
+                        self.writer,
+                        " {}:{}='{}'",
+                        prefix,
+                        name,
+                        self.config.escape(value)
+                    )?
+                }
+                None if self.config.minimizes(name, None, value) => {
+                    write!(self.writer, " {}", name)?
+                }
+                None => write!(self.writer, " {}='{}'", name, self.config.escape(value))?,
+            }
+        }
+        self.open_tag = true;
+        Ok(())
+    }
+
+    fn write_attribute_chunk(&mut self, chunk: &AttributeChunk) -> io::Result<()> {
+        if !self.open_tag {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Attribute chunk outside of a start tag",
+            ));
+        }
+        let mut value = match self.open_attribute.take() {
+            Some(value) => value,
+            None => {
+                match chunk.ns {
+                    Some(ref ns) => {
+                        let prefix = self.prefix_for(ns)?.to_owned();
+                        write!(self.writer, " {}:{}='", prefix, chunk.name)?
+                    }
+                    None => write!(self.writer, " {}='", chunk.name)?,
+                }
+                String::new()
+            }
+        };
+        write!(self.writer, "{}", self.config.escape(&chunk.value))?;
+        let declares = chunk.ns.as_ref().is_some_and(|x| x == ns::XMLNS);
+        if declares {
+            value.push_str(&chunk.value);
+        }
+        if !chunk.last {
+            self.open_attribute = Some(value);
+            return Ok(());
+        }
+        if declares {
+            self.declare(&chunk.name, value);
+        }
+        write!(self.writer, "'")
+    }
+
+    fn write_end(&mut self, tag: &EndTag) -> io::Result<()> {
+        self.prefixes.pop();
+        self.preserve.pop();
+        self.raw_text.pop();
+        if self.open_source {
+            self.open_source = false;
+            return write!(self.writer, ">");
+        }
+        let qname = match tag.prefix {
+            Some(ref prefix) => format!("{}:{}", prefix, tag.name.name),
+            None => tag.name.name.to_string(),
+        };
+        if self.open_tag {
+            self.open_tag = false;
+            let end = self
+                .config
+                .empty_element_end(&qname, tag.name.ns.as_deref(), &tag.name.name);
+            return write!(self.writer, "{}", end);
+        }
+        write!(self.writer, "</{}>", qname)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EventWriter, LineEnding, WriterConfig};
+    use crate::{Element, Event, Parser, ParserConfig, QName};
+
+    fn roundtrip(input: &str) -> String {
+        let mut writer = EventWriter::new(Vec::new());
+        for event in Parser::new(input.as_bytes()) {
+            writer.write(&event.unwrap()).unwrap();
+        }
+        String::from_utf8(writer.into_inner().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let input = "<?pi x?><a>te&amp;xt<!--c--><![CDATA[<d>]]><b></b></a>";
+        assert_eq!(
+            roundtrip(input),
+            "<?pi x?><a>te&amp;xt<!--c--><![CDATA[<d>]]><b/></a>",
+        );
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let config = WriterConfig::new().deterministic(true);
+        let mut writer = EventWriter::new_with_config(Vec::new(), config);
+        for event in Parser::new("<a y='1' x='2'>\n <b/>\n<c>\u{a0}</c></a>".as_bytes()) {
+            writer.write(&event.unwrap()).unwrap();
+        }
+        let out = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(out, "<a x='2' y='1'><b/><c>\u{a0}</c></a>");
+    }
+
+    #[test]
+    fn test_ascii_only() {
+        let elem = crate::Element::build("a")
+            .attr("t", "caf\u{e9}")
+            .text("\u{1f600} & x")
+            .cdata("<\u{fc}>")
+            .cdata("<u>")
+            .comment("\u{e9}")
+            .finish();
+        let expected = "<a t='caf&#xE9;'>&#x1F600; &amp; x&lt;&#xFC;&gt;<![CDATA[<u>]]>\
+                        <!--\u{e9}--></a>";
+        let config = WriterConfig::new().ascii_only(true);
+        assert_eq!(elem.to_string_with(&config), expected);
+
+        let mut writer = EventWriter::new_with_config(Vec::new(), config);
+        for event in elem.to_events() {
+            writer.write(&event).unwrap();
+        }
+        let out = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_line_ending() {
+        let input = "<a>1\n2<![CDATA[3\n]]><!--\n--><?pi \n?></a>";
+        let write = |config: WriterConfig| {
+            let mut writer = EventWriter::new_with_config(Vec::new(), config);
+            for event in Parser::new(input.as_bytes()) {
+                writer.write(&event.unwrap()).unwrap();
+            }
+            String::from_utf8(writer.into_inner().unwrap()).unwrap()
+        };
+        let config = WriterConfig::new()
+            .xml_declaration(true)
+            .line_ending(LineEnding::CrLf);
+        let prolog = "<?xml version='1.0' encoding='UTF-8'?>\r\n";
+        assert_eq!(write(config.clone()), format!("{}{}", prolog, input));
+        assert_eq!(
+            write(config.normalize_line_endings(true)),
+            format!(
+                "{}<a>1\r\n2<![CDATA[3\r\n]]><!--\r\n--><?pi \r\n?></a>",
+                prolog
+            )
+        );
+
+        let mut elem = crate::Element::new("a".to_owned(), None, vec![]);
+        elem.text("1\r\n2\r3".to_owned());
+        let config = WriterConfig::new().normalize_line_endings(true);
+        assert_eq!(elem.to_string_with(&config), "<a>1\n2\n3</a>");
+    }
+
+    #[test]
+    fn test_svg_profile() {
+        let mut writer = EventWriter::new_with_config(Vec::new(), WriterConfig::svg());
+        let input = "<svg xmlns='http://www.w3.org/2000/svg'><text> a  b </text></svg>";
+        for event in Parser::new(input.as_bytes()) {
+            writer.write(&event.unwrap()).unwrap();
+        }
+        let out = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert!(out.starts_with("<?xml version='1.0' encoding='UTF-8'?>\n<!DOCTYPE svg PUBLIC"));
+        assert!(out.ends_with(
+            "<svg xmlns:xlink='http://www.w3.org/1999/xlink' \
+             xmlns='http://www.w3.org/2000/svg'><text> a  b </text></svg>"
+        ));
+    }
+
+    #[test]
+    fn test_html_profile() {
+        let mut writer = EventWriter::new_with_config(Vec::new(), WriterConfig::html());
+        let input = "<p><br/><script src='a.js'/><input checked=''/></p>";
+        for event in Parser::new(input.as_bytes()) {
+            writer.write(&event.unwrap()).unwrap();
+        }
+        let out = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            out,
+            "<!DOCTYPE html>\n<p><br><script src='a.js'></script><input checked></p>",
+        );
+
+        let input = "<p><script>if (a &lt; b &amp;&amp; c) {}</script>\
+                     <style><![CDATA[a > b]]></style><i>&lt;</i></p>";
+        let mut writer = EventWriter::new_with_config(Vec::new(), WriterConfig::html());
+        for event in Parser::new(input.as_bytes()) {
+            writer.write(&event.unwrap()).unwrap();
+        }
+        let expected = "<!DOCTYPE html>\n\
+                        <p><script>if (a < b && c) {}</script><style>a > b</style><i>&lt;</i></p>";
+        let out = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(out, expected);
+        let elem: Element = input.parse().unwrap();
+        assert_eq!(elem.to_string_with(&WriterConfig::html()), expected);
+        assert_eq!(
+            elem.to_string_with(&WriterConfig::xhtml()),
+            "<!DOCTYPE html>\n<p><script>if (a &lt; b &amp;&amp; c) {}</script>\
+             <style><![CDATA[a > b]]></style><i>&lt;</i></p>"
+        );
+    }
+
+    #[test]
+    fn test_write_source() {
+        let input = "<?xml version=\"1.0\"?>\n<a>\n  <b x = \"1\" />\n  <c  y=\"2\"></c>&lt;\n</a>";
+        let config = ParserConfig::new().fidelity(true);
+        let mut parser = Parser::new_with_config(input.as_bytes(), config);
+        let mut writer = EventWriter::new(Vec::new());
+        while let Some(event) = parser.next() {
+            let mut event = event.unwrap();
+            match event {
+                Event::ElementStart(ref mut tag) if tag.name.name == "c" => {
+                    tag.attributes
+                        .insert(QName::new("y".to_owned(), None), "3".to_owned());
+                    writer.write(&event).unwrap();
+                }
+                Event::ElementEnd(ref tag) if tag.name.name == "c" => writer.write(&event).unwrap(),
+                _ => writer.write_source(&event, parser.source()).unwrap(),
+            }
+        }
+        writer.write_trailing(parser.source()).unwrap();
+        let out = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            out,
+            "<?xml version=\"1.0\"?>\n<a>\n  <b x = \"1\" />\n  <c y='3'/>&lt;\n</a>",
+        );
+    }
+
+    #[test]
+    fn test_namespaced_attribute() {
+        let input = "<p:a xmlns:p='urn:p'><b p:x='1'/></p:a>";
+        assert_eq!(roundtrip(input), input);
+    }
+
+    #[test]
+    fn test_undeclared_prefix() {
+        let input = "<p:a xmlns:p='urn:p'><b xmlns:p=''/></p:a>";
+        assert_eq!(roundtrip(input), input);
+
+        let mut events: Vec<_> = Parser::new(input.as_bytes()).map(Result::unwrap).collect();
+        if let Event::ElementStart(ref mut tag) = events[1] {
+            tag.attributes.insert(
+                QName::new("x".to_owned(), Some("urn:p".to_owned())),
+                "1".to_owned(),
+            );
+        }
+        let mut writer = EventWriter::new(Vec::new());
+        writer.write(&events[0]).unwrap();
+        assert!(writer.write(&events[1]).is_err());
+    }
+}
@@ -0,0 +1,404 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A reader with the event types of the `xml-rs` crate
+//!
+//! `EventReader` reads documents with this crate's `Parser` and returns events shaped like
+//! those of `xml::reader::EventReader` in `xml-rs`, so code matching on them only needs
+//! its imports changed. Like `xml-rs`, the reader starts with `StartDocument`, ends with
+//! `EndDocument`, reports whitespace-only text as `Whitespace` and leaves namespace
+//! declarations out of the attributes. Comments are reported rather than ignored.
+//!
+//! ~~~
+//! use xml::xmlrs::{EventReader, XmlEvent};
+//!
+//! let doc = "<?xml version='1.0'?><feed xmlns='urn:f'> <title lang='en'>News</title></feed>";
+//! let mut names = Vec::new();
+//! for event in EventReader::from_str(doc) {
+//!     match event.unwrap() {
+//!         XmlEvent::StartElement { name, attributes, namespace } => {
+//!             assert_eq!(namespace.get(""), Some("urn:f"));
+//!             names.push((name.local_name, attributes.len()));
+//!         }
+//!         XmlEvent::EndDocument => break,
+//!         _ => (),
+//!     }
+//! }
+//! assert_eq!(names, [("feed".to_owned(), 0), ("title".to_owned(), 1)]);
+//! ~~~
+
+use crate::{ns, Event, Parser, ParserError, QName};
+use std::collections::BTreeMap;
+use std::io::Read;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+/// The version of XML a document declares
+pub enum XmlVersion {
+    /// XML 1.0
+    Version10,
+    /// XML 1.1
+    Version11,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+/// A qualified name, like `xml::name::OwnedName`
+pub struct OwnedName {
+    /// The local part of the name
+    pub local_name: String,
+    /// The namespace the name is in
+    pub namespace: Option<String>,
+    /// The prefix of the name as written in the document
+    pub prefix: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+/// An attribute, like `xml::attribute::OwnedAttribute`
+pub struct OwnedAttribute {
+    /// The qualified name of the attribute
+    pub name: OwnedName,
+    /// The value of the attribute
+    pub value: String,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+/// The namespaces in scope, by prefix, like `xml::namespace::Namespace`.
+/// The default namespace is stored with the empty prefix.
+pub struct Namespace(pub BTreeMap<String, String>);
+
+impl Namespace {
+    /// Returns the namespace bound to `prefix`
+    pub fn get(&self, prefix: &str) -> Option<&str> {
+        self.0.get(prefix).map(|x| &x[..])
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+/// A reader event, like `xml::reader::XmlEvent`
+pub enum XmlEvent {
+    /// The start of the document, with the values of the XML declaration or their defaults
+    StartDocument {
+        /// The `version` of the XML declaration
+        version: XmlVersion,
+        /// The `encoding` of the XML declaration
+        encoding: String,
+        /// The `standalone` declaration, if any
+        standalone: Option<bool>,
+    },
+    /// The end of the document
+    EndDocument,
+    /// A document type declaration
+    Doctype {
+        /// The complete declaration
+        syntax: String,
+    },
+    /// A processing instruction
+    ProcessingInstruction {
+        /// The target of the processing instruction
+        name: String,
+        /// The data following the target, if any
+        data: Option<String>,
+    },
+    /// A start tag
+    StartElement {
+        /// The name of the element
+        name: OwnedName,
+        /// The attributes of the element, without namespace declarations
+        attributes: Vec<OwnedAttribute>,
+        /// The namespaces in scope at the element
+        namespace: Namespace,
+    },
+    /// An end tag
+    EndElement {
+        /// The name of the element
+        name: OwnedName,
+    },
+    /// A CDATA section
+    CData(String),
+    /// A comment
+    Comment(String),
+    /// Character data that is not only whitespace
+    Characters(String),
+    /// Character data that is only whitespace
+    Whitespace(String),
+}
+
+/// A reader returning `XmlEvent`s, like `xml::reader::EventReader`
+pub struct EventReader<R>
+where
+    R: Read,
+{
+    parser: Parser<R>,
+    // The namespaces in scope at each open element
+    namespaces: Vec<Namespace>,
+    // An event read along with the start of the document, returned next
+    pending: Option<Event>,
+    started: bool,
+    finished: bool,
+}
+
+impl<'a> EventReader<&'a [u8]> {
+    /// Returns a new `EventReader` reading the document `text`
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(text: &'a str) -> EventReader<&'a [u8]> {
+        EventReader::new(text.as_bytes())
+    }
+}
+
+impl<R> EventReader<R>
+where
+    R: Read,
+{
+    /// Returns a new `EventReader` reading a document from `source`
+    pub fn new(source: R) -> EventReader<R> {
+        let mut namespace = Namespace::default();
+        namespace.0.insert("xml".to_owned(), ns::XML.to_owned());
+        namespace.0.insert("xmlns".to_owned(), ns::XMLNS.to_owned());
+        EventReader {
+            parser: Parser::new(source),
+            namespaces: vec![namespace],
+            pending: None,
+            started: false,
+            finished: false,
+        }
+    }
+
+    /// Returns the next event. Once the document has ended, `EndDocument` is returned.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<XmlEvent, ParserError> {
+        if let Some(event) = self.pending.take() {
+            return Ok(self.convert(event));
+        }
+        if self.finished {
+            return Ok(XmlEvent::EndDocument);
+        }
+        let event = match self.parser.next().transpose()? {
+            Some(event) => event,
+            None => {
+                self.finished = true;
+                return Ok(XmlEvent::EndDocument);
+            }
+        };
+        if self.started {
+            return Ok(self.convert(event));
+        }
+        self.started = true;
+        let declaration = match event {
+            Event::XmlDecl(ref decl) => Some(decl),
+            _ => None,
+        };
+        let start = XmlEvent::StartDocument {
+            version: match declaration.map(|decl| &decl.version[..]) {
+                Some("1.1") => XmlVersion::Version11,
+                _ => XmlVersion::Version10,
+            },
+            encoding: declaration
+                .and_then(|decl| decl.encoding.clone())
+                .unwrap_or_else(|| "UTF-8".to_owned()),
+            standalone: declaration.and_then(|decl| decl.standalone),
+        };
+        if declaration.is_none() {
+            self.pending = Some(event);
+        }
+        Ok(start)
+    }
+
+    fn convert(&mut self, event: Event) -> XmlEvent {
+        match event {
+            Event::PI(text) => {
+                let (name, data) = match text.split_once(char::is_whitespace) {
+                    Some((name, data)) => (name.to_owned(), Some(data.trim_start().to_owned())),
+                    None => (text, None),
+                };
+                XmlEvent::ProcessingInstruction { name, data }
+            }
+            Event::ElementStart(tag) => {
+                let mut namespace = self.namespaces.last().cloned().unwrap_or_default();
+                for (QName { name, ns }, value) in &tag.attributes {
+                    match ns.as_deref() {
+                        None if name == "xmlns" => namespace.0.insert(String::new(), value.clone()),
+                        Some(ns::XMLNS) => namespace.0.insert(name.to_string(), value.clone()),
+                        _ => continue,
+                    };
+                }
+                namespace.0.retain(|_, ns| !ns.is_empty());
+                let attributes = tag
+                    .attributes
+                    .into_iter()
+                    .filter(|(QName { name, ns }, _)| {
+                        !(ns.is_none() && name == "xmlns" || ns.as_deref() == Some(ns::XMLNS))
+                    })
+                    .map(|(QName { name, ns }, value)| OwnedAttribute {
+                        name: OwnedName {
+                            prefix: ns.as_deref().and_then(|ns| {
+                                namespace
+                                    .0
+                                    .iter()
+                                    .find(|&(prefix, bound)| !prefix.is_empty() && bound == ns)
+                                    .map(|(prefix, _)| prefix.clone())
+                            }),
+                            local_name: String::from(name),
+                            namespace: ns,
+                        },
+                        value,
+                    })
+                    .collect();
+                self.namespaces.push(namespace.clone());
+                XmlEvent::StartElement {
+                    name: OwnedName {
+                        local_name: String::from(tag.name.name),
+                        namespace: tag.name.ns,
+                        prefix: tag.prefix,
+                    },
+                    attributes,
+                    namespace,
+                }
+            }
+            Event::ElementEnd(tag) => {
+                self.namespaces.pop();
+                XmlEvent::EndElement {
+                    name: OwnedName {
+                        local_name: String::from(tag.name.name),
+                        namespace: tag.name.ns,
+                        prefix: tag.prefix,
+                    },
+                }
+            }
+            Event::Characters(text) if text.chars().all(char::is_whitespace) => {
+                XmlEvent::Whitespace(text)
+            }
+            Event::Characters(text) => XmlEvent::Characters(text),
+            Event::CDATA(text) => XmlEvent::CData(text),
+            Event::Comment(text) => XmlEvent::Comment(text),
+            Event::AttributeChunk(_) => unreachable!("Attributes are not chunked"),
+            Event::XmlDecl(_) => unreachable!("The declaration is only found first"),
+            Event::Doctype(doctype) => XmlEvent::Doctype {
+                syntax: doctype.to_string(),
+            },
+        }
+    }
+}
+
+impl<R> IntoIterator for EventReader<R>
+where
+    R: Read,
+{
+    type Item = Result<XmlEvent, ParserError>;
+    type IntoIter = Events<R>;
+
+    fn into_iter(self) -> Events<R> {
+        Events {
+            reader: self,
+            done: false,
+        }
+    }
+}
+
+/// An iterator over the events of an `EventReader`, like `xml::reader::Events`.
+/// It ends after `EndDocument` or an error.
+pub struct Events<R>
+where
+    R: Read,
+{
+    reader: EventReader<R>,
+    done: bool,
+}
+
+impl<R> Iterator for Events<R>
+where
+    R: Read,
+{
+    type Item = Result<XmlEvent, ParserError>;
+
+    fn next(&mut self) -> Option<Result<XmlEvent, ParserError>> {
+        if self.done {
+            return None;
+        }
+        let event = self.reader.next();
+        self.done = matches!(event, Ok(XmlEvent::EndDocument) | Err(_));
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EventReader, OwnedName, XmlEvent, XmlVersion};
+
+    #[test]
+    fn test_events() {
+        let doc = "<?xml version=\"1.1\" encoding='ISO-8859-1' standalone='yes'?>\
+                   <a xmlns:p='urn:p' p:x='1'> <!--c--><?pi  data?><p:b xmlns=''>t<![CDATA[d]]></p:b></a>";
+        let events: Vec<_> = EventReader::from_str(doc)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            events[0],
+            XmlEvent::StartDocument {
+                version: XmlVersion::Version11,
+                encoding: "ISO-8859-1".to_owned(),
+                standalone: Some(true),
+            }
+        );
+        match events[1] {
+            XmlEvent::StartElement {
+                ref name,
+                ref attributes,
+                ref namespace,
+            } => {
+                assert_eq!(name.local_name, "a");
+                assert_eq!(attributes.len(), 1);
+                assert_eq!(attributes[0].name.prefix.as_deref(), Some("p"));
+                assert_eq!(attributes[0].name.namespace.as_deref(), Some("urn:p"));
+                assert_eq!(namespace.get("p"), Some("urn:p"));
+                assert_eq!(namespace.0.len(), 3);
+            }
+            ref event => panic!("Unexpected {:?}", event),
+        }
+        assert_eq!(events[2], XmlEvent::Whitespace(" ".to_owned()));
+        assert_eq!(events[3], XmlEvent::Comment("c".to_owned()));
+        assert_eq!(
+            events[4],
+            XmlEvent::ProcessingInstruction {
+                name: "pi".to_owned(),
+                data: Some("data".to_owned()),
+            }
+        );
+        assert!(matches!(
+            events[5],
+            XmlEvent::StartElement { ref attributes, .. } if attributes.is_empty()
+        ));
+        assert_eq!(events[6], XmlEvent::Characters("t".to_owned()));
+        assert_eq!(events[7], XmlEvent::CData("d".to_owned()));
+        assert_eq!(
+            events[8],
+            XmlEvent::EndElement {
+                name: OwnedName {
+                    local_name: "b".to_owned(),
+                    namespace: Some("urn:p".to_owned()),
+                    prefix: Some("p".to_owned()),
+                }
+            }
+        );
+        assert_eq!(events.len(), 11);
+        assert_eq!(events[10], XmlEvent::EndDocument);
+
+        let mut reader = EventReader::from_str("<a/>");
+        assert!(matches!(
+            reader.next(),
+            Ok(XmlEvent::StartDocument { ref encoding, .. }) if encoding == "UTF-8"
+        ));
+        assert!(matches!(reader.next(), Ok(XmlEvent::StartElement { .. })));
+        assert!(matches!(reader.next(), Ok(XmlEvent::EndElement { .. })));
+        assert_eq!(reader.next(), Ok(XmlEvent::EndDocument));
+        assert_eq!(reader.next(), Ok(XmlEvent::EndDocument));
+
+        let events: Vec<_> = EventReader::from_str("<a>&x;</a>").into_iter().collect();
+        assert!(events.last().unwrap().is_err());
+    }
+}
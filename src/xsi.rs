@@ -0,0 +1,293 @@
+// RustyXML
+// Copyright 2013-2016 RustyXML developers
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Interpreting element content according to `xsi:type`
+//!
+//! SOAP encoding and many WS-* payloads annotate elements with the XML Schema type of their
+//! content, such as `xsi:type='xsd:int'`. `typed_content()` resolves the type's QName against
+//! the namespaces in scope at a `Cursor` and converts the content to a `TypedValue`.
+//! `Element::typed_content()` does the same for an element, using only the namespaces
+//! declared on it.
+//!
+//! ~~~
+//! use xml::xsi::{self, TypedValue};
+//! use xml::Element;
+//!
+//! let doc: Element = "<Body xmlns:xsi='http://www.w3.org/2001/XMLSchema-instance'\
+//!                     xmlns:xsd='http://www.w3.org/2001/XMLSchema'>\
+//!                     <count xsi:type='xsd:int'> 42 </count>\
+//!                     <name xsi:type='xsd:string'>Ann</name></Body>"
+//!     .parse()
+//!     .unwrap();
+//! let mut cursor = doc.cursor();
+//! let mut values = Vec::new();
+//! assert!(cursor.first_child_element());
+//! loop {
+//!     values.push(xsi::typed_content(&cursor).unwrap());
+//!     if !cursor.next_sibling_element() {
+//!         break;
+//!     }
+//! }
+//! assert_eq!(values, [TypedValue::Integer(42), TypedValue::String("Ann".to_owned())]);
+//! ~~~
+
+use crate::codec::{self, ContentEncoding, DecodeError};
+use crate::{ns, Cursor, QName};
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, PartialEq, Debug)]
+/// Element content converted according to a built-in XML Schema type
+pub enum TypedValue {
+    /// `xs:string` and types derived from it, such as `xs:token`, or content without
+    /// `xsi:type`
+    String(String),
+    /// `xs:integer` and the integer types derived from it, such as `xs:int`. Values of the
+    /// unbounded types outside the range of `i128` are rejected.
+    Integer(i128),
+    /// `xs:decimal`, `xs:float` and `xs:double`
+    Float(f64),
+    /// `xs:boolean`
+    Boolean(bool),
+    /// `xs:dateTime`, `xs:date` and `xs:time`, as the ISO 8601 text found in the document
+    DateTime(String),
+    /// `xs:base64Binary` and `xs:hexBinary`
+    Binary(Vec<u8>),
+    /// An element with `xsi:nil='true'`
+    Nil,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+/// Errors returned when interpreting content according to `xsi:type`
+pub enum TypeError {
+    /// The prefix of the type name is not bound to a namespace
+    UnboundPrefix(String),
+    /// The type is not a built-in simple type, or the cursor is not at an element
+    UnsupportedType(String),
+    /// The content is not a valid value of the type
+    InvalidValue(String),
+    /// Binary content could not be decoded
+    Decode(DecodeError),
+}
+
+impl Error for TypeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            TypeError::Decode(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TypeError::UnboundPrefix(ref prefix) => write!(f, "Unbound prefix '{}'", prefix),
+            TypeError::UnsupportedType(ref name) => write!(f, "Unsupported type '{}'", name),
+            TypeError::InvalidValue(ref name) => write!(f, "Invalid value of type '{}'", name),
+            TypeError::Decode(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl From<DecodeError> for TypeError {
+    fn from(err: DecodeError) -> TypeError {
+        TypeError::Decode(err)
+    }
+}
+
+/// Returns the name of the `xsi:type` of the element at `cursor`, if it has one
+pub fn xsi_type(cursor: &Cursor) -> Result<Option<QName>, TypeError> {
+    let qname = match cursor
+        .element()
+        .and_then(|elem| elem.get_attribute("type", Some(ns::XSI)))
+    {
+        Some(qname) => qname.trim(),
+        None => return Ok(None),
+    };
+    let (prefix, name) = qname.split_once(':').unwrap_or(("", qname));
+    match cursor.namespace_for_prefix(prefix) {
+        Some(ns) => Ok(Some(QName::new(name.to_owned(), Some(ns.to_owned())))),
+        None if prefix.is_empty() => Ok(Some(QName::new(name.to_owned(), None))),
+        None => Err(TypeError::UnboundPrefix(prefix.to_owned())),
+    }
+}
+
+/// Returns the content of the element at `cursor`, converted according to its `xsi:type`.
+/// Content without `xsi:type` is returned as a string.
+pub fn typed_content(cursor: &Cursor) -> Result<TypedValue, TypeError> {
+    let elem = match cursor.element() {
+        Some(elem) => elem,
+        None => return Err(TypeError::UnsupportedType(String::new())),
+    };
+    if elem
+        .get_attribute("nil", Some(ns::XSI))
+        .is_some_and(|nil| matches!(nil.trim(), "true" | "1"))
+    {
+        return Ok(TypedValue::Nil);
+    }
+    let name = match xsi_type(cursor)? {
+        Some(QName {
+            name,
+            ns: Some(ref ns),
+        }) if ns == ns::XSD => String::from(name),
+        Some(QName { name, .. }) => return Err(TypeError::UnsupportedType(String::from(name))),
+        None => return Ok(TypedValue::String(elem.content_str())),
+    };
+    let text = elem.content_str();
+    let invalid = || TypeError::InvalidValue(name.clone());
+    // All types but xs:string and xs:normalizedString collapse whitespace
+    let value = text.trim();
+    Ok(match &name[..] {
+        "string" => TypedValue::String(text),
+        "normalizedString" => TypedValue::String(text.replace(['\t', '\r', '\n'], " ")),
+        "token" | "language" | "Name" | "NCName" | "NMTOKEN" | "ID" | "IDREF" | "ENTITY"
+        | "anyURI" => TypedValue::String(value.split_whitespace().collect::<Vec<_>>().join(" ")),
+        "integer" | "long" | "int" | "short" | "byte" | "nonNegativeInteger"
+        | "positiveInteger" | "nonPositiveInteger" | "negativeInteger" | "unsignedLong"
+        | "unsignedInt" | "unsignedShort" | "unsignedByte" => {
+            let n: i128 = value.parse().map_err(|_| invalid())?;
+            let in_range = match &name[..] {
+                "long" => i64::try_from(n).is_ok(),
+                "int" => i32::try_from(n).is_ok(),
+                "short" => i16::try_from(n).is_ok(),
+                "byte" => i8::try_from(n).is_ok(),
+                "nonNegativeInteger" => n >= 0,
+                "positiveInteger" => n > 0,
+                "nonPositiveInteger" => n <= 0,
+                "negativeInteger" => n < 0,
+                "unsignedLong" => u64::try_from(n).is_ok(),
+                "unsignedInt" => u32::try_from(n).is_ok(),
+                "unsignedShort" => u16::try_from(n).is_ok(),
+                "unsignedByte" => u8::try_from(n).is_ok(),
+                _ => true,
+            };
+            if !in_range {
+                return Err(invalid());
+            }
+            TypedValue::Integer(n)
+        }
+        "decimal" | "float" | "double" => TypedValue::Float(match value {
+            "INF" => f64::INFINITY,
+            "-INF" => f64::NEG_INFINITY,
+            "NaN" => f64::NAN,
+            _ if value.contains(|c: char| c.is_ascii_alphabetic() && c != 'e' && c != 'E') => {
+                return Err(invalid())
+            }
+            _ => value.parse().map_err(|_| invalid())?,
+        }),
+        "boolean" => TypedValue::Boolean(match value {
+            "true" | "1" => true,
+            "false" | "0" => false,
+            _ => return Err(invalid()),
+        }),
+        "dateTime" | "date" | "time" if value.is_empty() => return Err(invalid()),
+        "dateTime" | "date" | "time" => TypedValue::DateTime(value.to_owned()),
+        "base64Binary" => TypedValue::Binary(codec::decode(value, ContentEncoding::Base64)?),
+        "hexBinary" => TypedValue::Binary(codec::decode(value, ContentEncoding::Hex)?),
+        _ => return Err(TypeError::UnsupportedType(name)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{typed_content, xsi_type, TypeError, TypedValue};
+    use crate::{ns, Element, QName};
+
+    fn values(doc: &str) -> Vec<Result<TypedValue, TypeError>> {
+        let root: Element = doc.parse().unwrap();
+        let mut cursor = root.cursor();
+        let mut values = Vec::new();
+        assert!(cursor.first_child_element());
+        loop {
+            values.push(typed_content(&cursor));
+            if !cursor.next_sibling_element() {
+                return values;
+            }
+        }
+    }
+
+    #[test]
+    fn test_typed_content() {
+        let doc = "<r xmlns:i='http://www.w3.org/2001/XMLSchema-instance'\
+                   xmlns:s='http://www.w3.org/2001/XMLSchema'>\
+                   <v i:type='s:string'> a  b </v>\
+                   <v i:type='s:normalizedString'> a&#9;b&#13;&#10;</v>\
+                   <v i:type='s:token'> a  b </v>\
+                   <v i:type='s:long'>+7</v>\
+                   <v i:type='s:long'>9223372036854775808</v>\
+                   <v i:type='s:unsignedLong'>18446744073709551615</v>\
+                   <v i:type='s:unsignedLong'>-1</v>\
+                   <v i:type='s:integer'>-99999999999999999999</v>\
+                   <v i:type='s:unsignedByte'>256</v>\
+                   <v i:type='s:double'>-1.5E2</v>\
+                   <v i:type='s:float'>INF</v>\
+                   <v i:type='s:float'>inf</v>\
+                   <v i:type='s:boolean'> 0 </v>\
+                   <v i:type='s:dateTime'>2024-01-01T00:00:00Z</v>\
+                   <v i:type='s:base64Binary'>SGVs bG8=</v>\
+                   <v i:type='s:hexBinary'>0g</v>\
+                   <v xmlns:t='urn:t' i:type='t:Struct'/>\
+                   <v i:type='u:int'/>\
+                   <v i:type='s:int' i:nil='true'/>\
+                   <v>untyped</v>\
+                   </r>";
+        assert_eq!(
+            values(doc),
+            [
+                Ok(TypedValue::String(" a  b ".to_owned())),
+                Ok(TypedValue::String(" a b  ".to_owned())),
+                Ok(TypedValue::String("a b".to_owned())),
+                Ok(TypedValue::Integer(7)),
+                Err(TypeError::InvalidValue("long".to_owned())),
+                Ok(TypedValue::Integer(u64::MAX.into())),
+                Err(TypeError::InvalidValue("unsignedLong".to_owned())),
+                Ok(TypedValue::Integer(-99_999_999_999_999_999_999)),
+                Err(TypeError::InvalidValue("unsignedByte".to_owned())),
+                Ok(TypedValue::Float(-150.0)),
+                Ok(TypedValue::Float(f64::INFINITY)),
+                Err(TypeError::InvalidValue("float".to_owned())),
+                Ok(TypedValue::Boolean(false)),
+                Ok(TypedValue::DateTime("2024-01-01T00:00:00Z".to_owned())),
+                Ok(TypedValue::Binary(b"Hello".to_vec())),
+                Err(TypeError::Decode(
+                    crate::codec::DecodeError::InvalidCharacter('g')
+                )),
+                Err(TypeError::UnsupportedType("Struct".to_owned())),
+                Err(TypeError::UnboundPrefix("u".to_owned())),
+                Ok(TypedValue::Nil),
+                Ok(TypedValue::String("untyped".to_owned())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_xsi_type() {
+        let elem: Element =
+            "<v xmlns='urn:d' xmlns:xsi='http://www.w3.org/2001/XMLSchema-instance'\
+                             xsi:type=' Local '/>"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            xsi_type(&elem.cursor()),
+            Ok(Some(QName::new(
+                "Local".to_owned(),
+                Some("urn:d".to_owned())
+            )))
+        );
+        let elem: Element = format!(
+            "<v xmlns:xsi='{}' xmlns:xs='{}' xsi:type='xs:int'>3</v>",
+            ns::XSI,
+            ns::XSD
+        )
+        .parse()
+        .unwrap();
+        assert_eq!(elem.typed_content(), Ok(TypedValue::Integer(3)));
+    }
+}